@@ -0,0 +1,619 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2025 Julian Kahlert
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::borrow::Cow;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use anyhow::{anyhow, Result};
+use russh::keys::Algorithm;
+use russh::{cipher, compression, kex, mac, Preferred};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::connection::ConnectionMonitor;
+
+/// SSH message number of the key exchange init packet.
+const SSH_MSG_KEXINIT: u8 = 20;
+
+/// Upper bound of bytes inspected while looking for the server's KEXINIT.
+const MAX_KEXINIT_SCAN: usize = 256 * 1024;
+
+/// User supplied algorithm lists for the SSH key exchange.
+///
+/// Every list is ordered by preference and doubles as the allow-list:
+/// algorithms that are not listed are not offered to the server. Lists
+/// that are not set fall back to the `russh` defaults.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct AlgorithmPreferences {
+    pub(crate) kex: Option<Vec<String>>,
+    pub(crate) host_key: Option<Vec<String>>,
+    pub(crate) cipher: Option<Vec<String>>,
+    pub(crate) mac: Option<Vec<String>>,
+    pub(crate) compression: Option<bool>,
+    pub(crate) denied: Vec<String>,
+}
+
+impl AlgorithmPreferences {
+    /// Converts the preferences into a `russh` [`Preferred`] set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an algorithm name is unknown or if a list ends
+    /// up empty after removing denied algorithms.
+    pub(crate) fn to_preferred(&self) -> Result<Preferred> {
+        let defaults = Preferred::default();
+
+        let mut kex_list: Vec<kex::Name> = match &self.kex {
+            Some(names) => parse_names(names, "key exchange", |n| kex::Name::try_from(n).ok())?,
+            None => defaults.kex.to_vec(),
+        };
+        kex_list.retain(|n| !self.is_denied(n.as_ref()));
+        ensure_not_empty(&kex_list, "key exchange")?;
+        // Keep the protocol extension markers so ext-info and strict KEX
+        // continue to work with custom lists.
+        for ext in [
+            kex::EXTENSION_SUPPORT_AS_CLIENT,
+            kex::EXTENSION_OPENSSH_STRICT_KEX_AS_CLIENT,
+        ] {
+            if !kex_list.contains(&ext) {
+                kex_list.push(ext);
+            }
+        }
+
+        let mut key_list: Vec<Algorithm> = match &self.host_key {
+            Some(names) => parse_names(names, "host key", |n| Algorithm::from_str(n).ok())?,
+            None => defaults.key.to_vec(),
+        };
+        key_list.retain(|a| !self.is_denied(a.as_str()));
+        ensure_not_empty(&key_list, "host key")?;
+
+        let mut cipher_list: Vec<cipher::Name> = match &self.cipher {
+            Some(names) => parse_names(names, "cipher", |n| cipher::Name::try_from(n).ok())?,
+            None => defaults.cipher.to_vec(),
+        };
+        cipher_list.retain(|n| !self.is_denied(n.as_ref()));
+        ensure_not_empty(&cipher_list, "cipher")?;
+
+        let mut mac_list: Vec<mac::Name> = match &self.mac {
+            Some(names) => parse_names(names, "MAC", |n| mac::Name::try_from(n).ok())?,
+            None => defaults.mac.to_vec(),
+        };
+        mac_list.retain(|n| !self.is_denied(n.as_ref()));
+        ensure_not_empty(&mac_list, "MAC")?;
+
+        let compression_list: Vec<compression::Name> = match self.compression {
+            Some(true) => vec![
+                compression::ZLIB_LEGACY,
+                compression::ZLIB,
+                compression::NONE,
+            ],
+            Some(false) => vec![compression::NONE],
+            None => defaults.compression.to_vec(),
+        };
+
+        Ok(Preferred {
+            kex: Cow::Owned(kex_list),
+            key: Cow::Owned(key_list),
+            cipher: Cow::Owned(cipher_list),
+            mac: Cow::Owned(mac_list),
+            compression: Cow::Owned(compression_list),
+        })
+    }
+
+    fn is_denied(&self, name: &str) -> bool {
+        self.denied.iter().any(|d| d == name)
+    }
+}
+
+/// Parses a list of algorithm names, rejecting unknown entries.
+fn parse_names<T>(
+    names: &[String],
+    kind: &str,
+    parse: impl Fn(&str) -> Option<T>,
+) -> Result<Vec<T>> {
+    names
+        .iter()
+        .map(|n| parse(n).ok_or_else(|| anyhow!("Unsupported {} algorithm: {}", kind, n)))
+        .collect()
+}
+
+fn ensure_not_empty<T>(list: &[T], kind: &str) -> Result<()> {
+    if list.is_empty() {
+        return Err(anyhow!("No {} algorithm left to offer", kind));
+    }
+    Ok(())
+}
+
+/// Algorithms expected to be used for the current transport.
+///
+/// `russh` does not report the algorithms it settles on, so these values
+/// are predicted: the server's KEXINIT is read off the wire and the
+/// selection rules of `russh` are applied to it and to the client's
+/// preferences. Only [`server_id`](Self::server_id) is observed directly.
+/// A `russh` release that changes its selection rules may pick
+/// differently.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpectedAlgorithms {
+    /// Identification string sent by the server (e.g. `SSH-2.0-OpenSSH_9.6`).
+    pub server_id: String,
+    /// Key exchange algorithm.
+    pub kex: String,
+    /// Host key signature algorithm.
+    pub host_key: String,
+    /// Cipher for both directions. `russh` picks it from the server's
+    /// client to server list and uses it for both directions.
+    pub cipher: String,
+    /// MAC for client to server traffic (`None` if the cipher needs none).
+    pub mac_client_to_server: Option<String>,
+    /// MAC for server to client traffic (`None` if the cipher needs none).
+    pub mac_server_to_client: Option<String>,
+    /// Compression for client to server traffic.
+    pub compression_client_to_server: String,
+    /// Compression for server to client traffic.
+    pub compression_server_to_client: String,
+}
+
+/// Algorithm lists announced by the server in its KEXINIT packet.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct ServerKexInit {
+    pub(crate) server_id: String,
+    pub(crate) kex: Vec<String>,
+    pub(crate) host_key: Vec<String>,
+    pub(crate) cipher_c2s: Vec<String>,
+    pub(crate) cipher_s2c: Vec<String>,
+    pub(crate) mac_c2s: Vec<String>,
+    pub(crate) mac_s2c: Vec<String>,
+    pub(crate) compression_c2s: Vec<String>,
+    pub(crate) compression_s2c: Vec<String>,
+}
+
+impl ExpectedAlgorithms {
+    /// Predicts the algorithms `russh` picks for our preferences and the
+    /// server's lists: the first client algorithm the server also supports.
+    ///
+    /// Returns `None` if the key exchange is going to fail.
+    pub(crate) fn predict(preferred: &Preferred, server: &ServerKexInit) -> Option<Self> {
+        let pick = |ours: Vec<&str>, theirs: &[String]| -> Option<String> {
+            ours.into_iter()
+                .find(|o| theirs.iter().any(|t| t == o))
+                .map(str::to_string)
+        };
+
+        let kex = pick(
+            preferred
+                .kex
+                .iter()
+                .map(|n| n.as_ref())
+                .filter(|n| !n.starts_with("ext-info-") && !n.starts_with("kex-strict-"))
+                .collect(),
+            &server.kex,
+        )?;
+        let host_key = pick(
+            preferred.key.iter().map(|a| a.as_str()).collect(),
+            &server.host_key,
+        )?;
+        let cipher = pick(
+            preferred.cipher.iter().map(|n| n.as_ref()).collect(),
+            &server.cipher_c2s,
+        )?;
+        // Mirrors `Cipher::needs_mac` in russh: only the block ciphers
+        // (CTR and CBC modes) rely on a separate MAC.
+        let needs_mac = cipher.ends_with("-ctr") || cipher.ends_with("-cbc");
+        let macs: Vec<&str> = preferred.mac.iter().map(|n| n.as_ref()).collect();
+        let (mac_c2s, mac_s2c) = if needs_mac {
+            (
+                Some(pick(macs.clone(), &server.mac_c2s)?),
+                Some(pick(macs, &server.mac_s2c)?),
+            )
+        } else {
+            (None, None)
+        };
+        let compressions: Vec<&str> = preferred.compression.iter().map(|n| n.as_ref()).collect();
+
+        Some(ExpectedAlgorithms {
+            server_id: server.server_id.clone(),
+            kex,
+            host_key,
+            cipher,
+            mac_client_to_server: mac_c2s,
+            mac_server_to_client: mac_s2c,
+            compression_client_to_server: pick(compressions.clone(), &server.compression_c2s)?,
+            compression_server_to_client: pick(compressions, &server.compression_s2c)?,
+        })
+    }
+}
+
+/// Incremental parser for the server identification line and the
+/// first (unencrypted) binary packet, which is the server's KEXINIT.
+struct KexInitParser {
+    buffer: Vec<u8>,
+}
+
+impl KexInitParser {
+    fn new() -> Self {
+        Self { buffer: Vec::new() }
+    }
+
+    /// Feeds received bytes to the parser.
+    ///
+    /// Returns `Some(Ok(..))` once the KEXINIT has been parsed,
+    /// `Some(Err(()))` if the stream cannot be parsed, and `None` if more
+    /// data is needed.
+    fn feed(&mut self, data: &[u8]) -> Option<Result<ServerKexInit, ()>> {
+        self.buffer.extend_from_slice(data);
+        if self.buffer.len() > MAX_KEXINIT_SCAN {
+            return Some(Err(()));
+        }
+        match parse_server_kexinit(&self.buffer) {
+            Ok(Some(init)) => Some(Ok(init)),
+            Ok(None) => None,
+            Err(()) => Some(Err(())),
+        }
+    }
+}
+
+/// Parses the identification line and the KEXINIT packet from `data`.
+///
+/// Returns `Ok(None)` if `data` does not contain a complete packet yet.
+fn parse_server_kexinit(data: &[u8]) -> Result<Option<ServerKexInit>, ()> {
+    // The server may send other lines before its identification line.
+    let mut offset = 0;
+    let server_id = loop {
+        let Some(end) = data[offset..].iter().position(|&b| b == b'\n') else {
+            return Ok(None);
+        };
+        let line = &data[offset..offset + end];
+        offset += end + 1;
+        if line.starts_with(b"SSH-") {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            break String::from_utf8_lossy(line).to_string();
+        }
+    };
+
+    let packet = &data[offset..];
+    if packet.len() < 5 {
+        return Ok(None);
+    }
+    let packet_len = u32::from_be_bytes([packet[0], packet[1], packet[2], packet[3]]) as usize;
+    if packet_len > MAX_KEXINIT_SCAN {
+        return Err(());
+    }
+    if packet.len() < 4 + packet_len {
+        return Ok(None);
+    }
+    let padding_len = packet[4] as usize;
+    let payload_len = packet_len.checked_sub(padding_len + 1).ok_or(())?;
+    let payload = &packet[5..5 + payload_len];
+    if payload.first() != Some(&SSH_MSG_KEXINIT) || payload.len() < 17 {
+        return Err(());
+    }
+
+    let mut rest = &payload[17..];
+    let mut lists = Vec::with_capacity(8);
+    for _ in 0..8 {
+        if rest.len() < 4 {
+            return Err(());
+        }
+        let len = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let list = rest.get(4..4 + len).ok_or(())?;
+        lists.push(
+            String::from_utf8_lossy(list)
+                .split(',')
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+                .collect::<Vec<_>>(),
+        );
+        rest = &rest[4 + len..];
+    }
+
+    let mut lists = lists.into_iter();
+    let mut next = || lists.next().unwrap_or_default();
+    Ok(Some(ServerKexInit {
+        server_id,
+        kex: next(),
+        host_key: next(),
+        cipher_c2s: next(),
+        cipher_s2c: next(),
+        mac_c2s: next(),
+        mac_s2c: next(),
+        compression_c2s: next(),
+        compression_s2c: next(),
+    }))
+}
+
+/// Transport wrapper that observes the server's KEXINIT on its way to
+/// `russh` and records the expected algorithms on the connection
+/// monitor. All bytes are passed through unchanged.
+pub(crate) struct KexInitTap<S> {
+    inner: S,
+    parser: Option<KexInitParser>,
+    preferred: Preferred,
+    connection: Arc<ConnectionMonitor>,
+}
+
+impl<S> KexInitTap<S> {
+    pub(crate) fn new(inner: S, preferred: Preferred, connection: Arc<ConnectionMonitor>) -> Self {
+        connection.set_expected(None);
+        Self {
+            inner,
+            parser: Some(KexInitParser::new()),
+            preferred,
+            connection,
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for KexInitTap<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let before = buf.filled().len();
+        let this = &mut *self;
+        let res = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if let (Poll::Ready(Ok(())), Some(parser)) = (&res, this.parser.as_mut()) {
+            if let Some(result) = parser.feed(&buf.filled()[before..]) {
+                if let Ok(server) = result {
+                    this.connection
+                        .set_expected(ExpectedAlgorithms::predict(&this.preferred, &server));
+                }
+                this.parser = None;
+            }
+        }
+        res
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for KexInitTap<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name_list(names: &str) -> Vec<u8> {
+        let mut out = (names.len() as u32).to_be_bytes().to_vec();
+        out.extend_from_slice(names.as_bytes());
+        out
+    }
+
+    fn kexinit_stream(lists: [&str; 10]) -> Vec<u8> {
+        let mut payload = vec![SSH_MSG_KEXINIT];
+        payload.extend_from_slice(&[0u8; 16]);
+        for list in lists {
+            payload.extend_from_slice(&name_list(list));
+        }
+        payload.push(0); // first_kex_packet_follows
+        payload.extend_from_slice(&[0u8; 4]);
+        let padding = 4usize;
+        let packet_len = (payload.len() + padding + 1) as u32;
+
+        let mut out = b"banner line\r\nSSH-2.0-OpenSSH_9.6\r\n".to_vec();
+        out.extend_from_slice(&packet_len.to_be_bytes());
+        out.push(padding as u8);
+        out.extend_from_slice(&payload);
+        out.extend_from_slice(&[0u8; 4]);
+        out
+    }
+
+    fn legacy_server() -> Vec<u8> {
+        kexinit_stream([
+            "diffie-hellman-group14-sha1,diffie-hellman-group1-sha1",
+            "ssh-rsa",
+            "aes128-cbc,aes128-ctr",
+            "aes128-cbc,aes128-ctr",
+            "hmac-sha1",
+            "hmac-sha1",
+            "none,zlib@openssh.com",
+            "none,zlib@openssh.com",
+            "",
+            "",
+        ])
+    }
+
+    #[test]
+    fn test_parse_server_kexinit() {
+        let init = parse_server_kexinit(&legacy_server()).unwrap().unwrap();
+        assert_eq!(init.server_id, "SSH-2.0-OpenSSH_9.6");
+        assert_eq!(
+            init.kex,
+            vec!["diffie-hellman-group14-sha1", "diffie-hellman-group1-sha1"]
+        );
+        assert_eq!(init.host_key, vec!["ssh-rsa"]);
+        assert_eq!(init.mac_s2c, vec!["hmac-sha1"]);
+        assert_eq!(init.compression_c2s, vec!["none", "zlib@openssh.com"]);
+    }
+
+    #[test]
+    fn test_parser_split_input() {
+        let data = legacy_server();
+        let mut parser = KexInitParser::new();
+        for chunk in data[..data.len() - 1].chunks(7) {
+            assert!(parser.feed(chunk).is_none());
+        }
+        let init = parser.feed(&data[data.len() - 1..]).unwrap().unwrap();
+        assert_eq!(init.cipher_c2s, vec!["aes128-cbc", "aes128-ctr"]);
+    }
+
+    #[test]
+    fn test_parser_rejects_non_kexinit() {
+        let mut data = legacy_server();
+        let pos = data.windows(4).position(|w| w == b"\r\n\0\0").unwrap() + 2;
+        let payload_start = pos + 5;
+        data[payload_start] = 21;
+        assert_eq!(parse_server_kexinit(&data), Err(()));
+    }
+
+    #[test]
+    fn test_preferences_default_matches_russh() {
+        let preferred = AlgorithmPreferences::default().to_preferred().unwrap();
+        let defaults = Preferred::default();
+        assert_eq!(preferred.cipher.as_ref(), defaults.cipher.as_ref());
+        assert_eq!(preferred.mac.as_ref(), defaults.mac.as_ref());
+        assert_eq!(preferred.key.as_ref(), defaults.key.as_ref());
+    }
+
+    #[test]
+    fn test_preferences_custom_lists() {
+        let prefs = AlgorithmPreferences {
+            kex: Some(vec!["diffie-hellman-group1-sha1".to_string()]),
+            host_key: Some(vec!["ssh-rsa".to_string()]),
+            cipher: Some(vec!["aes128-cbc".to_string()]),
+            mac: Some(vec!["hmac-sha1".to_string()]),
+            compression: Some(true),
+            denied: Vec::new(),
+        };
+        let preferred = prefs.to_preferred().unwrap();
+        assert_eq!(preferred.kex[0], kex::DH_G1_SHA1);
+        assert!(preferred.kex.contains(&kex::EXTENSION_SUPPORT_AS_CLIENT));
+        assert_eq!(preferred.key.as_ref(), &[Algorithm::Rsa { hash: None }]);
+        assert_eq!(preferred.cipher.as_ref(), &[cipher::AES_128_CBC]);
+        assert_eq!(preferred.mac.as_ref(), &[mac::HMAC_SHA1]);
+        assert_eq!(preferred.compression[0], compression::ZLIB_LEGACY);
+    }
+
+    #[test]
+    fn test_preferences_unknown_algorithm() {
+        let prefs = AlgorithmPreferences {
+            cipher: Some(vec!["rot13".to_string()]),
+            ..Default::default()
+        };
+        let err = prefs.to_preferred().unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Unsupported cipher algorithm: rot13"));
+    }
+
+    #[test]
+    fn test_preferences_denied() {
+        let prefs = AlgorithmPreferences {
+            denied: vec!["aes256-ctr".to_string(), "hmac-sha1".to_string()],
+            ..Default::default()
+        };
+        let preferred = prefs.to_preferred().unwrap();
+        assert!(!preferred.cipher.contains(&cipher::AES_256_CTR));
+        assert!(!preferred.mac.contains(&mac::HMAC_SHA1));
+
+        let prefs = AlgorithmPreferences {
+            cipher: Some(vec!["aes128-cbc".to_string()]),
+            denied: vec!["aes128-cbc".to_string()],
+            ..Default::default()
+        };
+        assert!(prefs.to_preferred().is_err());
+    }
+
+    #[test]
+    fn test_predict_legacy_server() {
+        let prefs = AlgorithmPreferences {
+            kex: Some(vec![
+                "curve25519-sha256".to_string(),
+                "diffie-hellman-group14-sha1".to_string(),
+            ]),
+            host_key: Some(vec!["ssh-ed25519".to_string(), "ssh-rsa".to_string()]),
+            cipher: Some(vec!["aes128-ctr".to_string(), "aes128-cbc".to_string()]),
+            mac: Some(vec!["hmac-sha1".to_string()]),
+            compression: Some(true),
+            denied: Vec::new(),
+        };
+        let preferred = prefs.to_preferred().unwrap();
+        let server = parse_server_kexinit(&legacy_server()).unwrap().unwrap();
+        let expected = ExpectedAlgorithms::predict(&preferred, &server).unwrap();
+
+        assert_eq!(expected.kex, "diffie-hellman-group14-sha1");
+        assert_eq!(expected.host_key, "ssh-rsa");
+        assert_eq!(expected.cipher, "aes128-ctr");
+        assert_eq!(expected.mac_server_to_client, Some("hmac-sha1".to_string()));
+        assert_eq!(expected.compression_client_to_server, "zlib@openssh.com");
+    }
+
+    #[test]
+    fn test_predict_no_common_algorithm() {
+        let preferred = Preferred::default();
+        let server = parse_server_kexinit(&legacy_server()).unwrap().unwrap();
+        assert!(ExpectedAlgorithms::predict(&preferred, &server).is_none());
+    }
+
+    #[test]
+    fn test_predict_aead_has_no_mac() {
+        let preferred = Preferred::default();
+        let server = ServerKexInit {
+            server_id: "SSH-2.0-test".to_string(),
+            kex: vec!["curve25519-sha256".to_string()],
+            host_key: vec!["ssh-ed25519".to_string()],
+            cipher_c2s: vec!["chacha20-poly1305@openssh.com".to_string()],
+            cipher_s2c: vec!["chacha20-poly1305@openssh.com".to_string()],
+            mac_c2s: vec!["hmac-sha2-256".to_string()],
+            mac_s2c: vec!["hmac-sha2-256".to_string()],
+            compression_c2s: vec!["none".to_string()],
+            compression_s2c: vec!["none".to_string()],
+        };
+        let expected = ExpectedAlgorithms::predict(&preferred, &server).unwrap();
+        assert_eq!(expected.mac_client_to_server, None);
+        assert_eq!(expected.compression_server_to_client, "none");
+    }
+
+    #[test]
+    fn test_predict_cipher_from_client_to_server_list() {
+        let preferred = Preferred::default();
+        let server = ServerKexInit {
+            server_id: "SSH-2.0-test".to_string(),
+            kex: vec!["curve25519-sha256".to_string()],
+            host_key: vec!["ssh-ed25519".to_string()],
+            cipher_c2s: vec!["aes256-ctr".to_string()],
+            cipher_s2c: vec!["chacha20-poly1305@openssh.com".to_string()],
+            mac_c2s: vec!["hmac-sha2-256".to_string()],
+            mac_s2c: vec!["hmac-sha2-512".to_string()],
+            compression_c2s: vec!["none".to_string()],
+            compression_s2c: vec!["none".to_string()],
+        };
+        let expected = ExpectedAlgorithms::predict(&preferred, &server).unwrap();
+        assert_eq!(expected.cipher, "aes256-ctr");
+        assert_eq!(
+            expected.mac_client_to_server,
+            Some("hmac-sha2-256".to_string())
+        );
+        assert_eq!(
+            expected.mac_server_to_client,
+            Some("hmac-sha2-512".to_string())
+        );
+    }
+}
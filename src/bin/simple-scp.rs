@@ -24,10 +24,17 @@
 
 use anyhow::{anyhow, Result};
 use clap::{Parser, ValueEnum};
+use std::io::{IsTerminal, Write};
 use std::path::PathBuf;
 use tokio::time::{timeout, Duration};
 
-use simple_ssh::Session;
+use simple_ssh::{
+    copy_between_with_options, ProgressWatcher, RateLimiter, ScpOptions, Session, SymlinkPolicy,
+    SyncCompare, SyncOptions, TransferProgress, TransferStrategy,
+};
+
+/// Width of the progress bar in characters.
+const PROGRESS_BAR_WIDTH: usize = 30;
 
 /// Command line arguments for the simple-scp binary.
 #[derive(Debug, Parser, Clone, PartialEq)]
@@ -38,8 +45,8 @@ use simple_ssh::Session;
 struct Args {
     /// SSH host to connect to.
     #[arg(short = 'H', long)]
-    #[arg(help = "SSH host to connect to")]
-    host: String,
+    #[arg(help = "SSH host to connect to (optional with host:path syntax)")]
+    host: Option<String>,
 
     /// SSH username.
     #[arg(short, long, default_value = "root")]
@@ -71,15 +78,337 @@ struct Args {
     #[arg(help = "Authentication method")]
     auth: Option<AuthMethod>,
 
-    /// Local file to upload.
+    /// Copy directories recursively.
+    #[arg(short = 'r', long)]
+    #[arg(help = "Copy directories recursively")]
+    recursive: bool,
+
+    /// Preserve modification times, access times and modes.
+    #[arg(long)]
+    #[arg(help = "Preserve modification times, access times and modes")]
+    preserve: bool,
+
+    /// How symlinks are handled during recursive uploads.
+    #[arg(long, value_enum, default_value = "follow")]
+    #[arg(help = "How symlinks are handled during recursive uploads")]
+    symlinks: SymlinkMode,
+
+    /// How files are transferred on the remote side.
+    #[arg(long, value_enum, default_value = "auto")]
+    #[arg(help = "Transfer method: auto (scp, falling back to cat), scp, or cat")]
+    strategy: StrategyMode,
+
+    /// Verify transferred files with a checksum.
+    #[arg(long)]
+    #[arg(help = "Verify transferred files with sha256sum or md5sum on the remote host")]
+    verify: bool,
+
+    /// Remove uploaded files that fail verification.
+    #[arg(long, requires = "verify")]
+    #[arg(help = "Remove uploaded files that fail verification")]
+    remove_corrupt: bool,
+
+    /// Bandwidth ceiling in bytes per second.
+    #[arg(short = 'l', long, value_name = "RATE", value_parser = parse_rate)]
+    #[arg(help = "Limit bandwidth to RATE bytes per second (suffixes K, M, G), e.g. 500K")]
+    limit: Option<u64>,
+
+    /// Synchronise a local directory into a remote directory.
+    #[arg(long)]
+    #[arg(help = "Upload only new and changed files of a local directory")]
+    sync: bool,
+
+    /// Delete remote files that do not exist locally.
+    #[arg(long, requires = "sync")]
+    #[arg(help = "With --sync, delete remote files that do not exist locally")]
+    delete: bool,
+
+    /// Compare files by checksum instead of size and modification time.
+    #[arg(long, requires = "sync")]
+    #[arg(help = "With --sync, compare files by checksum instead of size and mtime")]
+    checksum: bool,
+
+    /// Only synchronise files matching these patterns.
+    #[arg(long, requires = "sync", value_name = "GLOB")]
+    #[arg(help = "With --sync, only transfer files matching GLOB (repeatable)")]
+    include: Vec<String>,
+
+    /// Leave files and directories matching these patterns alone.
+    #[arg(long, requires = "sync", value_name = "GLOB")]
+    #[arg(help = "With --sync, skip files and directories matching GLOB (repeatable)")]
+    exclude: Vec<String>,
+
+    /// Only report what a sync would change.
+    #[arg(short = 'n', long, requires = "sync")]
+    #[arg(help = "With --sync, only report the changes")]
+    dry_run: bool,
+
+    /// Source path, either a local file or `[user@]host:path`.
     #[arg(required = true)]
-    #[arg(help = "Local file to upload")]
-    local: PathBuf,
+    #[arg(help = "Source: local file to upload, or [user@]host:path to download or copy")]
+    source: String,
 
-    /// Remote destination path.
+    /// Destination path, either a remote path or a local path.
     #[arg(required = true)]
-    #[arg(help = "Remote destination path")]
-    remote: String,
+    #[arg(help = "Destination: remote path ([user@]host:path), or local path when downloading")]
+    destination: String,
+}
+
+/// Symlink handling for recursive uploads.
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq)]
+enum SymlinkMode {
+    /// Upload the link target.
+    #[value(name = "follow")]
+    Follow,
+    /// Leave links out.
+    #[value(name = "skip")]
+    Skip,
+    /// Abort on links.
+    #[value(name = "error")]
+    Error,
+}
+
+impl From<SymlinkMode> for SymlinkPolicy {
+    fn from(mode: SymlinkMode) -> Self {
+        match mode {
+            SymlinkMode::Follow => SymlinkPolicy::Follow,
+            SymlinkMode::Skip => SymlinkPolicy::Skip,
+            SymlinkMode::Error => SymlinkPolicy::Error,
+        }
+    }
+}
+
+/// Transfer method on the remote side.
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq)]
+enum StrategyMode {
+    /// Use scp, fall back to cat if it is missing.
+    #[value(name = "auto")]
+    Auto,
+    /// Always use scp.
+    #[value(name = "scp")]
+    Scp,
+    /// Always use cat.
+    #[value(name = "cat")]
+    Cat,
+}
+
+impl From<StrategyMode> for TransferStrategy {
+    fn from(mode: StrategyMode) -> Self {
+        match mode {
+            StrategyMode::Auto => TransferStrategy::Auto,
+            StrategyMode::Scp => TransferStrategy::Scp,
+            StrategyMode::Cat => TransferStrategy::Cat,
+        }
+    }
+}
+
+/// Parses a rate such as `800`, `500K`, `1.5M` or `2G` into bytes per
+/// second, using binary multiples.
+///
+/// # Arguments
+///
+/// * `value` - The command line value
+fn parse_rate(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let (number, multiplier) = match value.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => {
+            let multiplier = match c.to_ascii_uppercase() {
+                'K' => 1024.0,
+                'M' => 1024.0 * 1024.0,
+                'G' => 1024.0 * 1024.0 * 1024.0,
+                _ => return Err(format!("Unknown unit '{}' in '{}'", c, value)),
+            };
+            (&value[..i], multiplier)
+        }
+        _ => (value, 1.0),
+    };
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("Invalid rate '{}'", value))?;
+    let rate = (number * multiplier).round();
+    if !rate.is_finite() || rate < 1.0 {
+        return Err(format!(
+            "Rate must be at least 1 byte per second, got '{}'",
+            value
+        ));
+    }
+    Ok(rate as u64)
+}
+
+/// Builds the SCP transfer options from command line arguments.
+///
+/// # Arguments
+///
+/// * `args` - Command line arguments
+fn build_scp_options(args: &Args) -> ScpOptions {
+    let options = ScpOptions::new()
+        .recursive(args.recursive)
+        .preserve(args.preserve)
+        .symlinks(args.symlinks.into())
+        .strategy(args.strategy.into())
+        .verify(args.verify)
+        .remove_corrupt(args.remove_corrupt);
+    match args.limit {
+        Some(rate) => options.rate_limit(RateLimiter::new(rate)),
+        None => options,
+    }
+}
+
+/// Builds the sync options from command line arguments.
+///
+/// # Arguments
+///
+/// * `args` - Command line arguments
+fn build_sync_options(args: &Args) -> SyncOptions {
+    let compare = if args.checksum {
+        SyncCompare::Checksum
+    } else {
+        SyncCompare::SizeAndTime
+    };
+    let mut options = SyncOptions::new()
+        .compare(compare)
+        .delete(args.delete)
+        .dry_run(args.dry_run);
+    for pattern in &args.include {
+        options = options.include(pattern);
+    }
+    for pattern in &args.exclude {
+        options = options.exclude(pattern);
+    }
+    if let Some(rate) = args.limit {
+        options = options.rate_limit(RateLimiter::new(rate));
+    }
+    options
+}
+
+/// Direction and paths of a transfer resolved from the arguments.
+#[derive(Debug, Clone, PartialEq)]
+enum Transfer {
+    /// Copy a local file to the remote host.
+    Upload { local: PathBuf, remote: String },
+    /// Copy a remote file to the local host.
+    Download { remote: String, local: PathBuf },
+    /// Copy a file from the remote host to a second remote host.
+    Copy {
+        remote: String,
+        dst_user: String,
+        dst_host: String,
+        dst_remote: String,
+    },
+}
+
+/// A fully resolved transfer including the host to connect to.
+#[derive(Debug, Clone, PartialEq)]
+struct Target {
+    user: String,
+    host: String,
+    transfer: Transfer,
+}
+
+/// A `[user@]host:path` argument.
+#[derive(Debug, Clone, PartialEq)]
+struct RemoteSpec<'a> {
+    user: Option<&'a str>,
+    host: &'a str,
+    path: &'a str,
+}
+
+/// Splits a `[user@]host:path` argument.
+///
+/// IPv6 hosts must be enclosed in brackets (`[fe80::1]:path`). Arguments
+/// without a colon, or with a slash before the first colon, are local
+/// paths.
+///
+/// # Arguments
+///
+/// * `arg` - The positional argument to inspect
+fn parse_remote_spec(arg: &str) -> Option<RemoteSpec<'_>> {
+    let (user, rest) = match arg.split_once('@') {
+        Some((user, rest)) if !user.is_empty() && !user.contains('/') && !user.contains(':') => {
+            (Some(user), rest)
+        }
+        _ => (None, arg),
+    };
+
+    let (host, path) = if let Some(bracketed) = rest.strip_prefix('[') {
+        let (host, path) = bracketed.split_once(']')?;
+        (host, path.strip_prefix(':')?)
+    } else {
+        let (host, path) = rest.split_once(':')?;
+        if host.contains('/') {
+            return None;
+        }
+        (host, path)
+    };
+
+    if host.is_empty() {
+        return None;
+    }
+    Some(RemoteSpec { user, host, path })
+}
+
+/// Resolves host, user and transfer direction from the arguments.
+///
+/// # Arguments
+///
+/// * `args` - Command line arguments
+///
+/// # Errors
+///
+/// Returns an error if neither side names a host and no `-H` option is
+/// given.
+fn resolve_target(args: &Args) -> Result<Target> {
+    let source = parse_remote_spec(&args.source);
+    let destination = parse_remote_spec(&args.destination);
+
+    let (spec, transfer) = match (source, destination) {
+        (Some(spec), Some(dst)) => (
+            Some(spec.clone()),
+            Transfer::Copy {
+                remote: spec.path.to_string(),
+                dst_user: dst.user.unwrap_or(&args.user).to_string(),
+                dst_host: dst.host.to_string(),
+                dst_remote: dst.path.to_string(),
+            },
+        ),
+        (Some(spec), None) => (
+            Some(spec.clone()),
+            Transfer::Download {
+                remote: spec.path.to_string(),
+                local: PathBuf::from(&args.destination),
+            },
+        ),
+        (None, Some(spec)) => (
+            Some(spec.clone()),
+            Transfer::Upload {
+                local: PathBuf::from(&args.source),
+                remote: spec.path.to_string(),
+            },
+        ),
+        (None, None) => (
+            None,
+            Transfer::Upload {
+                local: PathBuf::from(&args.source),
+                remote: args.destination.clone(),
+            },
+        ),
+    };
+
+    let host = match (&spec, &args.host) {
+        (Some(spec), _) => spec.host.to_string(),
+        (None, Some(host)) => host.clone(),
+        (None, None) => return Err(anyhow!("No host given; use -H or host:path syntax")),
+    };
+    let user = spec
+        .and_then(|s| s.user)
+        .map(str::to_string)
+        .unwrap_or_else(|| args.user.clone());
+
+    Ok(Target {
+        user,
+        host,
+        transfer,
+    })
 }
 
 /// Authentication methods for SSH connections.
@@ -106,9 +435,21 @@ enum AuthMethod {
 ///
 /// A configured Session or an error if required arguments are missing.
 fn build_session_from_args(args: &Args) -> Result<Session> {
+    let target = resolve_target(args)?;
+    build_session(args, &target.host, &target.user)
+}
+
+/// Builds a Session to `host` with the credentials from the arguments.
+///
+/// # Arguments
+///
+/// * `args` - Parsed command line arguments
+/// * `host` - Host to connect to
+/// * `user` - User to log in as
+fn build_session(args: &Args, host: &str, user: &str) -> Result<Session> {
     let mut session = Session::init()
-        .with_host(&args.host)
-        .with_user(&args.user)
+        .with_host(host)
+        .with_user(user)
         .with_port(args.port);
 
     if let Some(scope) = &args.scope {
@@ -147,31 +488,163 @@ fn build_session_from_args(args: &Args) -> Result<Session> {
 ///
 /// # Arguments
 ///
-/// * `args` - Command line arguments
-fn format_transfer_message(args: &Args) -> String {
-    let local_path = args.local.to_string_lossy();
+/// * `target` - The resolved transfer target
+fn format_transfer_message(target: &Target) -> String {
+    match &target.transfer {
+        Transfer::Upload { local, remote } => format!(
+            "Transferring '{}' to '{}@{}:{}'",
+            local.to_string_lossy(),
+            target.user,
+            target.host,
+            remote
+        ),
+        Transfer::Download { remote, local } => format!(
+            "Transferring '{}@{}:{}' to '{}'",
+            target.user,
+            target.host,
+            remote,
+            local.to_string_lossy()
+        ),
+        Transfer::Copy {
+            remote,
+            dst_user,
+            dst_host,
+            dst_remote,
+        } => format!(
+            "Transferring '{}@{}:{}' to '{}@{}:{}'",
+            target.user, target.host, remote, dst_user, dst_host, dst_remote
+        ),
+    }
+}
+
+/// Connects a session, giving up after 30 seconds.
+///
+/// # Arguments
+///
+/// * `session` - The session to connect
+async fn connect(session: Session) -> Result<Session> {
+    match timeout(Duration::from_secs(30), session.connect()).await {
+        Ok(Ok(s)) => Ok(s),
+        Ok(Err(e)) => Err(anyhow!("Connection failed: {}", e)),
+        Err(_) => Err(anyhow!("Connection timed out")),
+    }
+}
+
+/// Formats a byte count with binary units (e.g. `1.5 MiB`).
+///
+/// # Arguments
+///
+/// * `bytes` - Number of bytes
+fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", value as u64, UNITS[unit])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Formats a duration as `MM:SS`, or `H:MM:SS` above one hour.
+///
+/// # Arguments
+///
+/// * `duration` - The duration to format
+fn format_eta(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, (secs / 60) % 60, secs % 60)
+    } else {
+        format!("{:02}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+/// Renders a single progress line for a file transfer.
+///
+/// # Arguments
+///
+/// * `progress` - The progress snapshot to render
+fn format_progress(progress: &TransferProgress) -> String {
+    let fraction = progress.fraction();
+    let filled = (fraction * PROGRESS_BAR_WIDTH as f64).round() as usize;
+    let bar = format!(
+        "{}{}",
+        "#".repeat(filled),
+        "-".repeat(PROGRESS_BAR_WIDTH - filled)
+    );
+    let name = std::path::Path::new(&progress.path)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| progress.path.clone());
+    let eta = match (progress.finished, progress.eta) {
+        (true, _) => format_eta(progress.elapsed),
+        (false, Some(eta)) => format!("ETA {}", format_eta(eta)),
+        (false, None) => "ETA --:--".to_string(),
+    };
+
     format!(
-        "Transferring '{}' to '{}@{}:{}'",
-        local_path, args.user, args.host, args.remote
+        "{} [{}] {:>3}% {}/{} {}/s {}",
+        name,
+        bar,
+        (fraction * 100.0) as u32,
+        format_bytes(progress.bytes as f64),
+        format_bytes(progress.total as f64),
+        format_bytes(progress.instantaneous_bps),
+        eta
     )
 }
 
-/// Gets the local file path as a string.
+/// Draws progress updates on stdout until the watcher closes.
+///
+/// Each file gets its own line, which is redrawn in place.
 ///
 /// # Arguments
 ///
-/// * `args` - Command line arguments
-fn get_local_path_str(args: &Args) -> String {
-    args.local.to_string_lossy().to_string()
+/// * `watcher` - Progress watcher of the running transfer
+async fn render_progress(mut watcher: ProgressWatcher) {
+    let mut stdout = std::io::stdout();
+    while let Some(progress) = watcher.changed().await {
+        let _ = write!(stdout, "\r\x1b[K{}", format_progress(&progress));
+        if progress.finished {
+            let _ = writeln!(stdout);
+        }
+        let _ = stdout.flush();
+    }
 }
 
-/// Gets the remote destination path.
+/// Runs a `--sync` upload and prints its report.
 ///
 /// # Arguments
 ///
 /// * `args` - Command line arguments
-fn get_remote_path(args: &Args) -> &str {
-    &args.remote
+/// * `target` - The resolved upload target
+/// * `ssh` - The connected session
+async fn run_sync(args: &Args, target: &Target, mut ssh: Session) -> Result<()> {
+    let Transfer::Upload { local, remote } = &target.transfer else {
+        return Err(anyhow!("--sync only uploads a local directory"));
+    };
+    let mut options = build_sync_options(args);
+    let renderer = std::io::stdout()
+        .is_terminal()
+        .then(|| tokio::spawn(render_progress(options.watch_progress())));
+
+    let result = ssh
+        .sync_dir(&local.to_string_lossy(), remote, &options)
+        .await;
+    drop(options);
+    if let Some(renderer) = renderer {
+        let _ = renderer.await;
+    }
+
+    let report = result.map_err(|e| anyhow!("Sync failed: {}", e))?;
+    println!("{}", report);
+    ssh.close().await?;
+    Ok(())
 }
 
 #[tokio::main]
@@ -179,22 +652,63 @@ async fn main() -> Result<()> {
     env_logger::init();
 
     let args = Args::parse();
+    let target = resolve_target(&args)?;
+    if args.sync && !matches!(target.transfer, Transfer::Upload { .. }) {
+        return Err(anyhow!("--sync only uploads a local directory"));
+    }
     let session = build_session_from_args(&args)?;
+    let dst_session = match &target.transfer {
+        Transfer::Copy {
+            dst_user, dst_host, ..
+        } => Some(build_session(&args, dst_host, dst_user)?),
+        _ => None,
+    };
 
-    let mut ssh = match timeout(Duration::from_secs(30), session.connect()).await {
-        Ok(Ok(s)) => s,
-        Ok(Err(e)) => return Err(anyhow!("Connection failed: {}", e)),
-        Err(_) => return Err(anyhow!("Connection timed out")),
+    let mut ssh = connect(session).await?;
+    let mut dst_ssh = match dst_session {
+        Some(session) => Some(connect(session).await?),
+        None => None,
     };
 
-    println!("{}", format_transfer_message(&args));
+    println!("{}", format_transfer_message(&target));
 
-    match timeout(
-        Duration::from_secs(3000),
-        ssh.scp(&get_local_path_str(&args), get_remote_path(&args)),
-    )
-    .await
-    {
+    if args.sync {
+        return run_sync(&args, &target, ssh).await;
+    }
+
+    let mut options = build_scp_options(&args);
+    let renderer = std::io::stdout()
+        .is_terminal()
+        .then(|| tokio::spawn(render_progress(options.watch_progress())));
+    let transfer = async {
+        match &target.transfer {
+            Transfer::Upload { local, remote } => {
+                ssh.scp_with_options(&local.to_string_lossy(), remote, &options)
+                    .await
+            }
+            Transfer::Download { remote, local } => {
+                ssh.scp_download_with_options(remote, &local.to_string_lossy(), &options)
+                    .await
+            }
+            Transfer::Copy {
+                remote, dst_remote, ..
+            } => {
+                let dst = dst_ssh.as_mut().expect("destination session is connected");
+                copy_between_with_options(&mut ssh, remote, dst, dst_remote, &options)
+                    .await
+                    .map(|_| ())
+            }
+        }
+    };
+
+    let result = timeout(Duration::from_secs(3000), transfer).await;
+    // Closing the progress channel lets the renderer draw the last update.
+    drop(options);
+    if let Some(renderer) = renderer {
+        let _ = renderer.await;
+    }
+
+    match result {
         Ok(Ok(())) => {
             println!("File transferred successfully.");
         }
@@ -207,6 +721,9 @@ async fn main() -> Result<()> {
     }
 
     ssh.close().await?;
+    if let Some(mut dst) = dst_ssh {
+        dst.close().await?;
+    }
     Ok(())
 }
 
@@ -217,23 +734,23 @@ mod tests {
 
     #[test]
     fn test_args_parsing_basic() {
-        let args = Args::parse_from(&[
+        let args = Args::parse_from([
             "simple-scp",
             "-H",
             "localhost",
             "/local/file.txt",
             "/remote/path.txt",
         ]);
-        assert_eq!(args.host, "localhost");
+        assert_eq!(args.host, Some("localhost".to_string()));
         assert_eq!(args.user, "root");
         assert_eq!(args.port, 22);
-        assert_eq!(args.local, PathBuf::from("/local/file.txt"));
-        assert_eq!(args.remote, "/remote/path.txt");
+        assert_eq!(args.source, "/local/file.txt");
+        assert_eq!(args.destination, "/remote/path.txt");
     }
 
     #[test]
     fn test_args_parsing_with_options() {
-        let args = Args::parse_from(&[
+        let args = Args::parse_from([
             "simple-scp",
             "-H",
             "192.168.1.1",
@@ -246,17 +763,17 @@ mod tests {
             "/local/file.txt",
             "/remote/path.txt",
         ]);
-        assert_eq!(args.host, "192.168.1.1");
+        assert_eq!(args.host, Some("192.168.1.1".to_string()));
         assert_eq!(args.user, "admin");
         assert_eq!(args.port, 2222);
         assert_eq!(args.passwd, Some("secret".to_string()));
-        assert_eq!(args.local, PathBuf::from("/local/file.txt"));
-        assert_eq!(args.remote, "/remote/path.txt");
+        assert_eq!(args.source, "/local/file.txt");
+        assert_eq!(args.destination, "/remote/path.txt");
     }
 
     #[test]
     fn test_args_parsing_with_key() {
-        let args = Args::parse_from(&[
+        let args = Args::parse_from([
             "simple-scp",
             "-H",
             "server.example.com",
@@ -266,13 +783,13 @@ mod tests {
             "/remote/path.txt",
         ]);
         assert_eq!(args.key, Some(PathBuf::from("/path/to/key")));
-        assert_eq!(args.local, PathBuf::from("/local/file.txt"));
-        assert_eq!(args.remote, "/remote/path.txt");
+        assert_eq!(args.source, "/local/file.txt");
+        assert_eq!(args.destination, "/remote/path.txt");
     }
 
     #[test]
     fn test_args_parsing_with_scope() {
-        let args = Args::parse_from(&[
+        let args = Args::parse_from([
             "simple-scp",
             "-H",
             "fe80::1",
@@ -282,13 +799,13 @@ mod tests {
             "/remote/path.txt",
         ]);
         assert_eq!(args.scope, Some("eth0".to_string()));
-        assert_eq!(args.local, PathBuf::from("/local/file.txt"));
-        assert_eq!(args.remote, "/remote/path.txt");
+        assert_eq!(args.source, "/local/file.txt");
+        assert_eq!(args.destination, "/remote/path.txt");
     }
 
     #[test]
     fn test_args_parsing_auth_method() {
-        let args = Args::parse_from(&[
+        let args = Args::parse_from([
             "simple-scp",
             "-H",
             "server.example.com",
@@ -302,7 +819,7 @@ mod tests {
 
     #[test]
     fn test_args_parsing_default_user() {
-        let args = Args::parse_from(&[
+        let args = Args::parse_from([
             "simple-scp",
             "-H",
             "localhost",
@@ -314,7 +831,7 @@ mod tests {
 
     #[test]
     fn test_args_parsing_default_port() {
-        let args = Args::parse_from(&[
+        let args = Args::parse_from([
             "simple-scp",
             "-H",
             "localhost",
@@ -353,31 +870,31 @@ mod tests {
 
     #[test]
     fn test_pathbuf_display() {
-        let args = Args::parse_from(&[
+        let args = Args::parse_from([
             "simple-scp",
             "-H",
             "localhost",
             "/local/dir/file.txt",
             "/remote/path.txt",
         ]);
-        assert_eq!(args.local.to_string_lossy(), "/local/dir/file.txt");
+        assert_eq!(args.source, "/local/dir/file.txt");
     }
 
     #[test]
     fn test_args_parsing_remote_path_with_spaces() {
-        let args = Args::parse_from(&[
+        let args = Args::parse_from([
             "simple-scp",
             "-H",
             "localhost",
             "/local/file.txt",
             "/remote/path/with spaces/file.txt",
         ]);
-        assert_eq!(args.remote, "/remote/path/with spaces/file.txt");
+        assert_eq!(args.destination, "/remote/path/with spaces/file.txt");
     }
 
     #[test]
     fn test_build_session_from_args_password() {
-        let args = Args::parse_from(&[
+        let args = Args::parse_from([
             "simple-scp",
             "-H",
             "testhost",
@@ -396,7 +913,7 @@ mod tests {
 
     #[test]
     fn test_build_session_from_args_key() {
-        let args = Args::parse_from(&[
+        let args = Args::parse_from([
             "simple-scp",
             "-H",
             "testhost",
@@ -413,7 +930,7 @@ mod tests {
 
     #[test]
     fn test_build_session_from_args_with_scope() {
-        let args = Args::parse_from(&[
+        let args = Args::parse_from([
             "simple-scp",
             "-H",
             "fe80::1",
@@ -430,7 +947,7 @@ mod tests {
 
     #[test]
     fn test_build_session_error_missing_password() {
-        let args = Args::parse_from(&[
+        let args = Args::parse_from([
             "simple-scp",
             "-H",
             "testhost",
@@ -448,7 +965,7 @@ mod tests {
 
     #[test]
     fn test_build_session_error_missing_key() {
-        let args = Args::parse_from(&[
+        let args = Args::parse_from([
             "simple-scp",
             "-H",
             "testhost",
@@ -466,7 +983,7 @@ mod tests {
 
     #[test]
     fn test_format_transfer_message() {
-        let args = Args::parse_from(&[
+        let args = Args::parse_from([
             "simple-scp",
             "-H",
             "example.com",
@@ -475,35 +992,298 @@ mod tests {
             "/home/user/file.txt",
             "/remote/dest/file.txt",
         ]);
-        let msg = format_transfer_message(&args);
+        let msg = format_transfer_message(&resolve_target(&args).unwrap());
         assert!(msg.contains("/home/user/file.txt"));
         assert!(msg.contains("user@example.com"));
         assert!(msg.contains("/remote/dest/file.txt"));
     }
 
     #[test]
-    fn test_get_local_path_str() {
-        let args = Args::parse_from(&[
+    fn test_resolve_target_upload() {
+        let args = Args::parse_from([
             "simple-scp",
             "-H",
             "localhost",
             "/local/path/file.txt",
-            "/remote.txt",
+            "/remote/path/file.txt",
         ]);
-        let path = get_local_path_str(&args);
-        assert_eq!(path, "/local/path/file.txt");
+        let target = resolve_target(&args).unwrap();
+        assert_eq!(target.host, "localhost");
+        assert_eq!(target.user, "root");
+        assert_eq!(
+            target.transfer,
+            Transfer::Upload {
+                local: PathBuf::from("/local/path/file.txt"),
+                remote: "/remote/path/file.txt".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_resolve_target_upload_host_syntax() {
+        let args = Args::parse_from(["simple-scp", "/local.txt", "admin@server:/remote.txt"]);
+        let target = resolve_target(&args).unwrap();
+        assert_eq!(target.host, "server");
+        assert_eq!(target.user, "admin");
+        assert_eq!(
+            target.transfer,
+            Transfer::Upload {
+                local: PathBuf::from("/local.txt"),
+                remote: "/remote.txt".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_resolve_target_download() {
+        let args = Args::parse_from(["simple-scp", "server:/var/log/syslog", "./syslog"]);
+        let target = resolve_target(&args).unwrap();
+        assert_eq!(target.host, "server");
+        assert_eq!(target.user, "root");
+        assert_eq!(
+            target.transfer,
+            Transfer::Download {
+                remote: "/var/log/syslog".to_string(),
+                local: PathBuf::from("./syslog"),
+            }
+        );
+
+        let msg = format_transfer_message(&target);
+        assert_eq!(
+            msg,
+            "Transferring 'root@server:/var/log/syslog' to './syslog'"
+        );
+    }
+
+    #[test]
+    fn test_resolve_target_download_ipv6() {
+        let args = Args::parse_from(["simple-scp", "--scope", "eth0", "[fe80::1]:file", "."]);
+        let target = resolve_target(&args).unwrap();
+        assert_eq!(target.host, "fe80::1");
+        assert!(matches!(target.transfer, Transfer::Download { .. }));
+    }
+
+    #[test]
+    fn test_resolve_target_errors() {
+        let args = Args::parse_from(["simple-scp", "/local.txt", "/remote.txt"]);
+        assert!(resolve_target(&args).is_err());
     }
 
     #[test]
-    fn test_get_remote_path() {
-        let args = Args::parse_from(&[
+    fn test_resolve_target_copy() {
+        let args = Args::parse_from(["simple-scp", "-u", "admin", "a:/x", "deploy@b:/y/"]);
+        let target = resolve_target(&args).unwrap();
+        assert_eq!(target.host, "a");
+        assert_eq!(target.user, "admin");
+        assert_eq!(
+            target.transfer,
+            Transfer::Copy {
+                remote: "/x".to_string(),
+                dst_user: "deploy".to_string(),
+                dst_host: "b".to_string(),
+                dst_remote: "/y/".to_string(),
+            }
+        );
+
+        let msg = format_transfer_message(&target);
+        assert_eq!(msg, "Transferring 'admin@a:/x' to 'deploy@b:/y/'");
+    }
+
+    #[test]
+    fn test_args_parsing_recursive() {
+        let args = Args::parse_from(["simple-scp", "/local.txt", "host:/remote.txt"]);
+        assert!(!args.recursive);
+        assert_eq!(args.symlinks, SymlinkMode::Follow);
+        assert_eq!(build_scp_options(&args), ScpOptions::new());
+
+        let args = Args::parse_from([
             "simple-scp",
-            "-H",
-            "localhost",
-            "/local.txt",
-            "/remote/path/file.txt",
+            "-r",
+            "--symlinks",
+            "skip",
+            "./build",
+            "host:/opt/app",
+        ]);
+        assert!(args.recursive);
+        assert_eq!(args.symlinks, SymlinkMode::Skip);
+        assert_eq!(
+            build_scp_options(&args),
+            ScpOptions::new()
+                .recursive(true)
+                .symlinks(SymlinkPolicy::Skip)
+        );
+    }
+
+    #[test]
+    fn test_args_parsing_preserve() {
+        let args = Args::parse_from(["simple-scp", "--preserve", "host:/bin/tool", "."]);
+        assert!(args.preserve);
+        assert_eq!(build_scp_options(&args), ScpOptions::new().preserve(true));
+    }
+
+    #[test]
+    fn test_args_parsing_strategy() {
+        let args = Args::parse_from(["simple-scp", "/local.txt", "host:/remote.txt"]);
+        assert_eq!(args.strategy, StrategyMode::Auto);
+
+        let args = Args::parse_from(["simple-scp", "--strategy", "cat", "host:/x", "."]);
+        assert_eq!(args.strategy, StrategyMode::Cat);
+        assert_eq!(
+            build_scp_options(&args),
+            ScpOptions::new().strategy(TransferStrategy::Cat)
+        );
+
+        let result = Args::try_parse_from(["simple-scp", "--strategy", "ftp", "host:/x", "."]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_args_parsing_verify() {
+        let args = Args::parse_from(["simple-scp", "--verify", "fw.bin", "host:/tmp/fw.bin"]);
+        assert!(args.verify);
+        assert!(!args.remove_corrupt);
+        assert_eq!(build_scp_options(&args), ScpOptions::new().verify(true));
+
+        let args = Args::parse_from([
+            "simple-scp",
+            "--verify",
+            "--remove-corrupt",
+            "fw.bin",
+            "host:/tmp/fw.bin",
+        ]);
+        assert_eq!(
+            build_scp_options(&args),
+            ScpOptions::new().verify(true).remove_corrupt(true)
+        );
+
+        let result =
+            Args::try_parse_from(["simple-scp", "--remove-corrupt", "fw.bin", "host:/tmp/x"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_rate() {
+        assert_eq!(parse_rate("800"), Ok(800));
+        assert_eq!(parse_rate("500K"), Ok(500 * 1024));
+        assert_eq!(parse_rate("500k"), Ok(500 * 1024));
+        assert_eq!(parse_rate("1.5M"), Ok(1536 * 1024));
+        assert_eq!(parse_rate("2G"), Ok(2 * 1024 * 1024 * 1024));
+        assert!(parse_rate("").is_err());
+        assert!(parse_rate("0").is_err());
+        assert!(parse_rate("-5K").is_err());
+        assert!(parse_rate("10X").is_err());
+        assert!(parse_rate("fast").is_err());
+    }
+
+    #[test]
+    fn test_args_parsing_limit() {
+        let args = Args::parse_from(["simple-scp", "-l", "256K", "fw.bin", "host:/tmp/fw.bin"]);
+        assert_eq!(args.limit, Some(256 * 1024));
+        assert_ne!(build_scp_options(&args), ScpOptions::new());
+
+        let args = Args::parse_from(["simple-scp", "--limit", "1M", "fw.bin", "host:/tmp/fw.bin"]);
+        assert_eq!(args.limit, Some(1024 * 1024));
+
+        let args = Args::parse_from(["simple-scp", "fw.bin", "host:/tmp/fw.bin"]);
+        assert_eq!(args.limit, None);
+        assert_eq!(build_scp_options(&args), ScpOptions::new());
+
+        let result = Args::try_parse_from(["simple-scp", "-l", "0", "fw.bin", "host:/tmp/x"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_args_parsing_sync() {
+        let args = Args::parse_from(["simple-scp", "--sync", "site", "host:/srv/www"]);
+        assert!(args.sync);
+        assert_eq!(build_sync_options(&args), SyncOptions::new());
+
+        let args = Args::parse_from([
+            "simple-scp",
+            "--sync",
+            "--delete",
+            "--checksum",
+            "--include",
+            "*.html",
+            "--exclude",
+            "*.tmp",
+            "--exclude",
+            ".git",
+            "-n",
+            "site",
+            "host:/srv/www",
         ]);
-        let path = get_remote_path(&args);
-        assert_eq!(path, "/remote/path/file.txt");
+        assert_eq!(
+            build_sync_options(&args),
+            SyncOptions::new()
+                .compare(SyncCompare::Checksum)
+                .delete(true)
+                .include("*.html")
+                .exclude("*.tmp")
+                .exclude(".git")
+                .dry_run(true)
+        );
+
+        let result = Args::try_parse_from(["simple-scp", "--delete", "site", "host:/srv/www"]);
+        assert!(result.is_err());
+        let result = Args::try_parse_from(["simple-scp", "--dry-run", "site", "host:/srv/www"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(0.0), "0 B");
+        assert_eq!(format_bytes(1023.0), "1023 B");
+        assert_eq!(format_bytes(1536.0), "1.5 KiB");
+        assert_eq!(format_bytes(10.0 * 1024.0 * 1024.0), "10.0 MiB");
+    }
+
+    #[test]
+    fn test_format_eta() {
+        assert_eq!(format_eta(Duration::from_secs(0)), "00:00");
+        assert_eq!(format_eta(Duration::from_secs(75)), "01:15");
+        assert_eq!(format_eta(Duration::from_secs(3725)), "1:02:05");
+    }
+
+    #[test]
+    fn test_format_progress() {
+        let mut progress = TransferProgress {
+            path: "/tmp/firmware.bin".to_string(),
+            bytes: 512,
+            total: 1024,
+            instantaneous_bps: 2048.0,
+            average_bps: 1024.0,
+            eta: Some(Duration::from_secs(1)),
+            elapsed: Duration::from_secs(1),
+            finished: false,
+        };
+        let line = format_progress(&progress);
+        assert!(line.starts_with("firmware.bin ["));
+        assert!(line.contains(&format!("{}{}", "#".repeat(15), "-".repeat(15))));
+        assert!(line.contains(" 50% 512 B/1.0 KiB 2.0 KiB/s ETA 00:01"));
+
+        progress.bytes = 1024;
+        progress.finished = true;
+        let line = format_progress(&progress);
+        assert!(line.contains("100%"));
+        assert!(line.ends_with(" 00:01"));
+        assert!(!line.contains("ETA"));
+    }
+
+    #[test]
+    fn test_parse_remote_spec() {
+        assert_eq!(
+            parse_remote_spec("user@host:path"),
+            Some(RemoteSpec {
+                user: Some("user"),
+                host: "host",
+                path: "path",
+            })
+        );
+        assert_eq!(parse_remote_spec("host:").unwrap().path, "");
+        assert_eq!(parse_remote_spec("./a:b"), None);
+        assert_eq!(parse_remote_spec("/tmp/file"), None);
+        assert_eq!(parse_remote_spec(":path"), None);
+        assert_eq!(parse_remote_spec("[fe80::1]file"), None);
     }
 }
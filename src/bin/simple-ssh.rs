@@ -27,9 +27,13 @@ use clap::{Parser, ValueEnum};
 use shell_escape::escape;
 use std::borrow::Cow;
 use std::path::PathBuf;
-use tokio::time::{timeout, Duration};
+use tokio::time::{timeout, Duration, MissedTickBehavior};
 
-use simple_ssh::{PtyExitStatus, PwdWatcher, Session};
+use simple_ssh::{
+    ClipboardConfig, ClipboardEvent, ClipboardRequest, ClipboardWatcher, NotificationWatcher,
+    Player, PlayerEvent, PtyExitStatus, PtyRecordingConfig, PwdWatcher, Recording, ReplayOptions,
+    ScreenColor, ScreenSnapshot, Session, TitleWatcher,
+};
 use std::io::Write as _;
 
 use crossterm::{
@@ -55,9 +59,9 @@ use tokio::sync::mpsc;
 #[command(about = "A simple SSH client with PTY support", long_about = None)]
 struct Args {
     /// SSH host to connect to.
-    #[arg(short = 'H', long)]
+    #[arg(short = 'H', long, required_unless_present = "replay")]
     #[arg(help = "SSH host to connect to")]
-    host: String,
+    host: Option<String>,
 
     /// SSH username.
     #[arg(short, long, default_value = "root")]
@@ -99,6 +103,56 @@ struct Args {
     #[arg(long, value_enum)]
     #[arg(help = "Multiplexer layout: 1x2, 2x1, or 2x2")]
     mux: Option<MuxMode>,
+
+    /// Record the interactive session to an asciicast v2 file.
+    #[arg(long, value_name = "FILE", conflicts_with = "mux")]
+    #[arg(help = "Record the interactive session to an asciicast v2 file")]
+    record: Option<PathBuf>,
+
+    /// Include keyboard input in the recording.
+    #[arg(long, requires = "record")]
+    #[arg(help = "Include keyboard input in the recording (may capture passwords)")]
+    record_input: bool,
+
+    /// Play back an asciicast v2 file instead of connecting.
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["mux", "record"])]
+    #[arg(
+        help = "Play back an asciicast v2 file (space: pause, arrows: seek, +/-: speed, q: quit)"
+    )]
+    replay: Option<PathBuf>,
+
+    /// Initial playback speed factor.
+    #[arg(long, default_value = "1.0", requires = "replay", value_parser = parse_speed)]
+    #[arg(help = "Initial replay speed factor, e.g. 2 for twice as fast")]
+    speed: f64,
+
+    /// Longest pause kept during playback, in seconds.
+    #[arg(long, value_name = "SECS", requires = "replay", value_parser = parse_speed)]
+    #[arg(help = "Shorten pauses in the replay to at most SECS seconds")]
+    idle_limit: Option<f64>,
+
+    /// Where clipboard writes (OSC 52) from the remote side go.
+    #[arg(long, value_enum, default_value = "terminal")]
+    #[arg(conflicts_with = "clipboard_command")]
+    #[arg(help = "Send remote clipboard writes (OSC 52) to the terminal, or turn them off")]
+    clipboard: ClipboardMode,
+
+    /// Command receiving clipboard writes instead of the local terminal.
+    #[arg(long, value_name = "CMD")]
+    #[arg(help = "Pipe remote clipboard writes into CMD instead, e.g. wl-copy or pbcopy")]
+    clipboard_command: Option<String>,
+}
+
+/// Parses a positive number such as a speed factor or a duration.
+///
+/// # Arguments
+///
+/// * `value` - The command line value
+fn parse_speed(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(number) if number.is_finite() && number > 0.0 => Ok(number),
+        _ => Err(format!("Expected a positive number, got '{}'", value)),
+    }
 }
 
 /// Authentication methods for SSH connections.
@@ -129,6 +183,96 @@ enum MuxMode {
     TwoByTwo,
 }
 
+/// Handling of clipboard writes from the remote side.
+#[derive(Debug, Clone, ValueEnum, PartialEq)]
+enum ClipboardMode {
+    /// Pass permitted writes on to the local terminal.
+    #[value(name = "terminal")]
+    Terminal,
+    /// Drop all clipboard requests.
+    #[value(name = "off")]
+    Off,
+}
+
+/// Builds the clipboard policy for the `--clipboard` options.
+///
+/// Writes are permitted unless turned off and reads are always denied.
+/// Writes only pass through to the local terminal when no
+/// `--clipboard-command` takes them.
+///
+/// # Arguments
+///
+/// * `args` - Command line arguments
+fn clipboard_config(args: &Args) -> ClipboardConfig {
+    ClipboardConfig {
+        allow_write: args.clipboard != ClipboardMode::Off,
+        passthrough: args.clipboard_command.is_none(),
+        ..ClipboardConfig::default()
+    }
+}
+
+/// Pipes clipboard data into the `--clipboard-command`.
+///
+/// # Arguments
+///
+/// * `command` - Shell command reading the data on stdin
+/// * `data` - The decoded clipboard contents
+async fn copy_to_command(command: &str, data: &[u8]) -> Result<()> {
+    use tokio::io::AsyncWriteExt as _;
+
+    #[cfg(unix)]
+    let mut process = tokio::process::Command::new("sh");
+    #[cfg(unix)]
+    process.arg("-c");
+    #[cfg(not(unix))]
+    let mut process = tokio::process::Command::new("cmd");
+    #[cfg(not(unix))]
+    process.arg("/C");
+
+    let mut child = process
+        .arg(command)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .map_err(|e| anyhow!("Cannot run clipboard command '{}': {}", command, e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(data).await?;
+    }
+    let status = child.wait().await?;
+    if !status.success() {
+        return Err(anyhow!(
+            "Clipboard command '{}' failed: {}",
+            command,
+            status
+        ));
+    }
+    Ok(())
+}
+
+/// Forwards a permitted clipboard write to the `--clipboard-command`.
+///
+/// # Arguments
+///
+/// * `event` - The clipboard request
+/// * `args` - Command line arguments
+fn spawn_clipboard_command(event: &ClipboardEvent, args: &Args) {
+    let (Some(command), ClipboardRequest::Write(data)) = (&args.clipboard_command, &event.request)
+    else {
+        return;
+    };
+    if !event.is_allowed() {
+        return;
+    }
+    let command = command.clone();
+    let data = data.clone();
+    tokio::spawn(async move {
+        if let Err(e) = copy_to_command(&command, &data).await {
+            log::warn!("{}", e);
+        }
+    });
+}
+
 /// Builds a Session from command line arguments.
 ///
 /// # Arguments
@@ -139,8 +283,12 @@ enum MuxMode {
 ///
 /// A configured Session or an error if required arguments are missing.
 fn build_session_from_args(args: &Args) -> Result<Session> {
+    let host = args
+        .host
+        .as_deref()
+        .ok_or_else(|| anyhow!("No host given; use -H"))?;
     let mut session = Session::init()
-        .with_host(&args.host)
+        .with_host(host)
         .with_user(&args.user)
         .with_port(args.port);
 
@@ -215,14 +363,40 @@ struct PaneLayout {
     height: u16,
 }
 
-/// A single multiplexer pane with its virtual terminal and state.
+/// A single multiplexer pane with the last snapshot of its screen and state.
+///
+/// The terminal emulation runs in the library (`PtyBuilder::with_screen`);
+/// the pane only keeps the snapshot taken after the latest output.
 struct Pane {
     layout: PaneLayout,
-    parser: vt100::Parser,
+    screen: ScreenSnapshot,
     exited: bool,
     exit_status: Option<PtyExitStatus>,
 }
 
+/// Watchers for the state shown in a pane's header.
+struct PaneWatchers {
+    pwd: PwdWatcher,
+    title: TitleWatcher,
+    notifications: NotificationWatcher,
+    clipboard: ClipboardWatcher,
+    /// Set when the pane rang the bell while unfocused; cleared on focus.
+    bell: bool,
+}
+
+impl PaneWatchers {
+    /// Drains pending bells and notifications, flagging an unfocused pane.
+    fn check_bell(&mut self, focused: bool) {
+        let mut rang = false;
+        while self.notifications.try_next().is_some() {
+            rang = true;
+        }
+        if rang && !focused {
+            self.bell = true;
+        }
+    }
+}
+
 /// Channel senders for communicating with a pane's background actor task.
 struct PaneProxy {
     write_tx: mpsc::Sender<Vec<u8>>,
@@ -231,7 +405,7 @@ struct PaneProxy {
 
 /// Events from pane actor tasks to the main mux loop.
 enum MuxEvent {
-    PtyOutput { pane: usize, data: Vec<u8> },
+    PtyOutput { pane: usize, screen: ScreenSnapshot },
     PtyExit { pane: usize, status: PtyExitStatus },
 }
 
@@ -320,29 +494,42 @@ fn calculate_layouts(cols: u16, rows: u16, mode: &MuxMode) -> Vec<PaneLayout> {
     }
 }
 
-/// Formats the pane title with optional PWD, truncating with ellipsis if needed.
+/// Formats the pane header from the program title or the PWD.
 ///
-/// `max_width` is the number of characters available between `┌` and `┐`.
-fn format_pane_title(idx_label: &str, pwd: Option<&str>, max_width: usize) -> String {
-    let Some(path) = pwd else {
+/// A non-empty title set by the program wins and is truncated at the end;
+/// otherwise the PWD is shown, truncated from the beginning. Both use an
+/// ellipsis when cut. `max_width` is the number of characters available
+/// between `┌` and `┐`.
+fn format_pane_title(
+    idx_label: &str,
+    title: Option<&str>,
+    pwd: Option<&str>,
+    max_width: usize,
+) -> String {
+    let title = title.filter(|t| !t.trim().is_empty());
+    let Some(text) = title.or(pwd) else {
         return idx_label.to_string();
     };
 
-    // Need at least space for "[N] X" (label + space + 1 path char)
-    let min_path_display = idx_label.len() + 2;
-    if max_width < min_path_display {
+    // Need at least space for "[N] X" (label + space + 1 char)
+    let min_display = idx_label.len() + 2;
+    if max_width < min_display {
         return idx_label.to_string();
     }
 
-    let max_path_cols = max_width - idx_label.len() - 1; // -1 for space separator
-    let path_chars: usize = path.chars().count();
-    if path_chars <= max_path_cols {
-        format!("{} {}", idx_label, path)
-    } else if max_path_cols >= 2 {
-        // Truncate from beginning with ellipsis
-        let suffix_chars = max_path_cols - 1; // -1 for '…'
-        let suffix: String = path.chars().skip(path_chars - suffix_chars).collect();
-        format!("{} \u{2026}{}", idx_label, suffix)
+    let max_cols = max_width - idx_label.len() - 1; // -1 for space separator
+    let chars: usize = text.chars().count();
+    if chars <= max_cols {
+        format!("{} {}", idx_label, text)
+    } else if max_cols >= 2 {
+        let keep = max_cols - 1; // -1 for '…'
+        if title.is_some() {
+            let prefix: String = text.chars().take(keep).collect();
+            format!("{} {}\u{2026}", idx_label, prefix)
+        } else {
+            let suffix: String = text.chars().skip(chars - keep).collect();
+            format!("{} \u{2026}{}", idx_label, suffix)
+        }
     } else {
         idx_label.to_string()
     }
@@ -353,15 +540,26 @@ fn draw_pane_box(
     pane_idx: usize,
     layout: &PaneLayout,
     focused: bool,
-    pwd: Option<&str>,
+    watchers: &PaneWatchers,
 ) -> Result<()> {
     let color = if focused {
         Color::Cyan
+    } else if watchers.bell {
+        Color::Yellow
     } else {
         Color::DarkGrey
     };
-    let idx_label = format!("[{}]", pane_idx + 1);
-    let title = format_pane_title(&idx_label, pwd, layout.width as usize);
+    let idx_label = if watchers.bell {
+        format!("[{}!]", pane_idx + 1)
+    } else {
+        format!("[{}]", pane_idx + 1)
+    };
+    let title = format_pane_title(
+        &idx_label,
+        watchers.title.current().as_deref(),
+        watchers.pwd.current().as_deref(),
+        layout.width as usize,
+    );
     let left_x = layout.x - 1;
     let right_x = layout.x + layout.width;
     let bottom_y = layout.y + layout.height + 1;
@@ -400,25 +598,24 @@ fn draw_borders(
     stdout: &mut std::io::Stdout,
     focused: usize,
     layouts: &[PaneLayout],
-    pwd_watchers: &[PwdWatcher],
+    watchers: &[PaneWatchers],
 ) -> Result<()> {
     for (i, layout) in layouts.iter().enumerate() {
-        let pwd = pwd_watchers[i].current();
-        draw_pane_box(stdout, i, layout, i == focused, pwd.as_deref())?;
+        draw_pane_box(stdout, i, layout, i == focused, &watchers[i])?;
     }
     Ok(())
 }
 
-fn vt100_to_crossterm_color(color: vt100::Color) -> Color {
+fn screen_to_crossterm_color(color: ScreenColor) -> Color {
     match color {
-        vt100::Color::Default => Color::Reset,
-        vt100::Color::Idx(i) => Color::AnsiValue(i),
-        vt100::Color::Rgb(r, g, b) => Color::Rgb { r, g, b },
+        ScreenColor::Default => Color::Reset,
+        ScreenColor::Indexed(i) => Color::AnsiValue(i),
+        ScreenColor::Rgb(r, g, b) => Color::Rgb { r, g, b },
     }
 }
 
 fn render_pane(stdout: &mut std::io::Stdout, pane: &Pane) -> Result<()> {
-    let screen = pane.parser.screen();
+    let screen = &pane.screen;
     let content_start_y = pane.layout.y + 1;
 
     for row in 0..pane.layout.height {
@@ -432,18 +629,18 @@ fn render_pane(stdout: &mut std::io::Stdout, pane: &Pane) -> Result<()> {
         while col < pane.layout.width {
             let cell = screen.cell(row, col);
             if let Some(cell) = cell {
-                if cell.is_wide_continuation() {
+                if cell.wide_continuation {
                     col += 1;
                     continue;
                 }
 
-                let fg = vt100_to_crossterm_color(cell.fgcolor());
+                let fg = screen_to_crossterm_color(cell.fg);
                 if Some(fg) != prev_fg {
                     queue!(stdout, SetForegroundColor(fg))?;
                     prev_fg = Some(fg);
                 }
 
-                let bg = vt100_to_crossterm_color(cell.bgcolor());
+                let bg = screen_to_crossterm_color(cell.bg);
                 if Some(bg) != prev_bg {
                     queue!(stdout, SetBackgroundColor(bg))?;
                     prev_bg = Some(bg);
@@ -451,31 +648,31 @@ fn render_pane(stdout: &mut std::io::Stdout, pane: &Pane) -> Result<()> {
 
                 let mut attrs: Vec<Attribute> = Vec::new();
                 let mut removed: Vec<Attribute> = Vec::new();
-                if cell.bold() {
+                if cell.bold {
                     attrs.push(Attribute::Bold);
                 }
-                if cell.dim() {
+                if cell.dim {
                     attrs.push(Attribute::Dim);
                 }
-                if !cell.bold()
-                    && !cell.dim()
+                if !cell.bold
+                    && !cell.dim
                     && (prev_attrs.contains(&Attribute::Bold)
                         || prev_attrs.contains(&Attribute::Dim))
                     && !removed.contains(&Attribute::NormalIntensity)
                 {
                     removed.push(Attribute::NormalIntensity);
                 }
-                if cell.italic() {
+                if cell.italic {
                     attrs.push(Attribute::Italic);
                 } else if prev_attrs.contains(&Attribute::Italic) {
                     removed.push(Attribute::NoItalic);
                 }
-                if cell.underline() {
+                if cell.underline {
                     attrs.push(Attribute::Underlined);
                 } else if prev_attrs.contains(&Attribute::Underlined) {
                     removed.push(Attribute::NoUnderline);
                 }
-                if cell.inverse() {
+                if cell.inverse {
                     attrs.push(Attribute::Reverse);
                 } else if prev_attrs.contains(&Attribute::Reverse) {
                     removed.push(Attribute::NoReverse);
@@ -491,12 +688,11 @@ fn render_pane(stdout: &mut std::io::Stdout, pane: &Pane) -> Result<()> {
                 }
                 prev_attrs = attrs;
 
-                let contents = cell.contents();
-                if contents.is_empty() {
+                if cell.contents.is_empty() {
                     queue!(stdout, Print(' '))?;
                 } else {
-                    queue!(stdout, Print(contents))?;
-                    if cell.is_wide() {
+                    queue!(stdout, Print(&cell.contents))?;
+                    if cell.wide {
                         col += 1;
                     }
                 }
@@ -546,7 +742,7 @@ fn position_cursor(stdout: &mut std::io::Stdout, pane: &Pane) -> Result<()> {
     if pane.exited {
         queue!(stdout, cursor::Hide)?;
     } else {
-        let (vrow, vcol) = pane.parser.screen().cursor_position();
+        let (vrow, vcol) = pane.screen.cursor();
         let content_start_y = pane.layout.y + 1;
         queue!(
             stdout,
@@ -646,6 +842,9 @@ fn navigate_pane(focused: usize, direction: KeyCode, mode: &MuxMode) -> Option<u
     }
 }
 
+/// Minimum time between two screen snapshots of a mux pane.
+const PANE_RENDER_INTERVAL: Duration = Duration::from_millis(16);
+
 async fn pane_actor(
     mut handle: simple_ssh::PtyHandle,
     pane_idx: usize,
@@ -653,19 +852,20 @@ async fn pane_actor(
     mut write_rx: mpsc::Receiver<Vec<u8>>,
     mut resize_rx: mpsc::Receiver<(u32, u32)>,
 ) {
+    // Output arriving between two ticks is drawn from a single snapshot.
+    let mut render = tokio::time::interval(PANE_RENDER_INTERVAL);
+    render.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut dirty = false;
     loop {
         tokio::select! {
             data = handle.read() => {
                 match data {
-                    Some(data) => {
-                        if mux_tx.send(MuxEvent::PtyOutput {
-                            pane: pane_idx,
-                            data,
-                        }).await.is_err() {
+                    // The library's emulator has already seen the data.
+                    Some(_) => dirty = true,
+                    None => {
+                        if dirty && !send_snapshot(&handle, pane_idx, &mux_tx).await {
                             return;
                         }
-                    }
-                    None => {
                         let status = handle.try_wait()
                             .unwrap_or(PtyExitStatus::ChannelClosed);
                         let _ = mux_tx.send(MuxEvent::PtyExit {
@@ -676,6 +876,12 @@ async fn pane_actor(
                     }
                 }
             }
+            _ = render.tick(), if dirty => {
+                dirty = false;
+                if !send_snapshot(&handle, pane_idx, &mux_tx).await {
+                    return;
+                }
+            }
             Some(data) = write_rx.recv() => {
                 if let Err(e) = handle.write(&data).await {
                     eprintln!("Pane {} write error: {}", pane_idx, e);
@@ -690,6 +896,26 @@ async fn pane_actor(
     }
 }
 
+/// Sends the current screen of a pane to the mux loop.
+///
+/// Returns false once the mux loop has gone away.
+async fn send_snapshot(
+    handle: &simple_ssh::PtyHandle,
+    pane_idx: usize,
+    mux_tx: &mpsc::Sender<MuxEvent>,
+) -> bool {
+    let Some(screen) = handle.screen_snapshot() else {
+        return true;
+    };
+    mux_tx
+        .send(MuxEvent::PtyOutput {
+            pane: pane_idx,
+            screen,
+        })
+        .await
+        .is_ok()
+}
+
 async fn mux_session(args: &Args, mode: &MuxMode) -> Result<()> {
     let mut stdout = std::io::stdout();
 
@@ -709,7 +935,7 @@ async fn mux_session(args: &Args, mode: &MuxMode) -> Result<()> {
     let mut pane_proxies = Vec::new();
     let mut sessions = Vec::new();
     let mut panes = Vec::new();
-    let mut pwd_watchers = Vec::new();
+    let mut watchers = Vec::new();
 
     for (i, layout) in layouts.iter().enumerate() {
         let session = build_session_from_args(args)?;
@@ -730,6 +956,10 @@ async fn mux_session(args: &Args, mode: &MuxMode) -> Result<()> {
             .with_term("xterm-256color")
             .with_size(layout.width as u32, layout.height as u32)
             .with_pwd_detection(true)
+            .with_title_detection()
+            .with_notification_detection()
+            .with_clipboard_config(clipboard_config(args))
+            .with_screen()
             .open()
             .await
         {
@@ -740,16 +970,28 @@ async fn mux_session(args: &Args, mode: &MuxMode) -> Result<()> {
             }
         };
 
-        let watcher = match handle.watch_pwd() {
+        let watcher = match handle.watch_pwd().and_then(|pwd| {
+            Ok(PaneWatchers {
+                pwd,
+                title: handle.watch_title()?,
+                notifications: handle.watch_notifications()?,
+                clipboard: handle.watch_clipboard()?,
+                bell: false,
+            })
+        }) {
             Ok(w) => w,
             Err(e) => {
                 cleanup_mux(&mut stdout, &mut sessions).await;
-                return Err(anyhow!("Pane {} PWD watcher failed: {}", i, e));
+                return Err(anyhow!("Pane {} watcher failed: {}", i, e));
             }
         };
 
         let (write_tx, write_rx) = mpsc::channel(64);
         let (resize_tx, resize_rx) = mpsc::channel(4);
+        let Some(screen) = handle.screen_snapshot() else {
+            cleanup_mux(&mut stdout, &mut sessions).await;
+            return Err(anyhow!("Pane {} has no screen emulation", i));
+        };
 
         let tx = mux_tx.clone();
         tokio::spawn(pane_actor(handle, i, tx, write_rx, resize_rx));
@@ -759,10 +1001,10 @@ async fn mux_session(args: &Args, mode: &MuxMode) -> Result<()> {
             resize_tx,
         });
         sessions.push(ssh);
-        pwd_watchers.push(watcher);
+        watchers.push(watcher);
         panes.push(Pane {
             layout: layout.clone(),
-            parser: vt100::Parser::new(layout.height, layout.width, 0),
+            screen,
             exited: false,
             exit_status: None,
         });
@@ -772,7 +1014,7 @@ async fn mux_session(args: &Args, mode: &MuxMode) -> Result<()> {
     let mut focused: usize = 0;
     let mut input_state = InputState::Normal;
 
-    draw_borders(&mut stdout, focused, &layouts, &pwd_watchers)?;
+    draw_borders(&mut stdout, focused, &layouts, &watchers)?;
     stdout.flush()?;
 
     let mut event_stream = EventStream::new();
@@ -804,6 +1046,7 @@ async fn mux_session(args: &Args, mode: &MuxMode) -> Result<()> {
                             InputState::Prefix => {
                                 input_state = InputState::Normal;
                                 match key_event.code {
+                                    #[allow(clippy::collapsible_match)]
                                     KeyCode::Char('b')
                                         if key_event.modifiers
                                             .contains(KeyModifiers::CONTROL) =>
@@ -821,9 +1064,10 @@ async fn mux_session(args: &Args, mode: &MuxMode) -> Result<()> {
                                             navigate_pane(focused, key_event.code, mode)
                                         {
                                             focused = new_focus;
+                                            watchers[focused].bell = false;
                                             draw_borders(
                                                 &mut stdout, focused, &layouts,
-                                                &pwd_watchers,
+                                                &watchers,
                                             )?;
                                             position_cursor(
                                                 &mut stdout, &panes[focused],
@@ -842,10 +1086,6 @@ async fn mux_session(args: &Args, mode: &MuxMode) -> Result<()> {
 
                         for (i, pane) in panes.iter_mut().enumerate() {
                             pane.layout = layouts[i].clone();
-                            pane.parser.screen_mut().set_size(
-                                pane.layout.height,
-                                pane.layout.width,
-                            );
                             let _ = pane_proxies[i].resize_tx.send((
                                 pane.layout.width as u32,
                                 pane.layout.height as u32,
@@ -855,7 +1095,7 @@ async fn mux_session(args: &Args, mode: &MuxMode) -> Result<()> {
                         queue!(stdout, Clear(ClearType::All))?;
                         draw_borders(
                             &mut stdout, focused, &layouts,
-                            &pwd_watchers,
+                            &watchers,
                         )?;
                         for pane in &panes {
                             render_pane(&mut stdout, pane)?;
@@ -869,12 +1109,21 @@ async fn mux_session(args: &Args, mode: &MuxMode) -> Result<()> {
 
             Some(mux_event) = mux_rx.recv() => {
                 match mux_event {
-                    MuxEvent::PtyOutput { pane, data } => {
-                        panes[pane].parser.process(&data);
-                        let pwd = pwd_watchers[pane].current();
+                    MuxEvent::PtyOutput { pane, screen } => {
+                        panes[pane].screen = screen;
+                        watchers[pane].check_bell(pane == focused);
+                        while let Some(event) = watchers[pane].clipboard.try_next() {
+                            if args.clipboard_command.is_some() {
+                                spawn_clipboard_command(&event, args);
+                            } else if event.is_allowed() {
+                                if let ClipboardRequest::Write(_) = event.request {
+                                    stdout.write_all(&event.to_sequence())?;
+                                }
+                            }
+                        }
                         draw_pane_box(
                             &mut stdout, pane, &panes[pane].layout,
-                            pane == focused, pwd.as_deref(),
+                            pane == focused, &watchers[pane],
                         )?;
                         render_pane(&mut stdout, &panes[pane])?;
                         if pane == focused {
@@ -917,6 +1166,13 @@ async fn main() -> Result<()> {
 
     let args = Args::parse();
 
+    if let Some(path) = &args.replay {
+        if has_command(&args) {
+            return Err(anyhow!("--replay cannot be used with a command"));
+        }
+        return replay_session(path, &args).await;
+    }
+
     if let Some(ref mode) = args.mux {
         if has_command(&args) {
             return Err(anyhow!("--mux cannot be used with a command"));
@@ -924,6 +1180,10 @@ async fn main() -> Result<()> {
         return mux_session(&args, mode).await;
     }
 
+    if args.record.is_some() && has_command(&args) {
+        return Err(anyhow!("--record needs an interactive shell"));
+    }
+
     let session = build_session_from_args(&args)?;
 
     let mut ssh = match timeout(Duration::from_secs(30), session.connect()).await {
@@ -935,7 +1195,7 @@ async fn main() -> Result<()> {
     if has_command(&args) {
         non_interactive(&mut ssh, &command_from_args(&args)).await?;
     } else {
-        interactive_shell(&mut ssh).await?;
+        interactive_shell(&mut ssh, &args).await?;
     }
 
     ssh.close().await?;
@@ -947,17 +1207,145 @@ async fn main() -> Result<()> {
 /// # Arguments
 ///
 /// * `ssh` - Connected SSH session
-async fn interactive_shell(ssh: &mut Session) -> Result<u32> {
-    let exit_code = ssh
+/// * `args` - Command line arguments
+async fn interactive_shell(ssh: &mut Session, args: &Args) -> Result<u32> {
+    let mut builder = ssh
         .pty_builder()
         .with_raw()
         .with_auto_resize()
-        .run()
-        .await?;
+        .with_clipboard_config(clipboard_config(args));
+    if args.clipboard_command.is_some() {
+        let mut clipboard = builder.watch_clipboard();
+        let args = args.clone();
+        tokio::spawn(async move {
+            while let Some(event) = clipboard.next().await {
+                spawn_clipboard_command(&event, &args);
+            }
+        });
+    }
+    if let Some(path) = &args.record {
+        let file = std::fs::File::create(path)
+            .map_err(|e| anyhow!("Cannot create '{}': {}", path.display(), e))?;
+        let title = format!("{}@{}", args.user, args.host.as_deref().unwrap_or_default());
+        let config = PtyRecordingConfig::new(std::io::BufWriter::new(file))
+            .input(args.record_input)
+            .title(&title);
+        builder = builder.with_recording_config(config);
+    }
+    let exit_code = builder.run().await?;
     println!("\r\nConnection closed with exit code: {}", exit_code);
     Ok(exit_code)
 }
 
+/// Playback controls of `--replay`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ReplayAction {
+    /// Pause or resume playback.
+    TogglePause,
+    /// Jump by this many seconds.
+    Seek(f64),
+    /// Multiply the speed by this factor.
+    Speed(f64),
+    /// Stop playback.
+    Quit,
+}
+
+/// Seconds skipped by the left and right arrow keys.
+const REPLAY_SEEK_STEP: f64 = 5.0;
+
+/// Maps a key press to a playback control.
+///
+/// # Arguments
+///
+/// * `event` - The key event
+fn replay_action(event: &KeyEvent) -> Option<ReplayAction> {
+    match event.code {
+        KeyCode::Char('c') if event.modifiers.contains(KeyModifiers::CONTROL) => {
+            Some(ReplayAction::Quit)
+        }
+        KeyCode::Char(' ') | KeyCode::Char('p') => Some(ReplayAction::TogglePause),
+        KeyCode::Left => Some(ReplayAction::Seek(-REPLAY_SEEK_STEP)),
+        KeyCode::Right => Some(ReplayAction::Seek(REPLAY_SEEK_STEP)),
+        KeyCode::Char('+') | KeyCode::Char('=') | KeyCode::Up => Some(ReplayAction::Speed(2.0)),
+        KeyCode::Char('-') | KeyCode::Down => Some(ReplayAction::Speed(0.5)),
+        KeyCode::Char('q') | KeyCode::Esc => Some(ReplayAction::Quit),
+        _ => None,
+    }
+}
+
+/// Plays back a recorded session in the local terminal.
+///
+/// # Arguments
+///
+/// * `path` - The asciicast v2 file
+/// * `args` - Command line arguments
+async fn replay_session(path: &std::path::Path, args: &Args) -> Result<()> {
+    let recording = Recording::load(path).await?;
+    let mut options = ReplayOptions::new().speed(args.speed);
+    if let Some(limit) = args.idle_limit {
+        options = options.idle_limit(Duration::from_secs_f64(limit));
+    }
+    let mut player = Player::new(&recording, &options);
+    eprintln!(
+        "Replaying {} ({:.1}s, {}x{}): space pause, arrows seek, +/- speed, q quit",
+        path.display(),
+        player.duration().as_secs_f64(),
+        recording.header.width,
+        recording.header.height
+    );
+
+    let mut stdout = std::io::stdout();
+    enable_raw_mode()?;
+    let result = replay_loop(&mut player, &mut stdout).await;
+    let _ = disable_raw_mode();
+    let _ = execute!(stdout, ResetColor, cursor::Show);
+    println!();
+    result
+}
+
+/// Writes the recording to stdout while handling playback keys.
+///
+/// # Arguments
+///
+/// * `player` - The player of the recording
+/// * `stdout` - The local terminal
+async fn replay_loop(player: &mut Player<'_>, stdout: &mut std::io::Stdout) -> Result<()> {
+    let mut event_stream = EventStream::new();
+    loop {
+        tokio::select! {
+            event = player.next() => {
+                match event {
+                    Some(PlayerEvent::Output(text)) => {
+                        stdout.write_all(text.as_bytes())?;
+                        stdout.flush()?;
+                    }
+                    Some(PlayerEvent::Resize(..)) => {}
+                    None => return Ok(()),
+                }
+            }
+            event = event_stream.next() => {
+                let Some(Ok(event)) = event else { return Ok(()) };
+                let Event::Key(key_event) = event else { continue };
+                if key_event.kind != KeyEventKind::Press {
+                    continue;
+                }
+                match replay_action(&key_event) {
+                    Some(ReplayAction::TogglePause) => player.toggle_pause(),
+                    Some(ReplayAction::Seek(seconds)) => {
+                        stdout.write_all(player.seek_by(seconds).as_bytes())?;
+                        stdout.flush()?;
+                    }
+                    Some(ReplayAction::Speed(factor)) => {
+                        player.set_speed((player.speed() * factor).clamp(0.125, 64.0));
+                    }
+                    Some(ReplayAction::Quit) => return Ok(()),
+                    None => {}
+                }
+            }
+        }
+    }
+}
+
 /// Executes a non-interactive command.
 ///
 /// # Arguments
@@ -976,15 +1364,15 @@ mod tests {
 
     #[test]
     fn test_args_parsing_basic() {
-        let args = Args::parse_from(&["simple-ssh", "-H", "localhost"]);
-        assert_eq!(args.host, "localhost");
+        let args = Args::parse_from(["simple-ssh", "-H", "localhost"]);
+        assert_eq!(args.host.as_deref(), Some("localhost"));
         assert_eq!(args.user, "root");
         assert_eq!(args.port, 22);
     }
 
     #[test]
     fn test_args_parsing_with_options() {
-        let args = Args::parse_from(&[
+        let args = Args::parse_from([
             "simple-ssh",
             "-H",
             "192.168.1.1",
@@ -995,7 +1383,7 @@ mod tests {
             "-P",
             "secret",
         ]);
-        assert_eq!(args.host, "192.168.1.1");
+        assert_eq!(args.host.as_deref(), Some("192.168.1.1"));
         assert_eq!(args.user, "admin");
         assert_eq!(args.port, 2222);
         assert_eq!(args.passwd, Some("secret".to_string()));
@@ -1003,7 +1391,7 @@ mod tests {
 
     #[test]
     fn test_args_parsing_with_command() {
-        let args = Args::parse_from(&[
+        let args = Args::parse_from([
             "simple-ssh",
             "-H",
             "server.example.com",
@@ -1018,25 +1406,25 @@ mod tests {
 
     #[test]
     fn test_args_parsing_with_scope() {
-        let args = Args::parse_from(&["simple-ssh", "-H", "fe80::1", "--scope", "eth0"]);
+        let args = Args::parse_from(["simple-ssh", "-H", "fe80::1", "--scope", "eth0"]);
         assert_eq!(args.scope, Some("eth0".to_string()));
     }
 
     #[test]
     fn test_args_parsing_auth_method() {
-        let args = Args::parse_from(&["simple-ssh", "-H", "server.example.com", "--auth", "key"]);
+        let args = Args::parse_from(["simple-ssh", "-H", "server.example.com", "--auth", "key"]);
         assert_eq!(args.auth, Some(AuthMethod::Key));
     }
 
     #[test]
     fn test_args_parsing_default_user() {
-        let args = Args::parse_from(&["simple-ssh", "-H", "localhost"]);
+        let args = Args::parse_from(["simple-ssh", "-H", "localhost"]);
         assert_eq!(args.user, "root");
     }
 
     #[test]
     fn test_args_parsing_default_port() {
-        let args = Args::parse_from(&["simple-ssh", "-H", "localhost"]);
+        let args = Args::parse_from(["simple-ssh", "-H", "localhost"]);
         assert_eq!(args.port, 22);
     }
 
@@ -1056,7 +1444,7 @@ mod tests {
 
     #[test]
     fn test_command_join() {
-        let cmd = vec!["echo", "hello", "world"]
+        let cmd = ["echo", "hello", "world"]
             .iter()
             .map(|s| s.to_string())
             .collect::<Vec<String>>();
@@ -1072,19 +1460,19 @@ mod tests {
 
     #[test]
     fn test_hyphen_command_value() {
-        let args = Args::parse_from(&["simple-ssh", "-H", "localhost", "--", "-c", "echo hello"]);
+        let args = Args::parse_from(["simple-ssh", "-H", "localhost", "--", "-c", "echo hello"]);
         assert_eq!(args.command, vec!["-c", "echo hello"]);
     }
 
     #[test]
     fn test_empty_command_vec() {
-        let args = Args::parse_from(&["simple-ssh", "-H", "localhost"]);
+        let args = Args::parse_from(["simple-ssh", "-H", "localhost"]);
         assert!(args.command.is_empty());
     }
 
     #[test]
     fn test_build_session_from_args_password() {
-        let args = Args::parse_from(&[
+        let args = Args::parse_from([
             "simple-ssh",
             "-H",
             "testhost",
@@ -1101,7 +1489,7 @@ mod tests {
 
     #[test]
     fn test_build_session_from_args_key() {
-        let args = Args::parse_from(&[
+        let args = Args::parse_from([
             "simple-ssh",
             "-H",
             "testhost",
@@ -1116,7 +1504,7 @@ mod tests {
 
     #[test]
     fn test_build_session_from_args_with_scope() {
-        let args = Args::parse_from(&[
+        let args = Args::parse_from([
             "simple-ssh",
             "-H",
             "fe80::1",
@@ -1131,14 +1519,14 @@ mod tests {
 
     #[test]
     fn test_build_session_from_args_no_auth() {
-        let args = Args::parse_from(&["simple-ssh", "-H", "testhost", "-u", "testuser"]);
+        let args = Args::parse_from(["simple-ssh", "-H", "testhost", "-u", "testuser"]);
         let session = build_session_from_args(&args);
         assert!(session.is_ok());
     }
 
     #[test]
     fn test_build_session_auth_password_explicit() {
-        let args = Args::parse_from(&[
+        let args = Args::parse_from([
             "simple-ssh",
             "-H",
             "testhost",
@@ -1153,7 +1541,7 @@ mod tests {
 
     #[test]
     fn test_build_session_auth_key_explicit() {
-        let args = Args::parse_from(&[
+        let args = Args::parse_from([
             "simple-ssh",
             "-H",
             "testhost",
@@ -1168,14 +1556,14 @@ mod tests {
 
     #[test]
     fn test_build_session_auth_none_explicit() {
-        let args = Args::parse_from(&["simple-ssh", "-H", "testhost", "--auth", "none"]);
+        let args = Args::parse_from(["simple-ssh", "-H", "testhost", "--auth", "none"]);
         let session = build_session_from_args(&args);
         assert!(session.is_ok());
     }
 
     #[test]
     fn test_build_session_error_missing_password() {
-        let args = Args::parse_from(&["simple-ssh", "-H", "testhost", "--auth", "password"]);
+        let args = Args::parse_from(["simple-ssh", "-H", "testhost", "--auth", "password"]);
         let session = build_session_from_args(&args);
         assert!(session.is_err());
         if let Err(e) = session {
@@ -1185,7 +1573,7 @@ mod tests {
 
     #[test]
     fn test_build_session_error_missing_key() {
-        let args = Args::parse_from(&["simple-ssh", "-H", "testhost", "--auth", "key"]);
+        let args = Args::parse_from(["simple-ssh", "-H", "testhost", "--auth", "key"]);
         let session = build_session_from_args(&args);
         assert!(session.is_err());
         if let Err(e) = session {
@@ -1195,61 +1583,175 @@ mod tests {
 
     #[test]
     fn test_command_from_args_empty() {
-        let args = Args::parse_from(&["simple-ssh", "-H", "localhost"]);
+        let args = Args::parse_from(["simple-ssh", "-H", "localhost"]);
         assert_eq!(command_from_args(&args), "");
     }
 
     #[test]
     fn test_command_from_args_single() {
-        let args = Args::parse_from(&["simple-ssh", "-H", "localhost", "ls"]);
+        let args = Args::parse_from(["simple-ssh", "-H", "localhost", "ls"]);
         assert_eq!(command_from_args(&args), "ls");
     }
 
     #[test]
     fn test_command_from_args_multiple() {
-        let args = Args::parse_from(&["simple-ssh", "-H", "localhost", "echo", "hello", "world"]);
+        let args = Args::parse_from(["simple-ssh", "-H", "localhost", "echo", "hello", "world"]);
         assert_eq!(command_from_args(&args), "echo hello world");
     }
 
     #[test]
     fn test_command_from_args_with_special_chars() {
-        let args = Args::parse_from(&["simple-ssh", "-H", "localhost", "echo", "hello world"]);
+        let args = Args::parse_from(["simple-ssh", "-H", "localhost", "echo", "hello world"]);
         assert_eq!(command_from_args(&args), r#"echo 'hello world'"#);
     }
 
     #[test]
     fn test_command_from_args_with_quotes() {
-        let args = Args::parse_from(&["simple-ssh", "-H", "localhost", "echo", "it's a test"]);
+        let args = Args::parse_from(["simple-ssh", "-H", "localhost", "echo", "it's a test"]);
         assert_eq!(command_from_args(&args), r#"echo 'it'\''s a test'"#);
     }
 
     #[test]
     fn test_has_command_true() {
-        let args = Args::parse_from(&["simple-ssh", "-H", "localhost", "ls"]);
+        let args = Args::parse_from(["simple-ssh", "-H", "localhost", "ls"]);
         assert!(has_command(&args));
     }
 
     #[test]
     fn test_has_command_false() {
-        let args = Args::parse_from(&["simple-ssh", "-H", "localhost"]);
+        let args = Args::parse_from(["simple-ssh", "-H", "localhost"]);
         assert!(!has_command(&args));
     }
 
     #[test]
     fn test_mux_mode_parsing() {
-        let args = Args::parse_from(&["simple-ssh", "-H", "localhost", "--mux", "1x2"]);
+        let args = Args::parse_from(["simple-ssh", "-H", "localhost", "--mux", "1x2"]);
         assert_eq!(args.mux, Some(MuxMode::OneByTwo));
 
-        let args = Args::parse_from(&["simple-ssh", "-H", "localhost", "--mux", "2x1"]);
+        let args = Args::parse_from(["simple-ssh", "-H", "localhost", "--mux", "2x1"]);
         assert_eq!(args.mux, Some(MuxMode::TwoByOne));
 
-        let args = Args::parse_from(&["simple-ssh", "-H", "localhost", "--mux", "2x2"]);
+        let args = Args::parse_from(["simple-ssh", "-H", "localhost", "--mux", "2x2"]);
         assert_eq!(args.mux, Some(MuxMode::TwoByTwo));
     }
 
+    #[test]
+    fn test_args_parsing_record() {
+        let args = Args::parse_from([
+            "simple-ssh",
+            "-H",
+            "host",
+            "--record",
+            "session.cast",
+            "--record-input",
+        ]);
+        assert_eq!(args.record, Some(PathBuf::from("session.cast")));
+        assert!(args.record_input);
+
+        let args = Args::parse_from(["simple-ssh", "-H", "host"]);
+        assert_eq!(args.record, None);
+        assert!(!args.record_input);
+
+        assert!(Args::try_parse_from(["simple-ssh", "-H", "host", "--record-input"]).is_err());
+        assert!(Args::try_parse_from([
+            "simple-ssh",
+            "-H",
+            "host",
+            "--mux",
+            "1x2",
+            "--record",
+            "a.cast"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_args_parsing_clipboard() {
+        let args = Args::parse_from(["simple-ssh", "-H", "host"]);
+        assert_eq!(args.clipboard, ClipboardMode::Terminal);
+        let config = clipboard_config(&args);
+        assert!(config.allow_write && config.passthrough && !config.allow_read);
+
+        let args = Args::parse_from(["simple-ssh", "-H", "host", "--clipboard", "off"]);
+        assert!(!clipboard_config(&args).allow_write);
+
+        let args = Args::parse_from(["simple-ssh", "-H", "host", "--clipboard-command", "wl-copy"]);
+        assert_eq!(args.clipboard_command.as_deref(), Some("wl-copy"));
+        let config = clipboard_config(&args);
+        assert!(config.allow_write && !config.passthrough);
+
+        assert!(Args::try_parse_from([
+            "simple-ssh",
+            "-H",
+            "host",
+            "--clipboard",
+            "off",
+            "--clipboard-command",
+            "wl-copy"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_args_parsing_replay() {
+        let args = Args::parse_from(["simple-ssh", "--replay", "session.cast", "--speed", "2"]);
+        assert_eq!(args.host, None);
+        assert_eq!(args.replay, Some(PathBuf::from("session.cast")));
+        assert_eq!(args.speed, 2.0);
+        assert_eq!(args.idle_limit, None);
+
+        let args = Args::parse_from(["simple-ssh", "--replay", "a.cast", "--idle-limit", "0.5"]);
+        assert_eq!(args.speed, 1.0);
+        assert_eq!(args.idle_limit, Some(0.5));
+
+        assert!(Args::try_parse_from(["simple-ssh"]).is_err());
+        assert!(
+            Args::try_parse_from(["simple-ssh", "--replay", "a.cast", "--speed", "0"]).is_err()
+        );
+        assert!(Args::try_parse_from(["simple-ssh", "-H", "host", "--speed", "2"]).is_err());
+        assert!(
+            Args::try_parse_from(["simple-ssh", "--replay", "a.cast", "--record", "b.cast"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_replay_action() {
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        assert_eq!(
+            replay_action(&key(KeyCode::Char(' '))),
+            Some(ReplayAction::TogglePause)
+        );
+        assert_eq!(
+            replay_action(&key(KeyCode::Left)),
+            Some(ReplayAction::Seek(-5.0))
+        );
+        assert_eq!(
+            replay_action(&key(KeyCode::Right)),
+            Some(ReplayAction::Seek(5.0))
+        );
+        assert_eq!(
+            replay_action(&key(KeyCode::Char('+'))),
+            Some(ReplayAction::Speed(2.0))
+        );
+        assert_eq!(
+            replay_action(&key(KeyCode::Char('-'))),
+            Some(ReplayAction::Speed(0.5))
+        );
+        assert_eq!(
+            replay_action(&key(KeyCode::Char('q'))),
+            Some(ReplayAction::Quit)
+        );
+        assert_eq!(
+            replay_action(&KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)),
+            Some(ReplayAction::Quit)
+        );
+        assert_eq!(replay_action(&key(KeyCode::Char('x'))), None);
+    }
+
     #[test]
     fn test_mux_mode_default_none() {
-        let args = Args::parse_from(&["simple-ssh", "-H", "localhost"]);
+        let args = Args::parse_from(["simple-ssh", "-H", "localhost"]);
         assert_eq!(args.mux, None);
     }
 
@@ -1359,17 +1861,43 @@ mod tests {
     }
 
     #[test]
-    fn test_vt100_to_crossterm_color() {
+    fn test_format_pane_title() {
+        assert_eq!(format_pane_title("[1]", None, None, 40), "[1]");
+        assert_eq!(
+            format_pane_title("[1]", None, Some("/home/user"), 40),
+            "[1] /home/user"
+        );
+        assert_eq!(
+            format_pane_title("[1]", Some("vim main.rs"), Some("/home/user"), 40),
+            "[1] vim main.rs"
+        );
+        assert_eq!(
+            format_pane_title("[1]", Some(" "), Some("/home/user"), 40),
+            "[1] /home/user"
+        );
+        assert_eq!(
+            format_pane_title("[2]", None, Some("/home/user/src"), 10),
+            "[2] \u{2026}r/src"
+        );
+        assert_eq!(
+            format_pane_title("[2]", Some("vim main.rs"), None, 10),
+            "[2] vim m\u{2026}"
+        );
+        assert_eq!(format_pane_title("[2]", Some("vim"), None, 4), "[2]");
+    }
+
+    #[test]
+    fn test_screen_to_crossterm_color() {
         assert!(matches!(
-            vt100_to_crossterm_color(vt100::Color::Default),
+            screen_to_crossterm_color(ScreenColor::Default),
             Color::Reset,
         ));
         assert!(matches!(
-            vt100_to_crossterm_color(vt100::Color::Idx(1)),
+            screen_to_crossterm_color(ScreenColor::Indexed(1)),
             Color::AnsiValue(1),
         ));
         assert!(matches!(
-            vt100_to_crossterm_color(vt100::Color::Rgb(255, 0, 128)),
+            screen_to_crossterm_color(ScreenColor::Rgb(255, 0, 128)),
             Color::Rgb {
                 r: 255,
                 g: 0,
@@ -0,0 +1,560 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2025 Julian Kahlert
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{anyhow, Error, Result};
+use log::debug;
+use russh::client;
+use russh::{ChannelMsg, ChannelReadHalf};
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::time::timeout;
+
+use crate::progress::ProgressReporter;
+use crate::rate_limit::RateLimited;
+use crate::scp::{
+    scp_create_file, scp_local_target, set_file_mode, set_file_times, FileHeaderSender,
+    SCPFileAttrs, ScpOptions,
+};
+use crate::verify::{Digests, Hashing};
+use crate::Client;
+
+/// Builds a shell snippet setting `$p` to the remote file of a transfer.
+///
+/// With a `file_name`, an existing directory at `remote_path` resolves to
+/// the file `file_name` inside it, like scp does for uploads.
+pub(crate) fn remote_path_expr(remote_path: &str, file_name: Option<&str>) -> String {
+    let path = shell_escape::escape(remote_path.into());
+    match file_name {
+        Some(name) => format!(
+            "p={}; if [ -d \"$p\" ]; then p=\"$p\"/{}; fi;",
+            path,
+            shell_escape::escape(name.into())
+        ),
+        None => format!("p={};", path),
+    }
+}
+
+/// Builds the remote command for a `cat` upload.
+///
+/// The mode is applied with `chmod` once the content is written, the
+/// timestamps (`mtime`, `atime`) with `touch -d @<seconds>` if given.
+fn cat_upload_command(
+    remote_path: &str,
+    file_name: &str,
+    mode: u32,
+    times: Option<(u64, u64)>,
+) -> String {
+    let mut command = format!(
+        "{} cat > \"$p\" && chmod {:o} \"$p\"",
+        remote_path_expr(remote_path, Some(file_name)),
+        mode
+    );
+    if let Some((mtime, atime)) = times {
+        command.push_str(&format!(
+            " && touch -m -d @{} \"$p\" && touch -a -d @{} \"$p\"",
+            mtime, atime
+        ));
+    }
+    command
+}
+
+/// Builds the remote command for a `cat` download.
+///
+/// The file size is printed on its own line before the content so the
+/// transfer can be checked for completeness. With `preserve` the line
+/// also holds the mode, modification and access time, see
+/// `parse_cat_header`.
+fn cat_download_command(remote_path: &str, preserve: bool) -> String {
+    let header = if preserve {
+        "stat -L -c '%s %a %Y %X' \"$p\""
+    } else {
+        "wc -c < \"$p\""
+    };
+    format!(
+        "{} {} && cat \"$p\"",
+        remote_path_expr(remote_path, None),
+        header
+    )
+}
+
+/// Parses the header line printed by `cat_download_command`.
+///
+/// # Returns
+///
+/// The file size and, with `preserve`, the mode and timestamps.
+fn parse_cat_header(line: &str, preserve: bool) -> Result<(u64, Option<SCPFileAttrs>)> {
+    let invalid = || anyhow!("Invalid file size from remote: {:?}", line);
+    let fields = line.split_whitespace().collect::<Vec<_>>();
+    match (preserve, fields.as_slice()) {
+        (false, [size]) => Ok((size.parse().map_err(|_| invalid())?, None)),
+        (true, [size, mode, mtime, atime]) => {
+            let attrs = SCPFileAttrs {
+                mode: u32::from_str_radix(mode, 8).map_err(|_| invalid())? & 0o7777,
+                times: (
+                    mtime.parse().map_err(|_| invalid())?,
+                    atime.parse().map_err(|_| invalid())?,
+                ),
+            };
+            Ok((size.parse().map_err(|_| invalid())?, Some(attrs)))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Converts the exit status and stderr output of a failed `cat` command
+/// into an error.
+fn cat_error(code: Option<u32>, stderr: &[u8]) -> Error {
+    anyhow!(
+        "Remote cat transfer failed{}: {}",
+        code.map(|c| format!(" with exit code {}", c))
+            .unwrap_or_default(),
+        String::from_utf8_lossy(stderr).trim_end()
+    )
+}
+
+/// Handles a channel message received while a `cat` upload is running.
+///
+/// The remote command only exits before its input ends if it failed,
+/// e.g. on a missing directory or a full disk.
+fn cat_upload_message(msg: Option<ChannelMsg>, stderr: &mut Vec<u8>) -> Result<()> {
+    match msg {
+        Some(ChannelMsg::ExtendedData { ref data, ext: 1 }) => {
+            stderr.extend_from_slice(data);
+            Ok(())
+        }
+        Some(ChannelMsg::ExitStatus { exit_status }) => Err(cat_error(Some(exit_status), stderr)),
+        Some(ChannelMsg::Close) | None => Err(cat_error(None, stderr)),
+        Some(_) => {
+            // Window adjust, EOF before the exit status, or other
+            // protocol messages - ignore
+            Ok(())
+        }
+    }
+}
+
+/// Waits for a `cat` command to exit and checks its exit status.
+///
+/// Anything the command writes to stderr, including the output already
+/// collected in `stderr`, is included in the error.
+async fn cat_finish(channel: &mut ChannelReadHalf, mut stderr: Vec<u8>) -> Result<()> {
+    let mut code = None;
+    while let Some(msg) = channel.wait().await {
+        match msg {
+            ChannelMsg::ExtendedData { ref data, ext: 1 } => stderr.extend_from_slice(data),
+            ChannelMsg::ExitStatus { exit_status } => code = Some(exit_status),
+            _ => {}
+        }
+    }
+    match code {
+        Some(0) => Ok(()),
+        Some(_) => Err(cat_error(code, &stderr)),
+        None => Err(anyhow!("Remote cat transfer did not exit cleanly")),
+    }
+}
+
+/// Streams a single file into `cat >` on the remote host.
+///
+/// # Arguments
+///
+/// * `session` - The SSH session handle
+/// * `reader` - Source of the file content
+/// * `file_size` - Number of bytes to send
+/// * `remote_path` - Destination path on the remote host
+/// * `file_name` - Name used if `remote_path` is a directory
+/// * `mode` - Permission bits applied with `chmod`
+/// * `times` - Timestamps applied with `touch`, if any
+/// * `label` - Description of the file for log messages and progress
+/// * `progress` - Optional progress reporter
+#[allow(clippy::too_many_arguments)]
+async fn cat_send_file<R: AsyncRead + Unpin>(
+    session: &mut client::Handle<Client>,
+    reader: R,
+    file_size: u64,
+    remote_path: &str,
+    file_name: &str,
+    mode: u32,
+    times: Option<(u64, u64)>,
+    label: &str,
+    progress: Option<&ProgressReporter>,
+) -> Result<()> {
+    let channel = session.channel_open_session().await?;
+    channel
+        .exec(
+            true,
+            cat_upload_command(remote_path, file_name, mode, times),
+        )
+        .await?;
+    // Split so channel messages are watched while a write waits for window.
+    let (mut messages, channel) = channel.split();
+    if let Some(progress) = progress {
+        progress.start(label, file_size);
+    }
+
+    const WRITE_TIMEOUT: Duration = Duration::from_secs(16);
+    let mut buffer = [0u8; 16 * 1024];
+    let mut reader = reader.take(file_size);
+    let mut sent = 0u64;
+    let mut stderr = Vec::new();
+    loop {
+        let n = tokio::select! {
+            result = reader.read(&mut buffer) => result?,
+            msg = messages.wait() => {
+                cat_upload_message(msg, &mut stderr)?;
+                continue;
+            }
+        };
+        if n == 0 {
+            break;
+        }
+
+        debug!("Writing {} bytes of {}", n, label);
+        let write = timeout(WRITE_TIMEOUT, channel.data(&buffer[..n]));
+        tokio::pin!(write);
+        loop {
+            tokio::select! {
+                result = &mut write => {
+                    result.map_err(|_| anyhow!("Write timed out after {:?}", WRITE_TIMEOUT))??;
+                    break;
+                }
+                msg = messages.wait() => cat_upload_message(msg, &mut stderr)?,
+            }
+        }
+        sent += n as u64;
+        if let Some(progress) = progress {
+            progress.advance(n as u64);
+        }
+    }
+    if sent != file_size {
+        return Err(anyhow!(
+            "Source ended early: sent {} of {} bytes",
+            sent,
+            file_size
+        ));
+    }
+
+    channel.eof().await?;
+    cat_finish(&mut messages, stderr).await?;
+    if let Some(progress) = progress {
+        progress.finish();
+    }
+    Ok(())
+}
+
+/// Uploads a local file with `cat >`.
+///
+/// # Arguments
+///
+/// * `session` - The SSH session handle
+/// * `local_path` - Path to the local file
+/// * `remote_path` - Destination path on the remote host
+/// * `options` - Transfer options
+pub(crate) async fn cat_send_path(
+    session: &mut client::Handle<Client>,
+    local_path: &str,
+    remote_path: &str,
+    options: &ScpOptions,
+    digests: Option<&mut Digests>,
+) -> Result<()> {
+    let file = File::open(local_path).await?;
+    let metadata = file.metadata().await?;
+    if metadata.is_dir() {
+        return Err(anyhow!(
+            "'{}' is a directory; recursive transfers need scp on the remote host",
+            local_path
+        ));
+    }
+    let attrs = SCPFileAttrs::from_metadata(&metadata, 0o644);
+    let file_name = Path::new(local_path)
+        .file_name()
+        .ok_or_else(|| anyhow!("Invalid file name"))?
+        .to_string_lossy()
+        .to_string();
+
+    cat_send_file(
+        session,
+        RateLimited::optional(Hashing::new(file, digests), options.rate_limit.clone()),
+        metadata.len(),
+        remote_path,
+        &file_name,
+        options.mode.unwrap_or(attrs.mode),
+        options.preserve.then_some(attrs.times),
+        local_path,
+        options.progress.as_deref(),
+    )
+    .await
+}
+
+/// Uploads the content of a reader with `cat >`.
+///
+/// # Arguments
+///
+/// * `session` - The SSH session handle
+/// * `reader` - Source of the file content
+/// * `size` - Number of bytes to send
+/// * `remote_path` - Destination path on the remote host
+/// * `options` - Transfer options; `mode` defaults to `0o644`
+pub(crate) async fn cat_send_reader<R: AsyncRead + Unpin>(
+    session: &mut client::Handle<Client>,
+    reader: R,
+    size: u64,
+    remote_path: &str,
+    options: &ScpOptions,
+) -> Result<()> {
+    let file_name = Path::new(remote_path)
+        .file_name()
+        .ok_or_else(|| anyhow!("Invalid file name"))?
+        .to_string_lossy()
+        .to_string();
+
+    cat_send_file(
+        session,
+        reader,
+        size,
+        remote_path,
+        &file_name,
+        options.mode.unwrap_or(0o644),
+        None,
+        remote_path,
+        options.progress.as_deref(),
+    )
+    .await
+}
+
+/// Downloads a remote file with `cat` into a writer.
+///
+/// # Arguments
+///
+/// * `session` - The SSH session handle
+/// * `remote_path` - Path of the file on the remote host
+/// * `writer` - Destination for the file content
+/// * `options` - Transfer options
+/// * `announce` - Receives the size of the file before its content; the
+///   mode is reported as `0o644` unless `options.preserve` is set
+///
+/// # Returns
+///
+/// The number of bytes written and, with `options.preserve`, the mode
+/// and timestamps of the remote file.
+pub(crate) async fn cat_receive_writer<W: AsyncWrite + Unpin>(
+    session: &mut client::Handle<Client>,
+    remote_path: &str,
+    writer: &mut W,
+    options: &ScpOptions,
+    announce: Option<&FileHeaderSender>,
+) -> Result<(u64, Option<SCPFileAttrs>)> {
+    let mut channel = session.channel_open_session().await?;
+    channel
+        .exec(true, cat_download_command(remote_path, options.preserve))
+        .await?;
+    let progress = options.progress.as_deref();
+
+    // Content before the size line has been parsed is kept in `header`.
+    let mut header = Vec::new();
+    let mut size = None;
+    let mut attrs = None;
+    let mut written = 0u64;
+    let mut stderr = Vec::new();
+    let mut code = None;
+    while let Some(msg) = channel.wait().await {
+        match msg {
+            ChannelMsg::Data { ref data } => {
+                let content = match size {
+                    Some(_) => &data[..],
+                    None => {
+                        header.extend_from_slice(data);
+                        let Some(end) = header.iter().position(|&b| b == b'\n') else {
+                            continue;
+                        };
+                        let line = String::from_utf8_lossy(&header[..end]).to_string();
+                        let (total, file_attrs) = parse_cat_header(&line, options.preserve)?;
+                        if let Some(announce) = announce {
+                            let mode = file_attrs.as_ref().map_or(0o644, |a| a.mode);
+                            announce.send_replace(Some((total, mode)));
+                        }
+                        attrs = file_attrs;
+                        if let Some(progress) = progress {
+                            progress.start(remote_path, total);
+                        }
+                        size = Some(total);
+                        header.drain(..=end);
+                        &header[..]
+                    }
+                };
+                writer.write_all(content).await?;
+                written += content.len() as u64;
+                if let Some(progress) = progress {
+                    progress.advance(content.len() as u64);
+                }
+            }
+            ChannelMsg::ExtendedData { ref data, ext: 1 } => stderr.extend_from_slice(data),
+            ChannelMsg::ExitStatus { exit_status } => code = Some(exit_status),
+            _ => {}
+        }
+    }
+    writer.flush().await?;
+
+    if code != Some(0) {
+        return Err(cat_error(code, &stderr));
+    }
+    let Some(size) = size else {
+        return Err(anyhow!("Remote sent no file size for '{}'", remote_path));
+    };
+    if written != size {
+        return Err(anyhow!(
+            "Transfer incomplete: received {} of {} bytes",
+            written,
+            size
+        ));
+    }
+    if let Some(progress) = progress {
+        progress.finish();
+    }
+    Ok((written, attrs))
+}
+
+/// Downloads a remote file with `cat` into a local file.
+///
+/// # Arguments
+///
+/// * `session` - The SSH session handle
+/// * `remote_path` - Path of the file on the remote host
+/// * `local_path` - Local destination path
+/// * `options` - Transfer options
+pub(crate) async fn cat_receive_path(
+    session: &mut client::Handle<Client>,
+    remote_path: &str,
+    local_path: &str,
+    options: &ScpOptions,
+    digests: Option<&mut Digests>,
+) -> Result<()> {
+    let name = Path::new(remote_path)
+        .file_name()
+        .ok_or_else(|| anyhow!("Invalid file name"))?
+        .to_string_lossy()
+        .to_string();
+    let target = scp_local_target(Path::new(local_path), &name);
+    debug!(
+        "Receiving {} into {} via cat",
+        remote_path,
+        target.display()
+    );
+
+    let file = scp_create_file(&target, 0o644).await?;
+    let mut file = RateLimited::optional(Hashing::new(file, digests), options.rate_limit.clone());
+    let (_, attrs) = cat_receive_writer(session, remote_path, &mut file, options, None).await?;
+    drop(file);
+
+    if let Some(attrs) = attrs {
+        set_file_mode(&target, attrs.mode)?;
+        set_file_times(&target, attrs.times.0, attrs.times.1)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use russh::CryptoVec;
+
+    #[test]
+    fn test_remote_path_expr() {
+        assert_eq!(remote_path_expr("/tmp/f", None), "p=/tmp/f;");
+        assert_eq!(
+            remote_path_expr("/opt/my app", Some("fw.bin")),
+            "p='/opt/my app'; if [ -d \"$p\" ]; then p=\"$p\"/fw.bin; fi;"
+        );
+    }
+
+    #[test]
+    fn test_cat_commands() {
+        assert_eq!(
+            cat_upload_command("/tmp/my file", "a'b", 0o755, None),
+            "p='/tmp/my file'; if [ -d \"$p\" ]; then p=\"$p\"/'a'\\''b'; fi; \
+             cat > \"$p\" && chmod 755 \"$p\""
+        );
+        assert_eq!(
+            cat_download_command("/etc/hostname", false),
+            "p=/etc/hostname; wc -c < \"$p\" && cat \"$p\""
+        );
+    }
+
+    #[test]
+    fn test_cat_commands_preserve() {
+        assert_eq!(
+            cat_upload_command("/tmp/f", "f", 0o600, Some((1_700_000_000, 1_700_000_100))),
+            "p=/tmp/f; if [ -d \"$p\" ]; then p=\"$p\"/f; fi; \
+             cat > \"$p\" && chmod 600 \"$p\" \
+             && touch -m -d @1700000000 \"$p\" && touch -a -d @1700000100 \"$p\""
+        );
+        assert_eq!(
+            cat_download_command("/etc/hostname", true),
+            "p=/etc/hostname; stat -L -c '%s %a %Y %X' \"$p\" && cat \"$p\""
+        );
+    }
+
+    #[test]
+    fn test_cat_upload_message() {
+        let mut stderr = Vec::new();
+        let noise = ChannelMsg::ExtendedData {
+            data: CryptoVec::from_slice(b"disk full\n"),
+            ext: 1,
+        };
+        assert!(cat_upload_message(Some(noise), &mut stderr).is_ok());
+        assert!(cat_upload_message(Some(ChannelMsg::Eof), &mut stderr).is_ok());
+        assert!(cat_upload_message(
+            Some(ChannelMsg::WindowAdjusted { new_size: 1 }),
+            &mut stderr
+        )
+        .is_ok());
+
+        let err = cat_upload_message(Some(ChannelMsg::ExitStatus { exit_status: 1 }), &mut stderr)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Remote cat transfer failed with exit code 1: disk full"
+        );
+        assert!(cat_upload_message(None, &mut stderr).is_err());
+    }
+
+    #[test]
+    fn test_parse_cat_header() {
+        let (size, attrs) = parse_cat_header("42\r", false).unwrap();
+        assert_eq!(size, 42);
+        assert!(attrs.is_none());
+
+        let (size, attrs) = parse_cat_header("7 4755 1700000000 1700000100", true).unwrap();
+        let attrs = attrs.unwrap();
+        assert_eq!(size, 7);
+        assert_eq!(attrs.mode, 0o4755);
+        assert_eq!(attrs.times, (1_700_000_000, 1_700_000_100));
+
+        assert!(parse_cat_header("7", true).is_err());
+        assert!(parse_cat_header("7 644 1 2", false).is_err());
+        assert!(parse_cat_header("7 9z 1 2", true).is_err());
+        assert!(parse_cat_header("", false).is_err());
+    }
+}
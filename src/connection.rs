@@ -0,0 +1,411 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2025 Julian Kahlert
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, watch};
+
+use crate::algorithms::ExpectedAlgorithms;
+
+/// Number of state change events a watcher can fall behind.
+const EVENT_CAPACITY: usize = 64;
+
+/// Represents the state of the SSH transport.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// No transport is established.
+    Disconnected,
+    /// The initial connection is being established.
+    Connecting,
+    /// The transport is established and authenticated.
+    Connected,
+    /// The transport was lost and a reconnect attempt is in progress.
+    Reconnecting {
+        /// The current attempt number, starting at 1.
+        attempt: u32,
+    },
+    /// All reconnect attempts have been exhausted.
+    Failed,
+}
+
+impl ConnectionState {
+    /// Returns true if the transport is established.
+    pub fn is_connected(&self) -> bool {
+        matches!(self, ConnectionState::Connected)
+    }
+}
+
+/// A connection state change event with old and new states.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionStateEvent {
+    /// The previous state before the change.
+    pub previous: ConnectionState,
+    /// The new state after the change.
+    pub current: ConnectionState,
+    /// Timestamp of when the change was detected.
+    pub timestamp: Instant,
+}
+
+/// Policy for automatically re-establishing a lost SSH transport.
+///
+/// Use the builder pattern to configure the backoff:
+///
+/// ```
+/// use simple_ssh::ReconnectPolicy;
+/// use std::time::Duration;
+///
+/// let policy = ReconnectPolicy::new()
+///     .max_attempts(10)
+///     .initial_backoff(Duration::from_millis(500))
+///     .max_backoff(Duration::from_secs(60));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReconnectPolicy {
+    pub(crate) max_attempts: u32,
+    pub(crate) initial_backoff: Duration,
+    pub(crate) max_backoff: Duration,
+    pub(crate) multiplier: f64,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            multiplier: 2.0,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Creates a new reconnect policy with defaults.
+    ///
+    /// Defaults:
+    /// - max_attempts: 5
+    /// - initial_backoff: 1 second
+    /// - max_backoff: 30 seconds
+    /// - multiplier: 2.0
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum number of reconnect attempts (minimum 1).
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = attempts.max(1);
+        self
+    }
+
+    /// Sets the delay before the second attempt.
+    pub fn initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Sets the upper bound for the delay between attempts.
+    pub fn max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Sets the factor the delay grows by after each failed attempt.
+    ///
+    /// Values below 1.0 are clamped to 1.0 (constant backoff).
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = if multiplier.is_finite() {
+            multiplier.max(1.0)
+        } else {
+            1.0
+        };
+        self
+    }
+
+    /// Returns the delay to wait after the given failed attempt (1-based).
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(64) as i32;
+        let secs = self.initial_backoff.as_secs_f64() * self.multiplier.powi(exponent);
+        if secs >= self.max_backoff.as_secs_f64() {
+            self.max_backoff
+        } else {
+            Duration::from_secs_f64(secs)
+        }
+    }
+}
+
+/// Internal connection state tracking shared between a `Session` and
+/// its russh client handler.
+///
+/// Every transport gets a generation number so that a late disconnect
+/// notification from a replaced transport does not clobber the state of
+/// its successor.
+pub(crate) struct ConnectionMonitor {
+    state: Mutex<ConnectionState>,
+    generation: AtomicU64,
+    expected: Mutex<Option<ExpectedAlgorithms>>,
+    event_tx: watch::Sender<ConnectionState>,
+    event_rx: watch::Receiver<ConnectionState>,
+    transition_tx: broadcast::Sender<ConnectionStateEvent>,
+}
+
+impl ConnectionMonitor {
+    /// Creates a new monitor in the `Disconnected` state.
+    pub(crate) fn new() -> Self {
+        let (tx, rx) = watch::channel(ConnectionState::Disconnected);
+        let (transition_tx, _) = broadcast::channel(EVENT_CAPACITY);
+        Self {
+            state: Mutex::new(ConnectionState::Disconnected),
+            generation: AtomicU64::new(0),
+            expected: Mutex::new(None),
+            event_tx: tx,
+            event_rx: rx,
+            transition_tx,
+        }
+    }
+
+    /// Returns the current connection state without blocking.
+    pub(crate) fn current(&self) -> ConnectionState {
+        *self.state.lock()
+    }
+
+    /// Updates the current state and notifies all watchers.
+    pub(crate) fn update(&self, new_state: ConnectionState) {
+        let mut current = self.state.lock();
+        if *current != new_state {
+            let previous = std::mem::replace(&mut *current, new_state);
+            let _ = self.event_tx.send(new_state);
+            let _ = self.transition_tx.send(ConnectionStateEvent {
+                previous,
+                current: new_state,
+                timestamp: Instant::now(),
+            });
+        }
+    }
+
+    /// Starts a new transport generation and returns its number.
+    pub(crate) fn next_generation(&self) -> u64 {
+        self.generation.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// Marks the transport of the given generation as lost.
+    ///
+    /// Ignored if the transport has already been replaced.
+    pub(crate) fn transport_lost(&self, generation: u64) {
+        if self.generation.load(Ordering::SeqCst) == generation {
+            self.update(ConnectionState::Disconnected);
+        }
+    }
+
+    /// Returns the algorithms expected for the current transport.
+    pub(crate) fn expected(&self) -> Option<ExpectedAlgorithms> {
+        self.expected.lock().clone()
+    }
+
+    /// Records the algorithms expected for the current transport.
+    pub(crate) fn set_expected(&self, expected: Option<ExpectedAlgorithms>) {
+        *self.expected.lock() = expected;
+    }
+
+    /// Creates a new watcher for observing connection state changes.
+    pub(crate) fn create_watcher(&self) -> ConnectionWatcher {
+        ConnectionWatcher {
+            inner: self.event_rx.clone(),
+            events: self.transition_tx.subscribe(),
+        }
+    }
+}
+
+/// An async-enabled watcher for connection state changes.
+///
+/// [`changed`](Self::changed) follows the latest state only, while
+/// [`next_event`](Self::next_event) delivers every transition.
+#[derive(Debug)]
+pub struct ConnectionWatcher {
+    inner: watch::Receiver<ConnectionState>,
+    events: broadcast::Receiver<ConnectionStateEvent>,
+}
+
+/// A clone receives the transitions that happen after it was created.
+impl Clone for ConnectionWatcher {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            events: self.events.resubscribe(),
+        }
+    }
+}
+
+impl ConnectionWatcher {
+    /// Returns the current connection state without waiting.
+    pub fn current(&self) -> ConnectionState {
+        *self.inner.borrow()
+    }
+
+    /// Waits for the state to change and returns the new state.
+    ///
+    /// Returns `None` if the session has been dropped.
+    pub async fn changed(&mut self) -> Option<ConnectionState> {
+        match self.inner.changed().await {
+            Ok(()) => Some(*self.inner.borrow()),
+            Err(_) => None,
+        }
+    }
+
+    /// Waits for the next state change event (with transition info).
+    ///
+    /// Every transition since the watcher was created is delivered in
+    /// order; a watcher that falls more than 64 transitions behind skips
+    /// the oldest ones.
+    /// Returns `None` if the session has been dropped.
+    pub async fn next_event(&mut self) -> Option<ConnectionStateEvent> {
+        loop {
+            match self.events.recv().await {
+                Ok(event) => return Some(event),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    log::warn!("Connection watcher skipped {} state changes", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reconnect_policy_default() {
+        let policy = ReconnectPolicy::default();
+        assert_eq!(policy.max_attempts, 5);
+        assert_eq!(policy.initial_backoff, Duration::from_secs(1));
+        assert_eq!(policy.max_backoff, Duration::from_secs(30));
+        assert_eq!(policy.multiplier, 2.0);
+    }
+
+    #[test]
+    fn test_reconnect_policy_builder() {
+        let policy = ReconnectPolicy::new()
+            .max_attempts(0)
+            .initial_backoff(Duration::from_millis(100))
+            .max_backoff(Duration::from_secs(2))
+            .multiplier(0.5);
+        assert_eq!(policy.max_attempts, 1);
+        assert_eq!(policy.initial_backoff, Duration::from_millis(100));
+        assert_eq!(policy.max_backoff, Duration::from_secs(2));
+        assert_eq!(policy.multiplier, 1.0);
+    }
+
+    #[test]
+    fn test_reconnect_policy_backoff() {
+        let policy = ReconnectPolicy::new()
+            .initial_backoff(Duration::from_secs(1))
+            .max_backoff(Duration::from_secs(10));
+        assert_eq!(policy.backoff(1), Duration::from_secs(1));
+        assert_eq!(policy.backoff(2), Duration::from_secs(2));
+        assert_eq!(policy.backoff(3), Duration::from_secs(4));
+        assert_eq!(policy.backoff(4), Duration::from_secs(8));
+        assert_eq!(policy.backoff(5), Duration::from_secs(10));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_monitor_update_deduplicates() {
+        let monitor = ConnectionMonitor::new();
+        let watcher = monitor.create_watcher();
+        assert_eq!(monitor.current(), ConnectionState::Disconnected);
+
+        monitor.update(ConnectionState::Connected);
+        monitor.update(ConnectionState::Connected);
+        assert_eq!(watcher.current(), ConnectionState::Connected);
+        assert!(monitor.current().is_connected());
+    }
+
+    #[test]
+    fn test_monitor_ignores_stale_generation() {
+        let monitor = ConnectionMonitor::new();
+        let old = monitor.next_generation();
+        let new = monitor.next_generation();
+        monitor.update(ConnectionState::Connected);
+
+        monitor.transport_lost(old);
+        assert_eq!(monitor.current(), ConnectionState::Connected);
+
+        monitor.transport_lost(new);
+        assert_eq!(monitor.current(), ConnectionState::Disconnected);
+    }
+
+    #[tokio::test]
+    async fn test_connection_watcher_next_event() {
+        let monitor = ConnectionMonitor::new();
+        let mut watcher = monitor.create_watcher();
+
+        monitor.update(ConnectionState::Reconnecting { attempt: 1 });
+
+        let event = watcher.next_event().await.unwrap();
+        assert_eq!(event.previous, ConnectionState::Disconnected);
+        assert_eq!(event.current, ConnectionState::Reconnecting { attempt: 1 });
+    }
+
+    #[tokio::test]
+    async fn test_connection_watcher_next_event_keeps_every_transition() {
+        let monitor = ConnectionMonitor::new();
+        let mut watcher = monitor.create_watcher();
+
+        monitor.update(ConnectionState::Connecting);
+        monitor.update(ConnectionState::Connected);
+        monitor.update(ConnectionState::Reconnecting { attempt: 1 });
+        monitor.update(ConnectionState::Connected);
+        assert_eq!(watcher.current(), ConnectionState::Connected);
+
+        let transitions = [
+            (ConnectionState::Disconnected, ConnectionState::Connecting),
+            (ConnectionState::Connecting, ConnectionState::Connected),
+            (
+                ConnectionState::Connected,
+                ConnectionState::Reconnecting { attempt: 1 },
+            ),
+            (
+                ConnectionState::Reconnecting { attempt: 1 },
+                ConnectionState::Connected,
+            ),
+        ];
+        for (previous, current) in transitions {
+            let event = watcher.next_event().await.unwrap();
+            assert_eq!((event.previous, event.current), (previous, current));
+        }
+
+        drop(monitor);
+        assert_eq!(watcher.next_event().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_connection_watcher_session_dropped() {
+        let monitor = ConnectionMonitor::new();
+        let mut watcher = monitor.create_watcher();
+        drop(monitor);
+
+        assert_eq!(watcher.changed().await, None);
+    }
+}
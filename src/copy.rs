@@ -0,0 +1,255 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2025 Julian Kahlert
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::future::Future;
+
+use anyhow::{anyhow, Result};
+use log::debug;
+use tokio::io::DuplexStream;
+use tokio::sync::watch;
+
+use crate::scp::{FileHeaderSender, ScpOptions};
+use crate::sftp_transfer::remote_file_name;
+use crate::Session;
+
+/// Capacity of the in-memory pipe between the two sessions.
+const COPY_PIPE_SIZE: usize = 256 * 1024;
+
+/// Copies a file from one remote host to another.
+///
+/// The content is streamed from the download of `src` into the upload
+/// to `dst` and never touches the local disk. A `dst_path` ending in `/`
+/// names a directory that receives the file under its source name.
+///
+/// # Arguments
+///
+/// * `src` - Session to the host holding the file
+/// * `src_path` - Path of the file on the source host
+/// * `dst` - Session to the destination host
+/// * `dst_path` - Destination path on the destination host
+///
+/// # Returns
+///
+/// The number of bytes copied.
+///
+/// # Errors
+///
+/// Returns an error if:
+/// - Either session is not connected
+/// - The source file does not exist or is a directory
+/// - Either transfer fails
+///
+/// # Example
+///
+/// ```no_run
+/// # use simple_ssh::{copy_between, Session};
+/// # async fn example(mut a: Session, mut b: Session) -> anyhow::Result<()> {
+/// let bytes = copy_between(&mut a, "/var/log/syslog", &mut b, "/tmp/").await?;
+/// println!("copied {} bytes", bytes);
+/// # Ok(())
+/// # }
+/// ```
+pub async fn copy_between(
+    src: &mut Session,
+    src_path: &str,
+    dst: &mut Session,
+    dst_path: &str,
+) -> Result<u64> {
+    copy_between_with_options(src, src_path, dst, dst_path, &ScpOptions::default()).await
+}
+
+/// Copies a file from one remote host to another with transfer options.
+///
+/// Like [`copy_between`]. The strategy and verification apply to both
+/// hosts. Progress and the rate limit apply to the upload, which paces
+/// the download through the pipe between them. Without an explicit
+/// mode the destination gets the mode reported by the source.
+///
+/// # Arguments
+///
+/// * `src` - Session to the host holding the file
+/// * `src_path` - Path of the file on the source host
+/// * `dst` - Session to the destination host
+/// * `dst_path` - Destination path on the destination host
+/// * `options` - Transfer options
+///
+/// # Errors
+///
+/// Returns an error if `options.recursive` is set, as only single files
+/// can be copied, or in the cases listed for [`copy_between`].
+pub async fn copy_between_with_options(
+    src: &mut Session,
+    src_path: &str,
+    dst: &mut Session,
+    dst_path: &str,
+    options: &ScpOptions,
+) -> Result<u64> {
+    if options.recursive {
+        return Err(anyhow!(
+            "Copying directories between hosts is not supported"
+        ));
+    }
+    src.ensure_connected().await?;
+    dst.ensure_connected().await?;
+
+    let dst_path = if dst_path.ends_with('/') {
+        format!("{}{}", dst_path, remote_file_name(src_path))
+    } else {
+        dst_path.to_string()
+    };
+    debug!("Copying {} to {} between hosts", src_path, dst_path);
+
+    let download_options = ScpOptions {
+        progress: None,
+        rate_limit: None,
+        ..options.clone()
+    };
+    let download = |mut pipe: DuplexStream, header: FileHeaderSender| async move {
+        src.inner
+            .download_writer(src_path, &mut pipe, &download_options, Some(&header))
+            .await
+    };
+    let upload = |pipe: DuplexStream, size: u64, mode: u32| {
+        let upload_options = ScpOptions {
+            mode: Some(options.mode.unwrap_or(mode)),
+            ..options.clone()
+        };
+        let dst_path = &dst_path;
+        async move {
+            dst.inner
+                .upload_reader(pipe, size, dst_path, &upload_options)
+                .await
+        }
+    };
+
+    let (copied, ()) = pipe_file(src_path, download, upload).await?;
+    Ok(copied)
+}
+
+/// Streams a file from `download` into `upload` through an in-memory pipe.
+///
+/// `download` writes the file into the pipe and announces its size and
+/// mode on the header before the content; `upload` starts once the
+/// header is known and reads the content from the pipe. A failure on
+/// either side drops the other transfer.
+async fn pipe_file<D, DF, U, UF, T>(src_path: &str, download: D, upload: U) -> Result<(u64, T)>
+where
+    D: FnOnce(DuplexStream, FileHeaderSender) -> DF,
+    DF: Future<Output = Result<u64>>,
+    U: FnOnce(DuplexStream, u64, u32) -> UF,
+    UF: Future<Output = Result<T>>,
+{
+    let (pipe_tx, pipe_rx) = tokio::io::duplex(COPY_PIPE_SIZE);
+    let (header_tx, mut header_rx) = watch::channel(None);
+
+    let download = download(pipe_tx, header_tx);
+    let upload = async {
+        let (size, mode) = header_rx
+            .wait_for(Option::is_some)
+            .await
+            .map_err(|_| anyhow!("Source closed before sending '{}'", src_path))?
+            .expect("header is set");
+        upload(pipe_rx, size, mode).await
+    };
+    tokio::try_join!(download, upload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scp::tests::{receive_file, MemoryChannel};
+    use russh::ChannelMsg;
+    use tokio::io::AsyncReadExt;
+
+    /// Reads the whole pipe and returns it with the announced mode.
+    async fn sink(mut pipe: DuplexStream, size: u64, mode: u32) -> Result<(Vec<u8>, u32)> {
+        let mut data = Vec::new();
+        pipe.read_to_end(&mut data).await?;
+        if data.len() as u64 != size {
+            return Err(anyhow!("Received {} of {} bytes", data.len(), size));
+        }
+        Ok((data, mode))
+    }
+
+    #[tokio::test]
+    async fn test_pipe_file() {
+        let (source, channel) = MemoryChannel::pair();
+        let content: Vec<u8> = (0..600_000u32).map(|i| (i % 251) as u8).collect();
+        source.send_data(format!("C0750 {} data.bin\n", content.len()).as_bytes());
+        // More than the pipe holds, so both sides have to run concurrently.
+        for chunk in content.chunks(32 * 1024) {
+            source.send_data(chunk);
+        }
+        source.send_data(b"\0");
+        source.send(ChannelMsg::Eof);
+
+        let download = |mut pipe: DuplexStream, header: FileHeaderSender| async move {
+            receive_file(channel, &mut pipe, Some(&header)).await
+        };
+        let (copied, (data, mode)) = pipe_file("/src", download, sink).await.unwrap();
+        assert_eq!(copied, content.len() as u64);
+        assert_eq!(data, content);
+        assert_eq!(mode, 0o750);
+    }
+
+    #[tokio::test]
+    async fn test_pipe_file_source_fails() {
+        // The source dies after the header and part of the content.
+        let (source, channel) = MemoryChannel::pair();
+        source.send_data(b"C0644 10 data.bin\n");
+        source.send_data(b"hello");
+        source.send(ChannelMsg::Eof);
+
+        let download = |mut pipe: DuplexStream, header: FileHeaderSender| async move {
+            receive_file(channel, &mut pipe, Some(&header)).await
+        };
+        let err = pipe_file("/src", download, sink).await.unwrap_err();
+        assert!(err.to_string().contains("outstanding"), "{}", err);
+
+        // Without a header the upload never starts.
+        let (source, channel) = MemoryChannel::pair();
+        source.send(ChannelMsg::Eof);
+        let download = |mut pipe: DuplexStream, header: FileHeaderSender| async move {
+            receive_file(channel, &mut pipe, Some(&header)).await
+        };
+        let upload = |_: DuplexStream, _: u64, _: u32| async { panic!("upload started") };
+        let err = pipe_file::<_, _, _, _, ()>("/src", download, upload)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("sent no file"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_copy_between_errors() {
+        let mut src = Session::init().with_passwd("pass").build().unwrap();
+        let mut dst = Session::init().with_passwd("pass").build().unwrap();
+
+        let options = ScpOptions::new().recursive(true);
+        let result = copy_between_with_options(&mut src, "/dir", &mut dst, "/dir", &options).await;
+        assert!(result.unwrap_err().to_string().contains("not supported"));
+
+        let result = copy_between(&mut src, "/file", &mut dst, "/file").await;
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,519 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2025 Julian Kahlert
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::fmt;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use regex::bytes::Regex;
+use tokio::time::Instant;
+
+use crate::PtyHandle;
+
+/// Default limit of unmatched output kept by [`Expect`].
+const DEFAULT_MAX_BUFFER: usize = 64 * 1024;
+
+/// A pattern to wait for in PTY output.
+///
+/// Strings convert into literal patterns; use [`ExpectPattern::regex`]
+/// for regular expressions. Patterns are matched against the raw output,
+/// including escape sequences.
+#[derive(Debug, Clone)]
+pub struct ExpectPattern {
+    regex: Regex,
+    description: String,
+}
+
+impl ExpectPattern {
+    /// Creates a pattern matching `text` literally.
+    pub fn literal(text: &str) -> Self {
+        Self {
+            regex: Regex::new(&regex::escape(text)).expect("escaped literal is a valid regex"),
+            description: format!("{:?}", text),
+        }
+    }
+
+    /// Creates a pattern from a regular expression.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `pattern` is not a valid regular expression.
+    pub fn regex(pattern: &str) -> Result<Self> {
+        let regex = Regex::new(pattern).map_err(|e| anyhow!("Invalid expect pattern: {}", e))?;
+        Ok(Self {
+            regex,
+            description: format!("/{}/", pattern),
+        })
+    }
+}
+
+impl From<&str> for ExpectPattern {
+    fn from(text: &str) -> Self {
+        Self::literal(text)
+    }
+}
+
+impl From<String> for ExpectPattern {
+    fn from(text: String) -> Self {
+        Self::literal(&text)
+    }
+}
+
+impl From<regex::Regex> for ExpectPattern {
+    fn from(regex: regex::Regex) -> Self {
+        Self::regex(regex.as_str()).expect("a valid regex is valid on bytes")
+    }
+}
+
+impl From<Regex> for ExpectPattern {
+    fn from(regex: Regex) -> Self {
+        Self {
+            description: format!("/{}/", regex.as_str()),
+            regex,
+        }
+    }
+}
+
+/// Output matched by [`Expect::expect`] or [`Expect::expect_any`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpectMatch {
+    /// Index of the matching pattern; always 0 for [`Expect::expect`].
+    pub index: usize,
+    /// Output between the previous match and this one.
+    pub before: String,
+    /// The matched output.
+    pub matched: String,
+    /// Capture groups of a regex pattern, starting with group 1.
+    pub groups: Vec<Option<String>>,
+}
+
+/// Why an expectation was not met.
+///
+/// Can be recovered from the [`anyhow::Error`] of an expect call with
+/// `error.downcast_ref::<ExpectError>()`. The unmatched output stays
+/// buffered for later calls.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExpectError {
+    /// No pattern matched before the timeout.
+    Timeout {
+        /// The patterns that were waited for.
+        expected: String,
+        /// Unmatched output received so far.
+        pending: String,
+    },
+    /// The PTY closed without a pattern matching.
+    Eof {
+        /// The patterns that were waited for.
+        expected: String,
+        /// Unmatched output received before the end.
+        pending: String,
+    },
+}
+
+impl fmt::Display for ExpectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExpectError::Timeout { expected, pending } => write!(
+                f,
+                "Timed out waiting for {}; pending output: {:?}",
+                expected,
+                tail(pending)
+            ),
+            ExpectError::Eof { expected, pending } => write!(
+                f,
+                "PTY closed while waiting for {}; pending output: {:?}",
+                expected,
+                tail(pending)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ExpectError {}
+
+/// Returns the last few hundred characters of `text` for error messages.
+fn tail(text: &str) -> &str {
+    const LIMIT: usize = 200;
+    let mut start = text.len().saturating_sub(LIMIT);
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    &text[start..]
+}
+
+/// Unmatched PTY output with a size limit.
+///
+/// Output is kept across reads, so patterns split over several output
+/// chunks still match.
+#[derive(Debug)]
+struct MatchBuffer {
+    data: Vec<u8>,
+    max: usize,
+}
+
+impl MatchBuffer {
+    fn new(max: usize) -> Self {
+        Self {
+            data: Vec::new(),
+            max,
+        }
+    }
+
+    /// Appends output, dropping the oldest bytes beyond the limit.
+    fn push(&mut self, chunk: &[u8]) {
+        self.data.extend_from_slice(chunk);
+        if self.data.len() > self.max {
+            let excess = self.data.len() - self.max;
+            self.data.drain(..excess);
+        }
+    }
+
+    /// Finds the earliest match of any pattern and consumes the output
+    /// up to its end.
+    ///
+    /// When two patterns match at the same position the first one wins.
+    fn find(&mut self, patterns: &[ExpectPattern]) -> Option<ExpectMatch> {
+        let (index, captures) = patterns
+            .iter()
+            .enumerate()
+            .filter_map(|(i, p)| p.regex.captures(&self.data).map(|c| (i, c)))
+            .min_by_key(|(i, c)| (c.get(0).map_or(usize::MAX, |m| m.start()), *i))?;
+        let whole = captures.get(0)?;
+        let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).into_owned();
+        let result = ExpectMatch {
+            index,
+            before: text(&self.data[..whole.start()]),
+            matched: text(whole.as_bytes()),
+            groups: captures
+                .iter()
+                .skip(1)
+                .map(|group| group.map(|g| text(g.as_bytes())))
+                .collect(),
+        };
+        let end = whole.end();
+        self.data.drain(..end);
+        Some(result)
+    }
+
+    /// Returns the unmatched output as text.
+    fn pending(&self) -> String {
+        String::from_utf8_lossy(&self.data).into_owned()
+    }
+
+    /// Removes and returns the unmatched output.
+    fn take(&mut self) -> String {
+        let pending = self.pending();
+        self.data.clear();
+        pending
+    }
+}
+
+/// Expect-style automation of a [`PtyHandle`].
+///
+/// Waits for patterns in the output of an interactive program and sends
+/// input in response, like `expect` or `pexpect`.
+///
+/// ```ignore
+/// use simple_ssh::{Expect, ExpectPattern};
+/// use std::time::Duration;
+///
+/// let handle = session.pty_builder().with_command("./install.sh").open().await?;
+/// let mut exp = Expect::new(handle);
+/// let timeout = Duration::from_secs(30);
+///
+/// exp.expect("Install location?", timeout).await?;
+/// exp.send_line("/opt/app").await?;
+///
+/// let answer = exp
+///     .expect_any(&["Continue? [y/N]".into(), ExpectPattern::regex(r"Error: (.*)")?], timeout)
+///     .await?;
+/// if answer.index == 1 {
+///     anyhow::bail!("installer failed: {:?}", answer.groups[0]);
+/// }
+/// exp.send_line("y").await?;
+/// exp.expect_eof(timeout).await?;
+/// ```
+#[derive(Debug)]
+pub struct Expect {
+    handle: PtyHandle,
+    buffer: MatchBuffer,
+    eof: bool,
+}
+
+impl Expect {
+    /// Wraps a PTY handle.
+    ///
+    /// Output read through the handle before this call is not seen.
+    pub fn new(handle: PtyHandle) -> Self {
+        Self {
+            handle,
+            buffer: MatchBuffer::new(DEFAULT_MAX_BUFFER),
+            eof: false,
+        }
+    }
+
+    /// Sets how much unmatched output is kept (default: 64 KiB).
+    ///
+    /// Older output is dropped beyond the limit, so a pattern must match
+    /// within the last `bytes` of output.
+    pub fn max_buffer(mut self, bytes: usize) -> Self {
+        self.buffer.max = bytes.max(1);
+        self
+    }
+
+    /// Returns the wrapped handle.
+    pub fn handle(&self) -> &PtyHandle {
+        &self.handle
+    }
+
+    /// Returns the wrapped handle mutably.
+    ///
+    /// Output read directly from the handle bypasses the match buffer.
+    pub fn handle_mut(&mut self) -> &mut PtyHandle {
+        &mut self.handle
+    }
+
+    /// Returns the wrapped handle, dropping any unmatched output.
+    pub fn into_inner(self) -> PtyHandle {
+        self.handle
+    }
+
+    /// Returns the output received but not yet consumed by a match.
+    pub fn pending(&self) -> String {
+        self.buffer.pending()
+    }
+
+    /// Sends raw input.
+    pub async fn send(&self, data: impl AsRef<[u8]>) -> Result<()> {
+        self.handle.write(data.as_ref()).await
+    }
+
+    /// Sends `line` followed by a newline.
+    pub async fn send_line(&self, line: &str) -> Result<()> {
+        let mut data = Vec::with_capacity(line.len() + 1);
+        data.extend_from_slice(line.as_bytes());
+        data.push(b'\n');
+        self.handle.write(&data).await
+    }
+
+    /// Sends a control character, e.g. `'c'` for Ctrl+C.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `key` has no control character.
+    pub async fn send_control(&self, key: char) -> Result<()> {
+        let byte = match key.to_ascii_uppercase() {
+            c @ '@'..='_' => c as u8 - b'@',
+            '?' => 0x7f,
+            _ => return Err(anyhow!("No control character for {:?}", key)),
+        };
+        self.handle.write(&[byte]).await
+    }
+
+    /// Waits until `pattern` appears in the output.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`ExpectError`] if the timeout passes or the PTY closes
+    /// first.
+    pub async fn expect(
+        &mut self,
+        pattern: impl Into<ExpectPattern>,
+        timeout: Duration,
+    ) -> Result<ExpectMatch> {
+        self.expect_any(&[pattern.into()], timeout).await
+    }
+
+    /// Waits until any of `patterns` appears in the output.
+    ///
+    /// The pattern matching earliest in the output wins; the returned
+    /// [`ExpectMatch::index`] tells which one it was.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`ExpectError`] if the timeout passes or the PTY closes
+    /// first.
+    pub async fn expect_any(
+        &mut self,
+        patterns: &[ExpectPattern],
+        timeout: Duration,
+    ) -> Result<ExpectMatch> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(found) = self.buffer.find(patterns) {
+                return Ok(found);
+            }
+            if self.eof || !self.read_until(deadline).await {
+                let expected = describe(patterns);
+                let pending = self.buffer.pending();
+                return Err(if self.eof {
+                    ExpectError::Eof { expected, pending }
+                } else {
+                    ExpectError::Timeout { expected, pending }
+                }
+                .into());
+            }
+        }
+    }
+
+    /// Waits until the PTY closes and returns the remaining output.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ExpectError::Timeout`] if the PTY is still open when the
+    /// timeout passes.
+    pub async fn expect_eof(&mut self, timeout: Duration) -> Result<String> {
+        let deadline = Instant::now() + timeout;
+        while !self.eof {
+            if !self.read_until(deadline).await {
+                return Err(ExpectError::Timeout {
+                    expected: "end of output".to_string(),
+                    pending: self.buffer.pending(),
+                }
+                .into());
+            }
+        }
+        Ok(self.buffer.take())
+    }
+
+    /// Reads the next output chunk into the buffer.
+    ///
+    /// Returns false if the deadline passed first.
+    async fn read_until(&mut self, deadline: Instant) -> bool {
+        match tokio::time::timeout_at(deadline, self.handle.read()).await {
+            Ok(Some(chunk)) => self.buffer.push(&chunk),
+            Ok(None) => self.eof = true,
+            Err(_) => return false,
+        }
+        true
+    }
+}
+
+/// Lists pattern descriptions for error messages.
+fn describe(patterns: &[ExpectPattern]) -> String {
+    patterns
+        .iter()
+        .map(|p| p.description.as_str())
+        .collect::<Vec<_>>()
+        .join(" or ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_match_buffer_split_chunks() {
+        let mut buffer = MatchBuffer::new(1024);
+        let patterns = [ExpectPattern::literal("password:")];
+        buffer.push(b"Welcome\r\npass");
+        assert_eq!(buffer.find(&patterns), None);
+        buffer.push(b"word: ");
+        let found = buffer.find(&patterns).unwrap();
+        assert_eq!(found.before, "Welcome\r\n");
+        assert_eq!(found.matched, "password:");
+        assert_eq!(buffer.pending(), " ");
+    }
+
+    #[test]
+    fn test_match_buffer_earliest_wins() {
+        let mut buffer = MatchBuffer::new(1024);
+        let patterns = [
+            ExpectPattern::literal("$ "),
+            ExpectPattern::regex(r"error (\d+)(x)?").unwrap(),
+        ];
+        buffer.push(b"error 42 then $ ");
+        let found = buffer.find(&patterns).unwrap();
+        assert_eq!(found.index, 1);
+        assert_eq!(found.matched, "error 42");
+        assert_eq!(found.groups, vec![Some("42".to_string()), None]);
+
+        let found = buffer.find(&patterns).unwrap();
+        assert_eq!(found.index, 0);
+        assert_eq!(found.before, " then ");
+        assert_eq!(buffer.find(&patterns), None);
+    }
+
+    #[test]
+    fn test_match_buffer_limit() {
+        let mut buffer = MatchBuffer::new(8);
+        buffer.push(b"0123456789");
+        assert_eq!(buffer.pending(), "23456789");
+        assert_eq!(buffer.take(), "23456789");
+        assert_eq!(buffer.pending(), "");
+    }
+
+    #[test]
+    fn test_pattern_conversions() {
+        let pattern: ExpectPattern = "a.b".into();
+        assert!(!pattern.regex.is_match(b"axb"));
+        assert!(pattern.regex.is_match(b"a.b"));
+
+        let pattern: ExpectPattern = regex::Regex::new("a.b").unwrap().into();
+        assert!(pattern.regex.is_match(b"axb"));
+        assert!(ExpectPattern::regex("(").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_expect_timeout_and_eof() {
+        let (handle, output_tx, mut input_rx) = PtyHandle::detached();
+        let mut exp = Expect::new(handle);
+
+        output_tx.send(b"login: ".to_vec()).await.unwrap();
+        let found = exp.expect("login:", Duration::from_secs(1)).await.unwrap();
+        assert_eq!(found.before, "");
+        exp.send_line("admin").await.unwrap();
+        exp.send_control('c').await.unwrap();
+        assert_eq!(input_rx.recv().await.unwrap(), b"admin\n");
+        assert_eq!(input_rx.recv().await.unwrap(), [0x03]);
+
+        output_tx.send(b"Pass".to_vec()).await.unwrap();
+        let err = exp
+            .expect("Password:", Duration::from_millis(50))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ExpectError>(),
+            Some(&ExpectError::Timeout {
+                expected: "\"Password:\"".to_string(),
+                pending: " Pass".to_string(),
+            })
+        );
+
+        output_tx.send(b"word: bye".to_vec()).await.unwrap();
+        drop(output_tx);
+        exp.expect("Password:", Duration::from_secs(1))
+            .await
+            .unwrap();
+        let err = exp.expect("$", Duration::from_secs(1)).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ExpectError>(),
+            Some(ExpectError::Eof { pending, .. }) if pending == " bye"
+        ));
+        assert_eq!(
+            exp.expect_eof(Duration::from_secs(1)).await.unwrap(),
+            " bye"
+        );
+    }
+}
@@ -54,11 +54,39 @@
 //! # Features
 //!
 //! - Execute remote commands (`cmd`, `exec`, `system`)
-//! - Transfer files via SCP protocol
+//! - Transfer files and directory trees via SCP protocol (upload and download)
+//! - Fall back to `cat` based transfers when the target has no `scp`
+//! - Optional checksum verification of transferred files
+//! - SFTP client for listing, stat, rename and random access
+//! - Remote files usable as `AsyncRead`/`AsyncWrite`/`AsyncSeek` streams
+//! - Resumable SFTP transfers, optionally split into parallel ranges
+//! - Host-to-host copies without a local temporary file
+//! - Directory synchronisation that only uploads changed files
+//! - Token bucket bandwidth limiting for transfers and streams
+//! - Per-file transfer progress reporting
 //! - Interactive PTY sessions with raw mode
 //! - Programmatic PTY sessions via `PtyHandle` for TUI embedding
+//! - Expect-style automation of interactive programs
+//! - Screen snapshots of PTY sessions with text, HTML and ANSI export
+//! - Shell command tracking via OSC 133/633 marks
+//! - Window title tracking via OSC 0/1/2
+//! - Bell and OSC 9/777 desktop notification detection
+//! - OSC 52 clipboard requests with a configurable policy
+//! - Terminal mode tracking (alternate screen, bracketed paste, mouse, ...)
+//! - Persistent shell sessions returning each command's output and exit code
+//! - Session recording and replay in the asciicast v2 format
 //! - Public key, password, and certificate authentication
 //! - IPv6 link-local address support
+//! - Keepalives and opt-in automatic reconnection
+//! - Configurable crypto algorithm preferences and compression
+//!
+//! # Cargo Features
+//!
+//! - `screen`: terminal emulation and screen snapshots of PTY sessions
+//! - `recording`: asciicast recording and replay
+//! - `expect`: expect-style automation and persistent shell sessions
+//! - `sync`: directory synchronisation
+//! - `cli`: the `simple-ssh` and `simple-scp` binaries
 
 use std::env;
 use std::io::Write;
@@ -81,26 +109,102 @@ use log::debug;
 use log::info;
 use russh::keys::*;
 use russh::*;
-use tokio::fs::File;
-use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::sync::{mpsc, watch};
 use tokio::task::JoinHandle;
-use tokio::time::timeout;
 
+use crate::algorithms::{AlgorithmPreferences, KexInitTap};
 use crate::client::Msg;
+use crate::connection::ConnectionMonitor;
+use crate::pty_clipboard::{ClipboardDetection, ClipboardFilter};
+use crate::pty_command::CommandDetection;
 use crate::pty_mode::ModeDetection;
+use crate::pty_notify::NotificationDetection;
 use crate::pty_pwd::PwdDetection;
+#[cfg(feature = "recording")]
+use crate::pty_record::PtyRecorder;
+#[cfg(feature = "screen")]
+use crate::pty_screen::PtyScreen;
+use crate::pty_title::TitleDetection;
+use crate::scp::FileHeaderSender;
 
 pub use russh::Pty;
 pub use russh::Sig;
 
+pub mod algorithms;
+mod cat;
+pub mod connection;
+pub mod copy;
+#[cfg(feature = "expect")]
+pub mod expect;
+pub mod progress;
+pub mod pty_clipboard;
+pub mod pty_command;
 pub mod pty_history;
 pub mod pty_mode;
+pub mod pty_notify;
+mod pty_osc;
 pub mod pty_pwd;
-
+#[cfg(feature = "recording")]
+pub mod pty_record;
+#[cfg(feature = "recording")]
+pub mod pty_replay;
+#[cfg(feature = "screen")]
+pub mod pty_screen;
+pub mod pty_title;
+pub mod rate_limit;
+pub mod remote_file;
+pub mod scp;
+pub mod sftp;
+pub mod sftp_transfer;
+#[cfg(feature = "expect")]
+pub mod shell;
+#[cfg(feature = "sync")]
+pub mod sync;
+#[cfg(test)]
+mod test_server;
+pub mod verify;
+
+pub use algorithms::ExpectedAlgorithms;
+pub use connection::{ConnectionState, ConnectionStateEvent, ConnectionWatcher, ReconnectPolicy};
+pub use copy::{copy_between, copy_between_with_options};
+#[cfg(feature = "expect")]
+pub use expect::{Expect, ExpectError, ExpectMatch, ExpectPattern};
+pub use progress::{ProgressWatcher, TransferProgress};
+pub use pty_clipboard::{
+    ClipboardConfig, ClipboardDenial, ClipboardEvent, ClipboardRequest, ClipboardWatcher,
+};
+pub use pty_command::{CommandDetectionConfig, CommandEvent, CommandWatcher};
 pub use pty_history::{HistoryEntry, HistoryWatcher, PtyHistoryConfig};
-pub use pty_mode::{ModeChangeEvent, ModeDetectionConfig, ModeWatcher, PtyMode};
+pub use pty_mode::{
+    ModeChangeEvent, ModeDetectionConfig, ModeWatcher, PtyMode, TerminalMode, TerminalModeEvent,
+    TerminalState,
+};
+pub use pty_notify::{
+    Notification, NotificationDetectionConfig, NotificationKind, NotificationWatcher,
+};
 pub use pty_pwd::{PwdChangeEvent, PwdDetectionConfig, PwdWatcher};
+#[cfg(feature = "recording")]
+pub use pty_record::PtyRecordingConfig;
+#[cfg(feature = "recording")]
+pub use pty_replay::{
+    CastEvent, CastEventKind, CastHeader, Player, PlayerEvent, Recording, ReplayOptions,
+};
+#[cfg(feature = "screen")]
+pub use pty_screen::{ScreenCell, ScreenColor, ScreenConfig, ScreenSnapshot};
+pub use pty_title::{TitleChangeEvent, TitleDetectionConfig, TitleWatcher};
+pub use rate_limit::{RateLimited, RateLimiter};
+pub use remote_file::RemoteFile;
+pub use scp::{ScpOptions, SymlinkPolicy, TransferStrategy};
+pub use sftp::{
+    DirEntry, FileAttributes, Sftp, SftpError, SftpHandle, SftpOpenOptions, SftpStatus,
+};
+pub use sftp_transfer::{SftpTransferOptions, SftpTransferSummary};
+#[cfg(feature = "expect")]
+pub use shell::{ShellOutput, ShellSession};
+#[cfg(feature = "sync")]
+pub use sync::{SyncAction, SyncCompare, SyncOptions, SyncReason, SyncReport};
+pub use verify::ChecksumMismatch;
 
 /// Type alias for the previous panic hook handler.
 type PanicHook = Box<dyn Fn(&panic::PanicHookInfo<'_>) + Send + Sync>;
@@ -211,7 +315,13 @@ pub struct PtyHandle {
     closed: bool,
     mode_detection: Option<Arc<ModeDetection>>,
     pwd_detection: Option<Arc<PwdDetection>>,
+    command_detection: Option<Arc<CommandDetection>>,
     history: Option<Arc<pty_history::PtyHistory>>,
+    #[cfg(feature = "screen")]
+    screen: Option<Arc<PtyScreen>>,
+    title_detection: Option<Arc<TitleDetection>>,
+    notification_detection: Option<Arc<NotificationDetection>>,
+    clipboard: Option<Arc<ClipboardDetection>>,
 }
 
 impl std::fmt::Debug for PtyHandle {
@@ -287,6 +397,36 @@ impl PtyHandle {
         self.input_tx = tx;
     }
 
+    /// Creates a handle that is not connected to a session.
+    ///
+    /// Returns the sender feeding the handle's output and the receiver
+    /// of everything written to it.
+    #[cfg(all(test, feature = "expect"))]
+    pub(crate) fn detached() -> (Self, mpsc::Sender<Vec<u8>>, mpsc::Receiver<Vec<u8>>) {
+        let (input_tx, input_rx) = mpsc::channel(64);
+        let (output_tx, output_rx) = mpsc::channel(256);
+        let (resize_tx, _) = mpsc::channel(4);
+        let (_, exit_rx) = watch::channel(None);
+        let handle = Self {
+            input_tx,
+            output_rx,
+            resize_tx,
+            task_handle: None,
+            exit_rx,
+            closed: false,
+            mode_detection: None,
+            pwd_detection: None,
+            command_detection: None,
+            history: None,
+            #[cfg(feature = "screen")]
+            screen: None,
+            title_detection: None,
+            notification_detection: None,
+            clipboard: None,
+        };
+        (handle, output_tx, input_rx)
+    }
+
     /// Returns the current PTY mode if detection is enabled.
     pub fn current_mode(&self) -> Option<PtyMode> {
         self.mode_detection
@@ -310,6 +450,14 @@ impl PtyHandle {
             .unwrap_or(true)
     }
 
+    /// Returns the set of terminal modes the remote side has enabled,
+    /// if mode detection is enabled.
+    pub fn terminal_state(&self) -> Option<TerminalState> {
+        self.mode_detection
+            .as_ref()
+            .map(|md: &Arc<ModeDetection>| md.current_state())
+    }
+
     /// Creates a watcher for mode change events.
     ///
     /// Returns a `ModeWatcher` that can be used to await mode changes.
@@ -347,6 +495,98 @@ impl PtyHandle {
         }
     }
 
+    /// Returns the most recently finished command if command detection is
+    /// enabled and the shell has reported one via OSC 133 or OSC 633.
+    pub fn last_command(&self) -> Option<CommandEvent> {
+        self.command_detection
+            .as_ref()
+            .and_then(|cd| cd.last_command())
+    }
+
+    /// Returns true while the shell reports a command as running.
+    ///
+    /// Always false if command detection is not enabled.
+    pub fn is_command_running(&self) -> bool {
+        self.command_detection
+            .as_ref()
+            .is_some_and(|cd| cd.is_running())
+    }
+
+    /// Creates a watcher for commands run in the remote shell.
+    ///
+    /// Returns a `CommandWatcher` yielding each command's line, output,
+    /// exit code, timing and working directory as it finishes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if command detection is not enabled.
+    pub fn watch_commands(&self) -> Result<CommandWatcher> {
+        match self.command_detection.as_ref() {
+            Some(cd) => Ok(cd.create_watcher()),
+            None => Err(anyhow!("command detection is not enabled")),
+        }
+    }
+
+    /// Returns the window title if title detection is enabled and a
+    /// program has set one via OSC 0 or OSC 2.
+    pub fn current_title(&self) -> Option<String> {
+        self.title_detection
+            .as_ref()
+            .and_then(|td| td.current_title())
+    }
+
+    /// Returns the icon name if title detection is enabled and a program
+    /// has set one via OSC 0 or OSC 1.
+    pub fn current_icon_name(&self) -> Option<String> {
+        self.title_detection
+            .as_ref()
+            .and_then(|td| td.current_icon_name())
+    }
+
+    /// Creates a watcher for window title changes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if title detection is not enabled.
+    pub fn watch_title(&self) -> Result<TitleWatcher> {
+        match self.title_detection.as_ref() {
+            Some(td) => Ok(td.create_watcher()),
+            None => Err(anyhow!("title detection is not enabled")),
+        }
+    }
+
+    /// Returns the most recent bell or notification if notification
+    /// detection is enabled.
+    pub fn last_notification(&self) -> Option<Notification> {
+        self.notification_detection
+            .as_ref()
+            .and_then(|nd| nd.last_notification())
+    }
+
+    /// Creates a watcher for bells and OSC 9/777 desktop notifications.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if notification detection is not enabled.
+    pub fn watch_notifications(&self) -> Result<NotificationWatcher> {
+        match self.notification_detection.as_ref() {
+            Some(nd) => Ok(nd.create_watcher()),
+            None => Err(anyhow!("notification detection is not enabled")),
+        }
+    }
+
+    /// Creates a watcher for OSC 52 clipboard requests.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if clipboard handling is not enabled.
+    pub fn watch_clipboard(&self) -> Result<ClipboardWatcher> {
+        match self.clipboard.as_ref() {
+            Some(cd) => Ok(cd.create_watcher()),
+            None => Err(anyhow!("clipboard handling is not enabled")),
+        }
+    }
+
     /// Returns the number of history entries if history capture is enabled.
     pub fn history_len(&self) -> usize {
         self.history.as_ref().map(|h| h.len()).unwrap_or(0)
@@ -375,6 +615,15 @@ impl PtyHandle {
             None => Vec::new(),
         }
     }
+
+    /// Returns a copy of the emulated terminal screen.
+    ///
+    /// Returns `None` if screen emulation is not enabled. See
+    /// [`PtyBuilder::with_screen`].
+    #[cfg(feature = "screen")]
+    pub fn screen_snapshot(&self) -> Option<ScreenSnapshot> {
+        self.screen.as_ref().map(|screen| screen.snapshot())
+    }
 }
 
 impl Drop for PtyHandle {
@@ -412,26 +661,82 @@ fn default_pty_terminal_modes() -> Vec<(Pty, u32)> {
     ]
 }
 
+/// The optional consumers of PTY output kept up to date by [`pty_io_task`].
+#[derive(Clone, Default)]
+struct PtyObservers {
+    mode_detection: Option<Arc<ModeDetection>>,
+    pwd_detection: Option<Arc<PwdDetection>>,
+    command_detection: Option<Arc<CommandDetection>>,
+    history: Option<Arc<pty_history::PtyHistory>>,
+    #[cfg(feature = "screen")]
+    screen: Option<Arc<PtyScreen>>,
+    title_detection: Option<Arc<TitleDetection>>,
+    notification_detection: Option<Arc<NotificationDetection>>,
+    clipboard: Option<Arc<ClipboardDetection>>,
+}
+
+impl PtyObservers {
+    /// Feeds an output chunk to every enabled observer.
+    fn feed(&self, data: &[u8]) {
+        if let Some(md) = self.mode_detection.as_ref() {
+            md.feed(data);
+        }
+        if let Some(pd) = self.pwd_detection.as_ref() {
+            pd.feed(data);
+        }
+        if let Some(cd) = self.command_detection.as_ref() {
+            cd.feed(data);
+        }
+        if let Some(h) = self.history.as_ref() {
+            h.feed(data);
+        }
+        #[cfg(feature = "screen")]
+        if let Some(screen) = self.screen.as_ref() {
+            screen.feed(data);
+        }
+        if let Some(td) = self.title_detection.as_ref() {
+            td.feed(data);
+        }
+        if let Some(nd) = self.notification_detection.as_ref() {
+            nd.feed(data);
+        }
+        if let Some(cd) = self.clipboard.as_ref() {
+            cd.feed(data);
+        }
+    }
+
+    /// Tells observers that track the terminal size about a resize.
+    #[cfg_attr(not(feature = "screen"), allow(unused_variables))]
+    fn resize(&self, cols: u32, rows: u32) {
+        #[cfg(feature = "screen")]
+        if let Some(screen) = self.screen.as_ref() {
+            screen.resize(cols, rows);
+        }
+    }
+}
+
 /// Background task that bridges channel-based I/O with a remote PTY session.
 ///
 /// Handles input forwarding, output collection, resize events, and
 /// exit status detection without any terminal or signal management.
-#[allow(clippy::too_many_arguments)]
 async fn pty_io_task(
     mut channel: Channel<Msg>,
     mut input_rx: mpsc::Receiver<Vec<u8>>,
     output_tx: mpsc::Sender<Vec<u8>>,
     mut resize_rx: mpsc::Receiver<(u32, u32)>,
     exit_tx: watch::Sender<Option<PtyExitStatus>>,
-    mode_detection: Option<Arc<ModeDetection>>,
-    pwd_detection: Option<Arc<PwdDetection>>,
-    history: Option<Arc<pty_history::PtyHistory>>,
+    observers: PtyObservers,
+    #[cfg(feature = "recording")] mut recorder: Option<PtyRecorder>,
 ) -> Result<PtyExitStatus> {
     let status = loop {
         tokio::select! {
             res = input_rx.recv() => {
                 match res {
                     Some(data) => {
+                        #[cfg(feature = "recording")]
+                        if let Some(r) = recorder.as_mut() {
+                            r.input(&data);
+                        }
                         channel.data(&data[..]).await?;
                     }
                     None => {
@@ -443,14 +748,10 @@ async fn pty_io_task(
             msg = channel.wait() => {
                 match msg {
                     Some(ChannelMsg::Data { ref data }) => {
-                        if let Some(md) = mode_detection.as_ref() {
-                            md.feed(data);
-                        }
-                        if let Some(pd) = pwd_detection.as_ref() {
-                            pd.feed(data);
-                        }
-                        if let Some(h) = history.as_ref() {
-                            h.feed(data);
+                        observers.feed(data);
+                        #[cfg(feature = "recording")]
+                        if let Some(r) = recorder.as_mut() {
+                            r.output(data);
                         }
                         // If the receiver is dropped, we still continue
                         // to process channel messages for exit status
@@ -478,6 +779,11 @@ async fn pty_io_task(
                 }
             }
             Some((cols, rows)) = resize_rx.recv() => {
+                observers.resize(cols, rows);
+                #[cfg(feature = "recording")]
+                if let Some(r) = recorder.as_mut() {
+                    r.resize(cols, rows);
+                }
                 let _ = channel.window_change(cols, rows, 0, 0).await;
             }
         }
@@ -487,11 +793,16 @@ async fn pty_io_task(
     drain_remaining_output(
         &mut channel,
         &output_tx,
-        mode_detection.as_deref(),
-        pwd_detection.as_deref(),
-        history.as_deref(),
+        &observers,
+        #[cfg(feature = "recording")]
+        recorder.as_mut(),
     )
     .await;
+    #[cfg(feature = "recording")]
+    if let Some(r) = recorder {
+        // Make sure the recording is complete once the exit status is out.
+        let _ = tokio::task::spawn_blocking(move || r.finish()).await;
+    }
 
     let _ = exit_tx.send(Some(status.clone()));
     Ok(status)
@@ -504,23 +815,18 @@ async fn pty_io_task(
 async fn drain_remaining_output(
     channel: &mut Channel<Msg>,
     output_tx: &mpsc::Sender<Vec<u8>>,
-    mode_detection: Option<&ModeDetection>,
-    pwd_detection: Option<&PwdDetection>,
-    history: Option<&pty_history::PtyHistory>,
+    observers: &PtyObservers,
+    #[cfg(feature = "recording")] mut recorder: Option<&mut PtyRecorder>,
 ) {
     loop {
         tokio::select! {
             msg = channel.wait() => {
                 match msg {
                     Some(ChannelMsg::Data { ref data }) => {
-                        if let Some(md) = mode_detection {
-                            md.feed(data);
-                        }
-                        if let Some(pd) = pwd_detection {
-                            pd.feed(data);
-                        }
-                        if let Some(h) = history {
-                            h.feed(data);
+                        observers.feed(data);
+                        #[cfg(feature = "recording")]
+                        if let Some(r) = recorder.as_deref_mut() {
+                            r.output(data);
                         }
                         let _ = output_tx.send(data.to_vec()).await;
                     }
@@ -566,12 +872,28 @@ fn resolve_socket_addr(host: &str, port: u16, scope: Option<&str>) -> Result<Soc
     }
 }
 
+/// Opens the TCP transport and runs the SSH handshake.
+///
+/// The stream is wrapped so the server's KEXINIT is observed and the
+/// expected algorithms are recorded on the connection monitor.
+async fn connect_transport(
+    config: client::Config,
+    addr: SocketAddr,
+    connection: &Arc<ConnectionMonitor>,
+) -> Result<client::Handle<Client>> {
+    let stream = tokio::net::TcpStream::connect(addr).await?;
+    let stream = KexInitTap::new(stream, config.preferred.clone(), connection.clone());
+    let handle = client::connect_stream(Arc::new(config), stream, Client::new(connection)).await?;
+    Ok(handle)
+}
+
 /// An SSH session handle that provides methods for executing commands,
 /// transferring files, and managing interactive shells.
 ///
 /// Use [`Session::init`] to create a new session builder.
 pub struct Session {
     inner: SessionInner,
+    connection: Arc<ConnectionMonitor>,
 }
 
 impl<'sb> Session {
@@ -606,6 +928,10 @@ impl<'sb> Session {
             port: 22,
             scope: None,
             inactivity_timeout: Some(Duration::from_secs(3000)),
+            keepalive_interval: None,
+            keepalive_max: 3,
+            reconnect: None,
+            algorithms: AlgorithmPreferences::default(),
         }
     }
 
@@ -618,12 +944,86 @@ impl<'sb> Session {
     /// - The connection fails
     /// - Authentication fails
     pub async fn connect(self) -> Result<Self> {
-        match self.inner.connect().await {
-            Ok(res) => Ok(Session { inner: res }),
-            Err(e) => Err(e),
+        self.connection.update(ConnectionState::Connecting);
+        match self.inner.connect(&self.connection).await {
+            Ok(res) => {
+                self.connection.update(ConnectionState::Connected);
+                Ok(Session {
+                    inner: res,
+                    connection: self.connection,
+                })
+            }
+            Err(e) => {
+                self.connection.update(ConnectionState::Disconnected);
+                Err(e)
+            }
         }
     }
 
+    /// Re-establishes the SSH transport using the original authentication data.
+    ///
+    /// If a [`ReconnectPolicy`] was configured, up to `max_attempts` attempts
+    /// are made with exponential backoff in between; otherwise a single
+    /// attempt is made. Any previous transport is dropped. Open channels and
+    /// PTY handles of the old transport are not restored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if every attempt fails.
+    pub async fn reconnect(&mut self) -> Result<()> {
+        self.inner.reconnect(&self.connection).await
+    }
+
+    /// Returns true if the SSH transport is established and not closed.
+    pub fn is_connected(&mut self) -> bool {
+        self.inner
+            .get_session()
+            .as_ref()
+            .is_some_and(|s| !s.is_closed())
+    }
+
+    /// Returns the current connection state.
+    pub fn connection_state(&self) -> ConnectionState {
+        self.connection.current()
+    }
+
+    /// Returns the algorithms expected to be in use with the server.
+    ///
+    /// `russh` does not report the outcome of the key exchange, so these
+    /// are predicted from the server's offer and `russh`'s selection
+    /// rules; see [`ExpectedAlgorithms`].
+    ///
+    /// Returns `None` before the session is connected, or if the server's
+    /// key exchange offer could not be inspected.
+    pub fn expected_algorithms(&self) -> Option<ExpectedAlgorithms> {
+        self.connection.expected()
+    }
+
+    /// Creates a watcher for connection state events.
+    ///
+    /// Returns a `ConnectionWatcher` that can be used to await transitions
+    /// such as a dropped transport or a reconnect attempt.
+    pub fn watch_connection(&self) -> ConnectionWatcher {
+        self.connection.create_watcher()
+    }
+
+    /// Reconnects if the transport was lost and a [`ReconnectPolicy`] is set.
+    ///
+    /// Sessions that were never connected are left untouched so that the
+    /// caller gets the usual "No open session" error.
+    async fn ensure_connected(&mut self) -> Result<()> {
+        let lost = self
+            .inner
+            .get_session()
+            .as_ref()
+            .is_some_and(|s| s.is_closed());
+        if lost && self.inner.reconnect_policy().is_some() {
+            self.connection.update(ConnectionState::Disconnected);
+            self.reconnect().await?;
+        }
+        Ok(())
+    }
+
     /// Opens an interactive PTY (pseudo-terminal) session.
     ///
     /// This is useful for interactive shell sessions where you want
@@ -666,7 +1066,15 @@ impl<'sb> Session {
             terminal_modes: None,
             mode_detection_config: None,
             pwd_detection_config: None,
+            command_detection_config: None,
+            title_detection_config: None,
+            notification_detection_config: None,
+            clipboard: None,
             history_config: None,
+            #[cfg(feature = "screen")]
+            screen_config: None,
+            #[cfg(feature = "recording")]
+            recording_config: None,
         }
     }
 
@@ -682,6 +1090,7 @@ impl<'sb> Session {
     ///
     /// Returns an error if no connection is established.
     pub async fn run(&mut self) -> Result<u32> {
+        self.ensure_connected().await?;
         self.inner.exec(None, true, true).await
     }
 
@@ -699,6 +1108,7 @@ impl<'sb> Session {
     ///
     /// Returns an error if no connection is established.
     pub async fn exec(&mut self, command: &Vec<String>) -> Result<u32> {
+        self.ensure_connected().await?;
         self.inner.exec(Some(command), false, false).await
     }
 
@@ -717,6 +1127,7 @@ impl<'sb> Session {
     /// Returns an error if no connection is established.
     pub async fn system(&mut self, command: &str) -> Result<u32> {
         let sys_cmd = vec!["sh".to_string(), "-c".to_string(), command.to_string()];
+        self.ensure_connected().await?;
         self.inner.exec(Some(&sys_cmd), false, false).await
     }
 
@@ -734,6 +1145,7 @@ impl<'sb> Session {
     ///
     /// Returns an error if no connection is established.
     pub async fn cmd(&mut self, command: &str) -> Result<u32> {
+        self.ensure_connected().await?;
         self.inner.cmd(command, false, false).await
     }
 
@@ -751,7 +1163,283 @@ impl<'sb> Session {
     /// - The local file cannot be read
     /// - The transfer fails
     pub async fn scp(&mut self, from: &str, to: &str) -> Result<()> {
-        self.inner.scp(from, to).await
+        self.scp_with_options(from, to, &ScpOptions::default())
+            .await
+    }
+
+    /// Transfers a file or directory tree to the remote host via SCP.
+    ///
+    /// With [`ScpOptions::recursive`] set, a local directory is sent as a
+    /// whole over a single `scp -r` channel.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - Local file or directory path
+    /// * `to` - Remote destination path
+    /// * `options` - Transfer options
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - No connection is established
+    /// - `from` is a directory and recursion is not enabled
+    /// - A symlink is found and the policy is [`SymlinkPolicy::Error`]
+    /// - The transfer fails
+    pub async fn scp_with_options(
+        &mut self,
+        from: &str,
+        to: &str,
+        options: &ScpOptions,
+    ) -> Result<()> {
+        self.ensure_connected().await?;
+        self.inner.scp(from, to, options).await
+    }
+
+    /// Transfers a file from the remote host via SCP.
+    ///
+    /// If `to` is an existing local directory, the file is stored inside
+    /// it under its remote name. Timestamps sent by the remote side are
+    /// applied to the local file.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - Remote source path
+    /// * `to` - Local destination path
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - No connection is established
+    /// - The remote file does not exist or is a directory
+    /// - The local file cannot be written
+    /// - The transfer fails
+    pub async fn scp_download(&mut self, from: &str, to: &str) -> Result<()> {
+        self.scp_download_with_options(from, to, &ScpOptions::default())
+            .await
+    }
+
+    /// Transfers a file or directory tree from the remote host via SCP.
+    ///
+    /// With [`ScpOptions::recursive`] set, a remote directory is received
+    /// over a single `scp -r` channel. If `to` is an existing directory,
+    /// the tree is created inside it, otherwise `to` becomes its root.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - Remote source path
+    /// * `to` - Local destination path
+    /// * `options` - Transfer options
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - No connection is established
+    /// - The remote path is a directory and recursion is not enabled
+    /// - The local files cannot be written
+    /// - The transfer fails
+    pub async fn scp_download_with_options(
+        &mut self,
+        from: &str,
+        to: &str,
+        options: &ScpOptions,
+    ) -> Result<()> {
+        self.ensure_connected().await?;
+        self.inner.scp_download(from, to, options).await
+    }
+
+    /// Uploads the content of an async reader to a remote file via SCP.
+    ///
+    /// Exactly `size` bytes are read from `reader`; the SCP protocol needs
+    /// the size up front. Useful for generated content or streaming from
+    /// another source such as an HTTP response.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - Source of the file content
+    /// * `size` - Number of bytes to upload
+    /// * `remote_path` - Remote destination path
+    /// * `mode` - Permission bits of the remote file (e.g. `0o644`)
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - No connection is established
+    /// - The reader fails or ends before `size` bytes
+    /// - The transfer fails
+    pub async fn upload_reader<R: AsyncRead + Unpin>(
+        &mut self,
+        reader: R,
+        size: u64,
+        remote_path: &str,
+        mode: u32,
+    ) -> Result<()> {
+        self.ensure_connected().await?;
+        let options = ScpOptions::new().mode(mode);
+        self.inner
+            .upload_reader(reader, size, remote_path, &options)
+            .await
+    }
+
+    /// Uploads an in-memory buffer to a remote file via SCP.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The file content
+    /// * `remote_path` - Remote destination path
+    /// * `mode` - Permission bits of the remote file (e.g. `0o644`)
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no connection is established or the transfer
+    /// fails.
+    pub async fn upload_bytes(&mut self, data: &[u8], remote_path: &str, mode: u32) -> Result<()> {
+        self.upload_reader(data, data.len() as u64, remote_path, mode)
+            .await
+    }
+
+    /// Downloads a remote file via SCP into an async writer.
+    ///
+    /// # Arguments
+    ///
+    /// * `remote_path` - Remote source path
+    /// * `writer` - Destination for the file content
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes written.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - No connection is established
+    /// - The remote file does not exist or is a directory
+    /// - The writer fails
+    /// - The transfer fails
+    pub async fn download_writer<W: AsyncWrite + Unpin>(
+        &mut self,
+        remote_path: &str,
+        writer: &mut W,
+    ) -> Result<u64> {
+        self.ensure_connected().await?;
+        self.inner
+            .download_writer(remote_path, writer, &ScpOptions::default(), None)
+            .await
+    }
+
+    /// Opens an SFTP client on the `sftp` subsystem.
+    ///
+    /// The client runs on its own channel and stays usable while the
+    /// session is used for other work. See [`Sftp`] for the operations.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - No connection is established
+    /// - The server has no SFTP subsystem
+    /// - The version handshake fails
+    pub async fn sftp(&mut self) -> Result<Sftp> {
+        self.ensure_connected().await?;
+        self.inner.sftp().await
+    }
+
+    /// Uploads a file over SFTP, optionally resuming and in parallel.
+    ///
+    /// Unlike [`scp_with_options`](Self::scp_with_options) the transfer
+    /// keeps many requests in flight, can continue an interrupted upload
+    /// after checking the part already on the remote host and can split
+    /// the file into ranges sent over separate channels. See
+    /// [`SftpTransferOptions`].
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - Local source file
+    /// * `to` - Remote destination file or directory
+    /// * `options` - Transfer options
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - No connection is established
+    /// - The local file cannot be read or is not a regular file
+    /// - The server has no SFTP subsystem
+    /// - The remote file cannot be written
+    pub async fn sftp_upload(
+        &mut self,
+        from: &str,
+        to: &str,
+        options: &SftpTransferOptions,
+    ) -> Result<SftpTransferSummary> {
+        self.ensure_connected().await?;
+        self.inner.sftp_upload(from, to, options).await
+    }
+
+    /// Downloads a file over SFTP, optionally resuming and in parallel.
+    ///
+    /// The counterpart of [`sftp_upload`](Self::sftp_upload). When
+    /// resuming, an existing local file is checked against the start of
+    /// the remote one before the rest is fetched.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - Remote source file
+    /// * `to` - Local destination file or directory
+    /// * `options` - Transfer options
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - No connection is established
+    /// - The remote file does not exist or is a directory
+    /// - The server has no SFTP subsystem
+    /// - The local file cannot be written
+    pub async fn sftp_download(
+        &mut self,
+        from: &str,
+        to: &str,
+        options: &SftpTransferOptions,
+    ) -> Result<SftpTransferSummary> {
+        self.ensure_connected().await?;
+        self.inner.sftp_download(from, to, options).await
+    }
+
+    /// Brings a remote directory in line with a local one.
+    ///
+    /// The contents of `local` are compared with those of `remote` and
+    /// only new or changed files are uploaded over SFTP, keeping their
+    /// modes and modification times. Files are compared by size and
+    /// modification time, or by checksum, see [`SyncOptions`]. Remote
+    /// entries missing locally are deleted if requested. A missing
+    /// `remote` directory is created. Local symlinks and special files
+    /// are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `local` - Local source directory
+    /// * `remote` - Remote destination directory
+    /// * `options` - Sync options
+    ///
+    /// # Returns
+    ///
+    /// The changes made, or with [`SyncOptions::dry_run`] the changes that
+    /// would be made.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - No connection is established
+    /// - Either side is not a directory or cannot be read
+    /// - A path is a file on one side and a directory on the other
+    /// - An include or exclude pattern is invalid
+    /// - A transfer or deletion fails
+    #[cfg(feature = "sync")]
+    pub async fn sync_dir(
+        &mut self,
+        local: &str,
+        remote: &str,
+        options: &SyncOptions,
+    ) -> Result<SyncReport> {
+        self.ensure_connected().await?;
+        self.inner.sync_dir(local, remote, options).await
     }
 
     /// Closes the SSH session gracefully.
@@ -760,7 +1448,9 @@ impl<'sb> Session {
     ///
     /// Returns an error if the disconnect message fails to send.
     pub async fn close(&mut self) -> Result<()> {
-        self.inner.close().await
+        self.inner.close().await?;
+        self.connection.update(ConnectionState::Disconnected);
+        Ok(())
     }
 }
 
@@ -790,7 +1480,15 @@ pub struct PtyBuilder<'a> {
     terminal_modes: Option<Vec<(Pty, u32)>>,
     mode_detection_config: Option<pty_mode::ModeDetectionConfig>,
     pwd_detection_config: Option<pty_pwd::PwdDetectionConfig>,
+    command_detection_config: Option<pty_command::CommandDetectionConfig>,
+    title_detection_config: Option<pty_title::TitleDetectionConfig>,
+    notification_detection_config: Option<pty_notify::NotificationDetectionConfig>,
+    clipboard: Option<Arc<ClipboardDetection>>,
     history_config: Option<pty_history::PtyHistoryConfig>,
+    #[cfg(feature = "screen")]
+    screen_config: Option<pty_screen::ScreenConfig>,
+    #[cfg(feature = "recording")]
+    recording_config: Option<pty_record::PtyRecordingConfig>,
 }
 
 impl<'a> PtyBuilder<'a> {
@@ -866,7 +1564,8 @@ impl<'a> PtyBuilder<'a> {
     ///
     /// This enables detection of alternate screen buffer mode changes,
     /// allowing users to detect when full-screen applications like vim
-    /// or nano are running.
+    /// or nano are running, along with the other DEC modes tracked in
+    /// [`TerminalState`] such as bracketed paste and mouse reporting.
     pub fn with_mode_detection(mut self) -> Self {
         self.mode_detection_config = Some(pty_mode::ModeDetectionConfig {
             enabled: true,
@@ -911,6 +1610,113 @@ impl<'a> PtyBuilder<'a> {
         self
     }
 
+    /// Enables detection of command boundaries in the remote shell.
+    ///
+    /// Commands are delimited by OSC 133 (FinalTerm) or OSC 633 (VS Code)
+    /// shell integration marks and reported through
+    /// [`PtyHandle::watch_commands`].
+    ///
+    /// When `inject` is false, the remote shell must already emit the
+    /// marks. When `inject` is true, the library installs the prompt hooks
+    /// for bash and zsh itself, the same way
+    /// [`with_pwd_detection`](PtyBuilder::with_pwd_detection) does.
+    pub fn with_command_detection(mut self, inject: bool) -> Self {
+        self.command_detection_config = Some(pty_command::CommandDetectionConfig {
+            enabled: true,
+            inject,
+            ..pty_command::CommandDetectionConfig::default()
+        });
+        self
+    }
+
+    /// Enables command detection with custom config.
+    pub fn with_command_detection_config(
+        mut self,
+        mut config: pty_command::CommandDetectionConfig,
+    ) -> Self {
+        config.enabled = true;
+        self.command_detection_config = Some(config);
+        self
+    }
+
+    /// Enables window title detection.
+    ///
+    /// Tracks the title programs set with OSC 0, 1 and 2 escape
+    /// sequences; see [`PtyHandle::watch_title`].
+    pub fn with_title_detection(mut self) -> Self {
+        self.title_detection_config = Some(pty_title::TitleDetectionConfig {
+            enabled: true,
+            ..pty_title::TitleDetectionConfig::default()
+        });
+        self
+    }
+
+    /// Enables window title detection with custom config.
+    pub fn with_title_detection_config(
+        mut self,
+        mut config: pty_title::TitleDetectionConfig,
+    ) -> Self {
+        config.enabled = true;
+        self.title_detection_config = Some(config);
+        self
+    }
+
+    /// Enables bell and desktop notification detection.
+    ///
+    /// Reports BEL characters and OSC 9 / OSC 777 `notify` sequences; see
+    /// [`PtyHandle::watch_notifications`].
+    pub fn with_notification_detection(mut self) -> Self {
+        self.notification_detection_config = Some(pty_notify::NotificationDetectionConfig {
+            enabled: true,
+            ..pty_notify::NotificationDetectionConfig::default()
+        });
+        self
+    }
+
+    /// Enables bell and desktop notification detection with custom config.
+    pub fn with_notification_detection_config(
+        mut self,
+        mut config: pty_notify::NotificationDetectionConfig,
+    ) -> Self {
+        config.enabled = true;
+        self.notification_detection_config = Some(config);
+        self
+    }
+
+    /// Enables OSC 52 clipboard handling with the default policy.
+    ///
+    /// Programs may set the clipboard but not read it; see
+    /// [`ClipboardConfig`]. With clipboard handling enabled, [`run`](Self::run)
+    /// only passes permitted requests on to the local terminal.
+    pub fn with_clipboard(self) -> Self {
+        self.with_clipboard_config(pty_clipboard::ClipboardConfig::default())
+    }
+
+    /// Enables OSC 52 clipboard handling with a custom policy.
+    pub fn with_clipboard_config(mut self, mut config: pty_clipboard::ClipboardConfig) -> Self {
+        config.enabled = true;
+        self.clipboard = Some(Arc::new(ClipboardDetection::new(config)));
+        self
+    }
+
+    /// Creates a watcher for OSC 52 clipboard requests before the session
+    /// is opened, enabling clipboard handling with the default policy if
+    /// no policy was set.
+    ///
+    /// This is the way to observe requests in a session started with
+    /// [`run`](Self::run), for example to forward permitted writes to a
+    /// clipboard command instead of the terminal.
+    pub fn watch_clipboard(&mut self) -> ClipboardWatcher {
+        self.clipboard
+            .get_or_insert_with(|| {
+                Arc::new(ClipboardDetection::new(pty_clipboard::ClipboardConfig {
+                    enabled: true,
+                    ..pty_clipboard::ClipboardConfig::default()
+                }))
+            })
+            .create_watcher()
+    }
+
     /// Enables PTY history capture with custom configuration.
     ///
     /// Captures terminal output as scrollable history with configurable
@@ -945,6 +1751,66 @@ impl<'a> PtyBuilder<'a> {
         self
     }
 
+    /// Enables terminal emulation of the PTY output.
+    ///
+    /// The screen is kept up to date as output arrives and resized along
+    /// with the PTY, so [`PtyHandle::screen_snapshot`] always returns what
+    /// a terminal would show: cells with colours and attributes, the
+    /// cursor and the window title. Snapshots can be exported as text,
+    /// HTML or ANSI.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let handle = session.pty_builder().with_screen().open().await?;
+    /// handle.write(b"top\n").await?;
+    /// tokio::time::sleep(Duration::from_secs(1)).await;
+    ///
+    /// if let Some(screen) = handle.screen_snapshot() {
+    ///     std::fs::write("top.html", screen.to_html())?;
+    /// }
+    /// ```
+    #[cfg(feature = "screen")]
+    pub fn with_screen(mut self) -> Self {
+        self.screen_config = Some(pty_screen::ScreenConfig::default());
+        self
+    }
+
+    /// Enables terminal emulation with custom config.
+    #[cfg(feature = "screen")]
+    pub fn with_screen_config(mut self, config: pty_screen::ScreenConfig) -> Self {
+        self.screen_config = Some(config);
+        self
+    }
+
+    /// Records the session as an asciicast v2 stream into `writer`.
+    ///
+    /// Every output chunk is written with its time relative to the start,
+    /// together with the initial size and later resizes. Input is not
+    /// recorded; use [`with_recording_config`](PtyBuilder::with_recording_config)
+    /// to include it.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let file = std::io::BufWriter::new(std::fs::File::create("session.cast")?);
+    /// let handle = session.pty_builder()
+    ///     .with_recording(file)
+    ///     .open()
+    ///     .await?;
+    /// ```
+    #[cfg(feature = "recording")]
+    pub fn with_recording(self, writer: impl std::io::Write + Send + 'static) -> Self {
+        self.with_recording_config(pty_record::PtyRecordingConfig::new(writer))
+    }
+
+    /// Records the session as an asciicast v2 stream with custom config.
+    #[cfg(feature = "recording")]
+    pub fn with_recording_config(mut self, config: pty_record::PtyRecordingConfig) -> Self {
+        self.recording_config = Some(config);
+        self
+    }
+
     /// Opens a programmatic PTY session and returns a [`PtyHandle`].
     ///
     /// Unlike [`run()`](PtyBuilder::run), this does not manage stdin/stdout,
@@ -960,6 +1826,7 @@ impl<'a> PtyBuilder<'a> {
     /// Returns an error if no connection is established or the PTY
     /// request fails.
     pub async fn open(self) -> Result<PtyHandle> {
+        self.session.ensure_connected().await?;
         let command = self
             .command
             .unwrap_or_else(|| self.session.inner.get_command());
@@ -973,14 +1840,27 @@ impl<'a> PtyBuilder<'a> {
 
         let channel = sess.channel_open_session().await?;
 
-        // PWD injection: try set_env for PROMPT_COMMAND, then wrap exec command
+        // Shell integration injection: try set_env for PROMPT_COMMAND, then
+        // wrap exec command
         let inject_pwd = self.pwd_detection_config.as_ref().is_some_and(|c| c.inject);
+        let inject_commands = self
+            .command_detection_config
+            .as_ref()
+            .is_some_and(|c| c.inject);
 
-        let zsh_inject = if inject_pwd {
+        // The command hook reads `$?`, so it must run before the others
+        let mut prompt_cmds = Vec::new();
+        if inject_commands {
+            prompt_cmds.push(pty_command::osc133_prompt_command());
+        }
+        if inject_pwd {
+            prompt_cmds.extend(pty_pwd::osc7_prompt_command());
+        }
+        let prompt_cmd = prompt_cmds.join("; ");
+
+        let zsh_inject = if !prompt_cmd.is_empty() {
             // Layer 1: try set_env (works for bash if server AcceptEnv allows)
-            if let Some(prompt_cmd) = pty_pwd::osc7_prompt_command() {
-                let _ = channel.set_env(false, "PROMPT_COMMAND", &prompt_cmd).await;
-            }
+            let _ = channel.set_env(false, "PROMPT_COMMAND", &prompt_cmd).await;
             // Detect if zsh needs input injection instead of exec wrapping
             pty_pwd::detect_shell(&command) == pty_pwd::Shell::Zsh
         } else {
@@ -988,8 +1868,13 @@ impl<'a> PtyBuilder<'a> {
         };
 
         // Layer 2: wrap exec command with shell-specific setup (not for zsh)
-        let command = if inject_pwd && !zsh_inject {
-            match pty_pwd::osc7_injection_snippet(&command) {
+        let command = if !prompt_cmd.is_empty() && !zsh_inject {
+            let snippet = if inject_commands {
+                pty_pwd::prompt_command_snippet(&command, &prompt_cmd)
+            } else {
+                pty_pwd::osc7_injection_snippet(&command)
+            };
+            match snippet {
                 Some(snippet) => format!("{} {}", snippet, command),
                 None => command,
             }
@@ -1009,9 +1894,15 @@ impl<'a> PtyBuilder<'a> {
 
         // Zsh injection: send precmd hook setup as initial input
         if zsh_inject {
-            let setup =
-                "precmd(){printf '\\033]7;file://%s%s\\a' \"$HOST\" \"$PWD\"};precmd;clear\n";
-            input_tx.send(setup.as_bytes().to_vec()).await.ok();
+            if inject_commands {
+                let setup = pty_command::osc133_zsh_setup();
+                input_tx.send(setup.as_bytes().to_vec()).await.ok();
+            }
+            if inject_pwd {
+                let setup =
+                    "precmd(){printf '\\033]7;file://%s%s\\a' \"$HOST\" \"$PWD\"};precmd;clear\n";
+                input_tx.send(setup.as_bytes().to_vec()).await.ok();
+            }
         }
 
         let mode_detection: Option<Arc<ModeDetection>> = self
@@ -1022,20 +1913,56 @@ impl<'a> PtyBuilder<'a> {
             .pwd_detection_config
             .map(|config| Arc::new(PwdDetection::new(config)));
 
+        let command_detection: Option<Arc<CommandDetection>> = self
+            .command_detection_config
+            .map(|config| Arc::new(CommandDetection::new(config)));
+
+        #[cfg(feature = "screen")]
+        let screen: Option<Arc<PtyScreen>> = self
+            .screen_config
+            .map(|config| Arc::new(PtyScreen::new(config, self.width, self.height)));
+
+        let title_detection: Option<Arc<TitleDetection>> = self
+            .title_detection_config
+            .map(|config| Arc::new(TitleDetection::new(config)));
+
+        let notification_detection: Option<Arc<NotificationDetection>> = self
+            .notification_detection_config
+            .map(|config| Arc::new(NotificationDetection::new(config)));
+
+        let clipboard = self.clipboard;
+
         let history: Option<Arc<pty_history::PtyHistory>> = self
             .history_config
             .filter(|c| c.enabled)
             .map(|config| Arc::new(pty_history::PtyHistory::new(config)));
 
+        #[cfg(feature = "recording")]
+        let recorder = self
+            .recording_config
+            .map(|config| PtyRecorder::new(config, self.width, self.height, &self.term))
+            .transpose()
+            .map_err(|e| anyhow!("Failed to start PTY recording: {}", e))?;
+
         let task_handle = tokio::spawn(pty_io_task(
             channel,
             input_rx,
             output_tx,
             resize_rx,
             exit_tx,
-            mode_detection.clone(),
-            pwd_detection.clone(),
-            history.clone(),
+            PtyObservers {
+                mode_detection: mode_detection.clone(),
+                pwd_detection: pwd_detection.clone(),
+                command_detection: command_detection.clone(),
+                history: history.clone(),
+                #[cfg(feature = "screen")]
+                screen: screen.clone(),
+                title_detection: title_detection.clone(),
+                notification_detection: notification_detection.clone(),
+                clipboard: clipboard.clone(),
+            },
+            #[cfg(feature = "recording")]
+            recorder,
         ));
 
         Ok(PtyHandle {
@@ -1047,7 +1974,13 @@ impl<'a> PtyBuilder<'a> {
             closed: false,
             mode_detection,
             pwd_detection,
+            command_detection,
             history,
+            #[cfg(feature = "screen")]
+            screen,
+            title_detection,
+            notification_detection,
+            clipboard,
         })
     }
 
@@ -1067,6 +2000,10 @@ impl<'a> PtyBuilder<'a> {
     pub async fn run(self) -> Result<u32> {
         let raw_mode = self.raw_mode;
         let auto_resize = self.auto_resize;
+        let mut clipboard_filter = self
+            .clipboard
+            .as_ref()
+            .map(|cd| ClipboardFilter::new(cd.config().clone()));
 
         let mut handle = self.open().await?;
 
@@ -1119,6 +2056,10 @@ impl<'a> PtyBuilder<'a> {
                 data = handle.read() => {
                     match data {
                         Some(bytes) => {
+                            let bytes = match clipboard_filter.as_mut() {
+                                Some(filter) => filter.filter(&bytes),
+                                None => bytes,
+                            };
                             stdout.write_all(&bytes).await?;
                             stdout.flush().await?;
                         }
@@ -1192,6 +2133,10 @@ pub struct SessionBuilder<'sb> {
     port: u16,
     scope: Option<String>,
     inactivity_timeout: Option<Duration>,
+    keepalive_interval: Option<Duration>,
+    keepalive_max: usize,
+    reconnect: Option<ReconnectPolicy>,
+    algorithms: AlgorithmPreferences,
 }
 
 impl<'sb> SessionBuilder<'sb> {
@@ -1323,18 +2268,142 @@ impl<'sb> SessionBuilder<'sb> {
         self
     }
 
+    /// Sets the keepalive interval for the SSH connection.
+    ///
+    /// If nothing is received from the server for this long, a keepalive
+    /// request is sent. This keeps NAT mappings alive and detects dead
+    /// peers. Set to `None` to disable keepalives (default).
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - Optional duration between keepalives
+    pub fn with_keepalive_interval(mut self, interval: Option<Duration>) -> Self {
+        self.keepalive_interval = interval;
+        self
+    }
+
+    /// Sets how many keepalives may go unanswered before the connection
+    /// is considered dead and closed.
+    ///
+    /// # Arguments
+    ///
+    /// * `max` - Maximum number of missed keepalives (default: 3)
+    pub fn with_keepalive_max(mut self, max: usize) -> Self {
+        self.keepalive_max = max;
+        self
+    }
+
+    /// Enables automatic reconnection with the given policy.
+    ///
+    /// When the transport is found to be closed at the start of an
+    /// operation, the session re-establishes it using the original
+    /// authentication data before continuing.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - Backoff and attempt limits for reconnecting
+    pub fn with_reconnect(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect = Some(policy);
+        self
+    }
+
+    /// Sets the key exchange algorithms to offer, in order of preference.
+    ///
+    /// Only the listed algorithms are offered. Useful to reach legacy
+    /// devices (e.g. `diffie-hellman-group14-sha1`) or to enforce a
+    /// hardened policy.
+    ///
+    /// # Arguments
+    ///
+    /// * `names` - SSH algorithm names such as `curve25519-sha256`
+    pub fn with_kex_algorithms<S: Into<String>>(
+        mut self,
+        names: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.algorithms.kex = Some(names.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Sets the host key algorithms to accept, in order of preference.
+    ///
+    /// # Arguments
+    ///
+    /// * `names` - SSH algorithm names such as `ssh-ed25519` or `rsa-sha2-256`
+    pub fn with_host_key_algorithms<S: Into<String>>(
+        mut self,
+        names: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.algorithms.host_key = Some(names.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Sets the ciphers to offer, in order of preference.
+    ///
+    /// # Arguments
+    ///
+    /// * `names` - SSH cipher names such as `aes256-gcm@openssh.com`
+    pub fn with_ciphers<S: Into<String>>(mut self, names: impl IntoIterator<Item = S>) -> Self {
+        self.algorithms.cipher = Some(names.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Sets the MAC algorithms to offer, in order of preference.
+    ///
+    /// # Arguments
+    ///
+    /// * `names` - SSH MAC names such as `hmac-sha2-256-etm@openssh.com`
+    pub fn with_macs<S: Into<String>>(mut self, names: impl IntoIterator<Item = S>) -> Self {
+        self.algorithms.mac = Some(names.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Removes the given algorithms from every offered list.
+    ///
+    /// Applied after the preference lists, so it also narrows the
+    /// defaults when no explicit list is set.
+    ///
+    /// # Arguments
+    ///
+    /// * `names` - SSH algorithm names to never offer
+    pub fn with_denied_algorithms<S: Into<String>>(
+        mut self,
+        names: impl IntoIterator<Item = S>,
+    ) -> Self {
+        self.algorithms.denied = names.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Enables or disables zlib compression.
+    ///
+    /// When enabled, `zlib@openssh.com` and `zlib` are preferred over no
+    /// compression, which helps on slow links with compressible data.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether compression should be negotiated
+    pub fn with_compression(mut self, enabled: bool) -> Self {
+        self.algorithms.compression = Some(enabled);
+        self
+    }
+
     /// Builds the [`Session`] with the configured settings.
     ///
     /// # Returns
     ///
     /// A [`Result`] containing the configured [`Session`] or an error.
     ///
+    /// # Errors
+    ///
+    /// Returns an error if an algorithm name is not supported or an
+    /// algorithm list is empty after applying the denied algorithms.
+    ///
     /// # Authentication Priority
     ///
     /// 1. If a key is provided, use public key authentication
     /// 2. If a password is provided, use password authentication
     /// 3. Otherwise, use no authentication (none)
     pub fn build(self) -> Result<Session> {
+        let preferred = self.algorithms.to_preferred()?;
         if let Some(key) = self.key {
             Ok(Session {
                 inner: SessionInner::PubKey {
@@ -1348,8 +2417,13 @@ impl<'sb> SessionBuilder<'sb> {
                         key,
                         scope: self.scope,
                         inactivity_timeout: self.inactivity_timeout,
+                        keepalive_interval: self.keepalive_interval,
+                        keepalive_max: self.keepalive_max,
+                        reconnect: self.reconnect,
+                        preferred,
                     },
                 },
+                connection: Arc::new(ConnectionMonitor::new()),
             })
         } else if let Some(passwd) = self.passwd {
             Ok(Session {
@@ -1363,8 +2437,13 @@ impl<'sb> SessionBuilder<'sb> {
                         passwd,
                         scope: self.scope,
                         inactivity_timeout: self.inactivity_timeout,
+                        keepalive_interval: self.keepalive_interval,
+                        keepalive_max: self.keepalive_max,
+                        reconnect: self.reconnect,
+                        preferred,
                     },
                 },
+                connection: Arc::new(ConnectionMonitor::new()),
             })
         } else {
             Ok(Session {
@@ -1377,8 +2456,13 @@ impl<'sb> SessionBuilder<'sb> {
                         port: self.port,
                         scope: self.scope,
                         inactivity_timeout: self.inactivity_timeout,
+                        keepalive_interval: self.keepalive_interval,
+                        keepalive_max: self.keepalive_max,
+                        reconnect: self.reconnect,
+                        preferred,
                     },
                 },
+                connection: Arc::new(ConnectionMonitor::new()),
             })
         }
     }
@@ -1386,8 +2470,22 @@ impl<'sb> SessionBuilder<'sb> {
 
 /// Internal SSH client handler that implements the russh client trait.
 ///
-/// Currently accepts all server keys without verification.
-struct Client;
+/// Currently accepts all server keys without verification. Reports a
+/// lost transport to the session's connection monitor.
+struct Client {
+    connection: Arc<ConnectionMonitor>,
+    generation: u64,
+}
+
+impl Client {
+    /// Creates a handler for a new transport generation.
+    fn new(connection: &Arc<ConnectionMonitor>) -> Self {
+        Client {
+            connection: connection.clone(),
+            generation: connection.next_generation(),
+        }
+    }
+}
 
 impl client::Handler for Client {
     type Error = russh::Error;
@@ -1398,6 +2496,17 @@ impl client::Handler for Client {
     ) -> Result<bool, Self::Error> {
         Ok(true)
     }
+
+    async fn disconnected(
+        &mut self,
+        reason: client::DisconnectReason<Self::Error>,
+    ) -> Result<(), Self::Error> {
+        self.connection.transport_lost(self.generation);
+        match reason {
+            client::DisconnectReason::ReceivedDisconnect(_) => Ok(()),
+            client::DisconnectReason::Error(e) => Err(e),
+        }
+    }
 }
 
 /// Session data for password authentication.
@@ -1417,6 +2526,14 @@ struct SessionDataPasswd {
     scope: Option<String>,
     /// Inactivity timeout duration.
     inactivity_timeout: Option<Duration>,
+    /// Interval between keepalive requests.
+    keepalive_interval: Option<Duration>,
+    /// Missed keepalives before the connection is closed.
+    keepalive_max: usize,
+    /// Algorithms offered during the key exchange.
+    preferred: Preferred,
+    /// Automatic reconnect policy.
+    reconnect: Option<ReconnectPolicy>,
 }
 
 /// Session data for public key authentication.
@@ -1438,6 +2555,14 @@ struct SessionDataPubKey {
     scope: Option<String>,
     /// Inactivity timeout duration.
     inactivity_timeout: Option<Duration>,
+    /// Interval between keepalive requests.
+    keepalive_interval: Option<Duration>,
+    /// Missed keepalives before the connection is closed.
+    keepalive_max: usize,
+    /// Algorithms offered during the key exchange.
+    preferred: Preferred,
+    /// Automatic reconnect policy.
+    reconnect: Option<ReconnectPolicy>,
 }
 
 /// Session data for no authentication (none auth).
@@ -1455,6 +2580,14 @@ struct SessionDataNoAuth {
     scope: Option<String>,
     /// Inactivity timeout duration.
     inactivity_timeout: Option<Duration>,
+    /// Interval between keepalive requests.
+    keepalive_interval: Option<Duration>,
+    /// Missed keepalives before the connection is closed.
+    keepalive_max: usize,
+    /// Algorithms offered during the key exchange.
+    preferred: Preferred,
+    /// Automatic reconnect policy.
+    reconnect: Option<ReconnectPolicy>,
 }
 
 /// Internal representation of an SSH session.
@@ -1484,38 +2617,187 @@ enum SessionInner {
     },
 }
 
-impl SessionInner {
-    async fn connect(self) -> Result<Self> {
-        match self {
-            Self::Passwd {
-                data: _,
-                session: _,
-            } => self.connect_passwd().await,
-            Self::PubKey {
-                data: _,
-                session: _,
-            } => self.connect_key().await,
-            Self::NoAuth {
-                data: _,
-                session: _,
-            } => self.connect_noauth().await,
-        }
+impl SessionInner {
+    async fn connect(self, connection: &Arc<ConnectionMonitor>) -> Result<Self> {
+        match self {
+            Self::Passwd {
+                data: _,
+                session: _,
+            } => self.connect_passwd(connection).await,
+            Self::PubKey {
+                data: _,
+                session: _,
+            } => self.connect_key(connection).await,
+            Self::NoAuth {
+                data: _,
+                session: _,
+            } => self.connect_noauth(connection).await,
+        }
+    }
+
+    /// Replaces the transport with a freshly authenticated one.
+    ///
+    /// Makes a single attempt unless a reconnect policy is configured.
+    async fn reconnect(&mut self, connection: &Arc<ConnectionMonitor>) -> Result<()> {
+        let policy = self
+            .reconnect_policy()
+            .cloned()
+            .unwrap_or_else(|| ReconnectPolicy::new().max_attempts(1));
+
+        let mut last_err = None;
+        for attempt in 1..=policy.max_attempts {
+            connection.update(ConnectionState::Reconnecting { attempt });
+            info!("Reconnect attempt {}/{}", attempt, policy.max_attempts);
+
+            match self.disconnected_clone().connect(connection).await {
+                Ok(inner) => {
+                    *self = inner;
+                    connection.update(ConnectionState::Connected);
+                    return Ok(());
+                }
+                Err(e) => {
+                    debug!("Reconnect attempt {} failed: {}", attempt, e);
+                    last_err = Some(e);
+                }
+            }
+
+            if attempt < policy.max_attempts {
+                tokio::time::sleep(policy.backoff(attempt)).await;
+            }
+        }
+
+        connection.update(ConnectionState::Failed);
+        Err(anyhow!(
+            "Reconnect failed after {} attempt(s): {}",
+            policy.max_attempts,
+            last_err.map(|e| e.to_string()).unwrap_or_default()
+        ))
+    }
+
+    fn reconnect_policy(&self) -> Option<&ReconnectPolicy> {
+        match self {
+            Self::Passwd { data, session: _ } => data.reconnect.as_ref(),
+            Self::PubKey { data, session: _ } => data.reconnect.as_ref(),
+            Self::NoAuth { data, session: _ } => data.reconnect.as_ref(),
+        }
+    }
+
+    /// Returns a copy of the configuration without a transport.
+    fn disconnected_clone(&self) -> Self {
+        match self {
+            Self::Passwd { data, session: _ } => Self::Passwd {
+                data: data.clone(),
+                session: None,
+            },
+            Self::PubKey { data, session: _ } => Self::PubKey {
+                data: data.clone(),
+                session: None,
+            },
+            Self::NoAuth { data, session: _ } => Self::NoAuth {
+                data: data.clone(),
+                session: None,
+            },
+        }
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        let Some(sess) = self.get_session() else {
+            return Ok(());
+        };
+
+        close_session(sess).await
+    }
+
+    async fn scp(&mut self, from: &str, to: &str, options: &ScpOptions) -> Result<()> {
+        let Some(sess) = self.get_session() else {
+            return Err(Error::msg("No open session"));
+        };
+
+        return scp::scp(sess, from, to, options).await;
+    }
+
+    async fn scp_download(&mut self, from: &str, to: &str, options: &ScpOptions) -> Result<()> {
+        let Some(sess) = self.get_session() else {
+            return Err(Error::msg("No open session"));
+        };
+
+        scp::scp_download(sess, from, to, options).await
+    }
+
+    async fn upload_reader<R: AsyncRead + Unpin>(
+        &mut self,
+        reader: R,
+        size: u64,
+        to: &str,
+        options: &ScpOptions,
+    ) -> Result<()> {
+        let Some(sess) = self.get_session() else {
+            return Err(Error::msg("No open session"));
+        };
+
+        scp::scp_upload_reader(sess, reader, size, to, options).await
+    }
+
+    async fn download_writer<W: AsyncWrite + Unpin>(
+        &mut self,
+        from: &str,
+        writer: &mut W,
+        options: &ScpOptions,
+        announce: Option<&FileHeaderSender>,
+    ) -> Result<u64> {
+        let Some(sess) = self.get_session() else {
+            return Err(Error::msg("No open session"));
+        };
+
+        scp::scp_download_writer(sess, from, writer, options, announce).await
     }
 
-    async fn close(&mut self) -> Result<()> {
+    async fn sftp(&mut self) -> Result<Sftp> {
         let Some(sess) = self.get_session() else {
-            return Ok(());
+            return Err(Error::msg("No open session"));
         };
 
-        close_session(sess).await
+        sftp::sftp_open(sess).await
     }
 
-    async fn scp(&mut self, from: &str, to: &str) -> Result<()> {
+    async fn sftp_upload(
+        &mut self,
+        from: &str,
+        to: &str,
+        options: &SftpTransferOptions,
+    ) -> Result<SftpTransferSummary> {
+        let Some(sess) = self.get_session() else {
+            return Err(Error::msg("No open session"));
+        };
+
+        sftp_transfer::upload(sess, from, to, options).await
+    }
+
+    async fn sftp_download(
+        &mut self,
+        from: &str,
+        to: &str,
+        options: &SftpTransferOptions,
+    ) -> Result<SftpTransferSummary> {
         let Some(sess) = self.get_session() else {
             return Err(Error::msg("No open session"));
         };
 
-        return scp(sess, from, to).await;
+        sftp_transfer::download(sess, from, to, options).await
+    }
+
+    #[cfg(feature = "sync")]
+    async fn sync_dir(
+        &mut self,
+        local: &str,
+        remote: &str,
+        options: &SyncOptions,
+    ) -> Result<SyncReport> {
+        let Some(sess) = self.get_session() else {
+            return Err(Error::msg("No open session"));
+        };
+
+        sync::sync_dir(sess, local, remote, options).await
     }
 
     async fn exec(&mut self, command: Option<&Vec<String>>, err: bool, out: bool) -> Result<u32> {
@@ -1561,16 +2843,17 @@ impl SessionInner {
             .join(" ")
     }
 
-    async fn connect_noauth(self) -> Result<Self> {
+    async fn connect_noauth(self, connection: &Arc<ConnectionMonitor>) -> Result<Self> {
         if let Self::NoAuth { data, session: _ } = self {
             let config = client::Config {
                 inactivity_timeout: data.inactivity_timeout,
+                keepalive_interval: data.keepalive_interval,
+                keepalive_max: data.keepalive_max,
+                preferred: data.preferred.clone(),
                 ..<_>::default()
             };
-            let config = Arc::new(config);
-            let sh = Client {};
-            let addrs = resolve_socket_addr(&data.host, data.port, data.scope.as_deref())?;
-            let mut session = client::connect(config, addrs, sh).await?;
+            let addr = resolve_socket_addr(&data.host, data.port, data.scope.as_deref())?;
+            let mut session = connect_transport(config, addr, connection).await?;
 
             info!(
                 "Connecting using password {}@{}:{}",
@@ -1590,16 +2873,17 @@ impl SessionInner {
         Err(Error::msg("connect_noauth called on non Session::NoAuth"))
     }
 
-    async fn connect_passwd(self) -> Result<Self> {
+    async fn connect_passwd(self, connection: &Arc<ConnectionMonitor>) -> Result<Self> {
         if let Self::Passwd { data, session: _ } = self {
             let config = client::Config {
                 inactivity_timeout: data.inactivity_timeout,
+                keepalive_interval: data.keepalive_interval,
+                keepalive_max: data.keepalive_max,
+                preferred: data.preferred.clone(),
                 ..<_>::default()
             };
-            let config = Arc::new(config);
-            let sh = Client {};
-            let addrs = resolve_socket_addr(&data.host, data.port, data.scope.as_deref())?;
-            let mut session = client::connect(config, addrs, sh).await?;
+            let addr = resolve_socket_addr(&data.host, data.port, data.scope.as_deref())?;
+            let mut session = connect_transport(config, addr, connection).await?;
 
             info!(
                 "Connecting using password {}@{}:{}",
@@ -1621,7 +2905,8 @@ impl SessionInner {
         Err(Error::msg("connect_passwd called on non Session::Passwd"))
     }
 
-    async fn connect_key(self) -> Result<Self> {
+    #[allow(clippy::unnecessary_unwrap)]
+    async fn connect_key(self, connection: &Arc<ConnectionMonitor>) -> Result<Self> {
         if let Self::PubKey { data, session: _ } = self {
             let key_pair = load_secret_key(data.key.clone(), None)?;
 
@@ -1633,13 +2918,14 @@ impl SessionInner {
 
             let config = client::Config {
                 inactivity_timeout: data.inactivity_timeout,
+                keepalive_interval: data.keepalive_interval,
+                keepalive_max: data.keepalive_max,
+                preferred: data.preferred.clone(),
                 ..<_>::default()
             };
 
-            let config = Arc::new(config);
-            let sh = Client {};
-            let addrs = resolve_socket_addr(&data.host, data.port, data.scope.as_deref())?;
-            let mut session = client::connect(config, addrs, sh).await?;
+            let addr = resolve_socket_addr(&data.host, data.port, data.scope.as_deref())?;
+            let mut session = connect_transport(config, addr, connection).await?;
 
             info!(
                 "Connecting using public key {}@{}:{}",
@@ -1759,6 +3045,7 @@ async fn close_session(session: &mut client::Handle<Client>) -> Result<()> {
 /// # Returns
 ///
 /// The exit code of the command.
+#[allow(clippy::collapsible_match)]
 async fn system(
     session: &mut client::Handle<Client>,
     command: &str,
@@ -1799,247 +3086,6 @@ async fn system(
     code.ok_or(Error::msg("program did not exit cleanly"))
 }
 
-/// Waits for data from an SSH channel.
-///
-/// # Arguments
-///
-/// * `channel` - The SSH channel
-///
-/// # Returns
-///
-/// The received data as a byte vector.
-async fn wait_for_data(channel: &mut Channel<Msg>) -> Result<Vec<u8>> {
-    loop {
-        match channel.wait().await {
-            Some(ChannelMsg::Data { ref data }) => {
-                return Ok(data.to_vec());
-            }
-            Some(ChannelMsg::ExtendedData { ref data, ext: 1 }) => {
-                return Err(Error::msg(format!(
-                    "SCP error: {}",
-                    String::from_utf8_lossy(data)
-                )));
-            }
-            Some(ChannelMsg::ExitStatus { exit_status }) => {
-                return Err(Error::msg(format!(
-                    "SCP failed with exit code {}",
-                    exit_status
-                )));
-            }
-            Some(_) => {
-                // Ignore other messages and continue waiting
-            }
-            None => {
-                // Channel closed unexpectedly
-                return Err(Error::msg("Channel closed unexpectedly"));
-            }
-        }
-    }
-}
-
-/// Initial state for SCP file transfer.
-struct SCPStateOpen {
-    channel: Channel<Msg>,
-}
-
-impl SCPStateOpen {
-    /// Initiates a file transfer to the remote path.
-    ///
-    /// # Arguments
-    ///
-    /// * `remote_path` - The destination path on the remote host
-    async fn start_tx(mut self, remote_path: &str) -> Result<SCPStateTxStart> {
-        let command = format!("scp -t {}", remote_path);
-        self.channel.exec(true, command).await?;
-        // TODO "cat > {}" is an alternative here if the target has no scp
-
-        let data = wait_for_data(&mut self.channel).await?;
-        if data[0] != 0 {
-            return Err(Error::msg(format!("SCP start failed: {:?}", data)));
-        }
-
-        Ok(SCPStateTxStart {
-            channel: self.channel,
-        })
-    }
-}
-
-/// Opens a new channel for SCP file transfer.
-///
-/// # Arguments
-///
-/// * `session` - The SSH session handle
-async fn scp_channel_open(session: &mut client::Handle<Client>) -> Result<SCPStateOpen> {
-    let res = session.channel_open_session().await;
-
-    match res {
-        Err(e) => Err(Error::msg(e.to_string())),
-        Ok(channel) => Ok(SCPStateOpen { channel }),
-    }
-}
-
-/// State for sending file metadata during SCP transfer.
-struct SCPStateTxStart {
-    channel: Channel<Msg>,
-}
-
-impl SCPStateTxStart {
-    /// Writes file metadata to initiate the transfer.
-    ///
-    /// # Arguments
-    ///
-    /// * `file_size` - Size of the file in bytes
-    /// * `file_name` - Name of the file
-    async fn write_metadata(mut self, file_size: u64, file_name: &str) -> Result<SCPStateTxData> {
-        let metadata_msg = format!("C0644 {} {}\n", file_size, file_name);
-        self.channel.data(metadata_msg.as_bytes()).await?;
-
-        let data = wait_for_data(&mut self.channel).await?;
-        if data[0] != 0 {
-            return Err(Error::msg(format!("SCP confirmation failed: {:?}", data)));
-        }
-
-        Ok(SCPStateTxData {
-            channel: self.channel,
-        })
-    }
-}
-
-/// State for sending file data during SCP transfer.
-struct SCPStateTxData {
-    channel: Channel<Msg>,
-}
-
-impl SCPStateTxData {
-    /// Writes a chunk of file data.
-    ///
-    /// # Arguments
-    ///
-    /// * `buf` - Buffer containing file data
-    async fn write_data(&mut self, buf: &[u8]) -> Result<()> {
-        self.channel.data(buf).await?;
-        Ok(())
-    }
-
-    /// Signals end of file and completes the transfer.
-    async fn eof(mut self) -> Result<SCPStateEOF> {
-        self.channel.data(&b"\0"[..]).await?;
-        let data = wait_for_data(&mut self.channel).await?;
-        if data[0] != 0 {
-            return Err(Error::msg(format!(
-                "SCP post-data confirmation failed: {:?}",
-                data
-            )));
-        }
-        self.channel.eof().await?;
-
-        Ok(SCPStateEOF {
-            channel: self.channel,
-        })
-    }
-}
-
-/// Final state for SCP file transfer.
-struct SCPStateEOF {
-    channel: Channel<Msg>,
-}
-
-impl SCPStateEOF {
-    /// Closes the SCP channel.
-    async fn close(self) -> Result<()> {
-        self.channel.close().await?;
-        Ok(())
-    }
-}
-
-/// Transfers a file to the remote host using SCP protocol.
-///
-/// # Arguments
-///
-/// * `session` - The SSH session handle
-/// * `local_path` - Path to the local file
-/// * `remote_path` - Destination path on the remote host
-async fn scp(
-    session: &mut client::Handle<Client>,
-    local_path: &str,
-    remote_path: &str,
-) -> Result<()> {
-    let file = File::open(local_path).await?;
-    let state = scp_channel_open(session).await?;
-    let state = state.start_tx(remote_path).await?;
-
-    // Get file size and name
-    let metadata = file.metadata().await?;
-    let file_size = metadata.len();
-    let file_name = std::path::Path::new(remote_path)
-        .file_name()
-        .ok_or_else(|| anyhow!("Invalid file name"))?
-        .to_string_lossy();
-
-    let mut state = state.write_metadata(file_size, &file_name).await?;
-
-    const WRITE_TIMEOUT: Duration = Duration::from_secs(16);
-    let mut buffer = [0u8; 16 * 1024];
-    let mut reader = file;
-
-    let mut eof_reached = false;
-
-    loop {
-        tokio::select! {
-            // Read from file and send data
-            result = reader.read(&mut buffer), if !eof_reached => {
-                match result {
-                    Ok(0) => {
-                        // EOF reached, mark it but continue processing channel messages
-                        eof_reached = true;
-                    }
-                    Ok(n) => {
-                        debug!("Writing {} bytes to {}", n, remote_path);
-                        // Apply timeout only to the write operation
-                        timeout(WRITE_TIMEOUT, state.write_data(&buffer[..n]))
-                            .await
-                            .map_err(|_| anyhow!("Write timed out after {:?}", WRITE_TIMEOUT))??;
-                    }
-                    Err(e) => return Err(e.into()),
-                }
-            }
-
-            // Handle SSH channel messages (window adjust, errors, etc.)
-            msg = state.channel.wait() => {
-                match msg {
-                    Some(ChannelMsg::ExtendedData { data, ext: 1 }) => {
-                        return Err(anyhow!(
-                            "Remote SCP error: {}",
-                            String::from_utf8_lossy(&data)
-                        ));
-                    }
-                    Some(ChannelMsg::ExitStatus { exit_status }) => {
-                        return Err(anyhow!(
-                            "Remote SCP exited early with code: {}",
-                            exit_status
-                        ));
-                    }
-                    Some(_) => {
-                        // Window adjust, keepalive, or other protocol messages - ignore
-                    }
-                    None => {
-                        // Channel closed unexpectedly
-                        return Err(anyhow!("Channel closed during transfer"));
-                    }
-                }
-            }
-        }
-
-        // Exit the loop after EOF and all pending operations complete
-        if eof_reached {
-            break;
-        }
-    }
-
-    let state = state.eof().await?;
-    state.close().await
-}
-
 #[tokio::test]
 async fn test_session_builder() {
     let session = Session::init()
@@ -2197,6 +3243,188 @@ async fn test_session_builder_with_timeout_disabled() {
     }
 }
 
+#[tokio::test]
+async fn test_session_builder_with_keepalive() {
+    let session = Session::init()
+        .with_host("localhost")
+        .with_user("user")
+        .with_keepalive_interval(Some(Duration::from_secs(15)))
+        .with_keepalive_max(5)
+        .with_passwd("pass")
+        .build();
+
+    assert!(session.is_ok());
+
+    let session = session.unwrap();
+    if let SessionInner::Passwd { data, .. } = session.inner {
+        assert_eq!(data.keepalive_interval, Some(Duration::from_secs(15)));
+        assert_eq!(data.keepalive_max, 5);
+        assert!(data.reconnect.is_none());
+    } else {
+        panic!("Expected Passwd session.");
+    }
+}
+
+#[tokio::test]
+async fn test_session_builder_with_algorithms() {
+    let session = Session::init()
+        .with_host("localhost")
+        .with_user("user")
+        .with_kex_algorithms(["diffie-hellman-group14-sha1"])
+        .with_host_key_algorithms(["ssh-rsa"])
+        .with_ciphers(["aes128-cbc", "aes128-ctr"])
+        .with_macs(["hmac-sha1"])
+        .with_compression(true)
+        .with_passwd("pass")
+        .build()
+        .unwrap();
+
+    assert!(session.expected_algorithms().is_none());
+    if let SessionInner::Passwd { data, .. } = session.inner {
+        assert_eq!(data.preferred.kex[0], kex::DH_G14_SHA1);
+        assert_eq!(
+            data.preferred.cipher.as_ref(),
+            &[cipher::AES_128_CBC, cipher::AES_128_CTR]
+        );
+        assert_eq!(data.preferred.mac.as_ref(), &[mac::HMAC_SHA1]);
+        assert_eq!(data.preferred.compression[0], compression::ZLIB_LEGACY);
+    } else {
+        panic!("Expected Passwd session.");
+    }
+}
+
+#[cfg(test)]
+struct KexTestServer;
+
+#[cfg(test)]
+impl server::Handler for KexTestServer {
+    type Error = russh::Error;
+}
+
+/// Runs a real handshake against an in-process `russh` server that only
+/// offers `server_preferred` and returns the expected algorithms.
+#[cfg(test)]
+async fn expected_after_handshake(server_preferred: Preferred) -> ExpectedAlgorithms {
+    let key = PrivateKey::from(ssh_key::private::Ed25519Keypair::from_seed(&[7u8; 32]));
+    let server_config = Arc::new(server::Config {
+        keys: vec![key],
+        preferred: server_preferred,
+        ..Default::default()
+    });
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        if let Ok(session) = server::run_stream(server_config, stream, KexTestServer).await {
+            let _ = session.await;
+        }
+    });
+
+    let connection = Arc::new(ConnectionMonitor::new());
+    let mut handle = connect_transport(client::Config::default(), addr, &connection)
+        .await
+        .unwrap();
+    // Authentication only runs once the key exchange has succeeded.
+    let auth = handle.authenticate_none("user").await.unwrap();
+    assert!(!auth.success());
+    connection.expected().unwrap()
+}
+
+#[tokio::test]
+async fn test_expected_algorithms_real_handshake() {
+    // The server offers a single algorithm per list, so a successful
+    // handshake leaves russh exactly one choice.
+    let expected = expected_after_handshake(Preferred {
+        kex: std::borrow::Cow::Owned(vec![kex::DH_G14_SHA256]),
+        key: std::borrow::Cow::Owned(vec![Algorithm::Ed25519]),
+        cipher: std::borrow::Cow::Owned(vec![cipher::AES_128_CTR]),
+        mac: std::borrow::Cow::Owned(vec![mac::HMAC_SHA1]),
+        compression: std::borrow::Cow::Owned(vec![compression::NONE]),
+    })
+    .await;
+    assert!(expected.server_id.starts_with("SSH-2.0-"));
+    assert_eq!(expected.kex, "diffie-hellman-group14-sha256");
+    assert_eq!(expected.host_key, "ssh-ed25519");
+    assert_eq!(expected.cipher, "aes128-ctr");
+    assert_eq!(expected.mac_client_to_server, Some("hmac-sha1".to_string()));
+    assert_eq!(expected.mac_server_to_client, Some("hmac-sha1".to_string()));
+    assert_eq!(expected.compression_client_to_server, "none");
+
+    let expected = expected_after_handshake(Preferred {
+        cipher: std::borrow::Cow::Owned(vec![cipher::CHACHA20_POLY1305]),
+        ..Preferred::default()
+    })
+    .await;
+    assert_eq!(expected.kex, "curve25519-sha256");
+    assert_eq!(expected.cipher, "chacha20-poly1305@openssh.com");
+    assert_eq!(expected.mac_client_to_server, None);
+}
+
+#[tokio::test]
+async fn test_session_builder_with_invalid_algorithm() {
+    let session = Session::init()
+        .with_host("localhost")
+        .with_ciphers(["none-such-cipher"])
+        .build();
+
+    assert!(session.is_err());
+
+    let session = Session::init()
+        .with_host("localhost")
+        .with_macs(["hmac-sha1"])
+        .with_denied_algorithms(["hmac-sha1"])
+        .build();
+
+    assert!(session.is_err());
+}
+
+#[tokio::test]
+async fn test_session_builder_with_reconnect() {
+    let policy = ReconnectPolicy::new().max_attempts(3);
+    let session = Session::init()
+        .with_host("localhost")
+        .with_user("user")
+        .with_key("/path/to/key".into())
+        .with_reconnect(policy.clone())
+        .build();
+
+    assert!(session.is_ok());
+
+    let session = session.unwrap();
+    assert_eq!(session.connection_state(), ConnectionState::Disconnected);
+    if let SessionInner::PubKey { data, .. } = session.inner {
+        assert_eq!(data.reconnect, Some(policy));
+    } else {
+        panic!("Expected PubKey session.");
+    }
+}
+
+#[tokio::test]
+async fn test_session_reconnect_invalid_host_fails() {
+    let mut session = Session::init()
+        .with_host("invalid..host..name")
+        .with_reconnect(
+            ReconnectPolicy::new()
+                .max_attempts(2)
+                .initial_backoff(Duration::from_millis(1)),
+        )
+        .build()
+        .unwrap();
+    let mut watcher = session.watch_connection();
+
+    let result = session.reconnect().await;
+    assert!(result.is_err());
+    assert!(result
+        .unwrap_err()
+        .to_string()
+        .contains("Reconnect failed after 2 attempt(s)"));
+    assert_eq!(session.connection_state(), ConnectionState::Failed);
+    assert!(!session.is_connected());
+
+    let event = watcher.next_event().await.unwrap();
+    assert_eq!(event.previous, ConnectionState::Disconnected);
+}
+
 #[tokio::test]
 async fn test_session_builder_with_key_and_cert() {
     let key_path = PathBuf::from("/path/to/key");
@@ -2234,7 +3462,7 @@ async fn test_session_builder_with_key_opt() {
 
     let session = session.unwrap();
     if let SessionInner::PubKey { data, .. } = session.inner {
-        assert_eq!(data.key, key_path.unwrap());
+        assert_eq!(Some(data.key), key_path);
     } else {
         panic!("Expected PubKey session.");
     }
@@ -2276,7 +3504,7 @@ async fn test_session_builder_with_passwd_opt() {
 
     let session = session.unwrap();
     if let SessionInner::Passwd { data, .. } = session.inner {
-        assert_eq!(data.passwd, passwd.unwrap());
+        assert_eq!(Some(data.passwd), passwd);
     } else {
         panic!("Expected Passwd session.");
     }
@@ -2388,6 +3616,58 @@ async fn test_session_error_no_connection_scp() {
     assert_eq!(result.unwrap_err().to_string(), "No open session");
 }
 
+#[tokio::test]
+async fn test_session_error_no_connection_scp_download() {
+    let mut session = Session::init().with_passwd("pass").build().unwrap();
+
+    let result = session.scp_download("/remote/file", "/local/file").await;
+    assert!(result.is_err());
+    assert_eq!(result.unwrap_err().to_string(), "No open session");
+}
+
+#[tokio::test]
+async fn test_session_error_no_connection_sftp() {
+    let mut session = Session::init().with_passwd("pass").build().unwrap();
+
+    let result = session.sftp().await;
+    assert_eq!(result.unwrap_err().to_string(), "No open session");
+}
+
+#[tokio::test]
+async fn test_session_error_no_connection_scp_with_options() {
+    let mut session = Session::init().with_passwd("pass").build().unwrap();
+    let options = ScpOptions::new().recursive(true);
+
+    let result = session
+        .scp_with_options("/local/dir", "/remote/dir", &options)
+        .await;
+    assert_eq!(result.unwrap_err().to_string(), "No open session");
+
+    let result = session
+        .scp_download_with_options("/remote/dir", "/local/dir", &options)
+        .await;
+    assert_eq!(result.unwrap_err().to_string(), "No open session");
+}
+
+#[tokio::test]
+async fn test_session_error_no_connection_streams() {
+    let mut session = Session::init().with_passwd("pass").build().unwrap();
+
+    let result = session
+        .upload_bytes(b"key=value\n", "/etc/app.conf", 0o600)
+        .await;
+    assert_eq!(result.unwrap_err().to_string(), "No open session");
+
+    let result = session
+        .upload_reader(&b"data"[..], 4, "/tmp/data", 0o644)
+        .await;
+    assert_eq!(result.unwrap_err().to_string(), "No open session");
+
+    let mut out = Vec::new();
+    let result = session.download_writer("/etc/hostname", &mut out).await;
+    assert_eq!(result.unwrap_err().to_string(), "No open session");
+}
+
 #[tokio::test]
 async fn test_session_close_no_connection() {
     let mut session = Session::init().with_passwd("pass").build().unwrap();
@@ -2403,11 +3683,11 @@ async fn test_client_handler_check_server_key() {
     use ssh_key::PublicKey as SshPublicKey;
 
     // Create a client handler
-    let mut client = Client {};
+    let mut client = Client::new(&Arc::new(ConnectionMonitor::new()));
 
     // Create a minimal Ed25519 public key for testing
     // This is a valid Ed25519 public key (32 bytes of zeros for testing)
-    let key_data = vec![0u8; 32];
+    let key_data = [0u8; 32];
     let public_key = SshPublicKey::new(
         ssh_key::public::KeyData::Ed25519(
             ssh_key::public::Ed25519PublicKey::try_from(&key_data[..]).unwrap(),
@@ -2418,7 +3698,7 @@ async fn test_client_handler_check_server_key() {
     // Test that check_server_key returns Ok(true) (accepts any key)
     let result = client.check_server_key(&public_key).await;
     assert!(result.is_ok());
-    assert_eq!(result.unwrap(), true);
+    assert!(result.unwrap());
 }
 
 #[test]
@@ -2439,6 +3719,10 @@ fn test_session_inner_get_command_escaping() {
             port: 22,
             scope: None,
             inactivity_timeout: Some(Duration::from_secs(3000)),
+            keepalive_interval: None,
+            keepalive_max: 3,
+            reconnect: None,
+            preferred: Preferred::default(),
         },
     };
 
@@ -2464,6 +3748,10 @@ fn test_session_inner_get_command_simple() {
             port: 22,
             scope: None,
             inactivity_timeout: Some(Duration::from_secs(3000)),
+            keepalive_interval: None,
+            keepalive_max: 3,
+            reconnect: None,
+            preferred: Preferred::default(),
         },
     };
 
@@ -2484,6 +3772,10 @@ fn test_session_inner_get_command_noauth() {
             port: 22,
             scope: None,
             inactivity_timeout: Some(Duration::from_secs(3000)),
+            keepalive_interval: None,
+            keepalive_max: 3,
+            reconnect: None,
+            preferred: Preferred::default(),
         },
     };
 
@@ -2502,6 +3794,10 @@ async fn test_session_data_clone_passwd() {
         port: 2222,
         scope: Some("eth0".to_string()),
         inactivity_timeout: Some(Duration::from_secs(600)),
+        keepalive_interval: None,
+        keepalive_max: 3,
+        reconnect: None,
+        preferred: Preferred::default(),
     };
 
     let cloned = data.clone();
@@ -2523,6 +3819,10 @@ async fn test_session_data_clone_pubkey() {
         port: 2222,
         scope: Some("eth0".to_string()),
         inactivity_timeout: Some(Duration::from_secs(600)),
+        keepalive_interval: None,
+        keepalive_max: 3,
+        reconnect: None,
+        preferred: Preferred::default(),
     };
 
     let cloned = data.clone();
@@ -2542,6 +3842,10 @@ async fn test_session_data_clone_noauth() {
         port: 2222,
         scope: Some("eth0".to_string()),
         inactivity_timeout: Some(Duration::from_secs(600)),
+        keepalive_interval: None,
+        keepalive_max: 3,
+        reconnect: None,
+        preferred: Preferred::default(),
     };
 
     let cloned = data.clone();
@@ -2682,6 +3986,10 @@ fn test_session_inner_variants_construction() {
             port: 22,
             scope: None,
             inactivity_timeout: Some(Duration::from_secs(3000)),
+            keepalive_interval: None,
+            keepalive_max: 3,
+            reconnect: None,
+            preferred: Preferred::default(),
         },
     };
 
@@ -2696,6 +4004,10 @@ fn test_session_inner_variants_construction() {
             port: 22,
             scope: None,
             inactivity_timeout: Some(Duration::from_secs(3000)),
+            keepalive_interval: None,
+            keepalive_max: 3,
+            reconnect: None,
+            preferred: Preferred::default(),
         },
     };
 
@@ -2708,6 +4020,10 @@ fn test_session_inner_variants_construction() {
             port: 22,
             scope: None,
             inactivity_timeout: Some(Duration::from_secs(3000)),
+            keepalive_interval: None,
+            keepalive_max: 3,
+            reconnect: None,
+            preferred: Preferred::default(),
         },
     };
 
@@ -2732,6 +4048,10 @@ fn test_pathbuf_operations() {
         port: 22,
         scope: None,
         inactivity_timeout: Some(Duration::from_secs(3000)),
+        keepalive_interval: None,
+        keepalive_max: 3,
+        reconnect: None,
+        preferred: Preferred::default(),
     };
 
     assert_eq!(data.key, key_path);
@@ -3058,7 +4378,7 @@ fn test_pty_builder_default_values() {
     let mut session_mut = session;
     let builder = session_mut.pty_builder();
 
-    assert_eq!(builder.raw_mode, false);
+    assert!(!builder.raw_mode);
     assert_eq!(builder.command, None);
 
     let term = env::var("TERM").ok().unwrap_or_else(|| "xterm".to_string());
@@ -3081,7 +4401,7 @@ fn test_pty_builder_with_raw_sets_flag() {
     let mut session_mut = session;
     let builder = session_mut.pty_builder().with_raw();
 
-    assert_eq!(builder.raw_mode, true);
+    assert!(builder.raw_mode);
 }
 
 #[test]
@@ -3147,12 +4467,12 @@ fn test_pty_builder_method_chaining() {
         .with_size(100, 30)
         .with_command("/bin/bash");
 
-    assert_eq!(builder.raw_mode, true);
+    assert!(builder.raw_mode);
     assert_eq!(builder.term, "vt100");
     assert_eq!(builder.width, 100);
     assert_eq!(builder.height, 30);
     assert_eq!(builder.command, Some("/bin/bash".to_string()));
-    assert_eq!(builder.auto_resize, false);
+    assert!(!builder.auto_resize);
 }
 
 #[test]
@@ -3167,7 +4487,7 @@ fn test_pty_builder_with_auto_resize_sets_flag() {
     let mut session_mut = session;
     let builder = session_mut.pty_builder().with_auto_resize();
 
-    assert_eq!(builder.auto_resize, true);
+    assert!(builder.auto_resize);
 }
 
 #[test]
@@ -3182,7 +4502,7 @@ fn test_pty_builder_auto_resize_default_false() {
     let mut session_mut = session;
     let builder = session_mut.pty_builder();
 
-    assert_eq!(builder.auto_resize, false);
+    assert!(!builder.auto_resize);
 }
 
 #[tokio::test]
@@ -3414,7 +4734,13 @@ async fn test_pty_handle_mode_detection_disabled_returns_none() {
         closed: false,
         mode_detection,
         pwd_detection: None,
+        command_detection: None,
         history: None,
+        #[cfg(feature = "screen")]
+        screen: None,
+        title_detection: None,
+        notification_detection: None,
+        clipboard: None,
     };
 
     assert!(handle.current_mode().is_none());
@@ -3443,7 +4769,13 @@ async fn test_pty_handle_mode_detection_enabled_shared_instance() {
         closed: false,
         mode_detection: Some(mode_detection.clone()),
         pwd_detection: None,
+        command_detection: None,
         history: None,
+        #[cfg(feature = "screen")]
+        screen: None,
+        title_detection: None,
+        notification_detection: None,
+        clipboard: None,
     };
 
     let handle_mode_detection = handle.mode_detection.clone();
@@ -3537,7 +4869,13 @@ async fn test_pty_handle_pwd_detection_disabled_returns_none() {
         closed: false,
         mode_detection: None,
         pwd_detection: None,
+        command_detection: None,
         history: None,
+        #[cfg(feature = "screen")]
+        screen: None,
+        title_detection: None,
+        notification_detection: None,
+        clipboard: None,
     };
 
     assert!(handle.current_pwd().is_none());
@@ -3567,7 +4905,13 @@ async fn test_pty_handle_pwd_detection_enabled_shared_instance() {
         closed: false,
         mode_detection: None,
         pwd_detection: Some(pwd_detection.clone()),
+        command_detection: None,
         history: None,
+        #[cfg(feature = "screen")]
+        screen: None,
+        title_detection: None,
+        notification_detection: None,
+        clipboard: None,
     };
 
     assert!(handle.current_pwd().is_none());
@@ -0,0 +1,365 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2025 Julian Kahlert
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use parking_lot::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// Minimum time between two published progress updates of a file.
+const PUBLISH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Window over which the instantaneous throughput is measured.
+const RATE_WINDOW: Duration = Duration::from_millis(500);
+
+/// A snapshot of the progress of a single file transfer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransferProgress {
+    /// Path or name of the file being transferred.
+    pub path: String,
+    /// Bytes transferred so far.
+    pub bytes: u64,
+    /// Total size of the file in bytes.
+    pub total: u64,
+    /// Throughput over the last half second, in bytes per second.
+    pub instantaneous_bps: f64,
+    /// Throughput since the file transfer started, in bytes per second.
+    pub average_bps: f64,
+    /// Estimated time until the file is complete, if it can be estimated.
+    pub eta: Option<Duration>,
+    /// Time since the file transfer started.
+    pub elapsed: Duration,
+    /// True once all bytes of the file have been transferred.
+    pub finished: bool,
+}
+
+impl TransferProgress {
+    /// Returns the completed fraction between 0.0 and 1.0.
+    ///
+    /// Empty files report 1.0.
+    pub fn fraction(&self) -> f64 {
+        if self.total == 0 {
+            1.0
+        } else {
+            (self.bytes as f64 / self.total as f64).min(1.0)
+        }
+    }
+}
+
+/// Per-file throughput bookkeeping.
+struct FileState {
+    path: String,
+    total: u64,
+    bytes: u64,
+    /// Bytes already present when the transfer started.
+    resumed: u64,
+    started: Instant,
+    window_start: Instant,
+    window_bytes: u64,
+    instantaneous_bps: f64,
+    last_publish: Option<Instant>,
+}
+
+/// Internal progress tracking shared between transfer code and watchers.
+pub(crate) struct ProgressReporter {
+    state: Mutex<Option<FileState>>,
+    event_tx: watch::Sender<Option<TransferProgress>>,
+}
+
+impl std::fmt::Debug for ProgressReporter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProgressReporter").finish_non_exhaustive()
+    }
+}
+
+impl ProgressReporter {
+    /// Creates a new reporter with no file in progress.
+    pub(crate) fn new() -> Self {
+        let (tx, _rx) = watch::channel(None);
+        Self {
+            state: Mutex::new(None),
+            event_tx: tx,
+        }
+    }
+
+    /// Starts tracking a new file and publishes its initial progress.
+    pub(crate) fn start(&self, path: &str, total: u64) {
+        self.start_at(path, total, 0);
+    }
+
+    /// Starts tracking a file of which `offset` bytes were transferred
+    /// before, e.g. by an interrupted transfer that is being resumed.
+    ///
+    /// The resumed bytes count towards the progress but not towards the
+    /// transfer rates.
+    pub(crate) fn start_at(&self, path: &str, total: u64, offset: u64) {
+        let now = Instant::now();
+        let mut state = self.state.lock();
+        *state = Some(FileState {
+            path: path.to_string(),
+            total,
+            bytes: offset,
+            resumed: offset,
+            started: now,
+            window_start: now,
+            window_bytes: offset,
+            instantaneous_bps: 0.0,
+            last_publish: None,
+        });
+        self.publish(state.as_mut(), now, true);
+    }
+
+    /// Records `bytes` more transferred bytes of the current file.
+    pub(crate) fn advance(&self, bytes: u64) {
+        let now = Instant::now();
+        let mut state = self.state.lock();
+        let Some(file) = state.as_mut() else {
+            return;
+        };
+        file.bytes += bytes;
+
+        let window = now.duration_since(file.window_start);
+        if window >= RATE_WINDOW {
+            file.instantaneous_bps = (file.bytes - file.window_bytes) as f64 / window.as_secs_f64();
+            file.window_start = now;
+            file.window_bytes = file.bytes;
+        }
+
+        let due = file
+            .last_publish
+            .is_none_or(|t| now.duration_since(t) >= PUBLISH_INTERVAL);
+        self.publish(state.as_mut(), now, due);
+    }
+
+    /// Marks the current file as complete and publishes the final progress.
+    pub(crate) fn finish(&self) {
+        let now = Instant::now();
+        let mut state = self.state.lock();
+        self.publish(state.as_mut(), now, true);
+        *state = None;
+    }
+
+    /// Sends a snapshot of `file` to all watchers if `force` is set.
+    fn publish(&self, file: Option<&mut FileState>, now: Instant, force: bool) {
+        let Some(file) = file else {
+            return;
+        };
+        if !force {
+            return;
+        }
+        file.last_publish = Some(now);
+        self.event_tx.send_replace(Some(Self::snapshot(file, now)));
+    }
+
+    /// Computes the public progress view of `file` at `now`.
+    fn snapshot(file: &FileState, now: Instant) -> TransferProgress {
+        let elapsed = now.duration_since(file.started);
+        let average_bps = if elapsed.is_zero() {
+            0.0
+        } else {
+            (file.bytes - file.resumed) as f64 / elapsed.as_secs_f64()
+        };
+        // Before the first full window the average is the best estimate.
+        let instantaneous_bps = if file.instantaneous_bps > 0.0 {
+            file.instantaneous_bps
+        } else {
+            average_bps
+        };
+        let remaining = file.total.saturating_sub(file.bytes);
+        let eta = if remaining == 0 {
+            Some(Duration::ZERO)
+        } else if average_bps > 0.0 {
+            Some(Duration::from_secs_f64(remaining as f64 / average_bps))
+        } else {
+            None
+        };
+
+        TransferProgress {
+            path: file.path.clone(),
+            bytes: file.bytes,
+            total: file.total,
+            instantaneous_bps,
+            average_bps,
+            eta,
+            elapsed,
+            finished: file.bytes >= file.total,
+        }
+    }
+
+    /// Creates a new watcher for observing progress updates.
+    pub(crate) fn create_watcher(&self) -> ProgressWatcher {
+        ProgressWatcher {
+            inner: self.event_tx.subscribe(),
+        }
+    }
+}
+
+/// An async-enabled watcher for file transfer progress.
+///
+/// Updates are rate limited to about ten per second per file; the first
+/// and the final update of every file are always published. Only the
+/// latest snapshot is observable, though: a watcher that is slower than
+/// the transfer misses the updates in between, including the start of a
+/// small file or the end of one that the next file already replaced.
+#[derive(Debug, Clone)]
+pub struct ProgressWatcher {
+    inner: watch::Receiver<Option<TransferProgress>>,
+}
+
+impl ProgressWatcher {
+    /// Returns the latest progress without waiting.
+    ///
+    /// Returns `None` if no transfer has started yet.
+    pub fn current(&self) -> Option<TransferProgress> {
+        self.inner.borrow().clone()
+    }
+
+    /// Waits for the next progress update.
+    ///
+    /// Returns `None` once the transfer options have been dropped.
+    pub async fn changed(&mut self) -> Option<TransferProgress> {
+        loop {
+            self.inner.changed().await.ok()?;
+            if let Some(progress) = self.inner.borrow_and_update().clone() {
+                return Some(progress);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fraction() {
+        let mut progress = TransferProgress {
+            path: "file".to_string(),
+            bytes: 25,
+            total: 100,
+            instantaneous_bps: 0.0,
+            average_bps: 0.0,
+            eta: None,
+            elapsed: Duration::ZERO,
+            finished: false,
+        };
+        assert_eq!(progress.fraction(), 0.25);
+
+        progress.total = 0;
+        assert_eq!(progress.fraction(), 1.0);
+    }
+
+    #[test]
+    fn test_reporter_start_and_finish() {
+        let reporter = ProgressReporter::new();
+        let watcher = reporter.create_watcher();
+        assert_eq!(watcher.current(), None);
+
+        reporter.start("firmware.bin", 1000);
+        let progress = watcher.current().unwrap();
+        assert_eq!(progress.path, "firmware.bin");
+        assert_eq!(progress.bytes, 0);
+        assert_eq!(progress.total, 1000);
+        assert!(!progress.finished);
+
+        reporter.advance(400);
+        reporter.advance(600);
+        reporter.finish();
+        let progress = watcher.current().unwrap();
+        assert_eq!(progress.bytes, 1000);
+        assert!(progress.finished);
+        assert_eq!(progress.eta, Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_reporter_start_at_offset() {
+        let reporter = ProgressReporter::new();
+        let watcher = reporter.create_watcher();
+
+        reporter.start_at("image.img", 1000, 900);
+        let progress = watcher.current().unwrap();
+        assert_eq!(progress.bytes, 900);
+        assert_eq!(progress.fraction(), 0.9);
+        assert_eq!(progress.average_bps, 0.0);
+        assert!(!progress.finished);
+        assert_eq!(progress.eta, None);
+    }
+
+    #[test]
+    fn test_reporter_rate_limits_updates() {
+        let reporter = ProgressReporter::new();
+        let mut watcher = reporter.create_watcher();
+
+        reporter.start("file", 100);
+        watcher.inner.mark_unchanged();
+        reporter.advance(10);
+        // The start was published just now, so this update is held back.
+        assert!(!watcher.inner.has_changed().unwrap());
+        assert_eq!(watcher.current().unwrap().bytes, 0);
+
+        reporter.finish();
+        assert_eq!(watcher.current().unwrap().bytes, 10);
+    }
+
+    #[test]
+    fn test_reporter_advance_without_file() {
+        let reporter = ProgressReporter::new();
+        let watcher = reporter.create_watcher();
+        reporter.advance(10);
+        reporter.finish();
+        assert_eq!(watcher.current(), None);
+    }
+
+    #[test]
+    fn test_snapshot_eta() {
+        let now = Instant::now();
+        let file = FileState {
+            path: "file".to_string(),
+            total: 300,
+            bytes: 100,
+            resumed: 0,
+            started: now - Duration::from_secs(1),
+            window_start: now,
+            window_bytes: 100,
+            instantaneous_bps: 0.0,
+            last_publish: None,
+        };
+        let progress = ProgressReporter::snapshot(&file, now);
+        assert_eq!(progress.average_bps, 100.0);
+        assert_eq!(progress.instantaneous_bps, 100.0);
+        assert_eq!(progress.eta, Some(Duration::from_secs(2)));
+    }
+
+    #[tokio::test]
+    async fn test_watcher_changed() {
+        let reporter = ProgressReporter::new();
+        let mut watcher = reporter.create_watcher();
+
+        reporter.start("file", 10);
+        let progress = watcher.changed().await.unwrap();
+        assert_eq!(progress.total, 10);
+
+        drop(reporter);
+        assert_eq!(watcher.changed().await, None);
+    }
+}
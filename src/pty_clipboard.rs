@@ -0,0 +1,551 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2025 Julian Kahlert
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig};
+use base64::engine::DecodePaddingMode;
+use base64::{alphabet, Engine as _};
+use parking_lot::Mutex;
+use std::time::Instant;
+use tokio::sync::broadcast;
+
+use crate::pty_osc::{OscScanner, OscToken};
+use crate::pty_title::sanitize;
+
+/// Number of clipboard requests a lagging watcher may fall behind by.
+const EVENT_CAPACITY: usize = 64;
+
+/// Base64 engine that accepts OSC 52 data with or without padding.
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// Policy for OSC 52 clipboard requests from the remote side.
+///
+/// By default programs may set the clipboard but not read it, since a
+/// readable clipboard leaks whatever the user copied last to every host
+/// they connect to.
+#[derive(Debug, Clone)]
+pub struct ClipboardConfig {
+    /// Whether to enable clipboard handling (default: false)
+    pub enabled: bool,
+    /// Whether programs may set the clipboard (default: true)
+    pub allow_write: bool,
+    /// Whether programs may query the clipboard (default: false)
+    pub allow_read: bool,
+    /// Largest clipboard write accepted, in decoded bytes (default: 1 MiB)
+    pub max_size: usize,
+    /// Whether [`PtyBuilder::run`](crate::PtyBuilder::run) passes permitted
+    /// requests on to the local terminal (default: true)
+    pub passthrough: bool,
+}
+
+impl Default for ClipboardConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            allow_write: true,
+            allow_read: false,
+            max_size: 1024 * 1024,
+            passthrough: true,
+        }
+    }
+}
+
+impl ClipboardConfig {
+    /// Returns the longest OSC payload a write within `max_size` can need.
+    fn payload_limit(&self) -> usize {
+        self.max_size
+            .div_ceil(3)
+            .saturating_mul(4)
+            .saturating_add(64)
+    }
+}
+
+/// What a program asked the terminal to do with the clipboard.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClipboardRequest {
+    /// Set the selection to the decoded data. Empty data, including data
+    /// that is not valid base64, clears the selection as in xterm.
+    Write(Vec<u8>),
+    /// Report the selection contents back to the program.
+    Query,
+}
+
+/// Why a clipboard request was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClipboardDenial {
+    /// Writes are disabled by [`ClipboardConfig::allow_write`].
+    WriteDisabled,
+    /// Queries are disabled by [`ClipboardConfig::allow_read`].
+    ReadDisabled,
+    /// The data exceeds [`ClipboardConfig::max_size`].
+    TooLarge,
+}
+
+/// An OSC 52 clipboard request together with the policy decision.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClipboardEvent {
+    /// The selection parameter, such as `c` for the clipboard or `p` for
+    /// the primary selection; empty means the terminal's default.
+    pub selection: String,
+    /// The request. Writes refused as [`ClipboardDenial::TooLarge`] carry
+    /// no data.
+    pub request: ClipboardRequest,
+    /// The reason the request was refused, or `None` if it is permitted.
+    pub denied: Option<ClipboardDenial>,
+    /// Timestamp of when the request was detected.
+    pub timestamp: Instant,
+}
+
+impl ClipboardEvent {
+    /// Returns true if the policy permits this request.
+    pub fn is_allowed(&self) -> bool {
+        self.denied.is_none()
+    }
+
+    /// Encodes the request as an OSC 52 sequence, e.g. to forward it to
+    /// the local terminal.
+    pub fn to_sequence(&self) -> Vec<u8> {
+        match &self.request {
+            ClipboardRequest::Write(data) => encode(&self.selection, &BASE64.encode(data)),
+            ClipboardRequest::Query => encode(&self.selection, "?"),
+        }
+    }
+
+    /// Builds the answer to a query, to be written back to the PTY.
+    pub fn reply(&self, contents: &[u8]) -> Vec<u8> {
+        encode(&self.selection, &BASE64.encode(contents))
+    }
+}
+
+/// Builds an OSC 52 sequence from its selection and data parameters.
+fn encode(selection: &str, data: &str) -> Vec<u8> {
+    format!("\x1b]52;{};{}\x07", selection, data).into_bytes()
+}
+
+/// Parses an OSC payload into a clipboard event and applies the policy.
+///
+/// Returns `None` for payloads that are not OSC 52 requests.
+fn parse_clipboard_payload(payload: &[u8], config: &ClipboardConfig) -> Option<ClipboardEvent> {
+    let rest = payload.strip_prefix(b"52;")?;
+    let split = rest.iter().position(|&b| b == b';')?;
+    let selection = sanitize(&String::from_utf8_lossy(&rest[..split]));
+    let data = &rest[split + 1..];
+
+    let (request, denied) = if data == b"?" {
+        let denied = (!config.allow_read).then_some(ClipboardDenial::ReadDisabled);
+        (ClipboardRequest::Query, denied)
+    } else {
+        let decoded = BASE64.decode(data).unwrap_or_default();
+        if decoded.len() > config.max_size {
+            (
+                ClipboardRequest::Write(Vec::new()),
+                Some(ClipboardDenial::TooLarge),
+            )
+        } else {
+            let denied = (!config.allow_write).then_some(ClipboardDenial::WriteDisabled);
+            (ClipboardRequest::Write(decoded), denied)
+        }
+    };
+
+    Some(ClipboardEvent {
+        selection,
+        request,
+        denied,
+        timestamp: Instant::now(),
+    })
+}
+
+/// Builds the event for an OSC 52 write whose payload overflowed the buffer.
+fn overflow_event(prefix: &[u8]) -> Option<ClipboardEvent> {
+    let rest = prefix.strip_prefix(b"52;")?;
+    let split = rest.iter().position(|&b| b == b';')?;
+    Some(ClipboardEvent {
+        selection: sanitize(&String::from_utf8_lossy(&rest[..split])),
+        request: ClipboardRequest::Write(Vec::new()),
+        denied: Some(ClipboardDenial::TooLarge),
+        timestamp: Instant::now(),
+    })
+}
+
+/// Internal clipboard detection state.
+///
+/// Manages the OSC scanner and broadcasts clipboard requests to watchers.
+pub(crate) struct ClipboardDetection {
+    config: ClipboardConfig,
+    scanner: Mutex<OscScanner>,
+    event_tx: broadcast::Sender<ClipboardEvent>,
+}
+
+impl ClipboardDetection {
+    /// Creates a new clipboard detector with the given policy.
+    pub(crate) fn new(config: ClipboardConfig) -> Self {
+        let (tx, _) = broadcast::channel(EVENT_CAPACITY);
+        Self {
+            scanner: Mutex::new(OscScanner::new(config.payload_limit())),
+            config,
+            event_tx: tx,
+        }
+    }
+
+    /// Returns the policy this detector applies.
+    pub(crate) fn config(&self) -> &ClipboardConfig {
+        &self.config
+    }
+
+    /// Feeds data to the OSC scanner.
+    ///
+    /// If clipboard handling is disabled, this is a no-op.
+    pub(crate) fn feed(&self, data: &[u8]) {
+        if !self.config.enabled {
+            return;
+        }
+        let tokens = self.scanner.lock().feed(data);
+        for token in tokens {
+            let event = match token {
+                OscToken::Osc(payload) => parse_clipboard_payload(&payload, &self.config),
+                OscToken::Overflow(prefix) => overflow_event(&prefix),
+                OscToken::Text(_) => None,
+            };
+            if let Some(event) = event {
+                if !event.is_allowed() {
+                    log::debug!("Denied clipboard request: {:?}", event.denied);
+                }
+                let _ = self.event_tx.send(event);
+            }
+        }
+    }
+
+    /// Creates a new watcher for observing clipboard requests.
+    pub(crate) fn create_watcher(&self) -> ClipboardWatcher {
+        ClipboardWatcher {
+            inner: self.event_tx.subscribe(),
+        }
+    }
+}
+
+/// States for the clipboard filter.
+enum FilterState {
+    Normal,
+    Escape,
+    Prefix,
+    Clipboard,
+    ClipboardEscape,
+    Overflow,
+    OverflowEscape,
+}
+
+/// Removes OSC 52 requests the policy refuses from a byte stream.
+///
+/// Everything else, including other OSC sequences of any length, passes
+/// through unchanged. Permitted requests are held back until complete and
+/// then passed on as they were, unless passthrough is disabled.
+pub(crate) struct ClipboardFilter {
+    config: ClipboardConfig,
+    pending: Vec<u8>,
+    state: FilterState,
+    limit: usize,
+}
+
+impl ClipboardFilter {
+    /// Creates a filter applying the given policy.
+    pub(crate) fn new(config: ClipboardConfig) -> Self {
+        Self {
+            limit: config.payload_limit(),
+            config,
+            pending: Vec::new(),
+            state: FilterState::Normal,
+        }
+    }
+
+    /// Filters a chunk of output, returning the bytes to display.
+    pub(crate) fn filter(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(data.len());
+
+        for &byte in data {
+            self.step(byte, &mut out);
+        }
+
+        out
+    }
+
+    /// Advances the filter by one byte.
+    fn step(&mut self, byte: u8, out: &mut Vec<u8>) {
+        match self.state {
+            FilterState::Normal => {
+                if byte == 0x1b {
+                    self.pending.push(byte);
+                    self.state = FilterState::Escape;
+                } else {
+                    out.push(byte);
+                }
+            }
+            FilterState::Escape => {
+                if byte == b']' {
+                    self.pending.push(byte);
+                    self.state = FilterState::Prefix;
+                } else if byte == 0x1b {
+                    out.push(0x1b);
+                } else {
+                    out.append(&mut self.pending);
+                    out.push(byte);
+                    self.state = FilterState::Normal;
+                }
+            }
+            FilterState::Prefix => {
+                self.pending.push(byte);
+                let seen = &self.pending[2..];
+                if seen == b"52;" {
+                    self.state = FilterState::Clipboard;
+                } else if !b"52;".starts_with(seen) {
+                    out.append(&mut self.pending);
+                    self.state = FilterState::Normal;
+                }
+            }
+            FilterState::Clipboard => {
+                if byte == 0x07 {
+                    self.pending.push(byte);
+                    self.finish(1, out);
+                } else if byte == 0x1b {
+                    self.state = FilterState::ClipboardEscape;
+                } else if self.pending.len() < self.limit {
+                    self.pending.push(byte);
+                } else {
+                    self.pending.clear();
+                    self.state = FilterState::Overflow;
+                }
+            }
+            FilterState::ClipboardEscape => {
+                if byte == b'\\' {
+                    self.pending.extend_from_slice(b"\x1b\\");
+                    self.finish(2, out);
+                } else {
+                    // Not ST — the request is abandoned, as in OscScanner
+                    self.pending.clear();
+                    self.state = FilterState::Normal;
+                    self.step(0x1b, out);
+                    self.step(byte, out);
+                }
+            }
+            FilterState::Overflow => {
+                if byte == 0x07 {
+                    self.state = FilterState::Normal;
+                } else if byte == 0x1b {
+                    self.state = FilterState::OverflowEscape;
+                }
+            }
+            FilterState::OverflowEscape => {
+                self.state = FilterState::Normal;
+                if byte != b'\\' {
+                    self.step(0x1b, out);
+                    self.step(byte, out);
+                }
+            }
+        }
+    }
+
+    /// Decides on a complete request held in `pending`, whose terminator is
+    /// `terminator_len` bytes long.
+    fn finish(&mut self, terminator_len: usize, out: &mut Vec<u8>) {
+        let payload = &self.pending[2..self.pending.len() - terminator_len];
+        let allowed =
+            parse_clipboard_payload(payload, &self.config).is_some_and(|event| event.is_allowed());
+        if allowed && self.config.passthrough {
+            out.append(&mut self.pending);
+        } else {
+            self.pending.clear();
+        }
+        self.state = FilterState::Normal;
+    }
+}
+
+/// An async-enabled watcher for OSC 52 clipboard requests.
+///
+/// Delivers every request, permitted or not, in order; a watcher that
+/// falls more than 64 requests behind skips the oldest ones.
+#[derive(Debug)]
+pub struct ClipboardWatcher {
+    inner: broadcast::Receiver<ClipboardEvent>,
+}
+
+impl ClipboardWatcher {
+    /// Returns the next request if one is already queued.
+    pub fn try_next(&mut self) -> Option<ClipboardEvent> {
+        loop {
+            match self.inner.try_recv() {
+                Ok(event) => return Some(event),
+                Err(broadcast::error::TryRecvError::Lagged(skipped)) => {
+                    log::warn!("Clipboard watcher skipped {} requests", skipped);
+                }
+                Err(_) => return None,
+            }
+        }
+    }
+
+    /// Waits for the next clipboard request.
+    ///
+    /// Returns `None` if the PTY session has ended.
+    pub async fn next(&mut self) -> Option<ClipboardEvent> {
+        loop {
+            match self.inner.recv().await {
+                Ok(event) => return Some(event),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    log::warn!("Clipboard watcher skipped {} requests", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> ClipboardConfig {
+        ClipboardConfig {
+            enabled: true,
+            ..ClipboardConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_clipboard_payloads() {
+        let event = parse_clipboard_payload(b"52;c;aGVsbG8=", &config()).unwrap();
+        assert_eq!(event.selection, "c");
+        assert_eq!(event.request, ClipboardRequest::Write(b"hello".to_vec()));
+        assert!(event.is_allowed());
+
+        let event = parse_clipboard_payload(b"52;;aGVsbG8", &config()).unwrap();
+        assert_eq!(event.selection, "");
+        assert_eq!(event.request, ClipboardRequest::Write(b"hello".to_vec()));
+
+        let event = parse_clipboard_payload(b"52;p;?", &config()).unwrap();
+        assert_eq!(event.request, ClipboardRequest::Query);
+        assert_eq!(event.denied, Some(ClipboardDenial::ReadDisabled));
+
+        let event = parse_clipboard_payload(b"52;c;!!", &config()).unwrap();
+        assert_eq!(event.request, ClipboardRequest::Write(Vec::new()));
+
+        assert!(parse_clipboard_payload(b"52;c", &config()).is_none());
+        assert!(parse_clipboard_payload(b"2;title", &config()).is_none());
+    }
+
+    #[test]
+    fn test_clipboard_policy() {
+        let policy = ClipboardConfig {
+            allow_write: false,
+            allow_read: true,
+            max_size: 4,
+            ..config()
+        };
+        let event = parse_clipboard_payload(b"52;c;aGk=", &policy).unwrap();
+        assert_eq!(event.denied, Some(ClipboardDenial::WriteDisabled));
+        let event = parse_clipboard_payload(b"52;c;aGVsbG8=", &policy).unwrap();
+        assert_eq!(event.denied, Some(ClipboardDenial::TooLarge));
+        assert_eq!(event.request, ClipboardRequest::Write(Vec::new()));
+        let event = parse_clipboard_payload(b"52;c;?", &policy).unwrap();
+        assert!(event.is_allowed());
+    }
+
+    #[test]
+    fn test_event_sequences() {
+        let event = parse_clipboard_payload(b"52;c;aGk=", &config()).unwrap();
+        assert_eq!(event.to_sequence(), b"\x1b]52;c;aGk=\x07");
+        let event = parse_clipboard_payload(b"52;p;?", &config()).unwrap();
+        assert_eq!(event.to_sequence(), b"\x1b]52;p;?\x07");
+        assert_eq!(event.reply(b"hi"), b"\x1b]52;p;aGk=\x07");
+    }
+
+    #[tokio::test]
+    async fn test_clipboard_watcher_events() {
+        let d = ClipboardDetection::new(ClipboardConfig {
+            max_size: 8,
+            ..config()
+        });
+        let mut watcher = d.create_watcher();
+
+        d.feed(b"text\x1b]52;c;aGk=\x07\x1b]52;c;");
+        d.feed(b"?\x1b\\");
+        let event = watcher.next().await.unwrap();
+        assert_eq!(event.request, ClipboardRequest::Write(b"hi".to_vec()));
+        let event = watcher.next().await.unwrap();
+        assert_eq!(event.denied, Some(ClipboardDenial::ReadDisabled));
+
+        let long = format!("\x1b]52;c;{}\x07", BASE64.encode("x".repeat(200)));
+        d.feed(long.as_bytes());
+        let event = watcher.next().await.unwrap();
+        assert_eq!(event.selection, "c");
+        assert_eq!(event.denied, Some(ClipboardDenial::TooLarge));
+        assert!(watcher.try_next().is_none());
+
+        drop(d);
+        assert!(watcher.next().await.is_none());
+    }
+
+    #[test]
+    fn test_disabled_detection_ignores_output() {
+        let d = ClipboardDetection::new(ClipboardConfig::default());
+        let mut watcher = d.create_watcher();
+        d.feed(b"\x1b]52;c;aGk=\x07");
+        assert!(watcher.try_next().is_none());
+    }
+
+    #[test]
+    fn test_filter_applies_policy() {
+        let mut filter = ClipboardFilter::new(config());
+        let data = b"a\x1b]52;c;aGk=\x07b\x1b]52;c;?\x1b\\c\x1b]2;t\x07\x1b[1md\x1b]5;x\x07";
+        assert_eq!(
+            filter.filter(data),
+            b"a\x1b]52;c;aGk=\x07bc\x1b]2;t\x07\x1b[1md\x1b]5;x\x07".to_vec()
+        );
+
+        let mut filter = ClipboardFilter::new(ClipboardConfig {
+            passthrough: false,
+            ..config()
+        });
+        assert_eq!(filter.filter(b"a\x1b]52;c;aGk=\x07b"), b"ab".to_vec());
+    }
+
+    #[test]
+    fn test_filter_split_and_overflow() {
+        let mut filter = ClipboardFilter::new(ClipboardConfig {
+            max_size: 8,
+            ..config()
+        });
+        let mut out = Vec::new();
+        for chunk in [&b"x\x1b"[..], b"]5", b"2;c;aG", b"k=\x1b", b"\\y"] {
+            out.extend(filter.filter(chunk));
+        }
+        assert_eq!(out, b"x\x1b]52;c;aGk=\x1b\\y".to_vec());
+
+        let long = format!("a\x1b]52;c;{}\x07b", "x".repeat(200));
+        assert_eq!(filter.filter(long.as_bytes()), b"ab".to_vec());
+
+        let long = format!("a\x1b]52;c;{}\x1b[1mb", "x".repeat(200));
+        assert_eq!(filter.filter(long.as_bytes()), b"a\x1b[1mb".to_vec());
+        assert_eq!(filter.filter(b"\x1b]52;c;aGk=\x1b[2J"), b"\x1b[2J".to_vec());
+    }
+}
@@ -0,0 +1,613 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2025 Julian Kahlert
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use parking_lot::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+
+use crate::pty_history::strip_ansi_codes;
+use crate::pty_osc::{OscScanner, OscToken};
+use crate::pty_pwd::parse_pwd_payload;
+
+/// Number of finished commands a lagging watcher may fall behind by.
+const EVENT_CAPACITY: usize = 64;
+
+/// Maximum number of bytes kept for a command line typed at the prompt.
+const MAX_COMMAND_LINE: usize = 4096;
+
+/// A command that ran in the remote shell, delimited by OSC 133/633 marks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandEvent {
+    /// The command line, from OSC 633 `E` or the input echoed after the prompt.
+    pub command_line: Option<String>,
+    /// The command's output with escape sequences stripped and `\r\n`
+    /// normalised to `\n`.
+    pub output: String,
+    /// The exit code reported with the end mark, if any.
+    pub exit_code: Option<i32>,
+    /// When the command started executing.
+    pub started: Instant,
+    /// When the command finished.
+    pub finished: Instant,
+    /// The working directory the command ran in, if reported.
+    pub cwd: Option<String>,
+}
+
+impl CommandEvent {
+    /// Returns how long the command ran.
+    pub fn duration(&self) -> Duration {
+        self.finished.saturating_duration_since(self.started)
+    }
+
+    /// Returns true if the command reported exit code 0.
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+}
+
+/// Configuration for command boundary detection.
+#[derive(Debug, Clone)]
+pub struct CommandDetectionConfig {
+    /// Whether to enable command detection (default: false)
+    pub enabled: bool,
+    /// Whether to inject OSC 133 prompt hooks into the remote shell (default: false)
+    ///
+    /// When enabled, the library installs bash `PROMPT_COMMAND`/`PS0` or zsh
+    /// precmd/preexec hooks that mark the prompt, command start and exit code.
+    pub inject: bool,
+    /// Buffer size for OSC payload parsing (default: 2048 bytes)
+    pub buffer_size: usize,
+    /// Maximum number of output bytes kept per command (default: 1 MiB)
+    pub max_output: usize,
+}
+
+impl Default for CommandDetectionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            inject: false,
+            buffer_size: 2048,
+            max_output: 1024 * 1024,
+        }
+    }
+}
+
+/// Where the shell is in its prompt/command cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    Idle,
+    Prompt,
+    Input,
+    Running,
+}
+
+/// Shell integration marks recognised in OSC 133 and OSC 633.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Mark {
+    PromptStart,
+    CommandStart,
+    OutputStart,
+    Finished(Option<i32>),
+    CommandLine(String),
+    Cwd(String),
+}
+
+/// Parses an OSC payload into a shell integration mark.
+fn parse_mark(payload: &[u8]) -> Option<Mark> {
+    if let Some(cwd) = parse_pwd_payload(payload) {
+        return Some(Mark::Cwd(cwd));
+    }
+    let payload = std::str::from_utf8(payload).ok()?;
+    let (code, rest) = payload.split_once(';')?;
+    if code != "133" && code != "633" {
+        return None;
+    }
+    let mut params = rest.splitn(2, ';');
+    let kind = params.next()?;
+    let args = params.next().unwrap_or("");
+
+    match kind {
+        "A" => Some(Mark::PromptStart),
+        "B" => Some(Mark::CommandStart),
+        "C" => Some(Mark::OutputStart),
+        "D" => {
+            let code = args.split(';').next().and_then(|c| c.trim().parse().ok());
+            Some(Mark::Finished(code))
+        }
+        "E" if code == "633" => {
+            let line = args.split(';').next().unwrap_or("");
+            Some(Mark::CommandLine(unescape_633(line)))
+        }
+        _ => None,
+    }
+}
+
+/// Decodes the `\\` and `\xHH` escapes VS Code uses in OSC 633 values.
+fn unescape_633(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'\\' {
+            if bytes.get(i + 1) == Some(&b'\\') {
+                result.push(b'\\');
+                i += 2;
+                continue;
+            }
+            if bytes.get(i + 1) == Some(&b'x') && i + 3 < bytes.len() {
+                let hex = std::str::from_utf8(&bytes[i + 2..i + 4]).ok();
+                if let Some(b) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    result.push(b);
+                    i += 4;
+                    continue;
+                }
+            }
+        }
+        result.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&result).into_owned()
+}
+
+/// Turns the input echoed between the prompt and the command into a command line.
+///
+/// Applies backspaces and carriage-return redraws so that line editing by
+/// the shell does not end up in the result.
+fn clean_command_line(echo: &[u8]) -> Option<String> {
+    let text = strip_ansi_codes(&String::from_utf8_lossy(echo));
+    let mut lines = Vec::new();
+    for raw in text.split('\n') {
+        let mut line = String::new();
+        for ch in raw.trim_end_matches('\r').chars() {
+            match ch {
+                '\r' => line.clear(),
+                '\x08' => {
+                    line.pop();
+                }
+                c if c.is_control() => {}
+                c => line.push(c),
+            }
+        }
+        if !line.trim().is_empty() {
+            lines.push(line.trim().to_string());
+        }
+    }
+    if lines.is_empty() {
+        None
+    } else {
+        Some(lines.join("\n"))
+    }
+}
+
+/// Normalises captured output for [`CommandEvent::output`].
+pub(crate) fn clean_output(output: &[u8]) -> String {
+    strip_ansi_codes(&String::from_utf8_lossy(output)).replace("\r\n", "\n")
+}
+
+/// Stateful tracker that turns shell integration marks into command events.
+struct CommandTracker {
+    scanner: OscScanner,
+    phase: Phase,
+    echo: Vec<u8>,
+    explicit_line: Option<String>,
+    command_line: Option<String>,
+    output: Vec<u8>,
+    started: Option<Instant>,
+    cwd: Option<String>,
+    command_cwd: Option<String>,
+    max_output: usize,
+}
+
+impl CommandTracker {
+    /// Creates a new tracker with the given OSC buffer size and output limit.
+    fn new(buffer_size: usize, max_output: usize) -> Self {
+        Self {
+            scanner: OscScanner::new(buffer_size),
+            phase: Phase::Idle,
+            echo: Vec::new(),
+            explicit_line: None,
+            command_line: None,
+            output: Vec::new(),
+            started: None,
+            cwd: None,
+            command_cwd: None,
+            max_output,
+        }
+    }
+
+    /// Feeds raw bytes to the tracker and returns any finished commands.
+    fn feed(&mut self, data: &[u8]) -> Vec<CommandEvent> {
+        let mut events = Vec::new();
+
+        for token in self.scanner.feed(data) {
+            match token {
+                OscToken::Text(text) => match self.phase {
+                    Phase::Input => {
+                        let room = MAX_COMMAND_LINE.saturating_sub(self.echo.len());
+                        self.echo.extend_from_slice(&text[..text.len().min(room)]);
+                    }
+                    Phase::Running => {
+                        let room = self.max_output.saturating_sub(self.output.len());
+                        self.output.extend_from_slice(&text[..text.len().min(room)]);
+                    }
+                    Phase::Idle | Phase::Prompt => {}
+                },
+                OscToken::Osc(payload) => {
+                    if let Some(mark) = parse_mark(&payload) {
+                        events.extend(self.apply(mark));
+                    }
+                }
+                OscToken::Overflow(_) => {}
+            }
+        }
+
+        events
+    }
+
+    /// Applies a single mark, returning the command it finished, if any.
+    fn apply(&mut self, mark: Mark) -> Option<CommandEvent> {
+        match mark {
+            Mark::PromptStart => {
+                // A new prompt without an end mark still ends the command
+                let finished = self.finish(None);
+                self.phase = Phase::Prompt;
+                finished
+            }
+            Mark::CommandStart => {
+                let finished = self.finish(None);
+                self.phase = Phase::Input;
+                self.echo.clear();
+                finished
+            }
+            Mark::OutputStart => {
+                let finished = self.finish(None);
+                let typed = if self.phase == Phase::Input {
+                    clean_command_line(&self.echo)
+                } else {
+                    None
+                };
+                self.command_line = self.explicit_line.take().or(typed);
+                self.echo.clear();
+                self.output.clear();
+                self.command_cwd = self.cwd.clone();
+                self.started = Some(Instant::now());
+                self.phase = Phase::Running;
+                finished
+            }
+            Mark::Finished(code) => {
+                let finished = self.finish(code);
+                self.phase = Phase::Idle;
+                finished
+            }
+            Mark::CommandLine(line) => {
+                if self.phase == Phase::Running {
+                    self.command_line = Some(line);
+                } else {
+                    self.explicit_line = Some(line);
+                }
+                None
+            }
+            Mark::Cwd(cwd) => {
+                self.cwd = Some(cwd);
+                None
+            }
+        }
+    }
+
+    /// Completes the running command, if there is one.
+    fn finish(&mut self, exit_code: Option<i32>) -> Option<CommandEvent> {
+        if self.phase != Phase::Running {
+            return None;
+        }
+        self.phase = Phase::Idle;
+        let finished = Instant::now();
+        Some(CommandEvent {
+            command_line: self.command_line.take(),
+            output: clean_output(&std::mem::take(&mut self.output)),
+            exit_code,
+            started: self.started.take().unwrap_or(finished),
+            finished,
+            cwd: self.command_cwd.take(),
+        })
+    }
+
+    /// Returns true while a command is executing.
+    fn is_running(&self) -> bool {
+        self.phase == Phase::Running
+    }
+}
+
+/// Returns the bash `PROMPT_COMMAND` value that emits OSC 133 marks.
+///
+/// Reports the previous command's exit code and the prompt start, and
+/// re-installs the `PS1` command-start mark and the `PS0` output-start mark
+/// on every prompt so that rc files cannot drop them. The exit code is
+/// captured first, so this must come before any other prompt command.
+pub(crate) fn osc133_prompt_command() -> String {
+    concat!(
+        r#"__st=$?; printf "\033]133;D;%s\a\033]133;A\a" "$__st"; "#,
+        r#"PS0="\033]133;C\a"; "#,
+        r#"case "$PS1" in *"133;B"*) ;; *) PS1="$PS1\[\033]133;B\a\]";; esac"#,
+    )
+    .to_string()
+}
+
+/// Returns the zsh input that installs OSC 133 precmd/preexec hooks.
+pub(crate) fn osc133_zsh_setup() -> &'static str {
+    concat!(
+        "__ssh_precmd(){printf '\\033]133;D;%s\\a\\033]133;A\\a' $?};",
+        "__ssh_preexec(){printf '\\033]133;C\\a'};",
+        "precmd_functions+=(__ssh_precmd);preexec_functions+=(__ssh_preexec);",
+        "PS1=$PS1$'%{\\e]133;B\\a%}';clear\n",
+    )
+}
+
+/// Internal command detection state for tracking shell command boundaries.
+///
+/// Manages the mark tracker and broadcasts finished commands to watchers.
+pub(crate) struct CommandDetection {
+    tracker: Mutex<CommandTracker>,
+    last: Mutex<Option<CommandEvent>>,
+    event_tx: broadcast::Sender<CommandEvent>,
+    enabled: bool,
+}
+
+impl CommandDetection {
+    /// Creates a new command detector with the given configuration.
+    pub(crate) fn new(config: CommandDetectionConfig) -> Self {
+        let (tx, _) = broadcast::channel(EVENT_CAPACITY);
+        Self {
+            tracker: Mutex::new(CommandTracker::new(config.buffer_size, config.max_output)),
+            last: Mutex::new(None),
+            event_tx: tx,
+            enabled: config.enabled,
+        }
+    }
+
+    /// Returns the most recently finished command, if any.
+    pub(crate) fn last_command(&self) -> Option<CommandEvent> {
+        self.last.lock().clone()
+    }
+
+    /// Returns true while the shell reports a command as running.
+    pub(crate) fn is_running(&self) -> bool {
+        self.tracker.lock().is_running()
+    }
+
+    /// Feeds data to the tracker.
+    ///
+    /// If command detection is disabled, this is a no-op.
+    pub(crate) fn feed(&self, data: &[u8]) {
+        if !self.enabled {
+            return;
+        }
+        let events = self.tracker.lock().feed(data);
+        for event in events {
+            *self.last.lock() = Some(event.clone());
+            let _ = self.event_tx.send(event);
+        }
+    }
+
+    /// Creates a new watcher for observing finished commands.
+    pub(crate) fn create_watcher(&self) -> CommandWatcher {
+        CommandWatcher {
+            inner: self.event_tx.subscribe(),
+            last: self.last_command(),
+        }
+    }
+}
+
+/// An async-enabled watcher for commands run in the remote shell.
+///
+/// Unlike the other PTY watchers, every finished command is delivered in
+/// order; a watcher that falls more than 64 commands behind skips the
+/// oldest ones.
+#[derive(Debug)]
+pub struct CommandWatcher {
+    inner: broadcast::Receiver<CommandEvent>,
+    last: Option<CommandEvent>,
+}
+
+impl CommandWatcher {
+    /// Returns the last command this watcher has seen, without waiting.
+    ///
+    /// Starts out as the command that finished most recently before the
+    /// watcher was created.
+    pub fn last(&self) -> Option<&CommandEvent> {
+        self.last.as_ref()
+    }
+
+    /// Returns the next finished command if one is already queued.
+    pub fn try_next(&mut self) -> Option<CommandEvent> {
+        loop {
+            match self.inner.try_recv() {
+                Ok(event) => {
+                    self.last = Some(event.clone());
+                    return Some(event);
+                }
+                Err(broadcast::error::TryRecvError::Lagged(skipped)) => {
+                    log::warn!("Command watcher skipped {} commands", skipped);
+                }
+                Err(_) => return None,
+            }
+        }
+    }
+
+    /// Waits for the next command to finish.
+    ///
+    /// Returns `None` if the PTY session has ended.
+    pub async fn next(&mut self) -> Option<CommandEvent> {
+        loop {
+            match self.inner.recv().await {
+                Ok(event) => {
+                    self.last = Some(event.clone());
+                    return Some(event);
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    log::warn!("Command watcher skipped {} commands", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker() -> CommandTracker {
+        CommandTracker::new(2048, 1024)
+    }
+
+    #[test]
+    fn test_osc133_full_cycle() {
+        let mut t = tracker();
+        assert!(t
+            .feed(b"\x1b]133;A\x07$ \x1b]133;B\x07ls -l\r\n\x1b]133;C\x07")
+            .is_empty());
+        assert!(t.is_running());
+        let events = t.feed(b"a.txt\r\nb.txt\r\n\x1b]133;D;0\x07\x1b]133;A\x07$ ");
+        assert_eq!(events.len(), 1);
+        let event = &events[0];
+        assert_eq!(event.command_line.as_deref(), Some("ls -l"));
+        assert_eq!(event.output, "a.txt\nb.txt\n");
+        assert_eq!(event.exit_code, Some(0));
+        assert!(event.success());
+        assert!(!t.is_running());
+    }
+
+    #[test]
+    fn test_osc133_exit_code_and_st_terminator() {
+        let mut t = tracker();
+        t.feed(b"\x1b]133;B\x1b\\false\r\n\x1b]133;C\x1b\\");
+        let events = t.feed(b"\x1b]133;D;1;aid=42\x1b\\");
+        assert_eq!(events[0].exit_code, Some(1));
+        assert_eq!(events[0].command_line.as_deref(), Some("false"));
+    }
+
+    #[test]
+    fn test_osc133_split_across_chunks() {
+        let mut t = tracker();
+        t.feed(b"\x1b]13");
+        t.feed(b"3;B\x07echo hi\r\n\x1b]133");
+        t.feed(b";C\x07hi\r");
+        let events = t.feed(b"\n\x1b]133;D;");
+        assert!(events.is_empty());
+        let events = t.feed(b"0\x07");
+        assert_eq!(events[0].output, "hi\n");
+        assert_eq!(events[0].command_line.as_deref(), Some("echo hi"));
+    }
+
+    #[test]
+    fn test_osc633_command_line_and_cwd() {
+        let mut t = tracker();
+        t.feed(b"\x1b]633;P;Cwd=/srv/app\x07\x1b]633;A\x07> \x1b]633;B\x07");
+        t.feed(b"\x1b]633;E;echo a\\x3bb \\\\ c;nonce\x07\x1b]633;C\x07");
+        let events = t.feed(b"a\r\n\x1b]633;D;3\x07");
+        assert_eq!(events[0].command_line.as_deref(), Some("echo a;b \\ c"));
+        assert_eq!(events[0].cwd.as_deref(), Some("/srv/app"));
+        assert_eq!(events[0].exit_code, Some(3));
+    }
+
+    #[test]
+    fn test_cwd_from_osc7_applies_to_next_command() {
+        let mut t = tracker();
+        t.feed(b"\x1b]7;file://host/home/user\x07\x1b]133;C\x07");
+        t.feed(b"\x1b]7;file://host/tmp\x07");
+        let events = t.feed(b"\x1b]133;D;0\x07");
+        assert_eq!(events[0].cwd.as_deref(), Some("/home/user"));
+        assert_eq!(events[0].command_line, None);
+    }
+
+    #[test]
+    fn test_end_mark_without_command_is_ignored() {
+        let mut t = tracker();
+        assert!(t.feed(b"\x1b]133;D;0\x07\x1b]133;A\x07$ ").is_empty());
+    }
+
+    #[test]
+    fn test_prompt_without_end_mark_finishes_command() {
+        let mut t = tracker();
+        t.feed(b"\x1b]133;C\x07partial");
+        let events = t.feed(b"^C\r\n\x1b]133;A\x07");
+        assert_eq!(events[0].exit_code, None);
+        assert_eq!(events[0].output, "partial^C\n");
+    }
+
+    #[test]
+    fn test_output_is_stripped_and_capped() {
+        let mut t = CommandTracker::new(2048, 8);
+        t.feed(b"\x1b]133;C\x07\x1b[31mred\x1b[0m and more text");
+        let events = t.feed(b"\x1b]133;D;0\x07");
+        assert_eq!(events[0].output, "red");
+    }
+
+    #[test]
+    fn test_clean_command_line_editing() {
+        assert_eq!(
+            clean_command_line(b"lss\x08 \x08\r\n").as_deref(),
+            Some("ls")
+        );
+        assert_eq!(clean_command_line(b"\r\n"), None);
+    }
+
+    #[tokio::test]
+    async fn test_command_watcher_receives_every_command() {
+        let detection = CommandDetection::new(CommandDetectionConfig {
+            enabled: true,
+            ..CommandDetectionConfig::default()
+        });
+        let mut watcher = detection.create_watcher();
+        detection.feed(b"\x1b]133;C\x07one\x1b]133;D;0\x07\x1b]133;C\x07two\x1b]133;D;2\x07");
+
+        assert_eq!(watcher.next().await.unwrap().output, "one");
+        let second = watcher.next().await.unwrap();
+        assert_eq!(second.output, "two");
+        assert_eq!(second.exit_code, Some(2));
+        assert_eq!(watcher.last().unwrap().output, "two");
+        assert_eq!(detection.last_command().unwrap().output, "two");
+
+        drop(detection);
+        assert!(watcher.next().await.is_none());
+    }
+
+    #[test]
+    fn test_disabled_detection_ignores_output() {
+        let detection = CommandDetection::new(CommandDetectionConfig::default());
+        detection.feed(b"\x1b]133;C\x07x\x1b]133;D;0\x07");
+        assert!(detection.last_command().is_none());
+    }
+
+    #[test]
+    fn test_osc133_prompt_command_captures_status_first() {
+        let cmd = osc133_prompt_command();
+        assert!(cmd.starts_with("__st=$?"));
+        assert!(cmd.contains("PS0="));
+        assert!(!cmd.contains('\''));
+        assert!(osc133_zsh_setup().contains("preexec_functions"));
+    }
+}
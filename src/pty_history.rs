@@ -317,7 +317,7 @@ impl HistoryWatcher {
 /// - CSI sequences: ESC '[' followed by parameters and a final letter
 /// - OSC sequences: ESC ']' followed by payload terminated by BEL ('\x07')
 ///   or ESC '\' (two-byte ST sequence)
-fn strip_ansi_codes(text: &str) -> String {
+pub(crate) fn strip_ansi_codes(text: &str) -> String {
     let mut result = String::with_capacity(text.len());
     let mut chars = text.chars().peekable();
 
@@ -615,7 +615,7 @@ mod tests {
         // Should have around 20 entries (2000 bytes / 100 bytes per line)
         let count = history2.len();
         assert!(
-            count >= 19 && count <= 21,
+            (19..=21).contains(&count),
             "Expected ~20 entries for 2KB limit with 100B lines, got {}",
             count
         );
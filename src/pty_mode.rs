@@ -25,7 +25,10 @@
 use parking_lot::Mutex;
 use std::sync::Arc;
 use std::time::Instant;
-use tokio::sync::watch;
+use tokio::sync::{broadcast, watch};
+
+/// Number of mode changes a lagging watcher may fall behind by.
+const EVENT_CAPACITY: usize = 64;
 
 /// Represents the current PTY screen buffer mode.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -59,6 +62,142 @@ pub struct ModeChangeEvent {
     pub timestamp: Instant,
 }
 
+/// A terminal mode that remote programs can switch on and off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TerminalMode {
+    /// Alternate screen buffer (`?47`, `?1047`, `?1049`)
+    AlternateScreen,
+    /// Bracketed paste (`?2004`)
+    BracketedPaste,
+    /// Application cursor keys (DECCKM, `?1`)
+    ApplicationCursor,
+    /// Application keypad (DECKPAM `ESC =` / DECKPNM `ESC >`, or `?66`)
+    ApplicationKeypad,
+    /// Mouse button press/release reporting (`?1000`)
+    MouseButtons,
+    /// Mouse reporting including drags (`?1002`)
+    MouseDrag,
+    /// Mouse reporting including all motion (`?1003`)
+    MouseMotion,
+    /// SGR extended mouse encoding (`?1006`)
+    MouseSgr,
+    /// Focus in/out reporting (`?1004`)
+    FocusEvents,
+    /// Cursor visibility (DECTCEM, `?25`)
+    CursorVisible,
+}
+
+impl TerminalMode {
+    /// All tracked modes, in a stable order.
+    pub const ALL: [TerminalMode; 10] = [
+        TerminalMode::AlternateScreen,
+        TerminalMode::BracketedPaste,
+        TerminalMode::ApplicationCursor,
+        TerminalMode::ApplicationKeypad,
+        TerminalMode::MouseButtons,
+        TerminalMode::MouseDrag,
+        TerminalMode::MouseMotion,
+        TerminalMode::MouseSgr,
+        TerminalMode::FocusEvents,
+        TerminalMode::CursorVisible,
+    ];
+
+    /// Maps a DEC private mode number to the tracked mode, if any.
+    fn from_dec_private(param: u16) -> Option<Self> {
+        match param {
+            1 => Some(TerminalMode::ApplicationCursor),
+            25 => Some(TerminalMode::CursorVisible),
+            47 | 1047 | 1049 => Some(TerminalMode::AlternateScreen),
+            66 => Some(TerminalMode::ApplicationKeypad),
+            1000 => Some(TerminalMode::MouseButtons),
+            1002 => Some(TerminalMode::MouseDrag),
+            1003 => Some(TerminalMode::MouseMotion),
+            1004 => Some(TerminalMode::FocusEvents),
+            1006 => Some(TerminalMode::MouseSgr),
+            2004 => Some(TerminalMode::BracketedPaste),
+            _ => None,
+        }
+    }
+
+    fn bit(self) -> u16 {
+        1 << self as u16
+    }
+}
+
+/// The set of terminal modes currently enabled by the remote side.
+///
+/// The default state matches a freshly reset terminal: only the cursor is
+/// visible, everything else is off.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TerminalState {
+    bits: u16,
+}
+
+impl Default for TerminalState {
+    fn default() -> Self {
+        Self {
+            bits: TerminalMode::CursorVisible.bit(),
+        }
+    }
+}
+
+impl std::fmt::Debug for TerminalState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.modes()).finish()
+    }
+}
+
+impl TerminalState {
+    /// Returns true if the given mode is enabled.
+    pub fn is_set(&self, mode: TerminalMode) -> bool {
+        self.bits & mode.bit() != 0
+    }
+
+    /// Returns the enabled modes.
+    pub fn modes(&self) -> impl Iterator<Item = TerminalMode> + '_ {
+        TerminalMode::ALL
+            .into_iter()
+            .filter(move |mode| self.is_set(*mode))
+    }
+
+    /// Returns the screen buffer mode.
+    pub fn pty_mode(&self) -> PtyMode {
+        if self.is_set(TerminalMode::AlternateScreen) {
+            PtyMode::Alternate
+        } else {
+            PtyMode::Standard
+        }
+    }
+
+    /// Returns true if any mouse reporting mode is enabled.
+    pub fn mouse_tracking(&self) -> bool {
+        self.is_set(TerminalMode::MouseButtons)
+            || self.is_set(TerminalMode::MouseDrag)
+            || self.is_set(TerminalMode::MouseMotion)
+    }
+
+    /// Returns a copy of this state with `mode` switched on or off.
+    fn with(mut self, mode: TerminalMode, enabled: bool) -> Self {
+        if enabled {
+            self.bits |= mode.bit();
+        } else {
+            self.bits &= !mode.bit();
+        }
+        self
+    }
+}
+
+/// A change of a single terminal mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TerminalModeEvent {
+    /// The mode that changed.
+    pub mode: TerminalMode,
+    /// Whether the mode is now enabled.
+    pub enabled: bool,
+    /// Timestamp of when the change was observed.
+    pub timestamp: Instant,
+}
+
 /// Configuration for mode detection behavior.
 #[derive(Debug, Clone)]
 pub struct ModeDetectionConfig {
@@ -80,8 +219,8 @@ impl Default for ModeDetectionConfig {
 /// Events detected by the escape sequence parser.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum SequenceEvent {
-    EnterAltMode,
-    ExitAltMode,
+    Mode(TerminalMode, bool),
+    FullReset,
 }
 
 enum ParserState {
@@ -91,10 +230,11 @@ enum ParserState {
     ModeSequence,
 }
 
-/// Parser for ANSI escape sequences that detects PTY mode changes.
+/// Parser for ANSI escape sequences that detects terminal mode changes.
 ///
-/// This parser identifies sequences like `\x1b[?1049h` and `\x1b[?1049l`
-/// which indicate transitions between standard and alternate screen buffers.
+/// This parser identifies DEC private mode sequences like `\x1b[?1049h` and
+/// `\x1b[?1000;1006l`, the keypad sequences `ESC =` / `ESC >` and the full
+/// reset `ESC c`.
 struct EscapeSequenceParser {
     buffer: Vec<u8>,
     state: ParserState,
@@ -121,51 +261,53 @@ impl EscapeSequenceParser {
                 ParserState::Normal => {
                     if byte == 0x1b {
                         self.state = ParserState::Escape;
-                        self.buffer.clear();
-                        self.buffer.push(byte);
                     }
                 }
-                ParserState::Escape => {
-                    if self.buffer.len() < self.max_buffer_size {
-                        self.buffer.push(byte);
+                ParserState::Escape => match byte {
+                    b'[' => self.state = ParserState::Csi,
+                    b'=' => {
+                        events.push(SequenceEvent::Mode(TerminalMode::ApplicationKeypad, true));
+                        self.reset();
                     }
-                    if byte == b'[' {
-                        self.state = ParserState::Csi;
-                    } else {
-                        self.state = ParserState::Normal;
-                        self.buffer.clear();
+                    b'>' => {
+                        events.push(SequenceEvent::Mode(TerminalMode::ApplicationKeypad, false));
+                        self.reset();
                     }
-                }
-                ParserState::Csi => {
-                    if self.buffer.len() < self.max_buffer_size {
-                        self.buffer.push(byte);
+                    b'c' => {
+                        events.push(SequenceEvent::FullReset);
+                        self.reset();
                     }
+                    0x1b => {}
+                    _ => self.reset(),
+                },
+                ParserState::Csi => {
                     if byte == b'?' {
                         self.state = ParserState::ModeSequence;
-                    } else if byte.is_ascii_digit() || byte == b';' {
+                    } else if byte == 0x1b {
+                        self.state = ParserState::Escape;
                     } else {
-                        self.state = ParserState::Normal;
-                        self.buffer.clear();
+                        self.reset();
                     }
                 }
                 ParserState::ModeSequence => {
-                    if self.buffer.len() >= self.max_buffer_size {
-                        self.reset();
-                    } else {
-                        self.buffer.push(byte);
-                        if byte == b'h' {
-                            if self.is_alt_mode_sequence() {
-                                events.push(SequenceEvent::EnterAltMode);
-                            }
-                            self.reset();
-                        } else if byte == b'l' {
-                            if self.is_alt_mode_sequence() {
-                                events.push(SequenceEvent::ExitAltMode);
-                            }
-                            self.reset();
-                        } else if !byte.is_ascii_digit() {
+                    if byte.is_ascii_digit() || byte == b';' {
+                        if self.buffer.len() >= self.max_buffer_size {
                             self.reset();
+                        } else {
+                            self.buffer.push(byte);
                         }
+                    } else if byte == b'h' || byte == b'l' {
+                        let enabled = byte == b'h';
+                        events.extend(
+                            self.mode_params()
+                                .map(|mode| SequenceEvent::Mode(mode, enabled)),
+                        );
+                        self.reset();
+                    } else if byte == 0x1b {
+                        self.reset();
+                        self.state = ParserState::Escape;
+                    } else {
+                        self.reset();
                     }
                 }
             }
@@ -174,13 +316,12 @@ impl EscapeSequenceParser {
         events
     }
 
-    /// Checks if the current buffer contains a recognized alternate mode sequence.
-    fn is_alt_mode_sequence(&self) -> bool {
-        if self.buffer.len() < 4 {
-            return false;
-        }
-        let seq = &self.buffer[2..];
-        seq == b"?47h" || seq == b"?47l" || seq == b"?1049h" || seq == b"?1049l"
+    /// Returns the recognized modes named by the buffered parameters.
+    fn mode_params(&self) -> impl Iterator<Item = TerminalMode> + '_ {
+        self.buffer
+            .split(|&b| b == b';')
+            .filter_map(|param| std::str::from_utf8(param).ok()?.parse().ok())
+            .filter_map(TerminalMode::from_dec_private)
     }
 
     /// Resets the parser state and clears the buffer.
@@ -190,41 +331,63 @@ impl EscapeSequenceParser {
     }
 }
 
-/// Internal PTY mode detection state for tracking screen buffer changes.
+/// Internal PTY mode detection state for tracking terminal mode changes.
 ///
-/// Manages the escape sequence parser and broadcasts mode changes to watchers.
+/// Manages the escape sequence parser, publishes the resulting state and
+/// broadcasts every single mode change to watchers.
 pub(crate) struct ModeDetection {
-    current_mode: Arc<Mutex<PtyMode>>,
+    state: Arc<Mutex<TerminalState>>,
     parser: Mutex<EscapeSequenceParser>,
-    event_tx: watch::Sender<PtyMode>,
-    event_rx: watch::Receiver<PtyMode>,
+    event_tx: watch::Sender<TerminalState>,
+    event_rx: watch::Receiver<TerminalState>,
+    mode_tx: broadcast::Sender<TerminalModeEvent>,
     enabled: bool,
 }
 
 impl ModeDetection {
     /// Creates a new mode detector with the given configuration.
     pub(crate) fn new(config: ModeDetectionConfig) -> Self {
-        let (tx, rx) = watch::channel(PtyMode::Standard);
+        let (tx, rx) = watch::channel(TerminalState::default());
+        let (mode_tx, _) = broadcast::channel(EVENT_CAPACITY);
         Self {
-            current_mode: Arc::new(Mutex::new(PtyMode::Standard)),
+            state: Arc::new(Mutex::new(TerminalState::default())),
             parser: Mutex::new(EscapeSequenceParser::new(config.buffer_size)),
             event_tx: tx,
             event_rx: rx,
+            mode_tx,
             enabled: config.enabled,
         }
     }
 
     /// Returns the current PTY mode without blocking.
     pub(crate) fn current_mode(&self) -> PtyMode {
-        *self.current_mode.lock()
+        self.state.lock().pty_mode()
     }
 
-    /// Updates the current mode and notifies all watchers.
-    pub(crate) fn update_mode(&self, new_mode: PtyMode) {
-        let mut current = self.current_mode.lock();
-        if *current != new_mode {
-            *current = new_mode;
-            let _ = self.event_tx.send(new_mode);
+    /// Returns the current terminal state without blocking.
+    pub(crate) fn current_state(&self) -> TerminalState {
+        *self.state.lock()
+    }
+
+    /// Updates the current state and notifies all watchers.
+    ///
+    /// Every mode that differs between the old and the new state is
+    /// broadcast as its own [`TerminalModeEvent`].
+    fn update_state(&self, new_state: TerminalState) {
+        let mut current = self.state.lock();
+        if *current != new_state {
+            let timestamp = Instant::now();
+            for mode in TerminalMode::ALL {
+                if current.is_set(mode) != new_state.is_set(mode) {
+                    let _ = self.mode_tx.send(TerminalModeEvent {
+                        mode,
+                        enabled: new_state.is_set(mode),
+                        timestamp,
+                    });
+                }
+            }
+            *current = new_state;
+            let _ = self.event_tx.send(new_state);
         }
     }
 
@@ -238,32 +401,64 @@ impl ModeDetection {
         let mut parser = self.parser.lock();
         let events = parser.feed(data);
         for event in events {
-            match event {
-                SequenceEvent::EnterAltMode => self.update_mode(PtyMode::Alternate),
-                SequenceEvent::ExitAltMode => self.update_mode(PtyMode::Standard),
-            }
+            let state = match event {
+                SequenceEvent::Mode(mode, enabled) => self.current_state().with(mode, enabled),
+                SequenceEvent::FullReset => TerminalState::default(),
+            };
+            self.update_state(state);
         }
     }
 
     /// Creates a new watcher for observing mode changes.
     pub(crate) fn create_watcher(&self) -> ModeWatcher {
-        ModeWatcher {
-            inner: self.event_rx.clone(),
-            last_known: *self.current_mode.lock(),
-        }
+        ModeWatcher::new(self.event_rx.clone(), self.mode_tx.subscribe())
     }
 }
 
 /// An async-enabled watcher for PTY mode changes.
-#[derive(Debug, Clone)]
+///
+/// [`changed`](Self::changed), [`wait_for`](Self::wait_for) and
+/// [`next_event`](Self::next_event) follow the screen buffer only, while
+/// [`next_mode_event`](Self::next_mode_event) and
+/// [`wait_for_mode`](Self::wait_for_mode) cover every [`TerminalMode`].
+#[derive(Debug)]
 pub struct ModeWatcher {
-    inner: watch::Receiver<PtyMode>,
+    inner: watch::Receiver<TerminalState>,
+    events: broadcast::Receiver<TerminalModeEvent>,
     last_known: PtyMode,
 }
 
+/// A clone receives the mode events sent after it was created.
+impl Clone for ModeWatcher {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            events: self.events.resubscribe(),
+            last_known: self.last_known,
+        }
+    }
+}
+
 impl ModeWatcher {
+    fn new(
+        inner: watch::Receiver<TerminalState>,
+        events: broadcast::Receiver<TerminalModeEvent>,
+    ) -> Self {
+        let last_known = inner.borrow().pty_mode();
+        Self {
+            inner,
+            events,
+            last_known,
+        }
+    }
+
     /// Returns the current mode without waiting.
     pub fn current(&self) -> PtyMode {
+        self.inner.borrow().pty_mode()
+    }
+
+    /// Returns the current terminal state without waiting.
+    pub fn state(&self) -> TerminalState {
         *self.inner.borrow()
     }
 
@@ -272,13 +467,13 @@ impl ModeWatcher {
     /// This is an async method that can be awaited.
     /// Returns `None` if the PTY session has ended.
     pub async fn changed(&mut self) -> Option<PtyMode> {
-        match self.inner.changed().await {
-            Ok(()) => {
-                let mode = *self.inner.borrow();
+        loop {
+            self.inner.changed().await.ok()?;
+            let mode = self.inner.borrow().pty_mode();
+            if mode != self.last_known {
                 self.last_known = mode;
-                Some(mode)
+                return Some(mode);
             }
-            Err(_) => None,
         }
     }
 
@@ -312,27 +507,87 @@ impl ModeWatcher {
             timestamp: Instant::now(),
         })
     }
+
+    /// Waits for the next change of any single terminal mode.
+    ///
+    /// Like [`NotificationWatcher`](crate::pty_notify::NotificationWatcher),
+    /// every change is delivered in order, including a mode that is switched
+    /// on and back off within one chunk of output; a watcher that falls more
+    /// than 64 changes behind skips the oldest ones.
+    /// Returns `None` if the PTY session has ended.
+    pub async fn next_mode_event(&mut self) -> Option<TerminalModeEvent> {
+        loop {
+            match self.events.recv().await {
+                Ok(event) => return Some(event),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    log::warn!("Mode watcher skipped {} mode changes", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Waits until `mode` is switched on (`enabled == true`) or off.
+    ///
+    /// Returns immediately if the mode is already in the requested state.
+    /// Returns `None` if the PTY session ends first.
+    pub async fn wait_for_mode(
+        &mut self,
+        mode: TerminalMode,
+        enabled: bool,
+    ) -> Option<TerminalState> {
+        loop {
+            let state = *self.inner.borrow_and_update();
+            if state.is_set(mode) == enabled {
+                return Some(state);
+            }
+            self.inner.changed().await.ok()?;
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    const ENTER_ALT: SequenceEvent = SequenceEvent::Mode(TerminalMode::AlternateScreen, true);
+    const EXIT_ALT: SequenceEvent = SequenceEvent::Mode(TerminalMode::AlternateScreen, false);
+
+    fn state_with(modes: &[TerminalMode]) -> TerminalState {
+        modes.iter().fold(TerminalState::default(), |state, mode| {
+            state.with(*mode, true)
+        })
+    }
+
+    fn watcher(rx: watch::Receiver<TerminalState>, last_known: PtyMode) -> ModeWatcher {
+        let (_, events) = broadcast::channel(EVENT_CAPACITY);
+        let mut watcher = ModeWatcher::new(rx, events);
+        watcher.last_known = last_known;
+        watcher
+    }
+
+    fn detection() -> ModeDetection {
+        ModeDetection::new(ModeDetectionConfig {
+            enabled: true,
+            ..Default::default()
+        })
+    }
+
     #[test]
     fn test_parser_complete_sequences() {
         let mut parser = EscapeSequenceParser::new(256);
 
         let events = parser.feed(b"\x1b[?1049h");
-        assert_eq!(events, vec![SequenceEvent::EnterAltMode]);
+        assert_eq!(events, vec![ENTER_ALT]);
 
         let events = parser.feed(b"\x1b[?1049l");
-        assert_eq!(events, vec![SequenceEvent::ExitAltMode]);
+        assert_eq!(events, vec![EXIT_ALT]);
 
         let events = parser.feed(b"\x1b[?47h");
-        assert_eq!(events, vec![SequenceEvent::EnterAltMode]);
+        assert_eq!(events, vec![ENTER_ALT]);
 
         let events = parser.feed(b"\x1b[?47l");
-        assert_eq!(events, vec![SequenceEvent::ExitAltMode]);
+        assert_eq!(events, vec![EXIT_ALT]);
     }
 
     #[test]
@@ -346,7 +601,7 @@ mod tests {
         assert_eq!(events, vec![]);
 
         let events = parser.feed(b"h");
-        assert_eq!(events, vec![SequenceEvent::EnterAltMode]);
+        assert_eq!(events, vec![ENTER_ALT]);
     }
 
     #[test]
@@ -354,10 +609,10 @@ mod tests {
         let mut parser = EscapeSequenceParser::new(256);
 
         let events = parser.feed(b"hello world\x1b[?1049hsome text");
-        assert_eq!(events, vec![SequenceEvent::EnterAltMode]);
+        assert_eq!(events, vec![ENTER_ALT]);
 
         let events = parser.feed(b"\x1b[?1049lmore text");
-        assert_eq!(events, vec![SequenceEvent::ExitAltMode]);
+        assert_eq!(events, vec![EXIT_ALT]);
     }
 
     #[test]
@@ -379,32 +634,105 @@ mod tests {
         let mut parser = EscapeSequenceParser::new(256);
 
         let events = parser.feed(&[0x00, 0xff, 0x1b, 0x5b, 0x3f, 0x31, 0x30, 0x34, 0x39, 0x68]);
-        assert_eq!(events, vec![SequenceEvent::EnterAltMode]);
+        assert_eq!(events, vec![ENTER_ALT]);
+    }
+
+    #[test]
+    fn test_parser_terminal_modes() {
+        let mut parser = EscapeSequenceParser::new(256);
+
+        let events = parser.feed(b"\x1b[?1h\x1b=\x1b[?2004h\x1b[?25l\x1b[?1004h");
+        assert_eq!(
+            events,
+            vec![
+                SequenceEvent::Mode(TerminalMode::ApplicationCursor, true),
+                SequenceEvent::Mode(TerminalMode::ApplicationKeypad, true),
+                SequenceEvent::Mode(TerminalMode::BracketedPaste, true),
+                SequenceEvent::Mode(TerminalMode::CursorVisible, false),
+                SequenceEvent::Mode(TerminalMode::FocusEvents, true),
+            ]
+        );
+
+        let events = parser.feed(b"\x1b[?1000;1002;1003;1006;9999l\x1b>\x1bc");
+        assert_eq!(
+            events,
+            vec![
+                SequenceEvent::Mode(TerminalMode::MouseButtons, false),
+                SequenceEvent::Mode(TerminalMode::MouseDrag, false),
+                SequenceEvent::Mode(TerminalMode::MouseMotion, false),
+                SequenceEvent::Mode(TerminalMode::MouseSgr, false),
+                SequenceEvent::Mode(TerminalMode::ApplicationKeypad, false),
+                SequenceEvent::FullReset,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mode_detection_state() {
+        let detection = ModeDetection::new(ModeDetectionConfig {
+            enabled: true,
+            ..Default::default()
+        });
+
+        detection.feed(b"\x1b[?1049h\x1b[?1000;1006h\x1b[?25l");
+        let state = detection.current_state();
+        assert_eq!(detection.current_mode(), PtyMode::Alternate);
+        assert!(state.mouse_tracking());
+        assert!(state.is_set(TerminalMode::MouseSgr));
+        assert!(!state.is_set(TerminalMode::CursorVisible));
+        assert_eq!(
+            state.modes().collect::<Vec<_>>(),
+            vec![
+                TerminalMode::AlternateScreen,
+                TerminalMode::MouseButtons,
+                TerminalMode::MouseSgr,
+            ]
+        );
+
+        detection.feed(b"\x1bc");
+        assert_eq!(detection.current_state(), TerminalState::default());
+        assert_eq!(detection.current_mode(), PtyMode::Standard);
     }
 
     #[tokio::test]
     async fn test_mode_watcher_changed() {
-        let (tx, rx) = watch::channel(PtyMode::Standard);
-        let mut watcher = ModeWatcher {
-            inner: rx,
-            last_known: PtyMode::Standard,
-        };
+        let (tx, rx) = watch::channel(TerminalState::default());
+        let mut watcher = watcher(rx, PtyMode::Standard);
 
         tokio::spawn(async move {
-            tx.send(PtyMode::Alternate).unwrap();
+            tx.send(state_with(&[TerminalMode::AlternateScreen]))
+                .unwrap();
         });
 
         let mode = watcher.changed().await;
         assert_eq!(mode, Some(PtyMode::Alternate));
     }
 
+    #[tokio::test]
+    async fn test_mode_watcher_ignores_other_modes() {
+        let (tx, rx) = watch::channel(TerminalState::default());
+        let mut watcher = watcher(rx, PtyMode::Standard);
+
+        tokio::spawn(async move {
+            tx.send(state_with(&[TerminalMode::BracketedPaste]))
+                .unwrap();
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            tx.send(state_with(&[
+                TerminalMode::BracketedPaste,
+                TerminalMode::AlternateScreen,
+            ]))
+            .unwrap();
+        });
+
+        let event = watcher.next_event().await.unwrap();
+        assert_eq!(event.previous, PtyMode::Standard);
+        assert_eq!(event.current, PtyMode::Alternate);
+    }
+
     #[tokio::test]
     async fn test_watcher_wait_for_immediate() {
-        let (_tx, rx) = watch::channel(PtyMode::Alternate);
-        let mut watcher = ModeWatcher {
-            inner: rx,
-            last_known: PtyMode::Standard,
-        };
+        let (_tx, rx) = watch::channel(state_with(&[TerminalMode::AlternateScreen]));
+        let mut watcher = watcher(rx, PtyMode::Standard);
 
         let mode = watcher.wait_for(PtyMode::Alternate).await;
         assert_eq!(mode, Some(PtyMode::Alternate));
@@ -412,15 +740,13 @@ mod tests {
 
     #[tokio::test]
     async fn test_watcher_wait_for_with_change() {
-        let (tx, rx) = watch::channel(PtyMode::Standard);
-        let mut watcher = ModeWatcher {
-            inner: rx,
-            last_known: PtyMode::Standard,
-        };
+        let (tx, rx) = watch::channel(TerminalState::default());
+        let mut watcher = watcher(rx, PtyMode::Standard);
 
         tokio::spawn(async move {
             tokio::time::sleep(std::time::Duration::from_millis(10)).await;
-            tx.send(PtyMode::Alternate).unwrap();
+            tx.send(state_with(&[TerminalMode::AlternateScreen]))
+                .unwrap();
         });
 
         let mode = watcher.wait_for(PtyMode::Alternate).await;
@@ -429,14 +755,12 @@ mod tests {
 
     #[tokio::test]
     async fn test_watcher_next_event() {
-        let (tx, rx) = watch::channel(PtyMode::Standard);
-        let mut watcher = ModeWatcher {
-            inner: rx,
-            last_known: PtyMode::Standard,
-        };
+        let (tx, rx) = watch::channel(TerminalState::default());
+        let mut watcher = watcher(rx, PtyMode::Standard);
 
         tokio::spawn(async move {
-            tx.send(PtyMode::Alternate).unwrap();
+            tx.send(state_with(&[TerminalMode::AlternateScreen]))
+                .unwrap();
         });
 
         let event = watcher.next_event().await;
@@ -446,19 +770,102 @@ mod tests {
         assert_eq!(event.current, PtyMode::Alternate);
     }
 
+    #[tokio::test]
+    async fn test_watcher_next_mode_event() {
+        let detection = detection();
+        let mut watcher = detection.create_watcher();
+
+        detection.feed(b"\x1b[?2004h\x1b[?1004h\x1b[?25l\x1b[?2004h");
+
+        let mut events = Vec::new();
+        for _ in 0..3 {
+            let event = watcher.next_mode_event().await.unwrap();
+            events.push((event.mode, event.enabled));
+        }
+        assert_eq!(
+            events,
+            vec![
+                (TerminalMode::BracketedPaste, true),
+                (TerminalMode::FocusEvents, true),
+                (TerminalMode::CursorVisible, false),
+            ]
+        );
+
+        drop(detection);
+        assert!(watcher.next_mode_event().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_watcher_mode_toggle_in_one_chunk() {
+        let detection = detection();
+        let mut watcher = detection.create_watcher();
+
+        detection.feed(b"\x1b[?2004hpasted\x1b[?2004l");
+        assert!(!detection
+            .current_state()
+            .is_set(TerminalMode::BracketedPaste));
+
+        let on = watcher.next_mode_event().await.unwrap();
+        assert_eq!((on.mode, on.enabled), (TerminalMode::BracketedPaste, true));
+        let off = watcher.next_mode_event().await.unwrap();
+        assert_eq!(
+            (off.mode, off.enabled),
+            (TerminalMode::BracketedPaste, false)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_watcher_full_reset_events() {
+        let detection = detection();
+        detection.feed(b"\x1b[?1049h\x1b[?25l");
+        let mut watcher = detection.create_watcher();
+
+        detection.feed(b"\x1bc");
+        let mut events = Vec::new();
+        for _ in 0..2 {
+            let event = watcher.next_mode_event().await.unwrap();
+            events.push((event.mode, event.enabled));
+        }
+        assert_eq!(
+            events,
+            vec![
+                (TerminalMode::AlternateScreen, false),
+                (TerminalMode::CursorVisible, true),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_watcher_wait_for_mode() {
+        let (tx, rx) = watch::channel(TerminalState::default());
+        let mut watcher = watcher(rx, PtyMode::Standard);
+
+        let state = watcher
+            .wait_for_mode(TerminalMode::CursorVisible, true)
+            .await;
+        assert_eq!(state, Some(TerminalState::default()));
+
+        tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            tx.send(state_with(&[TerminalMode::BracketedPaste]))
+                .unwrap();
+        });
+
+        let state = watcher
+            .wait_for_mode(TerminalMode::BracketedPaste, true)
+            .await
+            .unwrap();
+        assert!(state.is_set(TerminalMode::BracketedPaste));
+    }
+
     #[tokio::test]
     async fn test_multiple_watchers() {
-        let (tx, rx) = watch::channel(PtyMode::Standard);
-        let watcher1 = ModeWatcher {
-            inner: rx.clone(),
-            last_known: PtyMode::Standard,
-        };
-        let watcher2 = ModeWatcher {
-            inner: rx,
-            last_known: PtyMode::Standard,
-        };
-
-        tx.send(PtyMode::Alternate).unwrap();
+        let (tx, rx) = watch::channel(TerminalState::default());
+        let watcher1 = watcher(rx.clone(), PtyMode::Standard);
+        let watcher2 = watcher(rx, PtyMode::Standard);
+
+        tx.send(state_with(&[TerminalMode::AlternateScreen]))
+            .unwrap();
 
         assert_eq!(watcher1.current(), PtyMode::Alternate);
         assert_eq!(watcher2.current(), PtyMode::Alternate);
@@ -472,6 +879,18 @@ mod tests {
         assert!(!PtyMode::Alternate.is_standard());
     }
 
+    #[test]
+    fn test_terminal_state_default() {
+        let state = TerminalState::default();
+        assert_eq!(
+            state.modes().collect::<Vec<_>>(),
+            vec![TerminalMode::CursorVisible]
+        );
+        assert_eq!(state.pty_mode(), PtyMode::Standard);
+        assert!(!state.mouse_tracking());
+        assert_eq!(format!("{:?}", state), "{CursorVisible}");
+    }
+
     #[test]
     fn test_mode_detection_config_default() {
         let config = ModeDetectionConfig::default();
@@ -0,0 +1,291 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2025 Julian Kahlert
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use parking_lot::Mutex;
+use std::time::Instant;
+use tokio::sync::broadcast;
+
+use crate::pty_osc::{OscScanner, OscToken};
+use crate::pty_title::sanitize;
+
+/// Number of notifications a lagging watcher may fall behind by.
+const EVENT_CAPACITY: usize = 64;
+
+/// How the remote side asked for attention.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationKind {
+    /// A BEL character outside of any escape sequence.
+    Bell,
+    /// An OSC 9 desktop notification (iTerm2, Windows Terminal, ...).
+    Osc9,
+    /// An OSC 777 `notify` desktop notification (urxvt, foot, ...).
+    Osc777,
+}
+
+/// A bell or desktop notification emitted by the remote side.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    /// Which sequence produced the notification.
+    pub kind: NotificationKind,
+    /// The notification title; only OSC 777 carries one.
+    pub title: Option<String>,
+    /// The notification text; empty for a bell.
+    pub body: String,
+    /// Timestamp of when the notification was detected.
+    pub timestamp: Instant,
+}
+
+impl Notification {
+    /// Returns true if this is a plain terminal bell.
+    pub fn is_bell(&self) -> bool {
+        self.kind == NotificationKind::Bell
+    }
+}
+
+/// Configuration for bell and notification detection.
+#[derive(Debug, Clone)]
+pub struct NotificationDetectionConfig {
+    /// Whether to enable notification detection (default: false)
+    pub enabled: bool,
+    /// Buffer size for OSC payload parsing (default: 1024 bytes)
+    pub buffer_size: usize,
+}
+
+impl Default for NotificationDetectionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            buffer_size: 1024,
+        }
+    }
+}
+
+/// Parses an OSC payload into a notification kind, title and body.
+///
+/// ConEmu overloads OSC 9 with numeric sub-commands such as `9;4;1;50`
+/// for progress reports; those are not notifications and are ignored.
+fn parse_notification_payload(
+    payload: &[u8],
+) -> Option<(NotificationKind, Option<String>, String)> {
+    let text = String::from_utf8_lossy(payload);
+    if let Some(message) = text.strip_prefix("9;") {
+        let code = message.split(';').next().unwrap_or_default();
+        if !code.is_empty() && code.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        return Some((NotificationKind::Osc9, None, sanitize(message)));
+    }
+    let mut parts = text.strip_prefix("777;notify;")?.splitn(2, ';');
+    let title = sanitize(parts.next().unwrap_or_default());
+    let body = sanitize(parts.next().unwrap_or_default());
+    Some((NotificationKind::Osc777, Some(title), body))
+}
+
+/// Internal notification detection state.
+///
+/// Manages the OSC scanner and broadcasts bells and notifications to watchers.
+pub(crate) struct NotificationDetection {
+    scanner: Mutex<OscScanner>,
+    last: Mutex<Option<Notification>>,
+    event_tx: broadcast::Sender<Notification>,
+    enabled: bool,
+}
+
+impl NotificationDetection {
+    /// Creates a new notification detector with the given configuration.
+    pub(crate) fn new(config: NotificationDetectionConfig) -> Self {
+        let (tx, _) = broadcast::channel(EVENT_CAPACITY);
+        Self {
+            scanner: Mutex::new(OscScanner::new(config.buffer_size)),
+            last: Mutex::new(None),
+            event_tx: tx,
+            enabled: config.enabled,
+        }
+    }
+
+    /// Returns the most recent notification, if any.
+    pub(crate) fn last_notification(&self) -> Option<Notification> {
+        self.last.lock().clone()
+    }
+
+    /// Records a notification and sends it to all watchers.
+    fn notify(&self, kind: NotificationKind, title: Option<String>, body: String) {
+        let notification = Notification {
+            kind,
+            title,
+            body,
+            timestamp: Instant::now(),
+        };
+        *self.last.lock() = Some(notification.clone());
+        let _ = self.event_tx.send(notification);
+    }
+
+    /// Feeds data to the OSC scanner.
+    ///
+    /// Several bells within one chunk of output are reported once, so that
+    /// printing binary data cannot flood watchers. If notification detection
+    /// is disabled, this is a no-op.
+    pub(crate) fn feed(&self, data: &[u8]) {
+        if !self.enabled {
+            return;
+        }
+        let tokens = self.scanner.lock().feed(data);
+        let mut bell = false;
+        for token in tokens {
+            match token {
+                OscToken::Text(text) => {
+                    if !bell && text.contains(&0x07) {
+                        bell = true;
+                        self.notify(NotificationKind::Bell, None, String::new());
+                    }
+                }
+                OscToken::Osc(payload) => {
+                    if let Some((kind, title, body)) = parse_notification_payload(&payload) {
+                        self.notify(kind, title, body);
+                    }
+                }
+                OscToken::Overflow(_) => {}
+            }
+        }
+    }
+
+    /// Creates a new watcher for observing notifications.
+    pub(crate) fn create_watcher(&self) -> NotificationWatcher {
+        NotificationWatcher {
+            inner: self.event_tx.subscribe(),
+        }
+    }
+}
+
+/// An async-enabled watcher for bells and desktop notifications.
+///
+/// Like [`CommandWatcher`](crate::pty_command::CommandWatcher), every
+/// notification is delivered in order; a watcher that falls more than 64
+/// notifications behind skips the oldest ones.
+#[derive(Debug)]
+pub struct NotificationWatcher {
+    inner: broadcast::Receiver<Notification>,
+}
+
+impl NotificationWatcher {
+    /// Returns the next notification if one is already queued.
+    pub fn try_next(&mut self) -> Option<Notification> {
+        loop {
+            match self.inner.try_recv() {
+                Ok(notification) => return Some(notification),
+                Err(broadcast::error::TryRecvError::Lagged(skipped)) => {
+                    log::warn!("Notification watcher skipped {} notifications", skipped);
+                }
+                Err(_) => return None,
+            }
+        }
+    }
+
+    /// Waits for the next bell or notification.
+    ///
+    /// Returns `None` if the PTY session has ended.
+    pub async fn next(&mut self) -> Option<Notification> {
+        loop {
+            match self.inner.recv().await {
+                Ok(notification) => return Some(notification),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    log::warn!("Notification watcher skipped {} notifications", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detection() -> NotificationDetection {
+        NotificationDetection::new(NotificationDetectionConfig {
+            enabled: true,
+            ..NotificationDetectionConfig::default()
+        })
+    }
+
+    #[test]
+    fn test_parse_notification_payloads() {
+        assert_eq!(
+            parse_notification_payload(b"9;build done"),
+            Some((NotificationKind::Osc9, None, "build done".to_string()))
+        );
+        assert_eq!(parse_notification_payload(b"9;4;1;50"), None);
+        assert_eq!(
+            parse_notification_payload(b"777;notify;make;done; 0 errors\x01"),
+            Some((
+                NotificationKind::Osc777,
+                Some("make".to_string()),
+                "done; 0 errors".to_string()
+            ))
+        );
+        assert_eq!(
+            parse_notification_payload(b"777;notify;title"),
+            Some((
+                NotificationKind::Osc777,
+                Some("title".to_string()),
+                String::new()
+            ))
+        );
+        assert_eq!(parse_notification_payload(b"777;preexec"), None);
+        assert_eq!(parse_notification_payload(b"2;title"), None);
+    }
+
+    #[tokio::test]
+    async fn test_notification_watcher_events() {
+        let d = detection();
+        let mut watcher = d.create_watcher();
+
+        d.feed(b"\x1b]2;title\x07no bell here");
+        assert!(watcher.try_next().is_none());
+
+        d.feed(b"ding\x07\x07\x1b]9;hello\x1b\\");
+        let bell = watcher.next().await.unwrap();
+        assert!(bell.is_bell());
+        let message = watcher.next().await.unwrap();
+        assert_eq!(message.kind, NotificationKind::Osc9);
+        assert_eq!(message.body, "hello");
+        assert!(watcher.try_next().is_none());
+
+        d.feed(b"\x1b]777;notify;t");
+        d.feed(b";b\x07");
+        let message = watcher.next().await.unwrap();
+        assert_eq!(message.title.as_deref(), Some("t"));
+        assert_eq!(d.last_notification(), Some(message));
+
+        drop(d);
+        assert!(watcher.next().await.is_none());
+    }
+
+    #[test]
+    fn test_disabled_detection_ignores_output() {
+        let d = NotificationDetection::new(NotificationDetectionConfig::default());
+        d.feed(b"\x07\x1b]9;hi\x07");
+        assert_eq!(d.last_notification(), None);
+    }
+}
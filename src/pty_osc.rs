@@ -0,0 +1,221 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2025 Julian Kahlert
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+/// A piece of PTY output split by [`OscScanner`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum OscToken {
+    /// Output outside of OSC sequences, including other escape sequences.
+    Text(Vec<u8>),
+    /// The payload of a complete OSC sequence, without introducer or terminator.
+    Osc(Vec<u8>),
+    /// The first buffer-size bytes of an OSC payload that was too long.
+    Overflow(Vec<u8>),
+}
+
+/// States for the OSC scanner.
+enum ScanState {
+    Normal,
+    Escape,
+    Osc,
+    OscEscape,
+    Overflow,
+    OverflowEscape,
+}
+
+/// Stateful scanner that separates OSC sequences from the surrounding output.
+///
+/// OSC sequences start with `ESC ]` and end with BEL or ST (`ESC \`), and may
+/// be split across any number of chunks. Payloads longer than the buffer size
+/// are skipped up to their terminator so that they never leak into the text,
+/// and reported as [`OscToken::Overflow`] with the part that fit.
+pub(crate) struct OscScanner {
+    buffer: Vec<u8>,
+    state: ScanState,
+    max_buffer_size: usize,
+}
+
+impl OscScanner {
+    /// Creates a new scanner with the specified maximum payload size.
+    pub(crate) fn new(buffer_size: usize) -> Self {
+        let buffer_size = buffer_size.max(64);
+        Self {
+            buffer: Vec::new(),
+            state: ScanState::Normal,
+            max_buffer_size: buffer_size,
+        }
+    }
+
+    /// Feeds raw bytes to the scanner and returns the text and OSC payloads in order.
+    ///
+    /// A trailing `ESC` that may start an OSC sequence is held back until the
+    /// next chunk decides what it is.
+    pub(crate) fn feed(&mut self, data: &[u8]) -> Vec<OscToken> {
+        let mut tokens = Vec::new();
+        let mut text = Vec::new();
+
+        for &byte in data {
+            match self.state {
+                ScanState::Normal => {
+                    if byte == 0x1b {
+                        self.state = ScanState::Escape;
+                    } else {
+                        text.push(byte);
+                    }
+                }
+                ScanState::Escape => {
+                    if byte == b']' {
+                        self.state = ScanState::Osc;
+                        self.buffer.clear();
+                    } else {
+                        text.push(0x1b);
+                        if byte != 0x1b {
+                            text.push(byte);
+                            self.state = ScanState::Normal;
+                        }
+                    }
+                }
+                ScanState::Osc => {
+                    if byte == 0x07 {
+                        Self::flush_text(&mut tokens, &mut text);
+                        tokens.push(OscToken::Osc(std::mem::take(&mut self.buffer)));
+                        self.state = ScanState::Normal;
+                    } else if byte == 0x1b {
+                        self.state = ScanState::OscEscape;
+                    } else if self.buffer.len() < self.max_buffer_size {
+                        self.buffer.push(byte);
+                    } else {
+                        self.state = ScanState::Overflow;
+                    }
+                }
+                ScanState::OscEscape => {
+                    if byte == b'\\' {
+                        Self::flush_text(&mut tokens, &mut text);
+                        tokens.push(OscToken::Osc(std::mem::take(&mut self.buffer)));
+                        self.state = ScanState::Normal;
+                    } else {
+                        // Not ST — discard and treat as a new escape
+                        self.buffer.clear();
+                        self.state = ScanState::Escape;
+                        self.resume_escape(byte, &mut text);
+                    }
+                }
+                ScanState::Overflow => {
+                    if byte == 0x07 {
+                        Self::flush_text(&mut tokens, &mut text);
+                        tokens.push(OscToken::Overflow(std::mem::take(&mut self.buffer)));
+                        self.state = ScanState::Normal;
+                    } else if byte == 0x1b {
+                        self.state = ScanState::OverflowEscape;
+                    }
+                }
+                ScanState::OverflowEscape => {
+                    if byte == b'\\' {
+                        Self::flush_text(&mut tokens, &mut text);
+                        tokens.push(OscToken::Overflow(std::mem::take(&mut self.buffer)));
+                        self.state = ScanState::Normal;
+                    } else {
+                        self.buffer.clear();
+                        self.state = ScanState::Escape;
+                        self.resume_escape(byte, &mut text);
+                    }
+                }
+            }
+        }
+
+        Self::flush_text(&mut tokens, &mut text);
+        tokens
+    }
+
+    /// Handles the byte following an `ESC` that interrupted an OSC sequence.
+    fn resume_escape(&mut self, byte: u8, text: &mut Vec<u8>) {
+        if byte == b']' {
+            self.state = ScanState::Osc;
+        } else if byte != 0x1b {
+            text.push(0x1b);
+            text.push(byte);
+            self.state = ScanState::Normal;
+        }
+    }
+
+    /// Moves accumulated text into the token list.
+    fn flush_text(tokens: &mut Vec<OscToken>, text: &mut Vec<u8>) {
+        if !text.is_empty() {
+            tokens.push(OscToken::Text(std::mem::take(text)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> OscToken {
+        OscToken::Text(s.as_bytes().to_vec())
+    }
+
+    fn osc(s: &str) -> OscToken {
+        OscToken::Osc(s.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_scanner_splits_text_and_osc() {
+        let mut scanner = OscScanner::new(256);
+        let tokens = scanner.feed(b"a\x1b]0;title\x07b\x1b]133;A\x1b\\\x1b[1mc");
+        assert_eq!(
+            tokens,
+            vec![
+                text("a"),
+                osc("0;title"),
+                text("b"),
+                osc("133;A"),
+                text("\x1b[1mc")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scanner_split_across_chunks() {
+        let mut scanner = OscScanner::new(256);
+        assert_eq!(scanner.feed(b"x\x1b"), vec![text("x")]);
+        assert_eq!(scanner.feed(b"]2;ti"), vec![]);
+        assert_eq!(scanner.feed(b"tle\x1b"), vec![]);
+        assert_eq!(scanner.feed(b"\\y"), vec![osc("2;title"), text("y")]);
+        assert_eq!(scanner.feed(b"\x1b"), vec![]);
+        assert_eq!(scanner.feed(b"[K"), vec![text("\x1b[K")]);
+    }
+
+    #[test]
+    fn test_scanner_overflow_skips_payload() {
+        let mut scanner = OscScanner::new(64);
+        let seq = format!("a\x1b]52;c;{}\x07b\x1b]7;ok\x07", "x".repeat(100));
+        let overflow = OscToken::Overflow(format!("52;c;{}", "x".repeat(59)).into_bytes());
+        assert_eq!(
+            scanner.feed(seq.as_bytes()),
+            vec![text("a"), overflow.clone(), text("b"), osc("7;ok")]
+        );
+
+        let seq = format!("\x1b]52;c;{}\x1b\\c", "x".repeat(100));
+        assert_eq!(scanner.feed(seq.as_bytes()), vec![overflow, text("c")]);
+    }
+}
@@ -28,6 +28,8 @@ use std::sync::Arc;
 use std::time::Instant;
 use tokio::sync::watch;
 
+use crate::pty_osc::{OscScanner, OscToken};
+
 /// A PWD change event with old and new paths.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PwdChangeEvent {
@@ -64,154 +66,75 @@ impl Default for PwdDetectionConfig {
     }
 }
 
-/// States for the OSC escape sequence parser.
-enum OscParserState {
-    Normal,
-    Escape,
-    Osc,
-    OscEscape,
-}
-
-/// Stateful parser for OSC escape sequences that report working directory.
+/// Returns the working directories reported in a chunk of PTY output.
 ///
 /// Recognizes:
 /// - OSC 7: `\x1b]7;file://hostname/path\x07` (or ST-terminated)
 /// - OSC 9;9: `\x1b]9;9;path\x07` (ConEmu/Windows Terminal)
 /// - OSC 633: `\x1b]633;P;Cwd=/path\x07` (VS Code shell integration)
 /// - OSC 1337: `\x1b]1337;CurrentDir=/path\x07` (iTerm2)
-struct OscParser {
-    buffer: Vec<u8>,
-    state: OscParserState,
-    max_buffer_size: usize,
+fn scan_pwd(scanner: &mut OscScanner, data: &[u8]) -> Vec<String> {
+    scanner
+        .feed(data)
+        .into_iter()
+        .filter_map(|token| match token {
+            OscToken::Osc(payload) => parse_pwd_payload(&payload),
+            OscToken::Text(_) | OscToken::Overflow(_) => None,
+        })
+        .collect()
 }
 
-impl OscParser {
-    /// Creates a new parser with the specified maximum buffer size.
-    fn new(buffer_size: usize) -> Self {
-        let buffer_size = buffer_size.max(64);
-        Self {
-            buffer: Vec::with_capacity(buffer_size),
-            state: OscParserState::Normal,
-            max_buffer_size: buffer_size,
-        }
-    }
-
-    /// Feeds raw bytes to the parser and returns any detected PWD paths.
-    fn feed(&mut self, data: &[u8]) -> Vec<String> {
-        let mut paths = Vec::new();
-
-        for &byte in data {
-            match self.state {
-                OscParserState::Normal => {
-                    if byte == 0x1b {
-                        self.state = OscParserState::Escape;
-                    }
-                }
-                OscParserState::Escape => {
-                    if byte == b']' {
-                        self.state = OscParserState::Osc;
-                        self.buffer.clear();
-                    } else {
-                        self.state = OscParserState::Normal;
-                    }
-                }
-                OscParserState::Osc => {
-                    if byte == 0x07 {
-                        // BEL terminates OSC
-                        if let Some(path) = self.process_osc_payload() {
-                            paths.push(path);
-                        }
-                        self.reset();
-                    } else if byte == 0x1b {
-                        // Possible ST (\x1b\\)
-                        self.state = OscParserState::OscEscape;
-                    } else if self.buffer.len() < self.max_buffer_size {
-                        self.buffer.push(byte);
-                    } else {
-                        // Buffer overflow — discard
-                        self.reset();
-                    }
-                }
-                OscParserState::OscEscape => {
-                    if byte == b'\\' {
-                        // ST terminates OSC
-                        if let Some(path) = self.process_osc_payload() {
-                            paths.push(path);
-                        }
-                        self.reset();
-                    } else {
-                        // Not ST — discard and treat as new escape
-                        self.reset();
-                        if byte == b']' {
-                            self.state = OscParserState::Osc;
-                            self.buffer.clear();
-                        } else if byte == 0x1b {
-                            self.state = OscParserState::Escape;
-                        }
-                    }
-                }
-            }
-        }
+/// Extracts a working directory from an OSC payload, if it reports one.
+///
+/// Recognizes OSC 7, OSC 9;9, OSC 633 `P;Cwd=` and OSC 1337 `CurrentDir=`.
+pub(crate) fn parse_pwd_payload(payload: &[u8]) -> Option<String> {
+    let payload = std::str::from_utf8(payload).ok()?;
 
-        paths
+    // OSC 7: "7;file://hostname/path"
+    if let Some(rest) = payload.strip_prefix("7;") {
+        return parse_osc7_url(rest);
     }
 
-    /// Processes the collected OSC payload and extracts a PWD path if valid.
-    fn process_osc_payload(&self) -> Option<String> {
-        let payload = std::str::from_utf8(&self.buffer).ok()?;
-
-        // OSC 7: "7;file://hostname/path"
-        if let Some(rest) = payload.strip_prefix("7;") {
-            return self.parse_osc7_url(rest);
-        }
-
-        // OSC 9;9: "9;9;path" (ConEmu/Windows Terminal)
-        if let Some(rest) = payload.strip_prefix("9;9;") {
-            let decoded = percent_decode(rest);
-            if !decoded.is_empty() {
-                return Some(decoded);
-            }
-        }
-
-        // OSC 633: "633;P;Cwd=/path"
-        if let Some(rest) = payload.strip_prefix("633;P;Cwd=") {
-            let decoded = percent_decode(rest);
-            if !decoded.is_empty() {
-                return Some(decoded);
-            }
+    // OSC 9;9: "9;9;path" (ConEmu/Windows Terminal)
+    if let Some(rest) = payload.strip_prefix("9;9;") {
+        let decoded = percent_decode(rest);
+        if !decoded.is_empty() {
+            return Some(decoded);
         }
+    }
 
-        // OSC 1337: "1337;CurrentDir=/path" (iTerm2)
-        if let Some(rest) = payload.strip_prefix("1337;CurrentDir=") {
-            let decoded = percent_decode(rest);
-            if !decoded.is_empty() {
-                return Some(decoded);
-            }
+    // OSC 633: "633;P;Cwd=/path"
+    if let Some(rest) = payload.strip_prefix("633;P;Cwd=") {
+        let decoded = percent_decode(rest);
+        if !decoded.is_empty() {
+            return Some(decoded);
         }
-
-        None
     }
 
-    /// Parses an OSC 7 file:// URL and extracts the path component.
-    ///
-    /// Handles `file://hostname/path` and `file:///path` forms.
-    fn parse_osc7_url(&self, url: &str) -> Option<String> {
-        let rest = url.strip_prefix("file://")?;
-        // Skip hostname (everything up to the first '/')
-        let path_start = rest.find('/')?;
-        let path = &rest[path_start..];
-        let decoded = percent_decode(path);
+    // OSC 1337: "1337;CurrentDir=/path" (iTerm2)
+    if let Some(rest) = payload.strip_prefix("1337;CurrentDir=") {
+        let decoded = percent_decode(rest);
         if !decoded.is_empty() {
-            Some(decoded)
-        } else {
-            None
+            return Some(decoded);
         }
     }
 
-    /// Resets the parser state and clears the buffer.
-    fn reset(&mut self) {
-        self.state = OscParserState::Normal;
-        self.buffer.clear();
+    None
+}
+
+/// Parses an OSC 7 file:// URL and extracts the path component.
+///
+/// Handles `file://hostname/path` and `file:///path` forms.
+fn parse_osc7_url(url: &str) -> Option<String> {
+    let rest = url.strip_prefix("file://")?;
+    // Skip hostname (everything up to the first '/')
+    let path_start = rest.find('/')?;
+    let path = &rest[path_start..];
+    let decoded = percent_decode(path);
+    if !decoded.is_empty() {
+        Some(decoded)
+    } else {
+        None
     }
 }
 
@@ -306,12 +229,24 @@ pub(crate) fn osc7_injection_snippet(command: &str) -> Option<String> {
     }
 }
 
+/// Prefixes `command` with a `PROMPT_COMMAND` assignment running several
+/// prompt hooks, e.g. OSC 7 and OSC 133 reporting together.
+///
+/// Returns `None` for zsh, which needs input injection instead, and for
+/// fish.
+pub(crate) fn prompt_command_snippet(command: &str, prompt_cmd: &str) -> Option<String> {
+    match detect_shell(command) {
+        Shell::Bash | Shell::Other => Some(format!("PROMPT_COMMAND='{}'", prompt_cmd)),
+        Shell::Zsh | Shell::Fish => None,
+    }
+}
+
 /// Internal PWD detection state for tracking working directory changes.
 ///
 /// Manages the OSC parser and broadcasts PWD changes to watchers.
 pub(crate) struct PwdDetection {
     current_pwd: Arc<Mutex<Option<(String, Instant)>>>,
-    parser: Mutex<OscParser>,
+    scanner: Mutex<OscScanner>,
     event_tx: watch::Sender<Option<(String, Instant)>>,
     event_rx: watch::Receiver<Option<(String, Instant)>>,
     enabled: bool,
@@ -323,7 +258,7 @@ impl PwdDetection {
         let (tx, rx) = watch::channel(None);
         Self {
             current_pwd: Arc::new(Mutex::new(None)),
-            parser: Mutex::new(OscParser::new(config.buffer_size)),
+            scanner: Mutex::new(OscScanner::new(config.buffer_size)),
             event_tx: tx,
             event_rx: rx,
             enabled: config.enabled,
@@ -351,16 +286,16 @@ impl PwdDetection {
         }
     }
 
-    /// Feeds data to the OSC parser.
+    /// Feeds data to the OSC scanner.
     ///
     /// If PWD detection is disabled, this is a no-op.
     pub(crate) fn feed(&self, data: &[u8]) {
         if !self.enabled {
             return;
         }
-        let mut parser = self.parser.lock();
-        let paths = parser.feed(data);
-        drop(parser);
+        let mut scanner = self.scanner.lock();
+        let paths = scan_pwd(&mut scanner, data);
+        drop(scanner);
         for path in paths {
             self.update_pwd(path);
         }
@@ -433,118 +368,127 @@ mod tests {
 
     #[test]
     fn test_parser_osc7_bel_terminated() {
-        let mut parser = OscParser::new(2048);
+        let mut scanner = OscScanner::new(2048);
 
-        let paths = parser.feed(b"\x1b]7;file://hostname/home/user\x07");
+        let paths = scan_pwd(&mut scanner, b"\x1b]7;file://hostname/home/user\x07");
         assert_eq!(paths, vec!["/home/user"]);
     }
 
     #[test]
     fn test_parser_osc7_st_terminated() {
-        let mut parser = OscParser::new(2048);
+        let mut scanner = OscScanner::new(2048);
 
-        let paths = parser.feed(b"\x1b]7;file://hostname/home/user\x1b\\");
+        let paths = scan_pwd(&mut scanner, b"\x1b]7;file://hostname/home/user\x1b\\");
         assert_eq!(paths, vec!["/home/user"]);
     }
 
     #[test]
     fn test_parser_osc7_empty_hostname() {
-        let mut parser = OscParser::new(2048);
+        let mut scanner = OscScanner::new(2048);
 
-        let paths = parser.feed(b"\x1b]7;file:///tmp/dir\x07");
+        let paths = scan_pwd(&mut scanner, b"\x1b]7;file:///tmp/dir\x07");
         assert_eq!(paths, vec!["/tmp/dir"]);
     }
 
     #[test]
     fn test_parser_osc633() {
-        let mut parser = OscParser::new(2048);
+        let mut scanner = OscScanner::new(2048);
 
-        let paths = parser.feed(b"\x1b]633;P;Cwd=/home/user/projects\x07");
+        let paths = scan_pwd(&mut scanner, b"\x1b]633;P;Cwd=/home/user/projects\x07");
         assert_eq!(paths, vec!["/home/user/projects"]);
     }
 
     #[test]
     fn test_parser_osc633_st_terminated() {
-        let mut parser = OscParser::new(2048);
+        let mut scanner = OscScanner::new(2048);
 
-        let paths = parser.feed(b"\x1b]633;P;Cwd=/var/log\x1b\\");
+        let paths = scan_pwd(&mut scanner, b"\x1b]633;P;Cwd=/var/log\x1b\\");
         assert_eq!(paths, vec!["/var/log"]);
     }
 
     #[test]
     fn test_parser_split_sequences() {
-        let mut parser = OscParser::new(2048);
+        let mut scanner = OscScanner::new(2048);
 
-        let paths = parser.feed(b"\x1b]7;file://");
+        let paths = scan_pwd(&mut scanner, b"\x1b]7;file://");
         assert_eq!(paths, Vec::<String>::new());
 
-        let paths = parser.feed(b"host/home");
+        let paths = scan_pwd(&mut scanner, b"host/home");
         assert_eq!(paths, Vec::<String>::new());
 
-        let paths = parser.feed(b"/user\x07");
+        let paths = scan_pwd(&mut scanner, b"/user\x07");
         assert_eq!(paths, vec!["/home/user"]);
     }
 
     #[test]
     fn test_parser_mixed_content() {
-        let mut parser = OscParser::new(2048);
+        let mut scanner = OscScanner::new(2048);
 
-        let paths = parser.feed(b"prompt$ \x1b]7;file://host/home/user\x07some output after");
+        let paths = scan_pwd(
+            &mut scanner,
+            b"prompt$ \x1b]7;file://host/home/user\x07some output after",
+        );
         assert_eq!(paths, vec!["/home/user"]);
     }
 
     #[test]
     fn test_parser_multiple_sequences() {
-        let mut parser = OscParser::new(2048);
+        let mut scanner = OscScanner::new(2048);
 
-        let paths = parser.feed(b"\x1b]7;file://h/home/a\x07text\x1b]7;file://h/home/b\x07");
+        let paths = scan_pwd(
+            &mut scanner,
+            b"\x1b]7;file://h/home/a\x07text\x1b]7;file://h/home/b\x07",
+        );
         assert_eq!(paths, vec!["/home/a", "/home/b"]);
     }
 
     #[test]
     fn test_parser_invalid_osc() {
-        let mut parser = OscParser::new(2048);
+        let mut scanner = OscScanner::new(2048);
 
         // OSC with unrecognized code
-        let paths = parser.feed(b"\x1b]99;something\x07");
+        let paths = scan_pwd(&mut scanner, b"\x1b]99;something\x07");
         assert_eq!(paths, Vec::<String>::new());
 
         // Not a file:// URL
-        let paths = parser.feed(b"\x1b]7;http://example.com\x07");
+        let paths = scan_pwd(&mut scanner, b"\x1b]7;http://example.com\x07");
         assert_eq!(paths, Vec::<String>::new());
     }
 
     #[test]
     fn test_parser_buffer_overflow() {
-        let mut parser = OscParser::new(64);
+        let mut scanner = OscScanner::new(64);
 
         // Payload exceeds buffer
         let long_path = "a".repeat(100);
         let seq = format!("\x1b]7;file://host/{}\x07", long_path);
-        let paths = parser.feed(seq.as_bytes());
+        let paths = scan_pwd(&mut scanner, seq.as_bytes());
         assert_eq!(paths, Vec::<String>::new());
 
         // Parser should recover after overflow
-        let paths = parser.feed(b"\x1b]7;file://h/ok\x07");
+        let paths = scan_pwd(&mut scanner, b"\x1b]7;file://h/ok\x07");
         assert_eq!(paths, vec!["/ok"]);
     }
 
     #[test]
     fn test_parser_percent_decoding() {
-        let mut parser = OscParser::new(2048);
+        let mut scanner = OscScanner::new(2048);
 
-        let paths = parser.feed(b"\x1b]7;file://host/home/user/my%20project\x07");
+        let paths = scan_pwd(
+            &mut scanner,
+            b"\x1b]7;file://host/home/user/my%20project\x07",
+        );
         assert_eq!(paths, vec!["/home/user/my project"]);
 
-        let paths = parser.feed(b"\x1b]7;file://host/tmp/%E2%9C%93\x07");
+        let paths = scan_pwd(&mut scanner, b"\x1b]7;file://host/tmp/%E2%9C%93\x07");
         assert_eq!(paths, vec!["/tmp/\u{2713}"]);
     }
 
     #[test]
     fn test_parser_percent_decoding_osc633() {
-        let mut parser = OscParser::new(2048);
+        let mut scanner = OscScanner::new(2048);
 
-        let paths = parser.feed(b"\x1b]633;P;Cwd=/home/user/my%20dir\x07");
+        let paths = scan_pwd(&mut scanner, b"\x1b]633;P;Cwd=/home/user/my%20dir\x07");
         assert_eq!(paths, vec!["/home/user/my dir"]);
     }
 
@@ -697,49 +641,49 @@ mod tests {
 
     #[test]
     fn test_parser_osc9_9_bel_terminated() {
-        let mut parser = OscParser::new(2048);
+        let mut scanner = OscScanner::new(2048);
 
-        let paths = parser.feed(b"\x1b]9;9;/home/user\x07");
+        let paths = scan_pwd(&mut scanner, b"\x1b]9;9;/home/user\x07");
         assert_eq!(paths, vec!["/home/user"]);
     }
 
     #[test]
     fn test_parser_osc9_9_st_terminated() {
-        let mut parser = OscParser::new(2048);
+        let mut scanner = OscScanner::new(2048);
 
-        let paths = parser.feed(b"\x1b]9;9;/home/user\x1b\\");
+        let paths = scan_pwd(&mut scanner, b"\x1b]9;9;/home/user\x1b\\");
         assert_eq!(paths, vec!["/home/user"]);
     }
 
     #[test]
     fn test_parser_osc9_9_percent_encoded() {
-        let mut parser = OscParser::new(2048);
+        let mut scanner = OscScanner::new(2048);
 
-        let paths = parser.feed(b"\x1b]9;9;/home/user/my%20project\x07");
+        let paths = scan_pwd(&mut scanner, b"\x1b]9;9;/home/user/my%20project\x07");
         assert_eq!(paths, vec!["/home/user/my project"]);
     }
 
     #[test]
     fn test_parser_osc1337_bel_terminated() {
-        let mut parser = OscParser::new(2048);
+        let mut scanner = OscScanner::new(2048);
 
-        let paths = parser.feed(b"\x1b]1337;CurrentDir=/home/user\x07");
+        let paths = scan_pwd(&mut scanner, b"\x1b]1337;CurrentDir=/home/user\x07");
         assert_eq!(paths, vec!["/home/user"]);
     }
 
     #[test]
     fn test_parser_osc1337_st_terminated() {
-        let mut parser = OscParser::new(2048);
+        let mut scanner = OscScanner::new(2048);
 
-        let paths = parser.feed(b"\x1b]1337;CurrentDir=/home/user\x1b\\");
+        let paths = scan_pwd(&mut scanner, b"\x1b]1337;CurrentDir=/home/user\x1b\\");
         assert_eq!(paths, vec!["/home/user"]);
     }
 
     #[test]
     fn test_parser_osc1337_percent_encoded() {
-        let mut parser = OscParser::new(2048);
+        let mut scanner = OscScanner::new(2048);
 
-        let paths = parser.feed(b"\x1b]1337;CurrentDir=/tmp/%E2%9C%93\x07");
+        let paths = scan_pwd(&mut scanner, b"\x1b]1337;CurrentDir=/tmp/%E2%9C%93\x07");
         assert_eq!(paths, vec!["/tmp/\u{2713}"]);
     }
 
@@ -817,4 +761,12 @@ mod tests {
         let snippet = snippet.unwrap();
         assert!(snippet.starts_with("PROMPT_COMMAND="));
     }
+
+    #[test]
+    fn test_prompt_command_snippet() {
+        let snippet = prompt_command_snippet("bash", "a; b");
+        assert_eq!(snippet.as_deref(), Some("PROMPT_COMMAND='a; b'"));
+        assert!(prompt_command_snippet("zsh", "a").is_none());
+        assert!(prompt_command_snippet("fish", "a").is_none());
+    }
 }
@@ -0,0 +1,394 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2025 Julian Kahlert
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use log::warn;
+use serde_json::json;
+
+/// Configuration for recording a PTY session as an asciicast v2 stream.
+///
+/// The recording is written as the session runs, one event per line, and
+/// can be played back with `asciinema play`.
+///
+/// ```
+/// use simple_ssh::PtyRecordingConfig;
+///
+/// let file = std::io::BufWriter::new(Vec::new());
+/// let config = PtyRecordingConfig::new(file)
+///     .input(true)
+///     .title("deploy");
+/// ```
+pub struct PtyRecordingConfig {
+    pub(crate) writer: Box<dyn Write + Send>,
+    pub(crate) record_input: bool,
+    pub(crate) title: Option<String>,
+}
+
+impl std::fmt::Debug for PtyRecordingConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PtyRecordingConfig")
+            .field("record_input", &self.record_input)
+            .field("title", &self.title)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PtyRecordingConfig {
+    /// Creates a recording config writing to `writer`.
+    ///
+    /// Events are written on a separate thread, so a slow writer does not
+    /// stall the session. The writer is flushed whenever no more events
+    /// are queued; wrap files in a `BufWriter`.
+    ///
+    /// Defaults:
+    /// - input: not recorded
+    /// - title: none
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            writer: Box::new(writer),
+            record_input: false,
+            title: None,
+        }
+    }
+
+    /// Sets whether input sent to the PTY is recorded as `"i"` events.
+    ///
+    /// Input includes everything typed, such as passwords entered at
+    /// prompts, so only enable this when the recording is kept private.
+    pub fn input(mut self, record: bool) -> Self {
+        self.record_input = record;
+        self
+    }
+
+    /// Sets the title stored in the recording header.
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = Some(title.to_string());
+        self
+    }
+}
+
+/// Decodes a byte stream as UTF-8 across chunk boundaries.
+///
+/// Incomplete sequences at the end of a chunk are held back until the
+/// next chunk; invalid bytes become U+FFFD.
+#[derive(Default)]
+struct Utf8Stream {
+    pending: Vec<u8>,
+}
+
+impl Utf8Stream {
+    /// Decodes `data` together with any bytes held back before.
+    fn decode(&mut self, data: &[u8]) -> String {
+        self.pending.extend_from_slice(data);
+        let mut text = String::new();
+        let mut rest = &self.pending[..];
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(valid) => {
+                    text.push_str(valid);
+                    rest = &[];
+                    break;
+                }
+                Err(e) => {
+                    let (valid, after) = rest.split_at(e.valid_up_to());
+                    text.push_str(std::str::from_utf8(valid).unwrap_or_default());
+                    match e.error_len() {
+                        Some(len) => {
+                            text.push(char::REPLACEMENT_CHARACTER);
+                            rest = &after[len..];
+                        }
+                        None => {
+                            rest = after;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        self.pending = rest.to_vec();
+        text
+    }
+}
+
+/// Writes PTY activity of one session as asciicast v2.
+///
+/// Owned by the PTY I/O task, which only formats events; the lines are
+/// handed to a writer thread so blocking writes stay off the runtime. A
+/// failing writer stops the recording but not the session.
+pub(crate) struct PtyRecorder {
+    lines: Option<Sender<String>>,
+    writer: JoinHandle<()>,
+    record_input: bool,
+    started: Instant,
+    output: Utf8Stream,
+    input: Utf8Stream,
+    failed: Arc<AtomicBool>,
+}
+
+impl PtyRecorder {
+    /// Writes the recording header and starts the clock.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Where and what to record
+    /// * `width` - Initial terminal width in columns
+    /// * `height` - Initial terminal height in rows
+    /// * `term` - Terminal type, stored as `TERM` in the header
+    pub(crate) fn new(
+        config: PtyRecordingConfig,
+        width: u32,
+        height: u32,
+        term: &str,
+    ) -> std::io::Result<Self> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        // Written by hand to keep the customary field order.
+        let mut header = format!(
+            "{{\"version\": 2, \"width\": {}, \"height\": {}, \"timestamp\": {}, \"env\": {{\"TERM\": {}}}",
+            width,
+            height,
+            timestamp,
+            json!(term)
+        );
+        if let Some(title) = config.title {
+            header.push_str(&format!(", \"title\": {}", json!(title)));
+        }
+        header.push('}');
+
+        let mut writer = config.writer;
+        writeln!(writer, "{}", header)?;
+        writer.flush()?;
+
+        let (tx, rx) = mpsc::channel();
+        let failed = Arc::new(AtomicBool::new(false));
+        let thread_failed = failed.clone();
+        let handle = thread::Builder::new()
+            .name("pty-recorder".to_string())
+            .spawn(move || {
+                if let Err(e) = write_lines(writer, rx) {
+                    warn!("Stopping PTY recording: {}", e);
+                    thread_failed.store(true, Ordering::Relaxed);
+                }
+            })?;
+        Ok(Self {
+            lines: Some(tx),
+            writer: handle,
+            record_input: config.record_input,
+            started: Instant::now(),
+            output: Utf8Stream::default(),
+            input: Utf8Stream::default(),
+            failed,
+        })
+    }
+
+    /// Records output received from the remote PTY.
+    pub(crate) fn output(&mut self, data: &[u8]) {
+        let text = self.output.decode(data);
+        self.event("o", &text);
+    }
+
+    /// Records input sent to the remote PTY, if enabled.
+    pub(crate) fn input(&mut self, data: &[u8]) {
+        if self.record_input {
+            let text = self.input.decode(data);
+            self.event("i", &text);
+        }
+    }
+
+    /// Records a terminal size change.
+    pub(crate) fn resize(&mut self, width: u32, height: u32) {
+        self.event("r", &format!("{}x{}", width, height));
+    }
+
+    /// Closes the recording and waits until every queued event is written.
+    ///
+    /// Blocks the calling thread, so async callers go through
+    /// `spawn_blocking`.
+    pub(crate) fn finish(self) {
+        drop(self.lines);
+        if self.writer.join().is_err() {
+            warn!("PTY recording writer panicked");
+        }
+    }
+
+    /// Queues one event line with the time since the recording started.
+    fn event(&mut self, code: &str, data: &str) {
+        if data.is_empty() || self.failed.load(Ordering::Relaxed) {
+            return;
+        }
+        let Some(lines) = self.lines.as_ref() else {
+            return;
+        };
+        let time = self.started.elapsed().as_secs_f64();
+        let line = format!("[{:.6}, \"{}\", {}]\n", time, code, json!(data));
+        if lines.send(line).is_err() {
+            self.lines = None;
+        }
+    }
+}
+
+/// Writes queued event lines until the recorder is dropped.
+///
+/// The writer is flushed whenever the queue runs empty, so bursts of
+/// output are written in one go while idle sessions stay up to date on
+/// disk.
+fn write_lines(mut writer: Box<dyn Write + Send>, lines: Receiver<String>) -> std::io::Result<()> {
+    loop {
+        let line = match lines.try_recv() {
+            Ok(line) => line,
+            Err(TryRecvError::Empty) => {
+                writer.flush()?;
+                match lines.recv() {
+                    Ok(line) => line,
+                    Err(_) => return Ok(()),
+                }
+            }
+            Err(TryRecvError::Disconnected) => return writer.flush(),
+        };
+        writer.write_all(line.as_bytes())?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+    use serde_json::Value;
+    use std::sync::Arc;
+
+    /// A writer whose content stays readable after it is handed over.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A writer that fails once its flag is set.
+    struct FailingWriter(Arc<AtomicBool>);
+
+    impl Write for FailingWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.0.load(Ordering::Relaxed) {
+                return Err(std::io::Error::other("disk full"));
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuffer {
+        fn lines(&self) -> Vec<Value> {
+            String::from_utf8(self.0.lock().clone())
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_utf8_stream_split_sequence() {
+        let mut stream = Utf8Stream::default();
+        let bytes = "größe".as_bytes();
+        assert_eq!(stream.decode(&bytes[..3]), "gr");
+        assert_eq!(stream.decode(&bytes[3..]), "öße");
+        assert_eq!(stream.decode(b"a\xffb"), "a\u{fffd}b");
+    }
+
+    #[test]
+    fn test_recorder_header_and_events() {
+        let buffer = SharedBuffer::default();
+        let config = PtyRecordingConfig::new(buffer.clone()).title("demo");
+        let mut recorder = PtyRecorder::new(config, 120, 40, "xterm-256color").unwrap();
+        recorder.output(b"$ ls\r\n");
+        recorder.input(b"ls\r");
+        recorder.resize(100, 30);
+        recorder.finish();
+
+        let lines = buffer.lines();
+        assert_eq!(lines.len(), 3);
+        let header = &lines[0];
+        assert_eq!(header["version"], 2);
+        assert_eq!(header["width"], 120);
+        assert_eq!(header["height"], 40);
+        assert_eq!(header["env"]["TERM"], "xterm-256color");
+        assert_eq!(header["title"], "demo");
+        assert!(header["timestamp"].as_u64().unwrap() > 0);
+
+        assert!(lines[1][0].as_f64().unwrap() >= 0.0);
+        assert_eq!(lines[1][1], "o");
+        assert_eq!(lines[1][2], "$ ls\r\n");
+        assert_eq!(lines[2][1], "r");
+        assert_eq!(lines[2][2], "100x30");
+    }
+
+    #[test]
+    fn test_recorder_input() {
+        let buffer = SharedBuffer::default();
+        let config = PtyRecordingConfig::new(buffer.clone()).input(true);
+        let mut recorder = PtyRecorder::new(config, 80, 24, "xterm").unwrap();
+        recorder.input(b"\x1b[A");
+        recorder.finish();
+
+        let lines = buffer.lines();
+        assert_eq!(lines[0].get("title"), None);
+        assert_eq!(lines[1][1], "i");
+        assert_eq!(lines[1][2], "\x1b[A");
+    }
+
+    #[test]
+    fn test_recorder_stops_on_write_error() {
+        let broken = Arc::new(AtomicBool::new(false));
+        let config = PtyRecordingConfig::new(FailingWriter(broken.clone()));
+        let mut recorder = PtyRecorder::new(config, 80, 24, "xterm").unwrap();
+        broken.store(true, Ordering::Relaxed);
+        recorder.output(b"lost");
+        // Wait for the writer thread to run into the error.
+        while !recorder.failed.load(Ordering::Relaxed) {
+            std::thread::yield_now();
+        }
+        // Later events are dropped without being queued.
+        recorder.output(b"ignored");
+        recorder.finish();
+    }
+}
@@ -0,0 +1,583 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2025 Julian Kahlert
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::time::Instant;
+
+/// Header of an asciicast v2 recording.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CastHeader {
+    /// Terminal width in columns when the recording started.
+    pub width: u32,
+    /// Terminal height in rows when the recording started.
+    pub height: u32,
+    /// Unix time at which the recording started, if stored.
+    pub timestamp: Option<u64>,
+    /// Title of the recording, if stored.
+    pub title: Option<String>,
+    /// `TERM` of the recorded terminal, if stored.
+    pub term: Option<String>,
+    /// Longest pause kept during playback, if the recording suggests one.
+    pub idle_time_limit: Option<Duration>,
+}
+
+/// Kind of a recorded event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CastEventKind {
+    /// Output printed by the terminal (`"o"`).
+    Output,
+    /// Input sent to the terminal (`"i"`).
+    Input,
+    /// Terminal size change as `COLSxROWS` (`"r"`).
+    Resize,
+    /// Named marker (`"m"`).
+    Marker,
+}
+
+/// A single event of an asciicast v2 recording.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CastEvent {
+    /// Time since the start of the recording.
+    pub time: Duration,
+    /// What the event records.
+    pub kind: CastEventKind,
+    /// Event payload: text for output and input, `COLSxROWS` for resizes.
+    pub data: String,
+}
+
+impl CastEvent {
+    /// Returns the new size of a resize event as `(cols, rows)`.
+    pub fn size(&self) -> Option<(u32, u32)> {
+        if self.kind != CastEventKind::Resize {
+            return None;
+        }
+        let (cols, rows) = self.data.split_once('x')?;
+        Some((cols.trim().parse().ok()?, rows.trim().parse().ok()?))
+    }
+}
+
+/// An asciicast v2 recording, as written by
+/// [`PtyBuilder::with_recording`](crate::PtyBuilder::with_recording) or
+/// asciinema.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recording {
+    /// The recording header.
+    pub header: CastHeader,
+    /// Events ordered by time.
+    pub events: Vec<CastEvent>,
+}
+
+impl Recording {
+    /// Parses the content of a `.cast` file.
+    ///
+    /// Events with unknown codes are skipped; events out of order are
+    /// sorted by time.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the header is missing or not version 2, or a
+    /// line is not valid JSON.
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut lines = content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty());
+        let Some((_, first)) = lines.next() else {
+            return Err(anyhow!("Empty recording"));
+        };
+        let header = parse_header(first)?;
+
+        let mut events = Vec::new();
+        for (number, line) in lines {
+            let event = parse_event(line)
+                .map_err(|e| anyhow!("Invalid event on line {}: {}", number + 1, e))?;
+            events.extend(event);
+        }
+        events.sort_by_key(|event| event.time);
+        Ok(Self { header, events })
+    }
+
+    /// Reads and parses a `.cast` file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or parsed.
+    pub async fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = tokio::fs::read_to_string(path)
+            .await
+            .map_err(|e| anyhow!("Cannot read '{}': {}", path.display(), e))?;
+        Self::parse(&content)
+    }
+
+    /// Returns the time of the last event.
+    pub fn duration(&self) -> Duration {
+        self.events
+            .last()
+            .map_or(Duration::ZERO, |event| event.time)
+    }
+
+    /// Plays the output of the recording into `writer`.
+    ///
+    /// Output chunks are written with the recorded pauses between them,
+    /// adjusted by `options`. Resizes, input and markers are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the writer fails.
+    pub async fn play<W: AsyncWrite + Unpin>(
+        &self,
+        writer: &mut W,
+        options: &ReplayOptions,
+    ) -> Result<()> {
+        let mut player = Player::new(self, options);
+        while let Some(event) = player.next().await {
+            if let PlayerEvent::Output(text) = event {
+                writer.write_all(text.as_bytes()).await?;
+                writer.flush().await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses the header line of a recording.
+fn parse_header(line: &str) -> Result<CastHeader> {
+    let value: Value =
+        serde_json::from_str(line).map_err(|e| anyhow!("Invalid recording header: {}", e))?;
+    match value.get("version").and_then(Value::as_u64) {
+        Some(2) => {}
+        Some(version) => return Err(anyhow!("Unsupported asciicast version {}", version)),
+        None => return Err(anyhow!("Recording header has no version")),
+    }
+    let dimension = |name: &str| {
+        value
+            .get(name)
+            .and_then(Value::as_u64)
+            .and_then(|n| u32::try_from(n).ok())
+            .ok_or_else(|| anyhow!("Recording header has no {}", name))
+    };
+    Ok(CastHeader {
+        width: dimension("width")?,
+        height: dimension("height")?,
+        timestamp: value.get("timestamp").and_then(Value::as_u64),
+        title: value
+            .get("title")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        term: value
+            .pointer("/env/TERM")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        idle_time_limit: value
+            .get("idle_time_limit")
+            .and_then(Value::as_f64)
+            .filter(|limit| *limit > 0.0)
+            .map(Duration::from_secs_f64),
+    })
+}
+
+/// Parses an event line, returning `None` for unknown event codes.
+fn parse_event(line: &str) -> Result<Option<CastEvent>> {
+    let value: Value = serde_json::from_str(line)?;
+    let Some([time, code, data]) = value.as_array().map(Vec::as_slice) else {
+        return Err(anyhow!("expected [time, code, data]"));
+    };
+    let time = time
+        .as_f64()
+        .filter(|t| t.is_finite() && *t >= 0.0)
+        .ok_or_else(|| anyhow!("invalid time"))?;
+    let kind = match code.as_str() {
+        Some("o") => CastEventKind::Output,
+        Some("i") => CastEventKind::Input,
+        Some("r") => CastEventKind::Resize,
+        Some("m") => CastEventKind::Marker,
+        _ => return Ok(None),
+    };
+    let data = data
+        .as_str()
+        .ok_or_else(|| anyhow!("data is not a string"))?;
+    Ok(Some(CastEvent {
+        time: Duration::from_secs_f64(time),
+        kind,
+        data: data.to_string(),
+    }))
+}
+
+/// Timing options for replaying a recording.
+///
+/// ```
+/// use simple_ssh::ReplayOptions;
+/// use std::time::Duration;
+///
+/// // Twice as fast, never pausing for more than a second.
+/// let options = ReplayOptions::new()
+///     .speed(2.0)
+///     .idle_limit(Duration::from_secs(1));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayOptions {
+    pub(crate) speed: f64,
+    pub(crate) idle_limit: Option<Duration>,
+}
+
+impl Default for ReplayOptions {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            idle_limit: None,
+        }
+    }
+}
+
+impl ReplayOptions {
+    /// Creates options for playback with the original timing.
+    ///
+    /// Defaults:
+    /// - speed: 1.0
+    /// - idle limit: the recording's `idle_time_limit`, if any
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Scales the timing; 2.0 plays twice as fast.
+    ///
+    /// Non-positive or non-finite values are ignored.
+    pub fn speed(mut self, speed: f64) -> Self {
+        if speed.is_finite() && speed > 0.0 {
+            self.speed = speed;
+        }
+        self
+    }
+
+    /// Shortens every pause longer than `limit` to `limit`.
+    pub fn idle_limit(mut self, limit: Duration) -> Self {
+        self.idle_limit = Some(limit);
+        self
+    }
+}
+
+/// Something to present during playback.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlayerEvent {
+    /// Terminal output to write.
+    Output(String),
+    /// The recorded terminal changed its size to `(cols, rows)`.
+    Resize(u32, u32),
+}
+
+/// Interactive playback of a [`Recording`] with pause, seek and speed
+/// control.
+///
+/// Times passed to and returned by the player are on the playback
+/// timeline, where pauses longer than the idle limit are already
+/// shortened.
+///
+/// ```ignore
+/// let mut player = Player::new(&recording, &ReplayOptions::new());
+/// loop {
+///     tokio::select! {
+///         event = player.next() => match event {
+///             Some(PlayerEvent::Output(text)) => print!("{}", text),
+///             Some(PlayerEvent::Resize(..)) => {}
+///             None => break,
+///         },
+///         key = keys.recv() => match key {
+///             Some(' ') => player.toggle_pause(),
+///             Some('l') => print!("{}", player.seek_by(5.0)),
+///             _ => {}
+///         },
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Player<'a> {
+    events: Vec<&'a CastEvent>,
+    times: Vec<Duration>,
+    next: usize,
+    speed: f64,
+    /// Playback position at `anchor`.
+    position: Duration,
+    /// Wall clock time at which `position` was reached; `None` while paused.
+    anchor: Option<Instant>,
+}
+
+impl<'a> Player<'a> {
+    /// Creates a player positioned at the start of `recording`.
+    pub fn new(recording: &'a Recording, options: &ReplayOptions) -> Self {
+        let idle_limit = options.idle_limit.or(recording.header.idle_time_limit);
+        let events: Vec<&CastEvent> = recording
+            .events
+            .iter()
+            .filter(|event| matches!(event.kind, CastEventKind::Output | CastEventKind::Resize))
+            .collect();
+
+        let mut times = Vec::with_capacity(events.len());
+        let mut last = Duration::ZERO;
+        let mut shifted = Duration::ZERO;
+        for event in &events {
+            let mut gap = event.time.saturating_sub(last);
+            if let Some(limit) = idle_limit {
+                gap = gap.min(limit);
+            }
+            shifted += gap;
+            times.push(shifted);
+            last = event.time;
+        }
+
+        Self {
+            events,
+            times,
+            next: 0,
+            speed: options.speed,
+            position: Duration::ZERO,
+            anchor: Some(Instant::now()),
+        }
+    }
+
+    /// Returns the length of the playback timeline.
+    pub fn duration(&self) -> Duration {
+        self.times.last().copied().unwrap_or(Duration::ZERO)
+    }
+
+    /// Returns the current playback position.
+    pub fn position(&self) -> Duration {
+        match self.anchor {
+            Some(anchor) => (self.position + anchor.elapsed().mul_f64(self.speed))
+                .min(self.duration().max(self.position)),
+            None => self.position,
+        }
+    }
+
+    /// Returns the current speed factor.
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    /// Returns true while playback is paused.
+    pub fn is_paused(&self) -> bool {
+        self.anchor.is_none()
+    }
+
+    /// Returns true once every event has been returned.
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.events.len()
+    }
+
+    /// Waits until the next event is due and returns it.
+    ///
+    /// Waits forever while paused and returns `None` at the end of the
+    /// recording. Cancelling the call does not skip events.
+    pub async fn next(&mut self) -> Option<PlayerEvent> {
+        let due = *self.times.get(self.next)?;
+        let Some(anchor) = self.anchor else {
+            return std::future::pending().await;
+        };
+        let wait = due.saturating_sub(self.position).div_f64(self.speed);
+        tokio::time::sleep_until(anchor + wait).await;
+
+        let event = self.events[self.next];
+        self.next += 1;
+        Some(match event.size() {
+            Some((cols, rows)) => PlayerEvent::Resize(cols, rows),
+            None => PlayerEvent::Output(event.data.clone()),
+        })
+    }
+
+    /// Pauses or resumes playback.
+    pub fn toggle_pause(&mut self) {
+        if self.anchor.is_some() {
+            self.position = self.position();
+            self.anchor = None;
+        } else {
+            self.anchor = Some(Instant::now());
+        }
+    }
+
+    /// Changes the speed factor without moving the playback position.
+    ///
+    /// Non-positive or non-finite values are ignored.
+    pub fn set_speed(&mut self, speed: f64) {
+        if !(speed.is_finite() && speed > 0.0) {
+            return;
+        }
+        self.rebase(self.position());
+        self.speed = speed;
+    }
+
+    /// Moves the playback position to `target`.
+    ///
+    /// Returns the output that brings a terminal showing the current
+    /// position to the state at `target`. Seeking backwards starts with a
+    /// terminal reset and replays everything from the beginning.
+    pub fn seek(&mut self, target: Duration) -> String {
+        let target = target.min(self.duration());
+        let mut output = String::new();
+        let current = self.position();
+        if target < current {
+            output.push_str("\x1bc");
+            self.next = 0;
+        }
+        while self.next < self.events.len() && self.times[self.next] <= target {
+            if self.events[self.next].kind == CastEventKind::Output {
+                output.push_str(&self.events[self.next].data);
+            }
+            self.next += 1;
+        }
+        self.rebase(target);
+        output
+    }
+
+    /// Moves the playback position by `seconds`, backwards if negative.
+    ///
+    /// See [`seek`](Player::seek) for the returned output.
+    pub fn seek_by(&mut self, seconds: f64) -> String {
+        let current = self.position().as_secs_f64();
+        self.seek(Duration::from_secs_f64((current + seconds).max(0.0)))
+    }
+
+    /// Sets the playback position without touching the event cursor.
+    fn rebase(&mut self, position: Duration) {
+        self.position = position;
+        if self.anchor.is_some() {
+            self.anchor = Some(Instant::now());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAST: &str = r#"{"version": 2, "width": 100, "height": 30, "timestamp": 1700000000, "env": {"TERM": "xterm"}, "title": "demo", "idle_time_limit": 2.5}
+[0.5, "o", "$ "]
+[0.7, "i", "ls\r"]
+[1.0, "o", "ls\r\n"]
+[1.2, "r", "120x40"]
+[11.0, "o", "done\r\n"]
+[11.5, "x", "future event"]
+"#;
+
+    #[test]
+    fn test_parse_recording() {
+        let recording = Recording::parse(CAST).unwrap();
+        assert_eq!(recording.header.width, 100);
+        assert_eq!(recording.header.height, 30);
+        assert_eq!(recording.header.timestamp, Some(1700000000));
+        assert_eq!(recording.header.title.as_deref(), Some("demo"));
+        assert_eq!(recording.header.term.as_deref(), Some("xterm"));
+        assert_eq!(
+            recording.header.idle_time_limit,
+            Some(Duration::from_millis(2500))
+        );
+        assert_eq!(recording.events.len(), 5);
+        assert_eq!(recording.events[1].kind, CastEventKind::Input);
+        assert_eq!(recording.events[3].size(), Some((120, 40)));
+        assert_eq!(recording.events[0].size(), None);
+        assert_eq!(recording.duration(), Duration::from_secs(11));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Recording::parse("").is_err());
+        assert!(Recording::parse(r#"{"version": 1, "width": 80, "height": 24}"#).is_err());
+        assert!(Recording::parse(r#"{"version": 2, "height": 24}"#).is_err());
+
+        let bad = "{\"version\": 2, \"width\": 80, \"height\": 24}\n[0.1, \"o\"]\n";
+        let err = Recording::parse(bad).unwrap_err();
+        assert!(err.to_string().contains("line 2"));
+    }
+
+    #[test]
+    fn test_player_idle_limit() {
+        let recording = Recording::parse(CAST).unwrap();
+        let player = Player::new(&recording, &ReplayOptions::new());
+        // The 9.8 s pause before "done" is shortened to 2.5 s.
+        assert_eq!(player.duration(), Duration::from_millis(3700));
+
+        let options = ReplayOptions::new().idle_limit(Duration::from_millis(100));
+        let player = Player::new(&recording, &options);
+        assert_eq!(player.duration(), Duration::from_millis(400));
+    }
+
+    #[test]
+    fn test_player_seek() {
+        let recording = Recording::parse(CAST).unwrap();
+        let mut player = Player::new(&recording, &ReplayOptions::new());
+        player.toggle_pause();
+        assert!(player.is_paused());
+
+        assert_eq!(player.seek(Duration::from_secs(1)), "$ ls\r\n");
+        assert_eq!(player.position(), Duration::from_secs(1));
+        assert_eq!(player.seek(Duration::from_millis(600)), "\x1bc$ ");
+        assert_eq!(player.seek_by(100.0), "ls\r\ndone\r\n");
+        assert!(player.is_finished());
+        assert_eq!(player.seek_by(-100.0), "\x1bc");
+        assert_eq!(player.position(), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_play_scaled() {
+        let recording = Recording::parse(CAST).unwrap();
+        let options = ReplayOptions::new()
+            .speed(20.0)
+            .idle_limit(Duration::from_secs(1));
+        let mut out = Vec::new();
+        let started = std::time::Instant::now();
+        recording.play(&mut out, &options).await.unwrap();
+        assert_eq!(out, b"$ ls\r\ndone\r\n");
+        // 2.2 s of playback time at 20x speed.
+        assert!(started.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn test_player_pause_and_speed() {
+        let recording = Recording::parse(CAST).unwrap();
+        let mut player = Player::new(&recording, &ReplayOptions::new().speed(1000.0));
+        assert_eq!(player.next().await, Some(PlayerEvent::Output("$ ".into())));
+
+        player.toggle_pause();
+        let paused = tokio::time::timeout(Duration::from_millis(50), player.next()).await;
+        assert!(paused.is_err());
+
+        player.toggle_pause();
+        player.set_speed(2000.0);
+        assert_eq!(player.speed(), 2000.0);
+        assert_eq!(
+            player.next().await,
+            Some(PlayerEvent::Output("ls\r\n".into()))
+        );
+        assert_eq!(player.next().await, Some(PlayerEvent::Resize(120, 40)));
+        assert_eq!(
+            player.next().await,
+            Some(PlayerEvent::Output("done\r\n".into()))
+        );
+        assert_eq!(player.next().await, None);
+    }
+}
@@ -0,0 +1,571 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2025 Julian Kahlert
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::fmt::Write as _;
+
+use parking_lot::Mutex;
+
+/// Background colour of the HTML export where cells use the default.
+const HTML_BACKGROUND: &str = "#000000";
+
+/// Foreground colour of the HTML export where cells use the default.
+const HTML_FOREGROUND: &str = "#e5e5e5";
+
+/// The 16 standard colours, as xterm draws them.
+const BASE_COLORS: [(u8, u8, u8); 16] = [
+    (0x00, 0x00, 0x00),
+    (0xcd, 0x00, 0x00),
+    (0x00, 0xcd, 0x00),
+    (0xcd, 0xcd, 0x00),
+    (0x00, 0x00, 0xee),
+    (0xcd, 0x00, 0xcd),
+    (0x00, 0xcd, 0xcd),
+    (0xe5, 0xe5, 0xe5),
+    (0x7f, 0x7f, 0x7f),
+    (0xff, 0x00, 0x00),
+    (0x00, 0xff, 0x00),
+    (0xff, 0xff, 0x00),
+    (0x5c, 0x5c, 0xff),
+    (0xff, 0x00, 0xff),
+    (0x00, 0xff, 0xff),
+    (0xff, 0xff, 0xff),
+];
+
+/// Configuration for the PTY screen emulation.
+#[derive(Debug, Clone, Default)]
+pub struct ScreenConfig {
+    /// Number of lines scrolled off the top that the emulator keeps
+    /// (default: 0)
+    pub scrollback: usize,
+}
+
+/// A terminal colour.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScreenColor {
+    /// The terminal's default foreground or background.
+    #[default]
+    Default,
+    /// An entry of the 256-colour palette.
+    Indexed(u8),
+    /// A 24-bit colour.
+    Rgb(u8, u8, u8),
+}
+
+impl ScreenColor {
+    /// Returns the colour as RGB, using the xterm palette for indexed colours.
+    ///
+    /// Returns `None` for [`ScreenColor::Default`].
+    pub fn to_rgb(self) -> Option<(u8, u8, u8)> {
+        match self {
+            ScreenColor::Default => None,
+            ScreenColor::Rgb(r, g, b) => Some((r, g, b)),
+            ScreenColor::Indexed(i) if i < 16 => Some(BASE_COLORS[i as usize]),
+            ScreenColor::Indexed(i) if i < 232 => {
+                let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+                let i = i - 16;
+                Some((level(i / 36), level((i / 6) % 6), level(i % 6)))
+            }
+            ScreenColor::Indexed(i) => {
+                let gray = 8 + (i - 232) * 10;
+                Some((gray, gray, gray))
+            }
+        }
+    }
+
+    /// Converts a vt100 colour.
+    fn from_vt100(color: vt100::Color) -> Self {
+        match color {
+            vt100::Color::Default => ScreenColor::Default,
+            vt100::Color::Idx(i) => ScreenColor::Indexed(i),
+            vt100::Color::Rgb(r, g, b) => ScreenColor::Rgb(r, g, b),
+        }
+    }
+
+    /// Appends the SGR parameters selecting this colour.
+    ///
+    /// `base` is 30 for the foreground and 40 for the background.
+    fn write_sgr(self, base: u8, out: &mut String) {
+        match self {
+            ScreenColor::Default => {}
+            ScreenColor::Indexed(i) if i < 8 => {
+                let _ = write!(out, ";{}", base + i);
+            }
+            ScreenColor::Indexed(i) if i < 16 => {
+                let _ = write!(out, ";{}", base + 60 + i - 8);
+            }
+            ScreenColor::Indexed(i) => {
+                let _ = write!(out, ";{};5;{}", base + 8, i);
+            }
+            ScreenColor::Rgb(r, g, b) => {
+                let _ = write!(out, ";{};2;{};{};{}", base + 8, r, g, b);
+            }
+        }
+    }
+}
+
+/// A single character cell of a [`ScreenSnapshot`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ScreenCell {
+    /// The text in the cell; empty for blank cells and the right half of
+    /// wide characters.
+    pub contents: String,
+    /// Foreground colour.
+    pub fg: ScreenColor,
+    /// Background colour.
+    pub bg: ScreenColor,
+    /// Bold text.
+    pub bold: bool,
+    /// Dim (faint) text.
+    pub dim: bool,
+    /// Italic text.
+    pub italic: bool,
+    /// Underlined text.
+    pub underline: bool,
+    /// Foreground and background swapped.
+    pub inverse: bool,
+    /// The cell holds a character two columns wide.
+    pub wide: bool,
+    /// The cell is the right half of a wide character.
+    pub wide_continuation: bool,
+}
+
+impl ScreenCell {
+    /// Converts a vt100 cell.
+    fn from_vt100(cell: &vt100::Cell) -> Self {
+        Self {
+            contents: cell.contents().to_string(),
+            fg: ScreenColor::from_vt100(cell.fgcolor()),
+            bg: ScreenColor::from_vt100(cell.bgcolor()),
+            bold: cell.bold(),
+            dim: cell.dim(),
+            italic: cell.italic(),
+            underline: cell.underline(),
+            inverse: cell.inverse(),
+            wide: cell.is_wide(),
+            wide_continuation: cell.is_wide_continuation(),
+        }
+    }
+
+    /// Returns true if the cells would be drawn with the same attributes.
+    fn same_style(&self, other: &ScreenCell) -> bool {
+        self.fg == other.fg
+            && self.bg == other.bg
+            && self.bold == other.bold
+            && self.dim == other.dim
+            && self.italic == other.italic
+            && self.underline == other.underline
+            && self.inverse == other.inverse
+    }
+
+    /// Returns true if the cell draws nothing visible.
+    fn is_blank(&self) -> bool {
+        self.contents.trim().is_empty()
+            && self.bg == ScreenColor::Default
+            && !self.inverse
+            && !self.underline
+    }
+
+    /// Returns the text drawn for the cell.
+    fn text(&self) -> &str {
+        if self.contents.is_empty() {
+            " "
+        } else {
+            &self.contents
+        }
+    }
+}
+
+/// A copy of the emulated terminal screen at one point in time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScreenSnapshot {
+    rows: u16,
+    cols: u16,
+    cells: Vec<ScreenCell>,
+    cursor: (u16, u16),
+    cursor_visible: bool,
+    title: Option<String>,
+    alternate_screen: bool,
+}
+
+impl ScreenSnapshot {
+    /// Returns the screen size as `(rows, cols)`.
+    pub fn size(&self) -> (u16, u16) {
+        (self.rows, self.cols)
+    }
+
+    /// Returns the cell at `row`, `col`, if it is on the screen.
+    pub fn cell(&self, row: u16, col: u16) -> Option<&ScreenCell> {
+        if row < self.rows && col < self.cols {
+            self.cells
+                .get(row as usize * self.cols as usize + col as usize)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the cells of one row, or an empty slice past the last row.
+    pub fn row(&self, row: u16) -> &[ScreenCell] {
+        let cols = self.cols as usize;
+        let start = row as usize * cols;
+        self.cells.get(start..start + cols).unwrap_or(&[])
+    }
+
+    /// Returns the cursor position as `(row, col)`.
+    pub fn cursor(&self) -> (u16, u16) {
+        self.cursor
+    }
+
+    /// Returns true unless the program hid the cursor.
+    pub fn cursor_visible(&self) -> bool {
+        self.cursor_visible
+    }
+
+    /// Returns the window title set via OSC 0 or OSC 2, if any.
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    /// Returns true if the alternate screen buffer is active.
+    pub fn is_alternate_screen(&self) -> bool {
+        self.alternate_screen
+    }
+
+    /// Returns the visible cells in one row, without trailing blanks.
+    fn trimmed_row(&self, row: u16) -> &[ScreenCell] {
+        let cells = self.row(row);
+        let len = cells
+            .iter()
+            .rposition(|c| !c.is_blank())
+            .map_or(0, |i| i + 1);
+        &cells[..len]
+    }
+
+    /// Returns the number of rows up to the last one with visible content.
+    fn used_rows(&self) -> u16 {
+        (0..self.rows)
+            .rev()
+            .find(|&r| !self.trimmed_row(r).is_empty())
+            .map_or(0, |r| r + 1)
+    }
+
+    /// Exports the screen as plain text.
+    ///
+    /// Rows are separated by `\n`; trailing blanks and empty rows at the
+    /// bottom are dropped.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for row in 0..self.used_rows() {
+            if row > 0 {
+                out.push('\n');
+            }
+            let line: String = self
+                .row(row)
+                .iter()
+                .filter(|c| !c.wide_continuation)
+                .map(ScreenCell::text)
+                .collect();
+            out.push_str(line.trim_end());
+        }
+        out
+    }
+
+    /// Exports the screen as text with SGR escape sequences.
+    ///
+    /// Rows are separated by `\r\n` and every row ends with its attributes
+    /// reset, so the result can be printed to any ANSI terminal.
+    pub fn to_ansi(&self) -> String {
+        let mut out = String::new();
+        for row in 0..self.used_rows() {
+            if row > 0 {
+                out.push_str("\r\n");
+            }
+            let mut style: Option<&ScreenCell> = None;
+            for cell in self.trimmed_row(row) {
+                if cell.wide_continuation {
+                    continue;
+                }
+                if !style.is_some_and(|s| s.same_style(cell)) {
+                    out.push_str(&sgr(cell));
+                    style = Some(cell);
+                }
+                out.push_str(cell.text());
+            }
+            if style.is_some() {
+                out.push_str("\x1b[0m");
+            }
+        }
+        out
+    }
+
+    /// Exports the screen as an HTML `<pre>` block with inline styles.
+    ///
+    /// Default colours are rendered as light text on black.
+    pub fn to_html(&self) -> String {
+        let mut out = format!(
+            "<pre style=\"background-color:{};color:{}\">",
+            HTML_BACKGROUND, HTML_FOREGROUND
+        );
+        for row in 0..self.used_rows() {
+            if row > 0 {
+                out.push('\n');
+            }
+            let cells: Vec<&ScreenCell> = self
+                .trimmed_row(row)
+                .iter()
+                .filter(|c| !c.wide_continuation)
+                .collect();
+            for run in cells.chunk_by(|a, b| a.same_style(b)) {
+                let text: String = run.iter().map(|c| html_escape(c.text())).collect();
+                let style = css(run[0]);
+                if style.is_empty() {
+                    out.push_str(&text);
+                } else {
+                    let _ = write!(out, "<span style=\"{}\">{}</span>", style, text);
+                }
+            }
+        }
+        out.push_str("</pre>");
+        out
+    }
+}
+
+/// Returns the SGR sequence selecting the cell's attributes.
+fn sgr(cell: &ScreenCell) -> String {
+    let mut out = String::from("\x1b[0");
+    for (on, code) in [
+        (cell.bold, ";1"),
+        (cell.dim, ";2"),
+        (cell.italic, ";3"),
+        (cell.underline, ";4"),
+        (cell.inverse, ";7"),
+    ] {
+        if on {
+            out.push_str(code);
+        }
+    }
+    cell.fg.write_sgr(30, &mut out);
+    cell.bg.write_sgr(40, &mut out);
+    out.push('m');
+    out
+}
+
+/// Returns the inline CSS drawing the cell's attributes.
+fn css(cell: &ScreenCell) -> String {
+    let hex = |(r, g, b): (u8, u8, u8)| format!("#{:02x}{:02x}{:02x}", r, g, b);
+    let mut fg = cell.fg.to_rgb().map(hex);
+    let mut bg = cell.bg.to_rgb().map(hex);
+    if cell.inverse {
+        let swapped_fg = bg.unwrap_or_else(|| HTML_BACKGROUND.to_string());
+        let swapped_bg = fg.unwrap_or_else(|| HTML_FOREGROUND.to_string());
+        fg = Some(swapped_fg);
+        bg = Some(swapped_bg);
+    }
+
+    let mut style = String::new();
+    if let Some(fg) = fg {
+        let _ = write!(style, "color:{};", fg);
+    }
+    if let Some(bg) = bg {
+        let _ = write!(style, "background-color:{};", bg);
+    }
+    if cell.bold {
+        style.push_str("font-weight:bold;");
+    }
+    if cell.dim {
+        style.push_str("opacity:0.5;");
+    }
+    if cell.italic {
+        style.push_str("font-style:italic;");
+    }
+    if cell.underline {
+        style.push_str("text-decoration:underline;");
+    }
+    style
+}
+
+/// Escapes text for HTML element content.
+fn html_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Records the window title reported through the vt100 callbacks.
+#[derive(Default)]
+struct TitleCallbacks {
+    title: Option<String>,
+}
+
+impl vt100::Callbacks for TitleCallbacks {
+    fn set_window_title(&mut self, _: &mut vt100::Screen, title: &[u8]) {
+        self.title = Some(String::from_utf8_lossy(title).into_owned());
+    }
+}
+
+/// Internal terminal emulation kept up to date with the PTY output.
+pub(crate) struct PtyScreen {
+    parser: Mutex<vt100::Parser<TitleCallbacks>>,
+}
+
+impl PtyScreen {
+    /// Creates an emulated screen of the given size.
+    pub(crate) fn new(config: ScreenConfig, cols: u32, rows: u32) -> Self {
+        Self {
+            parser: Mutex::new(vt100::Parser::new_with_callbacks(
+                clamp_dimension(rows),
+                clamp_dimension(cols),
+                config.scrollback,
+                TitleCallbacks::default(),
+            )),
+        }
+    }
+
+    /// Feeds PTY output to the emulator.
+    pub(crate) fn feed(&self, data: &[u8]) {
+        self.parser.lock().process(data);
+    }
+
+    /// Resizes the emulated screen.
+    pub(crate) fn resize(&self, cols: u32, rows: u32) {
+        self.parser
+            .lock()
+            .screen_mut()
+            .set_size(clamp_dimension(rows), clamp_dimension(cols));
+    }
+
+    /// Copies the current screen state.
+    pub(crate) fn snapshot(&self) -> ScreenSnapshot {
+        let parser = self.parser.lock();
+        let screen = parser.screen();
+        let (rows, cols) = screen.size();
+        let mut cells = Vec::with_capacity(rows as usize * cols as usize);
+        for row in 0..rows {
+            for col in 0..cols {
+                cells.push(
+                    screen
+                        .cell(row, col)
+                        .map(ScreenCell::from_vt100)
+                        .unwrap_or_default(),
+                );
+            }
+        }
+        ScreenSnapshot {
+            rows,
+            cols,
+            cells,
+            cursor: screen.cursor_position(),
+            cursor_visible: !screen.hide_cursor(),
+            title: parser.callbacks().title.clone(),
+            alternate_screen: screen.alternate_screen(),
+        }
+    }
+}
+
+/// Clamps a PTY dimension to the range the emulator supports.
+fn clamp_dimension(value: u32) -> u16 {
+    value.clamp(1, u16::MAX as u32) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn screen(data: &[u8]) -> ScreenSnapshot {
+        let screen = PtyScreen::new(ScreenConfig::default(), 20, 4);
+        screen.feed(data);
+        screen.snapshot()
+    }
+
+    #[test]
+    fn test_snapshot_cells_cursor_and_title() {
+        let snap = screen(b"\x1b]2;vim main.rs\x07a\x1b[1;31mb\x1b[0m\r\nxy\x1b[?25l");
+        assert_eq!(snap.size(), (4, 20));
+        assert_eq!(snap.cell(0, 0).unwrap().contents, "a");
+        let b = snap.cell(0, 1).unwrap();
+        assert!(b.bold);
+        assert_eq!(b.fg, ScreenColor::Indexed(1));
+        assert_eq!(snap.cursor(), (1, 2));
+        assert!(!snap.cursor_visible());
+        assert_eq!(snap.title(), Some("vim main.rs"));
+        assert!(snap.cell(4, 0).is_none());
+        assert_eq!(snap.row(1).len(), 20);
+    }
+
+    #[test]
+    fn test_to_text_trims_blanks() {
+        let snap = screen(b"hello  \r\n\r\n  world\r\n");
+        assert_eq!(snap.to_text(), "hello\n\n  world");
+        assert_eq!(screen(b"").to_text(), "");
+    }
+
+    #[test]
+    fn test_to_ansi_emits_style_changes() {
+        let snap = screen(b"a\x1b[1;38;5;200mbc\x1b[0;44md\r\ne");
+        assert_eq!(
+            snap.to_ansi(),
+            "\x1b[0ma\x1b[0;1;38;5;200mbc\x1b[0;44md\x1b[0m\r\n\x1b[0;44me\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn test_to_html_escapes_and_styles() {
+        let snap = screen(b"<a&b>\x1b[7m!\x1b[0;38;2;1;2;3mz");
+        assert_eq!(
+            snap.to_html(),
+            "<pre style=\"background-color:#000000;color:#e5e5e5\">&lt;a&amp;b&gt;\
+             <span style=\"color:#000000;background-color:#e5e5e5;\">!</span>\
+             <span style=\"color:#010203;\">z</span></pre>"
+        );
+    }
+
+    #[test]
+    fn test_wide_characters_and_resize() {
+        let screen = PtyScreen::new(ScreenConfig::default(), 10, 2);
+        screen.feed("漢x".as_bytes());
+        let snap = screen.snapshot();
+        assert!(snap.cell(0, 0).unwrap().wide);
+        assert!(snap.cell(0, 1).unwrap().wide_continuation);
+        assert_eq!(snap.to_text(), "漢x");
+
+        screen.resize(30, 5);
+        assert_eq!(screen.snapshot().size(), (5, 30));
+    }
+
+    #[test]
+    fn test_palette_conversion() {
+        assert_eq!(ScreenColor::Default.to_rgb(), None);
+        assert_eq!(ScreenColor::Indexed(9).to_rgb(), Some((0xff, 0, 0)));
+        assert_eq!(ScreenColor::Indexed(16).to_rgb(), Some((0, 0, 0)));
+        assert_eq!(ScreenColor::Indexed(231).to_rgb(), Some((255, 255, 255)));
+        assert_eq!(ScreenColor::Indexed(232).to_rgb(), Some((8, 8, 8)));
+    }
+}
@@ -0,0 +1,294 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2025 Julian Kahlert
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use parking_lot::Mutex;
+use std::time::Instant;
+use tokio::sync::watch;
+
+use crate::pty_osc::{OscScanner, OscToken};
+
+/// A window title change event with old and new titles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TitleChangeEvent {
+    /// The previous title, if one was set.
+    pub previous: Option<String>,
+    /// The new title; empty if the program cleared it.
+    pub current: String,
+    /// Timestamp of when the change was detected.
+    pub timestamp: Instant,
+}
+
+/// Configuration for window title detection.
+#[derive(Debug, Clone)]
+pub struct TitleDetectionConfig {
+    /// Whether to enable title detection (default: false)
+    pub enabled: bool,
+    /// Buffer size for OSC payload parsing (default: 1024 bytes)
+    pub buffer_size: usize,
+}
+
+impl Default for TitleDetectionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            buffer_size: 1024,
+        }
+    }
+}
+
+/// A title-related change reported by an OSC sequence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TitleUpdate {
+    /// OSC 0: window title and icon name.
+    Both(String),
+    /// OSC 1: icon name only.
+    IconName(String),
+    /// OSC 2: window title only.
+    Title(String),
+}
+
+/// Parses an OSC payload into a title update.
+fn parse_title_payload(payload: &[u8]) -> Option<TitleUpdate> {
+    let (code, text) = match payload.iter().position(|&b| b == b';') {
+        Some(i) => (&payload[..i], &payload[i + 1..]),
+        None => return None,
+    };
+    let text = sanitize(&String::from_utf8_lossy(text));
+    match code {
+        b"0" => Some(TitleUpdate::Both(text)),
+        b"1" => Some(TitleUpdate::IconName(text)),
+        b"2" => Some(TitleUpdate::Title(text)),
+        _ => None,
+    }
+}
+
+/// Removes control characters so that titles are safe to print.
+pub(crate) fn sanitize(text: &str) -> String {
+    text.chars().filter(|c| !c.is_control()).collect()
+}
+
+/// Internal title detection state for tracking window title changes.
+///
+/// Manages the OSC scanner and broadcasts title changes to watchers.
+pub(crate) struct TitleDetection {
+    scanner: Mutex<OscScanner>,
+    icon_name: Mutex<Option<String>>,
+    event_tx: watch::Sender<Option<(String, Instant)>>,
+    event_rx: watch::Receiver<Option<(String, Instant)>>,
+    enabled: bool,
+}
+
+impl TitleDetection {
+    /// Creates a new title detector with the given configuration.
+    pub(crate) fn new(config: TitleDetectionConfig) -> Self {
+        let (tx, rx) = watch::channel(None);
+        Self {
+            scanner: Mutex::new(OscScanner::new(config.buffer_size)),
+            icon_name: Mutex::new(None),
+            event_tx: tx,
+            event_rx: rx,
+            enabled: config.enabled,
+        }
+    }
+
+    /// Returns the current window title, if one was set.
+    pub(crate) fn current_title(&self) -> Option<String> {
+        self.event_rx.borrow().as_ref().map(|(t, _)| t.clone())
+    }
+
+    /// Returns the current icon name, if one was set.
+    pub(crate) fn current_icon_name(&self) -> Option<String> {
+        self.icon_name.lock().clone()
+    }
+
+    /// Updates the title and notifies all watchers.
+    ///
+    /// Only sends notifications when the title actually changes.
+    fn update_title(&self, title: String) {
+        self.event_tx.send_if_modified(|current| {
+            if current.as_ref().is_some_and(|(old, _)| *old == title) {
+                return false;
+            }
+            *current = Some((title, Instant::now()));
+            true
+        });
+    }
+
+    /// Feeds data to the OSC scanner.
+    ///
+    /// If title detection is disabled, this is a no-op.
+    pub(crate) fn feed(&self, data: &[u8]) {
+        if !self.enabled {
+            return;
+        }
+        let tokens = self.scanner.lock().feed(data);
+        for token in tokens {
+            let OscToken::Osc(payload) = token else {
+                continue;
+            };
+            match parse_title_payload(&payload) {
+                Some(TitleUpdate::Both(text)) => {
+                    *self.icon_name.lock() = Some(text.clone());
+                    self.update_title(text);
+                }
+                Some(TitleUpdate::IconName(text)) => *self.icon_name.lock() = Some(text),
+                Some(TitleUpdate::Title(text)) => self.update_title(text),
+                None => {}
+            }
+        }
+    }
+
+    /// Creates a new watcher for observing title changes.
+    pub(crate) fn create_watcher(&self) -> TitleWatcher {
+        TitleWatcher {
+            inner: self.event_rx.clone(),
+            last_known: self.event_rx.borrow().clone(),
+        }
+    }
+}
+
+/// An async-enabled watcher for window title changes.
+///
+/// Tracks the title programs set with OSC 0 or OSC 2 escape sequences,
+/// such as the `user@host: dir` of many shell prompts or the file name
+/// shown by an editor.
+#[derive(Debug, Clone)]
+pub struct TitleWatcher {
+    inner: watch::Receiver<Option<(String, Instant)>>,
+    last_known: Option<(String, Instant)>,
+}
+
+impl TitleWatcher {
+    /// Returns the current title without waiting, if one was set.
+    pub fn current(&self) -> Option<String> {
+        self.inner.borrow().as_ref().map(|(t, _)| t.clone())
+    }
+
+    /// Waits for the title to change and returns it with the detection timestamp.
+    ///
+    /// Returns `None` if the PTY session has ended.
+    pub async fn changed(&mut self) -> Option<(String, Instant)> {
+        loop {
+            match self.inner.changed().await {
+                Ok(()) => {
+                    let title = self.inner.borrow().clone();
+                    if let Some(ref t) = title {
+                        self.last_known = Some(t.clone());
+                        return Some(t.clone());
+                    }
+                }
+                Err(_) => return None,
+            }
+        }
+    }
+
+    /// Waits for the next title change event (with transition info).
+    ///
+    /// Returns `None` if the PTY session has ended.
+    pub async fn next_event(&mut self) -> Option<TitleChangeEvent> {
+        let previous = self.last_known.clone();
+
+        self.changed()
+            .await
+            .map(|(current, timestamp)| TitleChangeEvent {
+                previous: previous.map(|(p, _)| p),
+                current,
+                timestamp,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detection() -> TitleDetection {
+        TitleDetection::new(TitleDetectionConfig {
+            enabled: true,
+            ..TitleDetectionConfig::default()
+        })
+    }
+
+    #[test]
+    fn test_parse_title_payloads() {
+        assert_eq!(
+            parse_title_payload(b"0;vim"),
+            Some(TitleUpdate::Both("vim".to_string()))
+        );
+        assert_eq!(
+            parse_title_payload(b"1;icon"),
+            Some(TitleUpdate::IconName("icon".to_string()))
+        );
+        assert_eq!(
+            parse_title_payload(b"2;a;b\x01"),
+            Some(TitleUpdate::Title("a;b".to_string()))
+        );
+        assert_eq!(parse_title_payload(b"7;file:///tmp"), None);
+        assert_eq!(parse_title_payload(b"2"), None);
+    }
+
+    #[test]
+    fn test_title_bel_st_and_split() {
+        let d = detection();
+        d.feed(b"\x1b]2;user@host: ~\x07");
+        assert_eq!(d.current_title().as_deref(), Some("user@host: ~"));
+
+        d.feed(b"out\x1b]0;top\x1b\\");
+        assert_eq!(d.current_title().as_deref(), Some("top"));
+        assert_eq!(d.current_icon_name().as_deref(), Some("top"));
+
+        d.feed(b"\x1b]2;ht");
+        d.feed(b"op\x1b");
+        assert_eq!(d.current_title().as_deref(), Some("top"));
+        d.feed(b"\\");
+        assert_eq!(d.current_title().as_deref(), Some("htop"));
+
+        d.feed(b"\x1b]1;icon only\x07");
+        assert_eq!(d.current_title().as_deref(), Some("htop"));
+        assert_eq!(d.current_icon_name().as_deref(), Some("icon only"));
+    }
+
+    #[tokio::test]
+    async fn test_title_watcher_events() {
+        let d = detection();
+        d.feed(b"\x1b]2;one\x07");
+        let mut watcher = d.create_watcher();
+        assert_eq!(watcher.current().as_deref(), Some("one"));
+
+        d.feed(b"\x1b]2;one\x07\x1b]2;two\x07");
+        let event = watcher.next_event().await.unwrap();
+        assert_eq!(event.previous.as_deref(), Some("one"));
+        assert_eq!(event.current, "two");
+
+        drop(d);
+        assert!(watcher.changed().await.is_none());
+    }
+
+    #[test]
+    fn test_disabled_detection_ignores_output() {
+        let d = TitleDetection::new(TitleDetectionConfig::default());
+        d.feed(b"\x1b]2;title\x07");
+        assert_eq!(d.current_title(), None);
+    }
+}
@@ -0,0 +1,338 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2025 Julian Kahlert
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::{Instant, Sleep};
+
+/// Smallest burst a limiter allows, about one transfer chunk.
+const MIN_BURST: u64 = 16 * 1024;
+
+/// Token bucket state.
+struct Bucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl Bucket {
+    /// Takes `bytes` tokens at `now` and returns how long the caller has
+    /// to wait to stay within the rate.
+    ///
+    /// The bucket may go into debt, so chunks larger than the burst are
+    /// allowed and paid for by waiting afterwards.
+    fn reserve(&mut self, bytes: u64, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.last = now;
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.tokens -= bytes as f64;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+}
+
+/// A token bucket limiting throughput to a number of bytes per second.
+///
+/// Clones share the same bucket, so one limiter passed to several
+/// transfers caps their combined bandwidth:
+///
+/// ```
+/// use simple_ssh::{RateLimiter, ScpOptions, SftpTransferOptions};
+///
+/// // 256 KiB/s for everything below
+/// let limiter = RateLimiter::new(256 * 1024);
+/// let scp = ScpOptions::new().rate_limit(limiter.clone());
+/// let sftp = SftpTransferOptions::new().rate_limit(limiter);
+/// ```
+///
+/// Two limiters compare equal if they share a bucket.
+#[derive(Clone)]
+pub struct RateLimiter {
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+impl fmt::Debug for RateLimiter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bucket = self.bucket.lock();
+        f.debug_struct("RateLimiter")
+            .field("bytes_per_second", &(bucket.rate as u64))
+            .field("burst", &(bucket.burst as u64))
+            .finish()
+    }
+}
+
+impl PartialEq for RateLimiter {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.bucket, &other.bucket)
+    }
+}
+
+impl RateLimiter {
+    /// Creates a limiter allowing `bytes_per_second` on average.
+    ///
+    /// The burst, the amount that may pass at once after a pause, is a
+    /// tenth of a second worth of data but at least 16 KiB. A rate of 0
+    /// is treated as 1.
+    pub fn new(bytes_per_second: u64) -> Self {
+        let rate = bytes_per_second.max(1);
+        let burst = (rate / 10).max(MIN_BURST);
+        RateLimiter {
+            bucket: Arc::new(Mutex::new(Bucket {
+                rate: rate as f64,
+                burst: burst as f64,
+                tokens: burst as f64,
+                last: Instant::now(),
+            })),
+        }
+    }
+
+    /// Sets the burst size in bytes.
+    pub fn with_burst(self, bytes: u64) -> Self {
+        {
+            let mut bucket = self.bucket.lock();
+            bucket.burst = bytes.max(1) as f64;
+            bucket.tokens = bucket.burst;
+        }
+        self
+    }
+
+    /// Returns the configured average rate.
+    pub fn bytes_per_second(&self) -> u64 {
+        self.bucket.lock().rate as u64
+    }
+
+    /// Waits until `bytes` more bytes may be transferred.
+    pub async fn acquire(&self, bytes: u64) {
+        let delay = self.reserve(bytes);
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Wraps `stream` so reads and writes through it are limited.
+    pub fn limit<S>(&self, stream: S) -> RateLimited<S> {
+        RateLimited::new(stream, self.clone())
+    }
+
+    /// Takes `bytes` tokens and returns the delay owed for them.
+    fn reserve(&self, bytes: u64) -> Duration {
+        self.bucket.lock().reserve(bytes, Instant::now())
+    }
+}
+
+/// A stream whose reads and writes are limited by a [`RateLimiter`].
+///
+/// Works with any tokio stream, e.g. a [`RemoteFile`](crate::RemoteFile).
+/// Data is passed through
+/// immediately and the next operation waits until the rate allows it.
+pub struct RateLimited<S> {
+    inner: S,
+    limiter: Option<RateLimiter>,
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl<S> fmt::Debug for RateLimited<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimited")
+            .field("limiter", &self.limiter)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> RateLimited<S> {
+    /// Wraps `inner`, limiting it with `limiter`.
+    pub fn new(inner: S, limiter: RateLimiter) -> Self {
+        Self::optional(inner, Some(limiter))
+    }
+
+    /// Wraps `inner`, passing data through unchanged without a limiter.
+    pub(crate) fn optional(inner: S, limiter: Option<RateLimiter>) -> Self {
+        RateLimited {
+            inner,
+            limiter,
+            delay: None,
+        }
+    }
+
+    /// Returns a reference to the wrapped stream.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped stream.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Returns the wrapped stream.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// Waits for the delay owed by the previous operation.
+    fn poll_delay(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if let Some(delay) = &mut self.delay {
+            ready!(delay.as_mut().poll(cx));
+            self.delay = None;
+        }
+        Poll::Ready(())
+    }
+
+    /// Accounts for `bytes` transferred bytes.
+    fn charge(&mut self, bytes: usize) {
+        let Some(limiter) = &self.limiter else {
+            return;
+        };
+        let delay = limiter.reserve(bytes as u64);
+        if !delay.is_zero() {
+            self.delay = Some(Box::pin(tokio::time::sleep(delay)));
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for RateLimited<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_delay(cx));
+        let before = buf.filled().len();
+        ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+        this.charge(buf.filled().len() - before);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for RateLimited<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_delay(cx));
+        let n = ready!(Pin::new(&mut this.inner).poll_write(cx, data))?;
+        this.charge(n);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn bucket(rate: f64, burst: f64, now: Instant) -> Bucket {
+        Bucket {
+            rate,
+            burst,
+            tokens: burst,
+            last: now,
+        }
+    }
+
+    #[test]
+    fn test_bucket_burst_then_debt() {
+        let now = Instant::now();
+        let mut bucket = bucket(1000.0, 500.0, now);
+        assert_eq!(bucket.reserve(500, now), Duration::ZERO);
+        assert_eq!(bucket.reserve(250, now), Duration::from_millis(250));
+        // Chunks larger than the burst are paid for afterwards.
+        assert_eq!(bucket.reserve(1000, now), Duration::from_millis(1250));
+    }
+
+    #[test]
+    fn test_bucket_refills_up_to_burst() {
+        let now = Instant::now();
+        let mut bucket = bucket(1000.0, 500.0, now);
+        bucket.reserve(500, now);
+        assert_eq!(
+            bucket.reserve(100, now + Duration::from_millis(100)),
+            Duration::ZERO
+        );
+        // A long pause does not allow more than the burst.
+        let later = now + Duration::from_secs(60);
+        assert_eq!(bucket.reserve(500, later), Duration::ZERO);
+        assert_eq!(bucket.reserve(100, later), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_limiter_settings() {
+        let limiter = RateLimiter::new(0);
+        assert_eq!(limiter.bytes_per_second(), 1);
+
+        let limiter = RateLimiter::new(10 * 1024 * 1024).with_burst(4096);
+        assert_eq!(limiter.bytes_per_second(), 10 * 1024 * 1024);
+        assert!(format!("{:?}", limiter).contains("burst: 4096"));
+        assert_eq!(limiter.clone(), limiter);
+        assert_ne!(limiter, RateLimiter::new(10 * 1024 * 1024));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limited_stream_throttles() {
+        let limiter = RateLimiter::new(100_000).with_burst(10_000);
+        let data = vec![7u8; 30_000];
+
+        let started = Instant::now();
+        let mut reader = limiter.limit(&data[..]);
+        let mut out = RateLimited::new(Vec::new(), RateLimiter::new(u64::MAX));
+        tokio::io::copy(&mut reader, &mut out).await.unwrap();
+        out.flush().await.unwrap();
+        assert_eq!(out.get_ref(), &data);
+        // 20 KB beyond the burst at 100 KB/s; timers round up to 1 ms.
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(200), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(210), "{:?}", elapsed);
+    }
+
+    #[tokio::test]
+    async fn test_optional_limiter_passes_through() {
+        let mut stream = RateLimited::optional(&b"abc"[..], None);
+        let mut out = String::new();
+        stream.read_to_string(&mut out).await.unwrap();
+        assert_eq!(out, "abc");
+        assert_eq!(stream.into_inner(), b"");
+    }
+}
@@ -0,0 +1,459 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2025 Julian Kahlert
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, SeekFrom};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use anyhow::Result;
+use tokio::io::{AsyncRead, AsyncSeek, AsyncWrite, AsyncWriteExt, ReadBuf};
+
+use crate::sftp::{
+    FileAttributes, PendingResponse, Response, Sftp, SftpError, SftpHandle, SftpStatus, MAX_CHUNK,
+};
+
+/// Requests kept in flight in each direction by default.
+const DEFAULT_PIPELINE: usize = 16;
+
+/// A read request sent ahead of the current position.
+struct ReadAhead {
+    offset: u64,
+    len: u32,
+    response: PendingResponse,
+}
+
+/// Converts an SFTP error into an I/O error, keeping [`SftpError`] as
+/// the inner error.
+fn to_io_error(error: anyhow::Error) -> io::Error {
+    match error.downcast::<SftpError>() {
+        Ok(error) => {
+            let kind = match error.status {
+                SftpStatus::NoSuchFile => io::ErrorKind::NotFound,
+                SftpStatus::PermissionDenied => io::ErrorKind::PermissionDenied,
+                SftpStatus::OpUnsupported => io::ErrorKind::Unsupported,
+                _ => io::ErrorKind::Other,
+            };
+            io::Error::new(kind, error)
+        }
+        Err(error) => match error.downcast::<io::Error>() {
+            Ok(error) => error,
+            Err(error) => io::Error::other(error.to_string()),
+        },
+    }
+}
+
+/// Applies a signed seek offset to `base`.
+fn seek_offset(base: u64, delta: i64) -> io::Result<u64> {
+    base.checked_add_signed(delta).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "invalid seek to a negative or overflowing position",
+        )
+    })
+}
+
+/// A remote file opened over SFTP, usable like a local file.
+///
+/// Implements tokio's [`AsyncRead`], [`AsyncWrite`] and [`AsyncSeek`], so
+/// it can be passed to `tokio::io::copy`, buffered readers or
+/// decompressors. Reads keep several requests in flight ahead of the
+/// current position and writes return before the server confirmed them;
+/// write errors surface on a later write, `flush` or `close`.
+///
+/// Obtained from [`Sftp::open_file`]. Call [`close`](Self::close) to
+/// wait for outstanding writes; dropping the file closes the handle in
+/// the background.
+///
+/// ```no_run
+/// use simple_ssh::{Session, SftpOpenOptions};
+///
+/// # async fn example(ssh: &mut Session) -> anyhow::Result<()> {
+/// let sftp = ssh.sftp().await?;
+/// let mut remote = sftp
+///     .open_file("/var/log/syslog", &SftpOpenOptions::new().read(true))
+///     .await?;
+/// let mut local = tokio::fs::File::create("syslog").await?;
+/// tokio::io::copy(&mut remote, &mut local).await?;
+/// remote.close().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct RemoteFile {
+    sftp: Sftp,
+    /// `None` once closed.
+    handle: Option<SftpHandle>,
+    position: u64,
+    pipeline: usize,
+    /// Data received for `position` onwards, starting at `buffer_pos`.
+    buffer: Vec<u8>,
+    buffer_pos: usize,
+    reads: VecDeque<ReadAhead>,
+    eof: bool,
+    writes: VecDeque<PendingResponse>,
+    /// Pending `SeekFrom::End` waiting for the file size.
+    seek: Option<(i64, PendingResponse)>,
+    closing: Option<PendingResponse>,
+}
+
+impl fmt::Debug for RemoteFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemoteFile")
+            .field("handle", &self.handle)
+            .field("position", &self.position)
+            .field("pipeline", &self.pipeline)
+            .finish()
+    }
+}
+
+impl RemoteFile {
+    pub(crate) fn new(sftp: Sftp, handle: SftpHandle) -> Self {
+        Self {
+            sftp,
+            handle: Some(handle),
+            position: 0,
+            pipeline: DEFAULT_PIPELINE,
+            buffer: Vec::new(),
+            buffer_pos: 0,
+            reads: VecDeque::new(),
+            eof: false,
+            writes: VecDeque::new(),
+            seek: None,
+            closing: None,
+        }
+    }
+
+    /// Sets how many 32 KiB requests are kept in flight (default 16).
+    ///
+    /// Higher values help on links with high latency.
+    pub fn with_pipeline(mut self, requests: usize) -> Self {
+        self.pipeline = requests.max(1);
+        self
+    }
+
+    /// Returns the attributes of the open file.
+    pub async fn metadata(&self) -> Result<FileAttributes> {
+        self.sftp.fstat(self.handle()?).await
+    }
+
+    /// Waits for outstanding writes and closes the remote handle.
+    pub async fn close(mut self) -> Result<()> {
+        self.shutdown().await?;
+        Ok(())
+    }
+
+    fn handle(&self) -> io::Result<&SftpHandle> {
+        self.handle
+            .as_ref()
+            .ok_or_else(|| io::Error::other("remote file is closed"))
+    }
+
+    fn discard_read_ahead(&mut self) {
+        self.reads.clear();
+        self.buffer.clear();
+        self.buffer_pos = 0;
+        self.eof = false;
+    }
+
+    fn set_position(&mut self, target: u64) {
+        let buffered = (self.buffer.len() - self.buffer_pos) as u64;
+        if target >= self.position && target - self.position <= buffered {
+            // Still inside the received data, keep the read-ahead.
+            self.buffer_pos += (target - self.position) as usize;
+        } else {
+            self.discard_read_ahead();
+        }
+        self.position = target;
+    }
+
+    /// Waits until at most `limit` writes are unconfirmed.
+    fn poll_writes(&mut self, cx: &mut Context<'_>, limit: usize) -> Poll<io::Result<()>> {
+        while self.writes.len() > limit {
+            let Some(front) = self.writes.front_mut() else {
+                break;
+            };
+            let result = ready!(front.poll_wait(cx));
+            self.writes.pop_front();
+            if let Err(e) = result.and_then(Response::into_ok) {
+                return Poll::Ready(Err(to_io_error(e)));
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncRead for RemoteFile {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        // Written data must reach the server before it is read back.
+        ready!(this.poll_writes(cx, 0))?;
+
+        loop {
+            let buffered = &this.buffer[this.buffer_pos..];
+            if !buffered.is_empty() {
+                let n = buffered.len().min(buf.remaining());
+                buf.put_slice(&buffered[..n]);
+                this.buffer_pos += n;
+                this.position += n as u64;
+                return Poll::Ready(Ok(()));
+            }
+            if this.eof || buf.remaining() == 0 {
+                return Poll::Ready(Ok(()));
+            }
+
+            let mut next = match this.reads.back() {
+                Some(read) => read.offset + read.len as u64,
+                None => this.position,
+            };
+            while this.reads.len() < this.pipeline {
+                let response = this
+                    .sftp
+                    .send_read(this.handle()?, next, MAX_CHUNK)
+                    .map_err(to_io_error)?;
+                this.reads.push_back(ReadAhead {
+                    offset: next,
+                    len: MAX_CHUNK,
+                    response,
+                });
+                next += MAX_CHUNK as u64;
+            }
+
+            let Some(front) = this.reads.front_mut() else {
+                continue;
+            };
+            let result = ready!(front.response.poll_wait(cx));
+            let Some(read) = this.reads.pop_front() else {
+                continue;
+            };
+            match result.and_then(Response::into_data) {
+                Ok(Some(data)) if !data.is_empty() => {
+                    if data.len() < read.len as usize {
+                        // Requests behind a short read start at the wrong offset.
+                        this.reads.clear();
+                    }
+                    this.buffer = data;
+                    this.buffer_pos = 0;
+                }
+                Ok(_) => {
+                    this.reads.clear();
+                    this.eof = true;
+                }
+                Err(e) => {
+                    this.reads.clear();
+                    return Poll::Ready(Err(to_io_error(e)));
+                }
+            }
+        }
+    }
+}
+
+impl AsyncWrite for RemoteFile {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        ready!(this.poll_writes(cx, this.pipeline - 1))?;
+        if !this.reads.is_empty() || !this.buffer.is_empty() || this.eof {
+            this.discard_read_ahead();
+        }
+
+        let n = buf.len().min(MAX_CHUNK as usize);
+        let response = this
+            .sftp
+            .send_write(this.handle()?, this.position, &buf[..n])
+            .map_err(to_io_error)?;
+        this.writes.push_back(response);
+        this.position += n as u64;
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_writes(cx, 0)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_writes(cx, 0))?;
+        if this.closing.is_none() {
+            let Some(handle) = this.handle.take() else {
+                return Poll::Ready(Ok(()));
+            };
+            this.discard_read_ahead();
+            this.closing = Some(this.sftp.send_close(&handle).map_err(to_io_error)?);
+        }
+        let Some(closing) = this.closing.as_mut() else {
+            return Poll::Ready(Ok(()));
+        };
+        let result = ready!(closing.poll_wait(cx));
+        this.closing = None;
+        Poll::Ready(result.and_then(Response::into_ok).map_err(to_io_error))
+    }
+}
+
+impl AsyncSeek for RemoteFile {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        let this = self.get_mut();
+        if this.seek.is_some() {
+            return Err(io::Error::other("another seek is in progress"));
+        }
+        let target = match position {
+            SeekFrom::Start(offset) => offset,
+            SeekFrom::Current(delta) => seek_offset(this.position, delta)?,
+            SeekFrom::End(delta) => {
+                // Queued behind outstanding writes, so the size includes them.
+                let response = this.sftp.send_fstat(this.handle()?).map_err(to_io_error)?;
+                this.seek = Some((delta, response));
+                return Ok(());
+            }
+        };
+        this.set_position(target);
+        Ok(())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        let this = self.get_mut();
+        if let Some((delta, response)) = this.seek.as_mut() {
+            let result = ready!(response.poll_wait(cx));
+            let delta = *delta;
+            this.seek = None;
+            let attrs = result.and_then(Response::into_attrs).map_err(to_io_error)?;
+            let size = attrs
+                .size
+                .ok_or_else(|| io::Error::other("server did not report the file size"))?;
+            this.set_position(seek_offset(size, delta)?);
+        }
+        Poll::Ready(Ok(this.position))
+    }
+}
+
+impl Drop for RemoteFile {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            // Queued behind any outstanding writes, the status is ignored.
+            let _ = self.sftp.send_close(&handle);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sftp::SftpOpenOptions;
+    use crate::test_server::{serve_sftp, MemoryFs};
+    use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+    async fn open(content: Vec<u8>) -> (RemoteFile, MemoryFs) {
+        let fs = MemoryFs::new();
+        fs.add_file("/f", &content, 0o644);
+        let (client, server) = tokio::io::duplex(1024 * 1024);
+        tokio::spawn(serve_sftp(server, fs.clone()));
+        let sftp = Sftp::from_stream(client).await.unwrap();
+        let file = sftp
+            .open_file("/f", &SftpOpenOptions::new().read(true).write(true))
+            .await
+            .unwrap();
+        (file, fs)
+    }
+
+    #[tokio::test]
+    async fn test_remote_file_read_and_seek() {
+        let content: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        let (mut file, _server) = open(content.clone()).await;
+
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).await.unwrap();
+        assert_eq!(data, content);
+
+        assert_eq!(file.seek(SeekFrom::End(-5)).await.unwrap(), 199_995);
+        let mut tail = Vec::new();
+        file.read_to_end(&mut tail).await.unwrap();
+        assert_eq!(tail, &content[199_995..]);
+
+        file.seek(SeekFrom::Start(1000)).await.unwrap();
+        let mut buf = [0u8; 4];
+        file.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, content[1000..1004]);
+        assert_eq!(file.seek(SeekFrom::Current(2)).await.unwrap(), 1006);
+        file.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, content[1006..1010]);
+
+        assert!(file.seek(SeekFrom::Current(-5000)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_remote_file_write() {
+        let (file, fs) = open(Vec::new()).await;
+        let mut file = file.with_pipeline(4);
+
+        let content: Vec<u8> = (0..100_000u32).map(|i| (i % 13) as u8).collect();
+        file.write_all(&content).await.unwrap();
+        file.seek(SeekFrom::Start(2)).await.unwrap();
+        file.write_all(b"xy").await.unwrap();
+
+        file.seek(SeekFrom::Start(0)).await.unwrap();
+        let mut head = [0u8; 6];
+        file.read_exact(&mut head).await.unwrap();
+        assert_eq!(head, [0, 1, b'x', b'y', 4, 5]);
+        file.close().await.unwrap();
+
+        let written = fs.file("/f").unwrap();
+        assert_eq!(written.len(), content.len());
+        assert_eq!(&written[4..], &content[4..]);
+    }
+
+    #[test]
+    fn test_to_io_error() {
+        let error = to_io_error(
+            SftpError {
+                status: SftpStatus::NoSuchFile,
+                message: "gone".into(),
+            }
+            .into(),
+        );
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        let inner = error.get_ref().unwrap().downcast_ref::<SftpError>();
+        assert_eq!(inner.unwrap().message, "gone");
+
+        let error = to_io_error(anyhow::anyhow!("SFTP session closed"));
+        assert_eq!(error.kind(), io::ErrorKind::Other);
+        assert_eq!(error.to_string(), "SFTP session closed");
+    }
+
+    #[test]
+    fn test_seek_offset() {
+        assert_eq!(seek_offset(10, -3).unwrap(), 7);
+        assert_eq!(seek_offset(10, 5).unwrap(), 15);
+        assert!(seek_offset(2, -3).is_err());
+    }
+}
@@ -0,0 +1,2087 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2025 Julian Kahlert
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Error, Result};
+use log::{debug, info};
+use russh::client::{self, Msg};
+use russh::{Channel, ChannelMsg};
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::watch;
+use tokio::time::timeout;
+
+use crate::cat::{
+    cat_receive_path, cat_receive_writer, cat_send_path, cat_send_reader, remote_path_expr,
+};
+use crate::progress::{ProgressReporter, ProgressWatcher};
+use crate::rate_limit::{RateLimited, RateLimiter};
+use crate::verify::{verify_remote_file, Digests, Hashing};
+use crate::Client;
+
+/// How symbolic links are treated when walking a local directory tree
+/// for a recursive upload.
+///
+/// Downloads are not affected: the remote `scp` always sends the
+/// contents of the link target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymlinkPolicy {
+    /// Upload the file or directory the link points to (like `scp -r`).
+    #[default]
+    Follow,
+    /// Leave links out of the transfer.
+    Skip,
+    /// Abort the transfer when a link is encountered.
+    Error,
+}
+
+/// How files are moved to and from the remote host.
+///
+/// Minimal targets (BusyBox, initramfs) often ship a shell and `cat` but
+/// no `scp` binary. The `cat` based transfer streams the raw file content
+/// over an exec channel and sets the mode with `chmod` afterwards. It only
+/// handles single files; [`ScpOptions::preserve`] needs `touch -d` for
+/// uploads and `stat -c` for downloads on the remote host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TransferStrategy {
+    /// Use `scp` and fall back to `cat` if the remote has no `scp`.
+    #[default]
+    Auto,
+    /// Always use the remote `scp`.
+    Scp,
+    /// Always use `cat >` for uploads and `cat` for downloads.
+    Cat,
+}
+
+/// Options for SCP transfers.
+///
+/// Use the builder pattern to configure the transfer:
+///
+/// ```
+/// use simple_ssh::{ScpOptions, SymlinkPolicy};
+///
+/// let options = ScpOptions::new()
+///     .recursive(true)
+///     .preserve(true)
+///     .symlinks(SymlinkPolicy::Skip);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ScpOptions {
+    pub(crate) recursive: bool,
+    pub(crate) symlinks: SymlinkPolicy,
+    pub(crate) preserve: bool,
+    pub(crate) mode: Option<u32>,
+    pub(crate) progress: Option<Arc<ProgressReporter>>,
+    pub(crate) strategy: TransferStrategy,
+    pub(crate) verify: bool,
+    pub(crate) remove_corrupt: bool,
+    pub(crate) rate_limit: Option<RateLimiter>,
+}
+
+impl PartialEq for ScpOptions {
+    fn eq(&self, other: &Self) -> bool {
+        let same_progress = match (&self.progress, &other.progress) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        };
+        self.recursive == other.recursive
+            && self.symlinks == other.symlinks
+            && self.preserve == other.preserve
+            && self.mode == other.mode
+            && self.strategy == other.strategy
+            && self.verify == other.verify
+            && self.remove_corrupt == other.remove_corrupt
+            && self.rate_limit == other.rate_limit
+            && same_progress
+    }
+}
+
+impl ScpOptions {
+    /// Creates new SCP options with defaults.
+    ///
+    /// Defaults:
+    /// - recursive: false
+    /// - symlinks: [`SymlinkPolicy::Follow`]
+    /// - preserve: false
+    /// - mode: taken from the local file
+    /// - strategy: [`TransferStrategy::Auto`]
+    /// - verify: false
+    /// - remove_corrupt: false
+    /// - rate_limit: none
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enables transferring whole directory trees (`scp -r`).
+    pub fn recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    /// Sets how symbolic links are handled during recursive uploads.
+    pub fn symlinks(mut self, policy: SymlinkPolicy) -> Self {
+        self.symlinks = policy;
+        self
+    }
+
+    /// Preserves modification times, access times and modes (`scp -p`).
+    ///
+    /// Uploads send a `T` record per file and directory and ask the remote
+    /// side to apply the exact mode. Downloads apply both to local files.
+    pub fn preserve(mut self, preserve: bool) -> Self {
+        self.preserve = preserve;
+        self
+    }
+
+    /// Overrides the mode of uploaded files (e.g. `0o755`).
+    ///
+    /// Useful when the content does not come from a local file with
+    /// meaningful permissions. Only the permission bits (`0o7777`) are
+    /// used; directories keep their own mode.
+    pub fn mode(mut self, mode: u32) -> Self {
+        self.mode = Some(mode & 0o7777);
+        self
+    }
+
+    /// Selects how the transfer is carried out on the remote side.
+    pub fn strategy(mut self, strategy: TransferStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Verifies single file transfers with a checksum.
+    ///
+    /// The data is hashed while it is streamed and compared with the
+    /// output of `sha256sum` (or `md5sum` if the remote has no
+    /// `sha256sum`) on the remote file. A difference fails the transfer
+    /// with a [`ChecksumMismatch`](crate::ChecksumMismatch) error.
+    /// Directory transfers cannot be verified and are rejected.
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Removes an uploaded file again if its verification fails.
+    ///
+    /// Only has an effect together with [`verify`](Self::verify); the
+    /// source of a download is never removed.
+    pub fn remove_corrupt(mut self, remove: bool) -> Self {
+        self.remove_corrupt = remove;
+        self
+    }
+
+    /// Limits the bandwidth of the transfer.
+    ///
+    /// Uploads are throttled while reading the source, downloads while
+    /// writing the destination; the channel's flow control then slows
+    /// the remote side down too.
+    pub fn rate_limit(mut self, limiter: RateLimiter) -> Self {
+        self.rate_limit = Some(limiter);
+        self
+    }
+
+    /// Enables progress reporting and returns a watcher for it.
+    ///
+    /// Every file of a transfer made with these options reports its
+    /// progress to the returned watcher. Clones of the options share the
+    /// same progress channel.
+    ///
+    /// ```no_run
+    /// use simple_ssh::ScpOptions;
+    ///
+    /// let mut options = ScpOptions::new();
+    /// let mut progress = options.watch_progress();
+    /// tokio::spawn(async move {
+    ///     while let Some(p) = progress.changed().await {
+    ///         println!("{}: {}/{} bytes", p.path, p.bytes, p.total);
+    ///     }
+    /// });
+    /// ```
+    pub fn watch_progress(&mut self) -> ProgressWatcher {
+        self.progress
+            .get_or_insert_with(|| Arc::new(ProgressReporter::new()))
+            .create_watcher()
+    }
+}
+
+/// The channel operations used by the SCP record state machines.
+///
+/// Implemented for session channels; the states only depend on this
+/// trait so the protocol can also be driven over an in-memory channel.
+trait SCPChannel {
+    /// Receives the next message, `None` once the channel is closed.
+    async fn wait(&mut self) -> Option<ChannelMsg>;
+    /// Sends bytes on the data stream.
+    async fn data(&mut self, data: &[u8]) -> Result<()>;
+    /// Signals that no more data follows.
+    async fn eof(&mut self) -> Result<()>;
+    /// Closes the channel.
+    async fn close(&mut self) -> Result<()>;
+}
+
+impl SCPChannel for Channel<Msg> {
+    async fn wait(&mut self) -> Option<ChannelMsg> {
+        Channel::wait(self).await
+    }
+
+    async fn data(&mut self, data: &[u8]) -> Result<()> {
+        Ok(Channel::data(self, data).await?)
+    }
+
+    async fn eof(&mut self) -> Result<()> {
+        Ok(Channel::eof(self).await?)
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        Ok(Channel::close(self).await?)
+    }
+}
+
+/// Waits for data from an SSH channel.
+///
+/// # Arguments
+///
+/// * `channel` - The SSH channel
+///
+/// # Returns
+///
+/// The received data as a byte vector.
+async fn wait_for_data<C: SCPChannel>(channel: &mut C) -> Result<Vec<u8>> {
+    // Stderr may arrive in pieces, it is reported once the remote exits.
+    let mut stderr = Vec::new();
+    loop {
+        match channel.wait().await {
+            Some(ChannelMsg::Data { ref data }) => {
+                return Ok(data.to_vec());
+            }
+            Some(ChannelMsg::ExtendedData { ref data, ext: 1 }) => {
+                stderr.extend_from_slice(data);
+            }
+            Some(ChannelMsg::ExitStatus { exit_status }) => {
+                return Err(scp_error(Some(exit_status), &stderr));
+            }
+            Some(_) => {
+                // Ignore other messages and continue waiting
+            }
+            None if !stderr.is_empty() => return Err(scp_error(None, &stderr)),
+            None => {
+                // Channel closed unexpectedly
+                return Err(Error::msg("Channel closed unexpectedly"));
+            }
+        }
+    }
+}
+
+/// Error for a remote host without a usable `scp` binary.
+///
+/// Kept as a distinct type so [`TransferStrategy::Auto`] can fall back
+/// to the `cat` based transfer.
+#[derive(Debug)]
+pub(crate) struct ScpNotFound(pub(crate) String);
+
+impl std::fmt::Display for ScpNotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "scp is not available on the remote host: {}", self.0)
+    }
+}
+
+impl std::error::Error for ScpNotFound {}
+
+/// Converts the exit status and stderr output of a failed remote scp
+/// into an error.
+///
+/// Shells report a missing command with exit status 127 and a message
+/// like `sh: scp: not found` (BusyBox) or
+/// `bash: line 1: scp: command not found`.
+fn scp_error(exit_status: Option<u32>, stderr: &[u8]) -> Error {
+    let message = String::from_utf8_lossy(stderr).trim_end().to_string();
+    if exit_status == Some(127) || (message.contains("scp") && message.contains("not found")) {
+        let reason = if message.is_empty() {
+            format!("exit code {}", exit_status.unwrap_or_default())
+        } else {
+            message
+        };
+        return ScpNotFound(reason).into();
+    }
+    match exit_status {
+        _ if !message.is_empty() => anyhow!("Remote SCP error: {}", message),
+        Some(code) => anyhow!("SCP failed with exit code {}", code),
+        None => anyhow!("Remote SCP failed"),
+    }
+}
+
+/// Initial state for SCP file transfer.
+struct SCPStateOpen {
+    channel: Channel<Msg>,
+}
+
+impl SCPStateOpen {
+    /// Initiates a file transfer to the remote path.
+    ///
+    /// # Arguments
+    ///
+    /// * `remote_path` - The destination path on the remote host
+    /// * `options` - Transfer options selecting `-r` and `-p`
+    async fn start_tx(
+        mut self,
+        remote_path: &str,
+        options: &ScpOptions,
+    ) -> Result<SCPStateTxStart<Channel<Msg>>> {
+        self.channel
+            .exec(true, scp_command(options, 't', remote_path))
+            .await?;
+
+        let data = wait_for_data(&mut self.channel).await?;
+        if data[0] != 0 {
+            return Err(Error::msg(format!("SCP start failed: {:?}", data)));
+        }
+
+        Ok(SCPStateTxStart {
+            channel: self.channel,
+        })
+    }
+}
+
+impl SCPStateOpen {
+    /// Initiates a file transfer from the remote path.
+    ///
+    /// # Arguments
+    ///
+    /// * `remote_path` - The source path on the remote host
+    /// * `options` - Transfer options selecting `-r` and `-p`
+    async fn start_rx(
+        self,
+        remote_path: &str,
+        options: &ScpOptions,
+    ) -> Result<SCPStateRx<Channel<Msg>>> {
+        self.channel
+            .exec(true, scp_command(options, 'f', remote_path))
+            .await?;
+
+        let mut state = SCPStateRx {
+            channel: self.channel,
+            pending: Vec::new(),
+            stderr: Vec::new(),
+        };
+        // A null byte asks the source to start sending records.
+        state.ack().await?;
+        Ok(state)
+    }
+}
+
+/// Returns the remote scp flags (with trailing space) for `options`.
+fn scp_flags(options: &ScpOptions) -> String {
+    let mut flags = String::new();
+    if options.recursive {
+        flags.push_str("-r ");
+    }
+    if options.preserve {
+        flags.push_str("-p ");
+    }
+    flags
+}
+
+/// Builds the remote scp command for a sink (`t`) or source (`f`).
+///
+/// The path is shell-escaped, the remote side runs the command through
+/// the user's shell.
+fn scp_command(options: &ScpOptions, direction: char, remote_path: &str) -> String {
+    format!(
+        "scp {}-{} {}",
+        scp_flags(options),
+        direction,
+        shell_escape::escape(remote_path.into())
+    )
+}
+
+/// Opens a new channel for SCP file transfer.
+///
+/// # Arguments
+///
+/// * `session` - The SSH session handle
+async fn scp_channel_open(session: &mut client::Handle<Client>) -> Result<SCPStateOpen> {
+    let res = session.channel_open_session().await;
+
+    match res {
+        Err(e) => Err(Error::msg(e.to_string())),
+        Ok(channel) => Ok(SCPStateOpen { channel }),
+    }
+}
+
+/// State for sending file metadata during SCP transfer.
+struct SCPStateTxStart<C> {
+    channel: C,
+}
+
+impl<C: SCPChannel> SCPStateTxStart<C> {
+    /// Writes file metadata to initiate the transfer.
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - Permission bits of the file
+    /// * `file_size` - Size of the file in bytes
+    /// * `file_name` - Name of the file
+    async fn write_metadata(
+        mut self,
+        mode: u32,
+        file_size: u64,
+        file_name: &str,
+    ) -> Result<SCPStateTxData<C>> {
+        let metadata_msg = format!("C{:04o} {} {}\n", mode & 0o7777, file_size, file_name);
+        self.channel.data(metadata_msg.as_bytes()).await?;
+
+        let data = wait_for_data(&mut self.channel).await?;
+        if data[0] != 0 {
+            return Err(Error::msg(format!("SCP confirmation failed: {:?}", data)));
+        }
+
+        Ok(SCPStateTxData {
+            channel: self.channel,
+        })
+    }
+
+    /// Starts a directory; following records are created inside it.
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - Permission bits of the directory
+    /// * `dir_name` - Name of the directory
+    async fn enter_dir(mut self, mode: u32, dir_name: &str) -> Result<Self> {
+        let metadata_msg = format!("D{:04o} 0 {}\n", mode & 0o7777, dir_name);
+        self.channel.data(metadata_msg.as_bytes()).await?;
+
+        let data = wait_for_data(&mut self.channel).await?;
+        if data[0] != 0 {
+            return Err(Error::msg(format!("SCP directory rejected: {:?}", data)));
+        }
+        Ok(self)
+    }
+
+    /// Sends the timestamps for the next file or directory record.
+    ///
+    /// # Arguments
+    ///
+    /// * `mtime` - Modification time in seconds since the epoch
+    /// * `atime` - Access time in seconds since the epoch
+    async fn write_times(mut self, mtime: u64, atime: u64) -> Result<Self> {
+        let metadata_msg = format!("T{} 0 {} 0\n", mtime, atime);
+        self.channel.data(metadata_msg.as_bytes()).await?;
+
+        let data = wait_for_data(&mut self.channel).await?;
+        if data[0] != 0 {
+            return Err(Error::msg(format!("SCP timestamps rejected: {:?}", data)));
+        }
+        Ok(self)
+    }
+
+    /// Ends the directory started by the matching [`Self::enter_dir`].
+    async fn leave_dir(mut self) -> Result<Self> {
+        self.channel.data(&b"E\n"[..]).await?;
+
+        let data = wait_for_data(&mut self.channel).await?;
+        if data[0] != 0 {
+            return Err(Error::msg(format!(
+                "SCP end of directory rejected: {:?}",
+                data
+            )));
+        }
+        Ok(self)
+    }
+
+    /// Signals that no more records follow.
+    async fn eof(mut self) -> Result<SCPStateEOF<C>> {
+        self.channel.eof().await?;
+
+        Ok(SCPStateEOF {
+            channel: self.channel,
+        })
+    }
+}
+
+/// State for sending file data during SCP transfer.
+struct SCPStateTxData<C> {
+    channel: C,
+}
+
+impl<C: SCPChannel> SCPStateTxData<C> {
+    /// Writes a chunk of file data.
+    ///
+    /// # Arguments
+    ///
+    /// * `buf` - Buffer containing file data
+    async fn write_data(&mut self, buf: &[u8]) -> Result<()> {
+        self.channel.data(buf).await?;
+        Ok(())
+    }
+
+    /// Signals end of file; further records may follow.
+    async fn finish(mut self) -> Result<SCPStateTxStart<C>> {
+        self.channel.data(&b"\0"[..]).await?;
+        let data = wait_for_data(&mut self.channel).await?;
+        if data[0] != 0 {
+            return Err(Error::msg(format!(
+                "SCP post-data confirmation failed: {:?}",
+                data
+            )));
+        }
+
+        Ok(SCPStateTxStart {
+            channel: self.channel,
+        })
+    }
+}
+
+/// Final state for SCP file transfer.
+struct SCPStateEOF<C> {
+    channel: C,
+}
+
+impl<C: SCPChannel> SCPStateEOF<C> {
+    /// Closes the SCP channel.
+    async fn close(mut self) -> Result<()> {
+        self.channel.close().await?;
+        Ok(())
+    }
+}
+
+/// A control record sent by the SCP source (`scp -f`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SCPRecord {
+    /// `C<mode> <size> <name>`: a regular file follows.
+    File { mode: u32, size: u64, name: String },
+    /// `D<mode> 0 <name>`: start of a directory.
+    Dir { mode: u32, name: String },
+    /// `E`: end of the current directory.
+    EndDir,
+    /// `T<mtime> 0 <atime> 0`: timestamps of the following file or directory.
+    Time { mtime: u64, atime: u64 },
+}
+
+/// Parses a single SCP control record (without the trailing newline).
+///
+/// # Arguments
+///
+/// * `line` - The record line as received from the remote scp
+fn parse_scp_record(line: &str) -> Result<SCPRecord> {
+    let invalid = || anyhow!("Invalid SCP record: {:?}", line);
+    let Some(kind) = line.chars().next() else {
+        return Err(invalid());
+    };
+    let rest = &line[kind.len_utf8()..];
+
+    match kind {
+        'C' | 'D' => {
+            let mut parts = rest.splitn(3, ' ');
+            let mode = parts.next().ok_or_else(invalid)?;
+            let size = parts.next().ok_or_else(invalid)?;
+            let name = parts.next().ok_or_else(invalid)?;
+            let mode = u32::from_str_radix(mode, 8).map_err(|_| invalid())?;
+            let size = size.parse::<u64>().map_err(|_| invalid())?;
+            if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+                return Err(anyhow!("Refusing unsafe SCP file name: {:?}", name));
+            }
+            let name = name.to_string();
+            if kind == 'C' {
+                Ok(SCPRecord::File { mode, size, name })
+            } else {
+                Ok(SCPRecord::Dir { mode, name })
+            }
+        }
+        'E' if rest.is_empty() => Ok(SCPRecord::EndDir),
+        'T' => {
+            let fields = rest
+                .split(' ')
+                .map(|f| f.parse::<u64>().map_err(|_| invalid()))
+                .collect::<Result<Vec<_>>>()?;
+            match fields.as_slice() {
+                [mtime, _, atime, _] => Ok(SCPRecord::Time {
+                    mtime: *mtime,
+                    atime: *atime,
+                }),
+                _ => Err(invalid()),
+            }
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Applies SCP `T` record timestamps to a local file.
+///
+/// # Arguments
+///
+/// * `path` - The local file
+/// * `mtime` - Modification time in seconds since the epoch
+/// * `atime` - Access time in seconds since the epoch
+pub(crate) fn set_file_times(path: &Path, mtime: u64, atime: u64) -> Result<()> {
+    let times = std::fs::FileTimes::new()
+        .set_modified(std::time::UNIX_EPOCH + Duration::from_secs(mtime))
+        .set_accessed(std::time::UNIX_EPOCH + Duration::from_secs(atime));
+    // Read-only is enough for the owner and also works for directories.
+    std::fs::File::open(path)?.set_times(times)?;
+    Ok(())
+}
+
+/// Mode and timestamps of a local file as announced in SCP records.
+pub(crate) struct SCPFileAttrs {
+    pub(crate) mode: u32,
+    pub(crate) times: (u64, u64),
+}
+
+impl SCPFileAttrs {
+    /// Reads the attributes from local metadata.
+    ///
+    /// On platforms without Unix permissions `default_mode` is used.
+    pub(crate) fn from_metadata(metadata: &std::fs::Metadata, default_mode: u32) -> Self {
+        #[cfg(unix)]
+        let mode = {
+            use std::os::unix::fs::PermissionsExt;
+            let _ = default_mode;
+            metadata.permissions().mode() & 0o7777
+        };
+        #[cfg(not(unix))]
+        let mode = default_mode;
+
+        let secs = |t: std::io::Result<std::time::SystemTime>| {
+            t.ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())
+        };
+        let mtime = secs(metadata.modified()).unwrap_or(0);
+        let atime = secs(metadata.accessed()).unwrap_or(mtime);
+
+        Self {
+            mode,
+            times: (mtime, atime),
+        }
+    }
+}
+
+/// Applies SCP record permission bits to a local file or directory.
+///
+/// # Arguments
+///
+/// * `path` - The local file or directory
+/// * `mode` - Permission bits from the `C` or `D` record
+pub(crate) fn set_file_mode(path: &Path, mode: u32) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode & 0o7777))?;
+    }
+    #[cfg(not(unix))]
+    let _ = (path, mode);
+    Ok(())
+}
+
+/// State for receiving files from a remote SCP source.
+///
+/// Channel data is not aligned to SCP records, so received bytes are
+/// buffered until a full record or payload chunk is available.
+struct SCPStateRx<C> {
+    channel: C,
+    pending: Vec<u8>,
+    stderr: Vec<u8>,
+}
+
+impl<C: SCPChannel> SCPStateRx<C> {
+    /// Receives the next channel message into the pending buffer.
+    ///
+    /// Returns `false` once the remote side has finished sending.
+    async fn fill(&mut self) -> Result<bool> {
+        loop {
+            match self.channel.wait().await {
+                Some(ChannelMsg::Data { ref data }) => {
+                    self.pending.extend_from_slice(data);
+                    return Ok(true);
+                }
+                Some(ChannelMsg::ExtendedData { ref data, ext: 1 }) => {
+                    // Banners and warnings are harmless on their own, the
+                    // output only explains a failure reported otherwise.
+                    self.stderr.extend_from_slice(data);
+                }
+                Some(ChannelMsg::ExitStatus { exit_status }) if exit_status != 0 => {
+                    return Err(scp_error(Some(exit_status), &self.stderr));
+                }
+                Some(ChannelMsg::Eof) | Some(ChannelMsg::Close) | None => {
+                    return Ok(false);
+                }
+                Some(_) => {
+                    // Window adjust, exit status 0, or other protocol messages - ignore
+                }
+            }
+        }
+    }
+
+    /// Returns the error for a source that ended without sending a file.
+    ///
+    /// Stderr output, such as a shell's `scp: not found`, is the reason
+    /// if there is any.
+    fn no_file_error(&self, remote_path: &str) -> Error {
+        if self.stderr.is_empty() {
+            anyhow!("Remote SCP sent no file for '{}'", remote_path)
+        } else {
+            scp_error(None, &self.stderr)
+        }
+    }
+
+    /// Sends a positive acknowledgement to the remote source.
+    async fn ack(&mut self) -> Result<()> {
+        self.channel.data(&b"\0"[..]).await?;
+        Ok(())
+    }
+
+    /// Reads and acknowledges the next control record.
+    ///
+    /// Returns `None` when the remote source has no more records.
+    async fn next_record(&mut self) -> Result<Option<SCPRecord>> {
+        let line = loop {
+            if let Some(pos) = self.pending.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = self.pending.drain(..=pos).collect();
+                break line;
+            }
+            if !self.fill().await? {
+                if self.pending.is_empty() {
+                    return Ok(None);
+                }
+                return Err(anyhow!("Channel closed inside an SCP record"));
+            }
+        };
+
+        let line = String::from_utf8_lossy(&line[..line.len() - 1]).to_string();
+        if let Some(msg) = line.strip_prefix('\x01').or(line.strip_prefix('\x02')) {
+            return Err(anyhow!("Remote SCP error: {}", msg));
+        }
+
+        let record = parse_scp_record(&line)?;
+        self.ack().await?;
+        Ok(Some(record))
+    }
+
+    /// Streams the payload of a `C` record into `writer`.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - Payload size announced by the `C` record
+    /// * `writer` - Destination for the payload
+    /// * `progress` - Optional progress reporter for the payload
+    async fn read_file_data<W: AsyncWrite + Unpin>(
+        &mut self,
+        size: u64,
+        writer: &mut W,
+        progress: Option<&ProgressReporter>,
+    ) -> Result<()> {
+        let mut remaining = size;
+        while remaining > 0 {
+            if self.pending.is_empty() && !self.fill().await? {
+                return Err(anyhow!(
+                    "Channel closed with {} bytes of file data outstanding",
+                    remaining
+                ));
+            }
+            let n = remaining.min(self.pending.len() as u64) as usize;
+            writer.write_all(&self.pending[..n]).await?;
+            self.pending.drain(..n);
+            remaining -= n as u64;
+            if let Some(progress) = progress {
+                progress.advance(n as u64);
+            }
+        }
+        writer.flush().await?;
+
+        // The source terminates every payload with a status byte.
+        while self.pending.is_empty() {
+            if !self.fill().await? {
+                return Err(anyhow!("Channel closed before SCP file status"));
+            }
+        }
+        if self.pending[0] != 0 {
+            let msg = String::from_utf8_lossy(&self.pending[1..]).to_string();
+            return Err(anyhow!("Remote SCP error: {}", msg.trim_end()));
+        }
+        self.pending.drain(..1);
+        self.ack().await
+    }
+
+    /// Closes the SCP channel.
+    async fn close(mut self) -> Result<()> {
+        self.channel.close().await?;
+        Ok(())
+    }
+}
+
+/// Returns the local path for a top-level SCP record.
+///
+/// Records are stored inside `local_path` if it is an existing
+/// directory, otherwise they replace it.
+pub(crate) fn scp_local_target(local_path: &Path, name: &str) -> PathBuf {
+    if local_path.is_dir() {
+        local_path.join(name)
+    } else {
+        local_path.to_path_buf()
+    }
+}
+
+/// A directory being received, finalized on its `E` record.
+struct SCPOpenDir {
+    path: PathBuf,
+    mode: u32,
+    times: Option<(u64, u64)>,
+}
+
+/// Creates or truncates a local file for an SCP `C` record.
+///
+/// New files get the record's mode, restricted by the umask.
+pub(crate) async fn scp_create_file(path: &Path, mode: u32) -> Result<File> {
+    let mut open = tokio::fs::OpenOptions::new();
+    open.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    open.mode(mode & 0o7777);
+    #[cfg(not(unix))]
+    let _ = mode;
+    Ok(open.open(path).await?)
+}
+
+/// Creates a local directory for an SCP `D` record.
+///
+/// The record's mode is restricted by the umask and always keeps the
+/// directory writable by its owner so its contents can be created.
+async fn scp_create_dir(path: &Path, mode: u32) -> Result<()> {
+    let mut builder = tokio::fs::DirBuilder::new();
+    #[cfg(unix)]
+    builder.mode((mode & 0o7777) | 0o700);
+    #[cfg(not(unix))]
+    let _ = mode;
+    builder.create(path).await?;
+    Ok(())
+}
+
+/// Transfers a file or directory tree from the remote host using SCP
+/// protocol.
+///
+/// If `local_path` is an existing directory, the file is stored inside it
+/// under its remote name.
+///
+/// # Arguments
+///
+/// * `session` - The SSH session handle
+/// * `remote_path` - Path of the file on the remote host
+/// * `local_path` - Local destination path
+/// * `options` - Transfer options
+pub(crate) async fn scp_receive_path(
+    session: &mut client::Handle<Client>,
+    remote_path: &str,
+    local_path: &str,
+    options: &ScpOptions,
+    digests: Option<&mut Digests>,
+) -> Result<()> {
+    let state = scp_channel_open(session).await?;
+    let mut state = state.start_rx(remote_path, options).await?;
+    scp_receive_records(
+        &mut state,
+        remote_path,
+        Path::new(local_path),
+        options,
+        digests,
+    )
+    .await?;
+    state.close().await
+}
+
+/// Stores the files and directories announced by a remote SCP source.
+///
+/// # Arguments
+///
+/// * `state` - The started receive channel
+/// * `remote_path` - Path of the source on the remote host, for errors
+/// * `local_path` - Local destination path
+/// * `options` - Transfer options
+/// * `digests` - Receives the digests of a single file
+async fn scp_receive_records<C: SCPChannel>(
+    state: &mut SCPStateRx<C>,
+    remote_path: &str,
+    local_path: &Path,
+    options: &ScpOptions,
+    mut digests: Option<&mut Digests>,
+) -> Result<()> {
+    // Directories entered via `D` records, with their mode and timestamps
+    // which are applied once the directory is complete.
+    let mut dirs: Vec<SCPOpenDir> = Vec::new();
+    let mut times = None;
+    let mut received = false;
+
+    while let Some(record) = state.next_record().await? {
+        match record {
+            SCPRecord::Time { mtime, atime } => times = Some((mtime, atime)),
+            SCPRecord::File { mode, size, name } => {
+                let target = match dirs.last() {
+                    Some(dir) => dir.path.join(&name),
+                    None if received && !local_path.is_dir() => {
+                        return Err(anyhow!("Remote SCP sent more than one file"));
+                    }
+                    None => scp_local_target(local_path, &name),
+                };
+                debug!("Receiving {} bytes into {}", size, target.display());
+
+                let progress = options.progress.as_deref();
+                if let Some(progress) = progress {
+                    progress.start(&target.to_string_lossy(), size);
+                }
+                let file = scp_create_file(&target, mode).await?;
+                let mut file = RateLimited::optional(
+                    Hashing::new(file, digests.as_deref_mut()),
+                    options.rate_limit.clone(),
+                );
+                state.read_file_data(size, &mut file, progress).await?;
+                drop(file);
+                if let Some(progress) = progress {
+                    progress.finish();
+                }
+
+                if options.preserve {
+                    set_file_mode(&target, mode)?;
+                }
+                if let Some((mtime, atime)) = times.take() {
+                    set_file_times(&target, mtime, atime)?;
+                }
+                received = true;
+            }
+            SCPRecord::Dir { mode, name } => {
+                if !options.recursive {
+                    return Err(anyhow!("'{}' is a directory", name));
+                }
+                if digests.is_some() {
+                    return Err(anyhow!("Directory transfers cannot be verified"));
+                }
+                let target = match dirs.last() {
+                    Some(dir) => dir.path.join(&name),
+                    None => scp_local_target(local_path, &name),
+                };
+                if !target.is_dir() {
+                    scp_create_dir(&target, mode).await?;
+                }
+                dirs.push(SCPOpenDir {
+                    path: target,
+                    mode,
+                    times: times.take(),
+                });
+                received = true;
+            }
+            SCPRecord::EndDir => {
+                let Some(dir) = dirs.pop() else {
+                    return Err(anyhow!("Unexpected SCP end of directory record"));
+                };
+                // Applied last so a read-only mode cannot block the contents.
+                if options.preserve {
+                    set_file_mode(&dir.path, dir.mode)?;
+                }
+                if let Some((mtime, atime)) = dir.times {
+                    set_file_times(&dir.path, mtime, atime)?;
+                }
+            }
+        }
+    }
+
+    if !received {
+        return Err(state.no_file_error(remote_path));
+    }
+    if !dirs.is_empty() {
+        return Err(anyhow!("Remote SCP ended inside a directory"));
+    }
+    Ok(())
+}
+
+/// Streams a single file over an SCP channel.
+///
+/// # Arguments
+///
+/// * `state` - The channel, ready for the next record
+/// * `reader` - Source of the file content
+/// * `file_size` - Number of bytes to send
+/// * `file_name` - Name announced in the `C` record
+/// * `mode` - Permission bits announced in the `C` record
+/// * `times` - Timestamps to send in a `T` record first, if any
+/// * `label` - Description of the file for log messages and progress
+/// * `progress` - Optional progress reporter
+#[allow(clippy::too_many_arguments)]
+async fn scp_send_file<C: SCPChannel, R: AsyncRead + Unpin>(
+    state: SCPStateTxStart<C>,
+    reader: R,
+    file_size: u64,
+    file_name: &str,
+    mode: u32,
+    times: Option<(u64, u64)>,
+    label: &str,
+    progress: Option<&ProgressReporter>,
+) -> Result<SCPStateTxStart<C>> {
+    let state = match times {
+        Some((mtime, atime)) => state.write_times(mtime, atime).await?,
+        None => state,
+    };
+    let mut state = state.write_metadata(mode, file_size, file_name).await?;
+    if let Some(progress) = progress {
+        progress.start(label, file_size);
+    }
+
+    const WRITE_TIMEOUT: Duration = Duration::from_secs(16);
+    let mut buffer = [0u8; 16 * 1024];
+    // Never send more than announced, even if the source grows meanwhile.
+    let mut reader = reader.take(file_size);
+    let mut sent = 0u64;
+
+    let mut eof_reached = false;
+
+    loop {
+        tokio::select! {
+            // Read from file and send data
+            result = reader.read(&mut buffer), if !eof_reached => {
+                match result {
+                    Ok(0) => {
+                        // EOF reached, mark it but continue processing channel messages
+                        eof_reached = true;
+                    }
+                    Ok(n) => {
+                        debug!("Writing {} bytes of {}", n, label);
+                        // Apply timeout only to the write operation
+                        timeout(WRITE_TIMEOUT, state.write_data(&buffer[..n]))
+                            .await
+                            .map_err(|_| anyhow!("Write timed out after {:?}", WRITE_TIMEOUT))??;
+                        sent += n as u64;
+                        if let Some(progress) = progress {
+                            progress.advance(n as u64);
+                        }
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            }
+
+            // Handle SSH channel messages (window adjust, errors, etc.)
+            msg = state.channel.wait() => {
+                match msg {
+                    Some(ChannelMsg::ExtendedData { data, ext: 1 }) => {
+                        return Err(anyhow!(
+                            "Remote SCP error: {}",
+                            String::from_utf8_lossy(&data)
+                        ));
+                    }
+                    Some(ChannelMsg::ExitStatus { exit_status }) => {
+                        return Err(anyhow!(
+                            "Remote SCP exited early with code: {}",
+                            exit_status
+                        ));
+                    }
+                    Some(_) => {
+                        // Window adjust, keepalive, or other protocol messages - ignore
+                    }
+                    None => {
+                        // Channel closed unexpectedly
+                        return Err(anyhow!("Channel closed during transfer"));
+                    }
+                }
+            }
+        }
+
+        // Exit the loop after EOF and all pending operations complete
+        if eof_reached {
+            break;
+        }
+    }
+
+    if sent != file_size {
+        return Err(anyhow!(
+            "Source ended early: sent {} of {} bytes",
+            sent,
+            file_size
+        ));
+    }
+    if let Some(progress) = progress {
+        progress.finish();
+    }
+    state.finish().await
+}
+
+/// Sends a local directory tree as `D`/`E` records.
+///
+/// Entries are sent in name order. `ancestors` holds the canonical paths
+/// of the directories currently being sent, to stop symlink loops.
+///
+/// # Arguments
+///
+/// * `state` - The channel, ready for the next record
+/// * `dir` - The local directory
+/// * `dir_name` - Name announced in the `D` record
+/// * `options` - Transfer options
+/// * `ancestors` - Directories on the current path
+async fn scp_send_dir<C: SCPChannel>(
+    state: SCPStateTxStart<C>,
+    dir: &Path,
+    dir_name: &str,
+    options: &ScpOptions,
+    ancestors: &mut Vec<PathBuf>,
+) -> Result<SCPStateTxStart<C>> {
+    let canonical = tokio::fs::canonicalize(dir).await?;
+    if ancestors.contains(&canonical) {
+        return Err(anyhow!("Symlink loop detected at '{}'", dir.display()));
+    }
+    ancestors.push(canonical);
+
+    let mut entries = Vec::new();
+    let mut read_dir = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = read_dir.next_entry().await? {
+        entries.push(entry.path());
+    }
+    entries.sort();
+
+    let attrs = SCPFileAttrs::from_metadata(&tokio::fs::metadata(dir).await?, 0o755);
+    let state = if options.preserve {
+        state.write_times(attrs.times.0, attrs.times.1).await?
+    } else {
+        state
+    };
+    let mut state = state.enter_dir(attrs.mode, dir_name).await?;
+    for path in entries {
+        let name = path
+            .file_name()
+            .ok_or_else(|| anyhow!("Invalid file name"))?
+            .to_string_lossy()
+            .to_string();
+
+        let mut metadata = tokio::fs::symlink_metadata(&path).await?;
+        if metadata.file_type().is_symlink() {
+            match options.symlinks {
+                SymlinkPolicy::Follow => metadata = tokio::fs::metadata(&path).await?,
+                SymlinkPolicy::Skip => {
+                    debug!("Skipping symlink {}", path.display());
+                    continue;
+                }
+                SymlinkPolicy::Error => {
+                    return Err(anyhow!("Refusing to upload symlink '{}'", path.display()));
+                }
+            }
+        }
+
+        if metadata.is_dir() {
+            state = Box::pin(scp_send_dir(state, &path, &name, options, ancestors)).await?;
+        } else if metadata.is_file() {
+            let file = RateLimited::optional(File::open(&path).await?, options.rate_limit.clone());
+            let attrs = SCPFileAttrs::from_metadata(&metadata, 0o644);
+            let mode = options.mode.unwrap_or(attrs.mode);
+            let times = options.preserve.then_some(attrs.times);
+            let label = path.display().to_string();
+            state = scp_send_file(
+                state,
+                file,
+                metadata.len(),
+                &name,
+                mode,
+                times,
+                &label,
+                options.progress.as_deref(),
+            )
+            .await?;
+        } else {
+            debug!("Skipping special file {}", path.display());
+        }
+    }
+    ancestors.pop();
+
+    state.leave_dir().await
+}
+
+/// Transfers the content of a reader to a remote file using SCP protocol.
+///
+/// The file name sent in the `C` record is taken from `remote_path`.
+///
+/// # Arguments
+///
+/// * `session` - The SSH session handle
+/// * `reader` - Source of the file content
+/// * `size` - Number of bytes to send
+/// * `remote_path` - Destination path on the remote host
+/// * `options` - Transfer options; `mode` defaults to `0o644`
+pub(crate) async fn scp_send_reader<R: AsyncRead + Unpin>(
+    session: &mut client::Handle<Client>,
+    reader: R,
+    size: u64,
+    remote_path: &str,
+    options: &ScpOptions,
+) -> Result<()> {
+    let file_name = Path::new(remote_path)
+        .file_name()
+        .ok_or_else(|| anyhow!("Invalid file name"))?
+        .to_string_lossy()
+        .to_string();
+
+    let state = scp_channel_open(session).await?;
+    let state = state.start_tx(remote_path, options).await?;
+    let state = scp_send_file(
+        state,
+        reader,
+        size,
+        &file_name,
+        options.mode.unwrap_or(0o644),
+        None,
+        remote_path,
+        options.progress.as_deref(),
+    )
+    .await?;
+    let state = state.eof().await?;
+    state.close().await
+}
+
+/// Announces the size and mode of a downloaded file before its content
+/// is written, so that a consumer can start forwarding it.
+pub(crate) type FileHeaderSender = watch::Sender<Option<(u64, u32)>>;
+
+/// Transfers a remote file into a writer using SCP protocol.
+///
+/// # Arguments
+///
+/// * `session` - The SSH session handle
+/// * `remote_path` - Path of the file on the remote host
+/// * `writer` - Destination for the file content
+/// * `options` - Transfer options
+/// * `announce` - Receives the size and mode of the file before its content
+///
+/// # Returns
+///
+/// The number of bytes written.
+pub(crate) async fn scp_receive_writer<W: AsyncWrite + Unpin>(
+    session: &mut client::Handle<Client>,
+    remote_path: &str,
+    writer: &mut W,
+    options: &ScpOptions,
+    announce: Option<&FileHeaderSender>,
+) -> Result<u64> {
+    let state = scp_channel_open(session).await?;
+    let mut state = state.start_rx(remote_path, options).await?;
+    let written = scp_receive_file(&mut state, remote_path, writer, options, announce).await?;
+    state.close().await?;
+    Ok(written)
+}
+
+/// Streams the single file announced by a remote SCP source into
+/// `writer`.
+///
+/// # Arguments
+///
+/// * `state` - The started receive channel
+/// * `remote_path` - Path of the file on the remote host
+/// * `writer` - Destination for the file content
+/// * `options` - Transfer options
+/// * `announce` - Receives the size and mode of the file before its content
+async fn scp_receive_file<C: SCPChannel, W: AsyncWrite + Unpin>(
+    state: &mut SCPStateRx<C>,
+    remote_path: &str,
+    writer: &mut W,
+    options: &ScpOptions,
+    announce: Option<&FileHeaderSender>,
+) -> Result<u64> {
+    let progress = options.progress.as_deref();
+
+    let mut written = None;
+    while let Some(record) = state.next_record().await? {
+        match record {
+            SCPRecord::Time { .. } => {}
+            SCPRecord::File { mode, size, .. } if written.is_none() => {
+                if let Some(announce) = announce {
+                    announce.send_replace(Some((size, mode)));
+                }
+                if let Some(progress) = progress {
+                    progress.start(remote_path, size);
+                }
+                state.read_file_data(size, writer, progress).await?;
+                if let Some(progress) = progress {
+                    progress.finish();
+                }
+                written = Some(size);
+            }
+            SCPRecord::File { .. } => {
+                return Err(anyhow!("Remote SCP sent more than one file"));
+            }
+            SCPRecord::Dir { name, .. } => {
+                return Err(anyhow!("'{}' is a directory", name));
+            }
+            SCPRecord::EndDir => {
+                return Err(anyhow!("Unexpected SCP end of directory record"));
+            }
+        }
+    }
+
+    written.ok_or_else(|| state.no_file_error(remote_path))
+}
+
+/// Transfers a file or directory tree to the remote host using SCP
+/// protocol.
+///
+/// # Arguments
+///
+/// * `session` - The SSH session handle
+/// * `local_path` - Path to the local file or directory
+/// * `remote_path` - Destination path on the remote host
+/// * `options` - Transfer options
+pub(crate) async fn scp_send_path(
+    session: &mut client::Handle<Client>,
+    local_path: &str,
+    remote_path: &str,
+    options: &ScpOptions,
+    digests: Option<&mut Digests>,
+) -> Result<()> {
+    let path = Path::new(local_path);
+    if tokio::fs::metadata(path).await?.is_dir() {
+        if !options.recursive {
+            return Err(anyhow!("'{}' is a directory", local_path));
+        }
+        let canonical = tokio::fs::canonicalize(path).await?;
+        let dir_name = canonical
+            .file_name()
+            .ok_or_else(|| anyhow!("Invalid directory name"))?
+            .to_string_lossy()
+            .to_string();
+
+        let state = scp_channel_open(session).await?;
+        let state = state.start_tx(remote_path, options).await?;
+        let state = scp_send_dir(state, path, &dir_name, options, &mut Vec::new()).await?;
+        let state = state.eof().await?;
+        return state.close().await;
+    }
+
+    let file = File::open(local_path).await?;
+    let state = scp_channel_open(session).await?;
+    let state = state.start_tx(remote_path, options).await?;
+
+    // Get file size, mode and name
+    let metadata = file.metadata().await?;
+    let file_size = metadata.len();
+    let attrs = SCPFileAttrs::from_metadata(&metadata, 0o644);
+    let mode = options.mode.unwrap_or(attrs.mode);
+    let times = options.preserve.then_some(attrs.times);
+    let file_name = Path::new(local_path)
+        .file_name()
+        .ok_or_else(|| anyhow!("Invalid file name"))?
+        .to_string_lossy();
+
+    let state = scp_send_file(
+        state,
+        RateLimited::optional(Hashing::new(file, digests), options.rate_limit.clone()),
+        file_size,
+        &file_name,
+        mode,
+        times,
+        local_path,
+        options.progress.as_deref(),
+    )
+    .await?;
+    let state = state.eof().await?;
+    state.close().await
+}
+
+/// Whether a failed scp transfer should be retried with `cat`.
+fn scp_fall_back<T>(options: &ScpOptions, result: &Result<T>) -> bool {
+    match result {
+        Err(e) if options.strategy == TransferStrategy::Auto => {
+            let missing = e.is::<ScpNotFound>();
+            if missing {
+                info!("{}, falling back to cat", e);
+            }
+            missing
+        }
+        _ => false,
+    }
+}
+
+/// Transfers a file or directory tree from the remote host.
+///
+/// Uses scp or cat depending on `options.strategy` and verifies the
+/// result if requested.
+///
+/// # Arguments
+///
+/// * `session` - The SSH session handle
+/// * `remote_path` - Path of the file on the remote host
+/// * `local_path` - Local destination path
+/// * `options` - Transfer options
+pub(crate) async fn scp_download(
+    session: &mut client::Handle<Client>,
+    remote_path: &str,
+    local_path: &str,
+    options: &ScpOptions,
+) -> Result<()> {
+    let mut digests = options.verify.then(Digests::new);
+
+    if options.strategy == TransferStrategy::Cat {
+        cat_receive_path(session, remote_path, local_path, options, digests.as_mut()).await?;
+    } else {
+        let result =
+            scp_receive_path(session, remote_path, local_path, options, digests.as_mut()).await;
+        if scp_fall_back(options, &result) {
+            cat_receive_path(session, remote_path, local_path, options, digests.as_mut()).await?;
+        } else {
+            result?;
+        }
+    }
+
+    if let Some(digests) = digests {
+        let path_expr = remote_path_expr(remote_path, None);
+        verify_remote_file(session, &path_expr, remote_path, &digests, false).await?;
+    }
+    Ok(())
+}
+
+/// Transfers the content of a reader to a remote file.
+///
+/// Uses scp or cat depending on `options.strategy` and verifies the
+/// result if requested.
+///
+/// # Arguments
+///
+/// * `session` - The SSH session handle
+/// * `reader` - Source of the file content
+/// * `size` - Number of bytes to send
+/// * `remote_path` - Destination path on the remote host
+/// * `options` - Transfer options; `mode` defaults to `0o644`
+pub(crate) async fn scp_upload_reader<R: AsyncRead + Unpin>(
+    session: &mut client::Handle<Client>,
+    reader: R,
+    size: u64,
+    remote_path: &str,
+    options: &ScpOptions,
+) -> Result<()> {
+    let mut digests = options.verify.then(Digests::new);
+    let mut reader = RateLimited::optional(
+        Hashing::new(reader, digests.as_mut()),
+        options.rate_limit.clone(),
+    );
+
+    if options.strategy == TransferStrategy::Cat {
+        cat_send_reader(session, &mut reader, size, remote_path, options).await?;
+    } else {
+        // A missing scp is detected before anything is read from `reader`.
+        let result = scp_send_reader(session, &mut reader, size, remote_path, options).await;
+        if scp_fall_back(options, &result) {
+            cat_send_reader(session, &mut reader, size, remote_path, options).await?;
+        } else {
+            result?;
+        }
+    }
+    drop(reader);
+
+    if let Some(digests) = digests {
+        let file_name = std::path::Path::new(remote_path)
+            .file_name()
+            .ok_or_else(|| anyhow!("Invalid file name"))?
+            .to_string_lossy()
+            .to_string();
+        let path_expr = remote_path_expr(remote_path, Some(&file_name));
+        verify_remote_file(
+            session,
+            &path_expr,
+            remote_path,
+            &digests,
+            options.remove_corrupt,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Transfers a remote file into a writer.
+///
+/// Uses scp or cat depending on `options.strategy` and verifies the
+/// result if requested.
+///
+/// # Arguments
+///
+/// * `session` - The SSH session handle
+/// * `remote_path` - Path of the file on the remote host
+/// * `writer` - Destination for the file content
+/// * `options` - Transfer options
+/// * `announce` - Receives the size and mode of the file before its content
+///
+/// # Returns
+///
+/// The number of bytes written.
+pub(crate) async fn scp_download_writer<W: AsyncWrite + Unpin>(
+    session: &mut client::Handle<Client>,
+    remote_path: &str,
+    writer: &mut W,
+    options: &ScpOptions,
+    announce: Option<&FileHeaderSender>,
+) -> Result<u64> {
+    let mut digests = options.verify.then(Digests::new);
+    let mut writer = RateLimited::optional(
+        Hashing::new(writer, digests.as_mut()),
+        options.rate_limit.clone(),
+    );
+
+    let written = if options.strategy == TransferStrategy::Cat {
+        cat_receive_writer(session, remote_path, &mut writer, options, announce)
+            .await?
+            .0
+    } else {
+        let result = scp_receive_writer(session, remote_path, &mut writer, options, announce).await;
+        if scp_fall_back(options, &result) {
+            cat_receive_writer(session, remote_path, &mut writer, options, announce)
+                .await?
+                .0
+        } else {
+            result?
+        }
+    };
+    drop(writer);
+
+    if let Some(digests) = digests {
+        let path_expr = remote_path_expr(remote_path, None);
+        verify_remote_file(session, &path_expr, remote_path, &digests, false).await?;
+    }
+    Ok(written)
+}
+
+/// Transfers a file or directory tree to the remote host.
+///
+/// Uses scp or cat depending on `options.strategy` and verifies the
+/// result if requested.
+///
+/// # Arguments
+///
+/// * `session` - The SSH session handle
+/// * `local_path` - Path to the local file or directory
+/// * `remote_path` - Destination path on the remote host
+/// * `options` - Transfer options
+pub(crate) async fn scp(
+    session: &mut client::Handle<Client>,
+    local_path: &str,
+    remote_path: &str,
+    options: &ScpOptions,
+) -> Result<()> {
+    let mut digests = options.verify.then(Digests::new);
+    if digests.is_some() && tokio::fs::metadata(local_path).await?.is_dir() {
+        return Err(anyhow!("Directory transfers cannot be verified"));
+    }
+
+    if options.strategy == TransferStrategy::Cat {
+        cat_send_path(session, local_path, remote_path, options, digests.as_mut()).await?;
+    } else {
+        let result =
+            scp_send_path(session, local_path, remote_path, options, digests.as_mut()).await;
+        if scp_fall_back(options, &result) {
+            cat_send_path(session, local_path, remote_path, options, digests.as_mut()).await?;
+        } else {
+            result?;
+        }
+    }
+
+    if let Some(digests) = digests {
+        let file_name = std::path::Path::new(local_path)
+            .file_name()
+            .ok_or_else(|| anyhow!("Invalid file name"))?
+            .to_string_lossy()
+            .to_string();
+        let path_expr = remote_path_expr(remote_path, Some(&file_name));
+        verify_remote_file(
+            session,
+            &path_expr,
+            remote_path,
+            &digests,
+            options.remove_corrupt,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use russh::CryptoVec;
+    use tokio::sync::mpsc;
+
+    /// One end of an in-memory channel pair.
+    pub(crate) struct MemoryChannel {
+        tx: mpsc::UnboundedSender<ChannelMsg>,
+        rx: mpsc::UnboundedReceiver<ChannelMsg>,
+    }
+
+    impl MemoryChannel {
+        pub(crate) fn pair() -> (Self, Self) {
+            let (a_tx, a_rx) = mpsc::unbounded_channel();
+            let (b_tx, b_rx) = mpsc::unbounded_channel();
+            (Self { tx: a_tx, rx: b_rx }, Self { tx: b_tx, rx: a_rx })
+        }
+
+        pub(crate) fn send(&self, msg: ChannelMsg) {
+            // The peer may already be done, like a remote that exited.
+            let _ = self.tx.send(msg);
+        }
+
+        pub(crate) fn send_data(&self, data: &[u8]) {
+            self.send(ChannelMsg::Data {
+                data: CryptoVec::from_slice(data),
+            });
+        }
+    }
+
+    impl SCPChannel for MemoryChannel {
+        async fn wait(&mut self) -> Option<ChannelMsg> {
+            self.rx.recv().await
+        }
+
+        async fn data(&mut self, data: &[u8]) -> Result<()> {
+            self.send_data(data);
+            Ok(())
+        }
+
+        async fn eof(&mut self) -> Result<()> {
+            self.send(ChannelMsg::Eof);
+            Ok(())
+        }
+
+        async fn close(&mut self) -> Result<()> {
+            self.send(ChannelMsg::Close);
+            Ok(())
+        }
+    }
+
+    /// Returns a receive state as left by `start_rx`.
+    async fn receiver(channel: MemoryChannel) -> SCPStateRx<MemoryChannel> {
+        let mut state = SCPStateRx {
+            channel,
+            pending: Vec::new(),
+            stderr: Vec::new(),
+        };
+        state.ack().await.unwrap();
+        state
+    }
+
+    /// Receives a single file from `channel` like `scp_receive_writer`.
+    pub(crate) async fn receive_file<W: AsyncWrite + Unpin>(
+        channel: MemoryChannel,
+        writer: &mut W,
+        announce: Option<&FileHeaderSender>,
+    ) -> Result<u64> {
+        let mut state = receiver(channel).await;
+        let options = ScpOptions::new();
+        let written = scp_receive_file(&mut state, "/src", writer, &options, announce).await?;
+        state.close().await?;
+        Ok(written)
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("simple_ssh_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_parse_scp_record_file() {
+        let record = parse_scp_record("C0755 1234 run.sh").unwrap();
+        assert_eq!(
+            record,
+            SCPRecord::File {
+                mode: 0o755,
+                size: 1234,
+                name: "run.sh".to_string()
+            }
+        );
+
+        let record = parse_scp_record("C0644 0 name with spaces.txt").unwrap();
+        assert_eq!(
+            record,
+            SCPRecord::File {
+                mode: 0o644,
+                size: 0,
+                name: "name with spaces.txt".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_scp_record_dir_end_time() {
+        assert_eq!(
+            parse_scp_record("D0700 0 etc").unwrap(),
+            SCPRecord::Dir {
+                mode: 0o700,
+                name: "etc".to_string()
+            }
+        );
+        assert_eq!(parse_scp_record("E").unwrap(), SCPRecord::EndDir);
+        assert_eq!(
+            parse_scp_record("T1700000000 0 1700000100 0").unwrap(),
+            SCPRecord::Time {
+                mtime: 1700000000,
+                atime: 1700000100
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_scp_record_invalid() {
+        assert!(parse_scp_record("").is_err());
+        assert!(parse_scp_record("C0644 abc file").is_err());
+        assert!(parse_scp_record("C0999 1 file").is_err());
+        assert!(parse_scp_record("C0644 1").is_err());
+        assert!(parse_scp_record("T1 0 2").is_err());
+        assert!(parse_scp_record("Ex").is_err());
+        assert!(parse_scp_record("X").is_err());
+        assert!(parse_scp_record("\u{FFFD}0644 1 file").is_err());
+        assert!(parse_scp_record("\u{e9}").is_err());
+    }
+
+    #[test]
+    fn test_parse_scp_record_rejects_unsafe_names() {
+        assert!(parse_scp_record("C0644 1 ../passwd").is_err());
+        assert!(parse_scp_record("C0644 1 ..").is_err());
+        assert!(parse_scp_record("D0755 0 a/b").is_err());
+        assert!(parse_scp_record("C0644 1 ").is_err());
+        assert!(parse_scp_record("D0755 0 ..").is_err());
+        assert!(parse_scp_record("D0755 0 .").is_err());
+        assert!(parse_scp_record("D0755 0 /etc").is_err());
+        assert!(parse_scp_record("C0644 1 dir/").is_err());
+    }
+
+    #[test]
+    fn test_parse_scp_record_nested_sequence() {
+        let records = ["D0755 0 my dir", "C0640 3 a b.txt", "D0700 0 sub", "E", "E"]
+            .iter()
+            .map(|line| parse_scp_record(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            records,
+            vec![
+                SCPRecord::Dir {
+                    mode: 0o755,
+                    name: "my dir".to_string()
+                },
+                SCPRecord::File {
+                    mode: 0o640,
+                    size: 3,
+                    name: "a b.txt".to_string()
+                },
+                SCPRecord::Dir {
+                    mode: 0o700,
+                    name: "sub".to_string()
+                },
+                SCPRecord::EndDir,
+                SCPRecord::EndDir,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_scp_dir_round_trip() {
+        let src = temp_dir("scp_src");
+        let dst = temp_dir("scp_dst");
+        let tree = src.join("my tree");
+        std::fs::create_dir_all(tree.join("sub dir").join("deeper")).unwrap();
+        std::fs::write(tree.join("top file.txt"), b"top").unwrap();
+        std::fs::write(tree.join("sub dir").join("a.bin"), [0u8, 1, 2, 255]).unwrap();
+        std::fs::write(tree.join("sub dir").join("deeper").join("b"), b"").unwrap();
+
+        let (tx, rx) = MemoryChannel::pair();
+        let options = ScpOptions::new().recursive(true);
+        let send = async {
+            let mut state = SCPStateTxStart { channel: tx };
+            wait_for_data(&mut state.channel).await?;
+            let state = scp_send_dir(state, &tree, "my tree", &options, &mut Vec::new()).await?;
+            state.eof().await?.close().await
+        };
+        let receive = async {
+            let mut state = receiver(rx).await;
+            scp_receive_records(&mut state, "my tree", &dst, &options, None).await
+        };
+        let (sent, received) = tokio::join!(send, receive);
+        sent.unwrap();
+        received.unwrap();
+
+        let out = dst.join("my tree");
+        assert_eq!(std::fs::read(out.join("top file.txt")).unwrap(), b"top");
+        assert_eq!(
+            std::fs::read(out.join("sub dir").join("a.bin")).unwrap(),
+            [0u8, 1, 2, 255]
+        );
+        assert!(std::fs::read(out.join("sub dir").join("deeper").join("b"))
+            .unwrap()
+            .is_empty());
+
+        std::fs::remove_dir_all(&src).unwrap();
+        std::fs::remove_dir_all(&dst).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_scp_receive_rejects_non_ascii_record() {
+        let (source, rx) = MemoryChannel::pair();
+        source.send_data(b"\xffC0644 1 f\n");
+        source.send(ChannelMsg::Eof);
+
+        let mut state = receiver(rx).await;
+        let err = state.next_record().await.unwrap_err();
+        assert!(err.to_string().starts_with("Invalid SCP record"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_scp_receive_ignores_stderr_noise() {
+        let dst = temp_dir("scp_noise");
+        let (source, rx) = MemoryChannel::pair();
+        let stderr = |text: &str| ChannelMsg::ExtendedData {
+            data: CryptoVec::from_slice(text.as_bytes()),
+            ext: 1,
+        };
+        source.send(stderr("Welcome to the build host\n"));
+        source.send_data(b"C0644 5 out.txt\n");
+        source.send_data(b"hello\0");
+        source.send(stderr("warning: locale not set\n"));
+        source.send(ChannelMsg::ExitStatus { exit_status: 0 });
+        source.send(ChannelMsg::Eof);
+
+        let mut state = receiver(rx).await;
+        let options = ScpOptions::new();
+        let target = dst.join("out.txt");
+        scp_receive_records(&mut state, "out.txt", &target, &options, None)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(&target).unwrap(), b"hello");
+
+        std::fs::remove_dir_all(&dst).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_scp_receive_reports_stderr_without_file() {
+        let dst = temp_dir("scp_missing");
+        let (source, rx) = MemoryChannel::pair();
+        source.send(ChannelMsg::ExtendedData {
+            data: CryptoVec::from_slice(b"sh: scp: not found\n"),
+            ext: 1,
+        });
+        source.send(ChannelMsg::Eof);
+
+        let mut state = receiver(rx).await;
+        let err = scp_receive_records(&mut state, "f", &dst, &ScpOptions::new(), None)
+            .await
+            .unwrap_err();
+        assert!(err.is::<ScpNotFound>(), "{}", err);
+
+        let (source, rx) = MemoryChannel::pair();
+        source.send(ChannelMsg::ExitStatus { exit_status: 1 });
+        let mut state = receiver(rx).await;
+        let err = scp_receive_records(&mut state, "f", &dst, &ScpOptions::new(), None)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "SCP failed with exit code 1");
+
+        std::fs::remove_dir_all(&dst).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_scp_receive_rejects_unsafe_names() {
+        for name in ["..", "../escape", "a/b"] {
+            let dst = temp_dir("scp_unsafe");
+            let (source, rx) = MemoryChannel::pair();
+            source.send_data(b"D0755 0 ok\n");
+            source.send_data(format!("C0644 4 {}\n", name).as_bytes());
+            source.send_data(b"evil\0");
+            source.send_data(b"E\n");
+            source.send(ChannelMsg::Eof);
+
+            let mut state = receiver(rx).await;
+            let options = ScpOptions::new().recursive(true);
+            let err = scp_receive_records(&mut state, "ok", &dst, &options, None)
+                .await
+                .unwrap_err();
+            assert!(err.to_string().contains("unsafe"), "{}", err);
+            assert!(dst.join("ok").is_dir());
+            assert_eq!(std::fs::read_dir(dst.join("ok")).unwrap().count(), 0);
+            assert!(!dst.join("escape").exists());
+
+            std::fs::remove_dir_all(&dst).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_set_file_times() {
+        let path = std::env::temp_dir().join(format!("simple_ssh_times_{}", std::process::id()));
+        std::fs::write(&path, b"data").unwrap();
+
+        set_file_times(&path, 1_000_000_000, 1_000_000_100).unwrap();
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+        assert_eq!(
+            modified,
+            std::time::UNIX_EPOCH + Duration::from_secs(1_000_000_000)
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_scp_flags() {
+        assert_eq!(scp_flags(&ScpOptions::new()), "");
+        assert_eq!(scp_flags(&ScpOptions::new().recursive(true)), "-r ");
+        assert_eq!(
+            scp_flags(&ScpOptions::new().recursive(true).preserve(true)),
+            "-r -p "
+        );
+    }
+
+    #[test]
+    fn test_scp_command_escapes_path() {
+        assert_eq!(
+            scp_command(&ScpOptions::new(), 't', "/tmp/out"),
+            "scp -t /tmp/out"
+        );
+        assert_eq!(
+            scp_command(&ScpOptions::new().recursive(true), 't', "/opt/my app"),
+            "scp -r -t '/opt/my app'"
+        );
+        assert_eq!(
+            scp_command(&ScpOptions::new(), 'f', "x; rm -rf ~"),
+            "scp -f 'x; rm -rf ~'"
+        );
+    }
+
+    #[test]
+    fn test_scp_error_detects_missing_scp() {
+        let err = scp_error(Some(127), b"sh: 1: ");
+        assert!(err.is::<ScpNotFound>());
+        let err = scp_error(None, b"bash: line 1: scp: command not found\n");
+        assert!(err.is::<ScpNotFound>());
+        assert!(err.to_string().ends_with("scp: command not found"));
+        let err = scp_error(Some(127), b"");
+        assert_eq!(
+            err.to_string(),
+            "scp is not available on the remote host: exit code 127"
+        );
+
+        let err = scp_error(Some(1), b"scp: /x: No such file or directory\n");
+        assert!(!err.is::<ScpNotFound>());
+        assert_eq!(
+            err.to_string(),
+            "Remote SCP error: scp: /x: No such file or directory"
+        );
+        let err = scp_error(Some(1), b"");
+        assert_eq!(err.to_string(), "SCP failed with exit code 1");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_scp_file_attrs_and_mode() {
+        let path = std::env::temp_dir().join(format!("simple_ssh_mode_{}", std::process::id()));
+        std::fs::write(&path, b"#!/bin/sh\n").unwrap();
+
+        set_file_mode(&path, 0o100750).unwrap();
+        set_file_times(&path, 1_500_000_000, 1_500_000_010).unwrap();
+
+        let attrs = SCPFileAttrs::from_metadata(&std::fs::metadata(&path).unwrap(), 0o644);
+        assert_eq!(attrs.mode, 0o750);
+        assert_eq!(attrs.times, (1_500_000_000, 1_500_000_010));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_scp_options_default() {
+        let options = ScpOptions::default();
+        assert!(!options.recursive);
+        assert_eq!(options.symlinks, SymlinkPolicy::Follow);
+        assert!(!options.preserve);
+        assert_eq!(options.mode, None);
+        assert_eq!(options.strategy, TransferStrategy::Auto);
+        assert!(!options.verify);
+        assert!(!options.remove_corrupt);
+        assert!(options.rate_limit.is_none());
+    }
+
+    #[test]
+    fn test_scp_options_rate_limit() {
+        let limiter = RateLimiter::new(1024);
+        let options = ScpOptions::new().rate_limit(limiter.clone());
+        assert_eq!(options.rate_limit.as_ref(), Some(&limiter));
+        assert_eq!(options, ScpOptions::new().rate_limit(limiter));
+        assert_ne!(
+            options,
+            ScpOptions::new().rate_limit(RateLimiter::new(1024))
+        );
+    }
+
+    #[test]
+    fn test_scp_options_verify() {
+        let options = ScpOptions::new().verify(true).remove_corrupt(true);
+        assert!(options.verify);
+        assert!(options.remove_corrupt);
+        assert_ne!(options, ScpOptions::new().verify(true));
+    }
+
+    #[test]
+    fn test_scp_options_strategy() {
+        let options = ScpOptions::new().strategy(TransferStrategy::Cat);
+        assert_eq!(options.strategy, TransferStrategy::Cat);
+        assert_ne!(options, ScpOptions::new());
+    }
+
+    #[test]
+    fn test_scp_options_mode_masks_file_type() {
+        let options = ScpOptions::new().preserve(true).mode(0o100755);
+        assert!(options.preserve);
+        assert_eq!(options.mode, Some(0o755));
+    }
+
+    #[test]
+    fn test_scp_options_watch_progress() {
+        let mut options = ScpOptions::new();
+        assert_eq!(options, ScpOptions::new());
+
+        let watcher = options.watch_progress();
+        let _second = options.watch_progress();
+        assert!(watcher.current().is_none());
+        assert_ne!(options, ScpOptions::new());
+        assert_eq!(options.clone(), options);
+
+        options.progress.as_ref().unwrap().start("file", 1);
+        assert_eq!(watcher.current().unwrap().path, "file");
+    }
+
+    #[test]
+    fn test_scp_options_builder() {
+        let options = ScpOptions::new()
+            .recursive(true)
+            .symlinks(SymlinkPolicy::Error);
+        assert!(options.recursive);
+        assert_eq!(options.symlinks, SymlinkPolicy::Error);
+    }
+
+    #[test]
+    fn test_scp_fall_back() {
+        let missing: Result<()> = Err(ScpNotFound("sh: scp: not found".into()).into());
+        let other: Result<()> = Err(anyhow!("Remote SCP error: denied"));
+        let auto = ScpOptions::new();
+        assert!(scp_fall_back(&auto, &missing));
+        assert!(!scp_fall_back(&auto, &other));
+        assert!(!scp_fall_back(&auto, &Ok(())));
+
+        let forced = ScpOptions::new().strategy(TransferStrategy::Scp);
+        assert!(!scp_fall_back(&forced, &missing));
+    }
+}
@@ -0,0 +1,1288 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2025 Julian Kahlert
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use anyhow::{anyhow, Error, Result};
+use log::debug;
+use parking_lot::Mutex;
+use russh::client;
+use russh::ChannelMsg;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+use crate::remote_file::RemoteFile;
+use crate::Client;
+
+/// Protocol version spoken by the client.
+const SFTP_VERSION: u32 = 3;
+
+/// Largest packet accepted from the server.
+const MAX_PACKET_LEN: usize = 1024 * 1024;
+
+/// Largest payload read or written by a single request.
+///
+/// 32 KiB is the size every SFTP server must support.
+pub(crate) const MAX_CHUNK: u32 = 32 * 1024;
+
+const SSH_FXP_INIT: u8 = 1;
+const SSH_FXP_VERSION: u8 = 2;
+const SSH_FXP_OPEN: u8 = 3;
+const SSH_FXP_CLOSE: u8 = 4;
+const SSH_FXP_READ: u8 = 5;
+const SSH_FXP_WRITE: u8 = 6;
+const SSH_FXP_LSTAT: u8 = 7;
+const SSH_FXP_FSTAT: u8 = 8;
+const SSH_FXP_SETSTAT: u8 = 9;
+const SSH_FXP_FSETSTAT: u8 = 10;
+const SSH_FXP_OPENDIR: u8 = 11;
+const SSH_FXP_READDIR: u8 = 12;
+const SSH_FXP_REMOVE: u8 = 13;
+const SSH_FXP_MKDIR: u8 = 14;
+const SSH_FXP_RMDIR: u8 = 15;
+const SSH_FXP_REALPATH: u8 = 16;
+const SSH_FXP_STAT: u8 = 17;
+const SSH_FXP_RENAME: u8 = 18;
+const SSH_FXP_READLINK: u8 = 19;
+const SSH_FXP_SYMLINK: u8 = 20;
+const SSH_FXP_STATUS: u8 = 101;
+const SSH_FXP_HANDLE: u8 = 102;
+const SSH_FXP_DATA: u8 = 103;
+const SSH_FXP_NAME: u8 = 104;
+const SSH_FXP_ATTRS: u8 = 105;
+
+const SSH_FILEXFER_ATTR_SIZE: u32 = 0x01;
+const SSH_FILEXFER_ATTR_UIDGID: u32 = 0x02;
+const SSH_FILEXFER_ATTR_PERMISSIONS: u32 = 0x04;
+const SSH_FILEXFER_ATTR_ACMODTIME: u32 = 0x08;
+const SSH_FILEXFER_ATTR_EXTENDED: u32 = 0x8000_0000;
+
+const SSH_FXF_READ: u32 = 0x01;
+const SSH_FXF_WRITE: u32 = 0x02;
+const SSH_FXF_APPEND: u32 = 0x04;
+const SSH_FXF_CREAT: u32 = 0x08;
+const SSH_FXF_TRUNC: u32 = 0x10;
+const SSH_FXF_EXCL: u32 = 0x20;
+
+/// File type bits of `FileAttributes::permissions`.
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+const S_IFLNK: u32 = 0o120000;
+
+/// Status code of an SFTP response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SftpStatus {
+    /// The operation succeeded.
+    Ok,
+    /// End of file or directory listing.
+    Eof,
+    /// The file or directory does not exist.
+    NoSuchFile,
+    /// The user lacks permission for the operation.
+    PermissionDenied,
+    /// Generic failure, e.g. a directory that is not empty.
+    Failure,
+    /// The server could not parse a request.
+    BadMessage,
+    /// No connection to the server.
+    NoConnection,
+    /// The connection to the server was lost.
+    ConnectionLost,
+    /// The server does not support the operation.
+    OpUnsupported,
+    /// A code outside of SFTP version 3.
+    Unknown(u32),
+}
+
+impl SftpStatus {
+    /// Maps a protocol status code.
+    pub fn from_code(code: u32) -> Self {
+        match code {
+            0 => SftpStatus::Ok,
+            1 => SftpStatus::Eof,
+            2 => SftpStatus::NoSuchFile,
+            3 => SftpStatus::PermissionDenied,
+            4 => SftpStatus::Failure,
+            5 => SftpStatus::BadMessage,
+            6 => SftpStatus::NoConnection,
+            7 => SftpStatus::ConnectionLost,
+            8 => SftpStatus::OpUnsupported,
+            code => SftpStatus::Unknown(code),
+        }
+    }
+
+    /// Returns the protocol status code.
+    pub fn code(&self) -> u32 {
+        match self {
+            SftpStatus::Ok => 0,
+            SftpStatus::Eof => 1,
+            SftpStatus::NoSuchFile => 2,
+            SftpStatus::PermissionDenied => 3,
+            SftpStatus::Failure => 4,
+            SftpStatus::BadMessage => 5,
+            SftpStatus::NoConnection => 6,
+            SftpStatus::ConnectionLost => 7,
+            SftpStatus::OpUnsupported => 8,
+            SftpStatus::Unknown(code) => *code,
+        }
+    }
+}
+
+impl fmt::Display for SftpStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SftpStatus::Ok => write!(f, "ok"),
+            SftpStatus::Eof => write!(f, "end of file"),
+            SftpStatus::NoSuchFile => write!(f, "no such file"),
+            SftpStatus::PermissionDenied => write!(f, "permission denied"),
+            SftpStatus::Failure => write!(f, "failure"),
+            SftpStatus::BadMessage => write!(f, "bad message"),
+            SftpStatus::NoConnection => write!(f, "no connection"),
+            SftpStatus::ConnectionLost => write!(f, "connection lost"),
+            SftpStatus::OpUnsupported => write!(f, "operation unsupported"),
+            SftpStatus::Unknown(code) => write!(f, "status {}", code),
+        }
+    }
+}
+
+/// Error status returned by the SFTP server.
+///
+/// Can be recovered from the [`anyhow::Error`] of an operation with
+/// `error.downcast_ref::<SftpError>()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SftpError {
+    /// Status code of the response.
+    pub status: SftpStatus,
+    /// Message sent by the server, may be empty.
+    pub message: String,
+}
+
+impl fmt::Display for SftpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.message.is_empty() {
+            write!(f, "SFTP error: {}", self.status)
+        } else {
+            write!(f, "SFTP error: {} ({})", self.message, self.status)
+        }
+    }
+}
+
+impl std::error::Error for SftpError {}
+
+/// Attributes of a remote file.
+///
+/// Every field is optional: servers only send what they know, and
+/// `setstat` only changes the fields that are set.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileAttributes {
+    /// Size in bytes.
+    pub size: Option<u64>,
+    /// Owner user id.
+    pub uid: Option<u32>,
+    /// Owner group id.
+    pub gid: Option<u32>,
+    /// Mode including the file type bits.
+    pub permissions: Option<u32>,
+    /// Access time in seconds since the epoch.
+    pub atime: Option<u32>,
+    /// Modification time in seconds since the epoch.
+    pub mtime: Option<u32>,
+}
+
+impl FileAttributes {
+    /// Creates empty attributes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the size, truncating or extending the file in `setstat`.
+    pub fn with_size(mut self, size: u64) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    /// Sets the permission bits.
+    pub fn with_permissions(mut self, permissions: u32) -> Self {
+        self.permissions = Some(permissions);
+        self
+    }
+
+    /// Sets the owner.
+    pub fn with_owner(mut self, uid: u32, gid: u32) -> Self {
+        self.uid = Some(uid);
+        self.gid = Some(gid);
+        self
+    }
+
+    /// Sets access and modification time.
+    pub fn with_times(mut self, atime: u32, mtime: u32) -> Self {
+        self.atime = Some(atime);
+        self.mtime = Some(mtime);
+        self
+    }
+
+    /// Returns true if the attributes describe a directory.
+    pub fn is_dir(&self) -> bool {
+        self.file_type() == Some(S_IFDIR)
+    }
+
+    /// Returns true if the attributes describe a regular file.
+    pub fn is_file(&self) -> bool {
+        self.file_type() == Some(S_IFREG)
+    }
+
+    /// Returns true if the attributes describe a symbolic link.
+    pub fn is_symlink(&self) -> bool {
+        self.file_type() == Some(S_IFLNK)
+    }
+
+    fn file_type(&self) -> Option<u32> {
+        self.permissions.map(|p| p & S_IFMT)
+    }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let mut flags = 0;
+        if self.size.is_some() {
+            flags |= SSH_FILEXFER_ATTR_SIZE;
+        }
+        if self.uid.is_some() && self.gid.is_some() {
+            flags |= SSH_FILEXFER_ATTR_UIDGID;
+        }
+        if self.permissions.is_some() {
+            flags |= SSH_FILEXFER_ATTR_PERMISSIONS;
+        }
+        if self.atime.is_some() && self.mtime.is_some() {
+            flags |= SSH_FILEXFER_ATTR_ACMODTIME;
+        }
+        put_u32(buf, flags);
+        if let Some(size) = self.size {
+            put_u64(buf, size);
+        }
+        if let (Some(uid), Some(gid)) = (self.uid, self.gid) {
+            put_u32(buf, uid);
+            put_u32(buf, gid);
+        }
+        if let Some(permissions) = self.permissions {
+            put_u32(buf, permissions);
+        }
+        if let (Some(atime), Some(mtime)) = (self.atime, self.mtime) {
+            put_u32(buf, atime);
+            put_u32(buf, mtime);
+        }
+    }
+
+    fn decode(reader: &mut PacketReader<'_>) -> Result<Self> {
+        let flags = reader.u32()?;
+        let mut attrs = FileAttributes::default();
+        if flags & SSH_FILEXFER_ATTR_SIZE != 0 {
+            attrs.size = Some(reader.u64()?);
+        }
+        if flags & SSH_FILEXFER_ATTR_UIDGID != 0 {
+            attrs.uid = Some(reader.u32()?);
+            attrs.gid = Some(reader.u32()?);
+        }
+        if flags & SSH_FILEXFER_ATTR_PERMISSIONS != 0 {
+            attrs.permissions = Some(reader.u32()?);
+        }
+        if flags & SSH_FILEXFER_ATTR_ACMODTIME != 0 {
+            attrs.atime = Some(reader.u32()?);
+            attrs.mtime = Some(reader.u32()?);
+        }
+        if flags & SSH_FILEXFER_ATTR_EXTENDED != 0 {
+            // Extended attributes are vendor specific and skipped.
+            for _ in 0..reader.u32()? {
+                reader.string()?;
+                reader.string()?;
+            }
+        }
+        Ok(attrs)
+    }
+}
+
+/// An entry of a remote directory listing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    /// File name without the directory.
+    pub name: String,
+    /// `ls -l` style description provided by the server.
+    pub long_name: String,
+    /// Attributes of the entry (not following symlinks).
+    pub attrs: FileAttributes,
+}
+
+/// Options for opening a remote file, like [`std::fs::OpenOptions`].
+///
+/// ```
+/// use simple_ssh::SftpOpenOptions;
+///
+/// let options = SftpOpenOptions::new().write(true).create(true).mode(0o600);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SftpOpenOptions {
+    read: bool,
+    write: bool,
+    append: bool,
+    create: bool,
+    truncate: bool,
+    create_new: bool,
+    mode: Option<u32>,
+}
+
+impl SftpOpenOptions {
+    /// Creates options with everything disabled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Opens the file for reading.
+    pub fn read(mut self, read: bool) -> Self {
+        self.read = read;
+        self
+    }
+
+    /// Opens the file for writing.
+    pub fn write(mut self, write: bool) -> Self {
+        self.write = write;
+        self
+    }
+
+    /// Appends all writes to the end of the file.
+    pub fn append(mut self, append: bool) -> Self {
+        self.append = append;
+        self
+    }
+
+    /// Creates the file if it does not exist.
+    pub fn create(mut self, create: bool) -> Self {
+        self.create = create;
+        self
+    }
+
+    /// Truncates an existing file to zero length.
+    pub fn truncate(mut self, truncate: bool) -> Self {
+        self.truncate = truncate;
+        self
+    }
+
+    /// Creates the file and fails if it already exists.
+    pub fn create_new(mut self, create_new: bool) -> Self {
+        self.create_new = create_new;
+        self
+    }
+
+    /// Sets the permission bits of a newly created file.
+    pub fn mode(mut self, mode: u32) -> Self {
+        self.mode = Some(mode & 0o7777);
+        self
+    }
+
+    fn flags(&self) -> u32 {
+        let mut flags = 0;
+        if self.read {
+            flags |= SSH_FXF_READ;
+        }
+        if self.write || self.append {
+            flags |= SSH_FXF_WRITE;
+        }
+        if self.append {
+            flags |= SSH_FXF_APPEND;
+        }
+        if self.create || self.create_new {
+            flags |= SSH_FXF_CREAT;
+        }
+        if self.truncate {
+            flags |= SSH_FXF_TRUNC;
+        }
+        if self.create_new {
+            flags |= SSH_FXF_EXCL;
+        }
+        flags
+    }
+}
+
+/// Opaque handle of an open remote file or directory.
+///
+/// Handles stay valid until passed to [`Sftp::close`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SftpHandle(Vec<u8>);
+
+fn put_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_u64(buf: &mut Vec<u8>, value: u64) {
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_string(buf: &mut Vec<u8>, value: &[u8]) {
+    put_u32(buf, value.len() as u32);
+    buf.extend_from_slice(value);
+}
+
+/// Cursor over the payload of a received packet.
+struct PacketReader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> PacketReader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.buf.len() < len {
+            return Err(anyhow!("Truncated SFTP packet"));
+        }
+        let (head, tail) = self.buf.split_at(len);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes(bytes.try_into()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        let bytes = self.take(8)?;
+        Ok(u64::from_be_bytes(bytes.try_into()?))
+    }
+
+    fn string(&mut self) -> Result<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    fn text(&mut self) -> Result<String> {
+        Ok(String::from_utf8_lossy(self.string()?).into_owned())
+    }
+}
+
+/// A response packet without its length and request id.
+#[derive(Debug)]
+pub(crate) struct Response {
+    kind: u8,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn reader(&self) -> PacketReader<'_> {
+        PacketReader::new(&self.body)
+    }
+
+    /// Converts a status response into its error, `None` for `Ok`.
+    fn status(&self) -> Result<Option<SftpError>> {
+        let mut reader = self.reader();
+        let status = SftpStatus::from_code(reader.u32()?);
+        // Servers before version 3 omit the message.
+        let message = reader.text().unwrap_or_default();
+        if status == SftpStatus::Ok {
+            return Ok(None);
+        }
+        Ok(Some(SftpError { status, message }))
+    }
+
+    /// Builds the error for a response of an unexpected type.
+    fn unexpected(&self) -> Error {
+        if self.kind == SSH_FXP_STATUS {
+            match self.status() {
+                Ok(Some(e)) => return e.into(),
+                Ok(None) => return anyhow!("Unexpected SFTP status ok"),
+                Err(e) => return e,
+            }
+        }
+        anyhow!("Unexpected SFTP response type {}", self.kind)
+    }
+
+    pub(crate) fn into_ok(self) -> Result<()> {
+        if self.kind == SSH_FXP_STATUS {
+            if let Some(e) = self.status()? {
+                return Err(e.into());
+            }
+            return Ok(());
+        }
+        Err(self.unexpected())
+    }
+
+    fn into_handle(self) -> Result<SftpHandle> {
+        if self.kind != SSH_FXP_HANDLE {
+            return Err(self.unexpected());
+        }
+        Ok(SftpHandle(self.reader().string()?.to_vec()))
+    }
+
+    pub(crate) fn into_attrs(self) -> Result<FileAttributes> {
+        if self.kind != SSH_FXP_ATTRS {
+            return Err(self.unexpected());
+        }
+        FileAttributes::decode(&mut self.reader())
+    }
+
+    /// Data of a read response, `None` at end of file.
+    pub(crate) fn into_data(self) -> Result<Option<Vec<u8>>> {
+        match self.kind {
+            SSH_FXP_DATA => Ok(Some(self.reader().string()?.to_vec())),
+            SSH_FXP_STATUS if self.is_eof() => Ok(None),
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    /// Entries of a name response, `None` at the end of a listing.
+    fn into_names(self) -> Result<Option<Vec<DirEntry>>> {
+        match self.kind {
+            SSH_FXP_NAME => {
+                let mut reader = self.reader();
+                let count = reader.u32()?;
+                let mut entries = Vec::new();
+                for _ in 0..count {
+                    entries.push(DirEntry {
+                        name: reader.text()?,
+                        long_name: reader.text()?,
+                        attrs: FileAttributes::decode(&mut reader)?,
+                    });
+                }
+                Ok(Some(entries))
+            }
+            SSH_FXP_STATUS if self.is_eof() => Ok(None),
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    /// The single name of a realpath or readlink response.
+    fn into_name(self) -> Result<String> {
+        self.into_names()?
+            .and_then(|entries| entries.into_iter().next())
+            .map(|entry| entry.name)
+            .ok_or_else(|| anyhow!("SFTP server returned no name"))
+    }
+
+    fn is_eof(&self) -> bool {
+        matches!(
+            self.status(),
+            Ok(Some(SftpError {
+                status: SftpStatus::Eof,
+                ..
+            }))
+        )
+    }
+}
+
+/// Requests waiting for their response, `None` once the session ended.
+type PendingMap = Arc<Mutex<Option<HashMap<u32, oneshot::Sender<Response>>>>>;
+
+/// A request that has been sent and awaits its response.
+pub(crate) struct PendingResponse {
+    rx: oneshot::Receiver<Response>,
+}
+
+impl PendingResponse {
+    pub(crate) async fn wait(self) -> Result<Response> {
+        self.rx.await.map_err(|_| anyhow!("SFTP session closed"))
+    }
+
+    /// Polls for the response, for use in `poll_*` implementations.
+    pub(crate) fn poll_wait(&mut self, cx: &mut Context<'_>) -> Poll<Result<Response>> {
+        Pin::new(&mut self.rx)
+            .poll(cx)
+            .map_err(|_| anyhow!("SFTP session closed"))
+    }
+}
+
+struct SftpShared {
+    packets: mpsc::UnboundedSender<Vec<u8>>,
+    pending: PendingMap,
+    next_id: AtomicU32,
+    version: u32,
+    reader: JoinHandle<()>,
+}
+
+impl Drop for SftpShared {
+    fn drop(&mut self) {
+        // The writer task ends by itself once queued packets are sent.
+        self.reader.abort();
+    }
+}
+
+/// Writes queued request packets to the server.
+async fn write_requests<W: AsyncWrite + Unpin>(
+    mut writer: W,
+    mut packets: mpsc::UnboundedReceiver<Vec<u8>>,
+    pending: PendingMap,
+) {
+    while let Some(packet) = packets.recv().await {
+        let mut result = writer.write_all(&packet).await;
+        if result.is_ok() && packets.is_empty() {
+            result = writer.flush().await;
+        }
+        if let Err(e) = result {
+            debug!("SFTP write failed: {}", e);
+            break;
+        }
+    }
+    pending.lock().take();
+}
+
+/// Reads response packets and hands them to the waiting requests.
+async fn read_responses<R: AsyncRead + Unpin>(mut reader: R, pending: PendingMap) {
+    loop {
+        let packet = match read_packet(&mut reader).await {
+            Ok(packet) => packet,
+            Err(e) => {
+                debug!("SFTP session ended: {}", e);
+                break;
+            }
+        };
+        if packet.len() < 5 {
+            debug!("Ignoring short SFTP packet");
+            continue;
+        }
+        let id = u32::from_be_bytes([packet[1], packet[2], packet[3], packet[4]]);
+        let response = Response {
+            kind: packet[0],
+            body: packet[5..].to_vec(),
+        };
+        let waiter = pending.lock().as_mut().and_then(|map| map.remove(&id));
+        match waiter {
+            Some(tx) => {
+                let _ = tx.send(response);
+            }
+            None => debug!("Ignoring SFTP response for unknown request {}", id),
+        }
+    }
+    // Dropping the senders fails all outstanding requests.
+    pending.lock().take();
+}
+
+/// Reads one length-prefixed packet.
+async fn read_packet<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Vec<u8>> {
+    let len = reader.read_u32().await? as usize;
+    if len == 0 || len > MAX_PACKET_LEN {
+        return Err(anyhow!("Invalid SFTP packet length {}", len));
+    }
+    let mut packet = vec![0u8; len];
+    reader.read_exact(&mut packet).await?;
+    Ok(packet)
+}
+
+/// SFTP version 3 client running over the `sftp` subsystem.
+///
+/// Obtained from [`Session::sftp`](crate::Session::sftp). Requests may be
+/// issued concurrently from clones of the client; they share one channel.
+///
+/// ```no_run
+/// use simple_ssh::{Session, SftpOpenOptions};
+///
+/// # async fn example(ssh: &mut Session) -> anyhow::Result<()> {
+/// let sftp = ssh.sftp().await?;
+/// for entry in sftp.read_dir("/var/log").await? {
+///     println!("{} {:?}", entry.name, entry.attrs.size);
+/// }
+///
+/// let handle = sftp
+///     .open("/tmp/hello.txt", &SftpOpenOptions::new().write(true).create(true))
+///     .await?;
+/// sftp.write(&handle, 0, b"hello\n").await?;
+/// sftp.close(handle).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Sftp {
+    shared: Arc<SftpShared>,
+}
+
+impl fmt::Debug for Sftp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sftp")
+            .field("version", &self.shared.version)
+            .finish()
+    }
+}
+
+impl Sftp {
+    /// Runs the version handshake on `stream` and starts the client.
+    pub(crate) async fn from_stream<S>(stream: S) -> Result<Self>
+    where
+        S: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (mut reader, mut writer) = tokio::io::split(stream);
+
+        let mut init = Vec::new();
+        put_u32(&mut init, 5);
+        init.push(SSH_FXP_INIT);
+        put_u32(&mut init, SFTP_VERSION);
+        writer.write_all(&init).await?;
+        writer.flush().await?;
+
+        let packet = read_packet(&mut reader).await?;
+        if packet.first() != Some(&SSH_FXP_VERSION) {
+            return Err(anyhow!("Unexpected SFTP handshake response"));
+        }
+        let version = PacketReader::new(&packet[1..]).u32()?;
+        if version < SFTP_VERSION {
+            return Err(anyhow!("Unsupported SFTP version {}", version));
+        }
+        debug!("SFTP server speaks version {}", version);
+
+        let pending: PendingMap = Arc::new(Mutex::new(Some(HashMap::new())));
+        let task = tokio::spawn(read_responses(reader, pending.clone()));
+        let (packets, queue) = mpsc::unbounded_channel();
+        tokio::spawn(write_requests(writer, queue, pending.clone()));
+        Ok(Self {
+            shared: Arc::new(SftpShared {
+                packets,
+                pending,
+                next_id: AtomicU32::new(0),
+                version,
+                reader: task,
+            }),
+        })
+    }
+
+    /// Returns the protocol version announced by the server.
+    pub fn version(&self) -> u32 {
+        self.shared.version
+    }
+
+    /// Queues a request without waiting for its response.
+    ///
+    /// Several requests may be in flight at once; they are sent in the
+    /// order they were queued.
+    pub(crate) fn send(&self, kind: u8, payload: &[u8]) -> Result<PendingResponse> {
+        let id = self.shared.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+        match self.shared.pending.lock().as_mut() {
+            Some(map) => map.insert(id, tx),
+            None => return Err(anyhow!("SFTP session closed")),
+        };
+
+        let mut packet = Vec::with_capacity(9 + payload.len());
+        put_u32(&mut packet, 5 + payload.len() as u32);
+        packet.push(kind);
+        put_u32(&mut packet, id);
+        packet.extend_from_slice(payload);
+
+        if self.shared.packets.send(packet).is_err() {
+            self.shared.pending.lock().take();
+            return Err(anyhow!("SFTP session closed"));
+        }
+        Ok(PendingResponse { rx })
+    }
+
+    async fn request(&self, kind: u8, payload: &[u8]) -> Result<Response> {
+        self.send(kind, payload)?.wait().await
+    }
+
+    /// Sends a request whose only argument is `path`.
+    async fn path_request(&self, kind: u8, path: &str) -> Result<Response> {
+        let mut payload = Vec::new();
+        put_string(&mut payload, path.as_bytes());
+        self.request(kind, &payload).await
+    }
+
+    /// Queues a request whose only argument is `handle`.
+    fn send_handle(&self, kind: u8, handle: &SftpHandle) -> Result<PendingResponse> {
+        let mut payload = Vec::new();
+        put_string(&mut payload, &handle.0);
+        self.send(kind, &payload)
+    }
+
+    /// Sends a request whose only argument is `handle`.
+    async fn handle_request(&self, kind: u8, handle: &SftpHandle) -> Result<Response> {
+        self.send_handle(kind, handle)?.wait().await
+    }
+
+    /// Queues a close request without waiting for the status.
+    pub(crate) fn send_close(&self, handle: &SftpHandle) -> Result<PendingResponse> {
+        self.send_handle(SSH_FXP_CLOSE, handle)
+    }
+
+    /// Queues an fstat request without waiting for the attributes.
+    pub(crate) fn send_fstat(&self, handle: &SftpHandle) -> Result<PendingResponse> {
+        self.send_handle(SSH_FXP_FSTAT, handle)
+    }
+
+    /// Opens a remote file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the remote file
+    /// * `options` - Access mode and creation flags
+    pub async fn open(&self, path: &str, options: &SftpOpenOptions) -> Result<SftpHandle> {
+        let mut payload = Vec::new();
+        put_string(&mut payload, path.as_bytes());
+        put_u32(&mut payload, options.flags());
+        let attrs = FileAttributes {
+            permissions: options.mode,
+            ..Default::default()
+        };
+        attrs.encode(&mut payload);
+        self.request(SSH_FXP_OPEN, &payload).await?.into_handle()
+    }
+
+    /// Opens a remote file as a [`RemoteFile`] stream.
+    ///
+    /// The stream implements tokio's `AsyncRead`, `AsyncWrite` and
+    /// `AsyncSeek`, keeping several requests in flight for throughput.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the remote file
+    /// * `options` - Access mode and creation flags
+    pub async fn open_file(&self, path: &str, options: &SftpOpenOptions) -> Result<RemoteFile> {
+        let handle = self.open(path, options).await?;
+        Ok(RemoteFile::new(self.clone(), handle))
+    }
+
+    /// Closes a file or directory handle.
+    pub async fn close(&self, handle: SftpHandle) -> Result<()> {
+        self.handle_request(SSH_FXP_CLOSE, &handle).await?.into_ok()
+    }
+
+    /// Builds the payload of a read request.
+    pub(crate) fn read_payload(handle: &SftpHandle, offset: u64, len: u32) -> Vec<u8> {
+        let mut payload = Vec::new();
+        put_string(&mut payload, &handle.0);
+        put_u64(&mut payload, offset);
+        put_u32(&mut payload, len.min(MAX_CHUNK));
+        payload
+    }
+
+    /// Queues a read request without waiting for the data.
+    pub(crate) fn send_read(
+        &self,
+        handle: &SftpHandle,
+        offset: u64,
+        len: u32,
+    ) -> Result<PendingResponse> {
+        self.send(SSH_FXP_READ, &Self::read_payload(handle, offset, len))
+    }
+
+    /// Reads up to `len` bytes at `offset`.
+    ///
+    /// Servers may return less than requested even before the end of
+    /// the file; at most 32 KiB are requested at once.
+    ///
+    /// # Returns
+    ///
+    /// The data read, or `None` at end of file.
+    pub async fn read(
+        &self,
+        handle: &SftpHandle,
+        offset: u64,
+        len: u32,
+    ) -> Result<Option<Vec<u8>>> {
+        self.send_read(handle, offset, len)?
+            .wait()
+            .await?
+            .into_data()
+    }
+
+    /// Queues a single write request without waiting for the status.
+    ///
+    /// `data` must not exceed [`MAX_CHUNK`].
+    pub(crate) fn send_write(
+        &self,
+        handle: &SftpHandle,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<PendingResponse> {
+        let mut payload = Vec::with_capacity(handle.0.len() + data.len() + 16);
+        put_string(&mut payload, &handle.0);
+        put_u64(&mut payload, offset);
+        put_string(&mut payload, data);
+        self.send(SSH_FXP_WRITE, &payload)
+    }
+
+    /// Writes `data` at `offset`.
+    ///
+    /// Large buffers are split into 32 KiB requests which are sent
+    /// without waiting for each other.
+    pub async fn write(&self, handle: &SftpHandle, offset: u64, data: &[u8]) -> Result<()> {
+        let mut offset = offset;
+        let mut statuses = Vec::new();
+        for chunk in data.chunks(MAX_CHUNK as usize) {
+            statuses.push(self.send_write(handle, offset, chunk)?);
+            offset += chunk.len() as u64;
+        }
+        for status in statuses {
+            status.wait().await?.into_ok()?;
+        }
+        Ok(())
+    }
+
+    /// Returns the attributes of `path`, following symlinks.
+    pub async fn stat(&self, path: &str) -> Result<FileAttributes> {
+        self.path_request(SSH_FXP_STAT, path).await?.into_attrs()
+    }
+
+    /// Returns the attributes of `path` without following symlinks.
+    pub async fn lstat(&self, path: &str) -> Result<FileAttributes> {
+        self.path_request(SSH_FXP_LSTAT, path).await?.into_attrs()
+    }
+
+    /// Returns the attributes of an open file.
+    pub async fn fstat(&self, handle: &SftpHandle) -> Result<FileAttributes> {
+        self.handle_request(SSH_FXP_FSTAT, handle)
+            .await?
+            .into_attrs()
+    }
+
+    /// Changes the attributes of `path`; unset fields are left alone.
+    pub async fn setstat(&self, path: &str, attrs: &FileAttributes) -> Result<()> {
+        let mut payload = Vec::new();
+        put_string(&mut payload, path.as_bytes());
+        attrs.encode(&mut payload);
+        self.request(SSH_FXP_SETSTAT, &payload).await?.into_ok()
+    }
+
+    /// Changes the attributes of an open file; unset fields are left alone.
+    pub async fn fsetstat(&self, handle: &SftpHandle, attrs: &FileAttributes) -> Result<()> {
+        let mut payload = Vec::new();
+        put_string(&mut payload, &handle.0);
+        attrs.encode(&mut payload);
+        self.request(SSH_FXP_FSETSTAT, &payload).await?.into_ok()
+    }
+
+    /// Opens a directory for [`readdir`](Self::readdir).
+    pub async fn opendir(&self, path: &str) -> Result<SftpHandle> {
+        self.path_request(SSH_FXP_OPENDIR, path)
+            .await?
+            .into_handle()
+    }
+
+    /// Reads the next batch of entries from a directory handle.
+    ///
+    /// # Returns
+    ///
+    /// The entries, or `None` once the listing is complete.
+    pub async fn readdir(&self, handle: &SftpHandle) -> Result<Option<Vec<DirEntry>>> {
+        self.handle_request(SSH_FXP_READDIR, handle)
+            .await?
+            .into_names()
+    }
+
+    /// Lists a directory, leaving out `.` and `..`.
+    pub async fn read_dir(&self, path: &str) -> Result<Vec<DirEntry>> {
+        let handle = self.opendir(path).await?;
+        let mut entries = Vec::new();
+        let result = loop {
+            match self.readdir(&handle).await {
+                Ok(Some(batch)) => entries.extend(
+                    batch
+                        .into_iter()
+                        .filter(|entry| entry.name != "." && entry.name != ".."),
+                ),
+                Ok(None) => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+        self.close(handle).await?;
+        result.map(|()| entries)
+    }
+
+    /// Creates a directory with the given permission bits.
+    pub async fn mkdir(&self, path: &str, mode: u32) -> Result<()> {
+        let mut payload = Vec::new();
+        put_string(&mut payload, path.as_bytes());
+        FileAttributes::new()
+            .with_permissions(mode & 0o7777)
+            .encode(&mut payload);
+        self.request(SSH_FXP_MKDIR, &payload).await?.into_ok()
+    }
+
+    /// Removes an empty directory.
+    pub async fn rmdir(&self, path: &str) -> Result<()> {
+        self.path_request(SSH_FXP_RMDIR, path).await?.into_ok()
+    }
+
+    /// Removes a file.
+    pub async fn remove(&self, path: &str) -> Result<()> {
+        self.path_request(SSH_FXP_REMOVE, path).await?.into_ok()
+    }
+
+    /// Renames `from` to `to`.
+    ///
+    /// SFTP version 3 fails if `to` already exists.
+    pub async fn rename(&self, from: &str, to: &str) -> Result<()> {
+        let mut payload = Vec::new();
+        put_string(&mut payload, from.as_bytes());
+        put_string(&mut payload, to.as_bytes());
+        self.request(SSH_FXP_RENAME, &payload).await?.into_ok()
+    }
+
+    /// Creates a symbolic link at `link` pointing to `target`.
+    ///
+    /// Arguments are sent in OpenSSH order (target first), which most
+    /// servers expect despite the draft specifying the opposite.
+    pub async fn symlink(&self, target: &str, link: &str) -> Result<()> {
+        let mut payload = Vec::new();
+        put_string(&mut payload, target.as_bytes());
+        put_string(&mut payload, link.as_bytes());
+        self.request(SSH_FXP_SYMLINK, &payload).await?.into_ok()
+    }
+
+    /// Returns the target of a symbolic link.
+    pub async fn readlink(&self, path: &str) -> Result<String> {
+        self.path_request(SSH_FXP_READLINK, path).await?.into_name()
+    }
+
+    /// Resolves `path` to an absolute canonical path.
+    ///
+    /// `"."` yields the remote working directory.
+    pub async fn realpath(&self, path: &str) -> Result<String> {
+        self.path_request(SSH_FXP_REALPATH, path).await?.into_name()
+    }
+}
+
+/// Opens a channel on the `sftp` subsystem and starts a client on it.
+///
+/// # Arguments
+///
+/// * `session` - The SSH session handle
+pub(crate) async fn sftp_open(session: &mut client::Handle<Client>) -> Result<Sftp> {
+    let mut channel = session.channel_open_session().await?;
+    channel.request_subsystem(true, "sftp").await?;
+    loop {
+        match channel.wait().await {
+            Some(ChannelMsg::Success) => break,
+            Some(ChannelMsg::Failure) => {
+                return Err(anyhow!("The server has no SFTP subsystem"));
+            }
+            Some(_) => {}
+            None => return Err(anyhow!("Channel closed before SFTP was started")),
+        }
+    }
+    Sftp::from_stream(channel.into_stream()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::DuplexStream;
+
+    #[test]
+    fn test_status_codes() {
+        for code in 0..=9 {
+            assert_eq!(SftpStatus::from_code(code).code(), code);
+        }
+        assert_eq!(SftpStatus::from_code(2), SftpStatus::NoSuchFile);
+        assert_eq!(SftpStatus::from_code(42), SftpStatus::Unknown(42));
+    }
+
+    #[test]
+    fn test_sftp_error_display() {
+        let err = SftpError {
+            status: SftpStatus::NoSuchFile,
+            message: "No such file".into(),
+        };
+        assert_eq!(err.to_string(), "SFTP error: No such file (no such file)");
+        let err = SftpError {
+            status: SftpStatus::Failure,
+            message: String::new(),
+        };
+        assert_eq!(err.to_string(), "SFTP error: failure");
+    }
+
+    #[test]
+    fn test_attrs_roundtrip() {
+        let attrs = FileAttributes::new()
+            .with_size(1234)
+            .with_owner(1000, 100)
+            .with_permissions(0o100644)
+            .with_times(10, 20);
+        let mut buf = Vec::new();
+        attrs.encode(&mut buf);
+        assert_eq!(&buf[..4], &[0, 0, 0, 0x0f]);
+        let decoded = FileAttributes::decode(&mut PacketReader::new(&buf)).unwrap();
+        assert_eq!(decoded, attrs);
+        assert!(decoded.is_file());
+        assert!(!decoded.is_dir());
+
+        let mut buf = Vec::new();
+        FileAttributes::new().encode(&mut buf);
+        assert_eq!(buf, [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_attrs_skip_extended() {
+        let mut buf = Vec::new();
+        put_u32(
+            &mut buf,
+            SSH_FILEXFER_ATTR_PERMISSIONS | SSH_FILEXFER_ATTR_EXTENDED,
+        );
+        put_u32(&mut buf, 0o040755);
+        put_u32(&mut buf, 1);
+        put_string(&mut buf, b"name");
+        put_string(&mut buf, b"value");
+        let attrs = FileAttributes::decode(&mut PacketReader::new(&buf)).unwrap();
+        assert!(attrs.is_dir());
+        assert_eq!(attrs.size, None);
+
+        assert!(FileAttributes::decode(&mut PacketReader::new(&buf[..6])).is_err());
+    }
+
+    #[test]
+    fn test_open_options_flags() {
+        assert_eq!(SftpOpenOptions::new().read(true).flags(), SSH_FXF_READ);
+        assert_eq!(
+            SftpOpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .flags(),
+            SSH_FXF_WRITE | SSH_FXF_CREAT | SSH_FXF_TRUNC
+        );
+        assert_eq!(
+            SftpOpenOptions::new().append(true).flags(),
+            SSH_FXF_WRITE | SSH_FXF_APPEND
+        );
+        assert_eq!(
+            SftpOpenOptions::new()
+                .create_new(true)
+                .mode(0o100600)
+                .flags(),
+            SSH_FXF_CREAT | SSH_FXF_EXCL
+        );
+        assert_eq!(SftpOpenOptions::new().mode(0o100600).mode, Some(0o600));
+    }
+
+    /// Serves a scripted SFTP session: answers INIT, then replies to each
+    /// request with the next response of `replies` (type and body).
+    async fn fake_server(mut stream: DuplexStream, replies: Vec<(u8, Vec<u8>)>) -> Vec<u8> {
+        let init = read_packet(&mut stream).await.unwrap();
+        assert_eq!(init, [SSH_FXP_INIT, 0, 0, 0, 3]);
+        stream
+            .write_all(&[0, 0, 0, 5, SSH_FXP_VERSION, 0, 0, 0, 3])
+            .await
+            .unwrap();
+
+        let mut kinds = Vec::new();
+        for (kind, body) in replies {
+            let request = read_packet(&mut stream).await.unwrap();
+            kinds.push(request[0]);
+            let mut packet = Vec::new();
+            put_u32(&mut packet, 5 + body.len() as u32);
+            packet.push(kind);
+            packet.extend_from_slice(&request[1..5]);
+            packet.extend_from_slice(&body);
+            stream.write_all(&packet).await.unwrap();
+        }
+        kinds
+    }
+
+    fn status_body(code: u32, message: &str) -> Vec<u8> {
+        let mut body = Vec::new();
+        put_u32(&mut body, code);
+        put_string(&mut body, message.as_bytes());
+        put_string(&mut body, b"");
+        body
+    }
+
+    #[tokio::test]
+    async fn test_client_requests() {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+
+        let mut attrs = Vec::new();
+        FileAttributes::new().with_size(5).encode(&mut attrs);
+        let mut handle = Vec::new();
+        put_string(&mut handle, b"h1");
+        let mut data = Vec::new();
+        put_string(&mut data, b"hello");
+        let mut names = Vec::new();
+        put_u32(&mut names, 2);
+        for name in [".", "a.txt"] {
+            put_string(&mut names, name.as_bytes());
+            put_string(&mut names, name.as_bytes());
+            put_u32(&mut names, 0);
+        }
+
+        let server = tokio::spawn(fake_server(
+            server,
+            vec![
+                (SSH_FXP_ATTRS, attrs),
+                (SSH_FXP_STATUS, status_body(2, "No such file")),
+                (SSH_FXP_HANDLE, handle.clone()),
+                (SSH_FXP_DATA, data),
+                (SSH_FXP_STATUS, status_body(1, "EOF")),
+                (SSH_FXP_STATUS, status_body(0, "")),
+                (SSH_FXP_HANDLE, handle),
+                (SSH_FXP_NAME, names),
+                (SSH_FXP_STATUS, status_body(1, "EOF")),
+                (SSH_FXP_STATUS, status_body(0, "")),
+            ],
+        ));
+
+        let sftp = Sftp::from_stream(client).await.unwrap();
+        assert_eq!(sftp.version(), 3);
+        assert_eq!(sftp.stat("/f").await.unwrap().size, Some(5));
+
+        let err = sftp.lstat("/missing").await.unwrap_err();
+        let status = err.downcast_ref::<SftpError>().unwrap();
+        assert_eq!(status.status, SftpStatus::NoSuchFile);
+
+        let file = sftp
+            .open("/f", &SftpOpenOptions::new().read(true))
+            .await
+            .unwrap();
+        assert_eq!(file, SftpHandle(b"h1".to_vec()));
+        assert_eq!(
+            sftp.read(&file, 0, 100).await.unwrap(),
+            Some(b"hello".to_vec())
+        );
+        assert_eq!(sftp.read(&file, 5, 100).await.unwrap(), None);
+        sftp.close(file).await.unwrap();
+
+        let entries = sftp.read_dir("/").await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "a.txt");
+
+        assert_eq!(
+            server.await.unwrap(),
+            vec![
+                SSH_FXP_STAT,
+                SSH_FXP_LSTAT,
+                SSH_FXP_OPEN,
+                SSH_FXP_READ,
+                SSH_FXP_READ,
+                SSH_FXP_CLOSE,
+                SSH_FXP_OPENDIR,
+                SSH_FXP_READDIR,
+                SSH_FXP_READDIR,
+                SSH_FXP_CLOSE,
+            ]
+        );
+
+        // The server is gone, requests fail instead of hanging.
+        assert!(sftp.realpath(".").await.is_err());
+    }
+}
@@ -0,0 +1,829 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2025 Julian Kahlert
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::collections::VecDeque;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use log::{debug, info};
+use russh::client;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+use crate::cat::remote_path_expr;
+use crate::progress::{ProgressReporter, ProgressWatcher};
+use crate::rate_limit::RateLimiter;
+use crate::scp::scp_create_file;
+use crate::sftp::{
+    sftp_open, FileAttributes, PendingResponse, Sftp, SftpHandle, SftpOpenOptions, MAX_CHUNK,
+};
+use crate::verify::{self, capture_output, Digests, Hashing};
+use crate::Client;
+
+/// Smallest range worth its own channel in a parallel transfer.
+const MIN_RANGE_SIZE: u64 = 1024 * 1024;
+
+/// Requests kept in flight per range unless configured otherwise.
+pub(crate) const DEFAULT_PIPELINE: usize = 16;
+
+/// Options for SFTP file transfers.
+///
+/// ```
+/// use simple_ssh::SftpTransferOptions;
+///
+/// let options = SftpTransferOptions::new().resume(true).ranges(4);
+/// ```
+#[derive(Debug, Clone)]
+pub struct SftpTransferOptions {
+    pub(crate) resume: bool,
+    pub(crate) ranges: usize,
+    pub(crate) pipeline: usize,
+    pub(crate) progress: Option<Arc<ProgressReporter>>,
+    pub(crate) rate_limit: Option<RateLimiter>,
+}
+
+impl Default for SftpTransferOptions {
+    fn default() -> Self {
+        Self {
+            resume: false,
+            ranges: 1,
+            pipeline: DEFAULT_PIPELINE,
+            progress: None,
+            rate_limit: None,
+        }
+    }
+}
+
+impl PartialEq for SftpTransferOptions {
+    fn eq(&self, other: &Self) -> bool {
+        let same_progress = match (&self.progress, &other.progress) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        };
+        self.resume == other.resume
+            && self.ranges == other.ranges
+            && self.pipeline == other.pipeline
+            && self.rate_limit == other.rate_limit
+            && same_progress
+    }
+}
+
+impl SftpTransferOptions {
+    /// Creates new transfer options with defaults.
+    ///
+    /// Defaults:
+    /// - resume: false
+    /// - ranges: 1
+    /// - pipeline: 16 requests
+    /// - rate_limit: none
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Continues an interrupted transfer instead of starting over.
+    ///
+    /// If the destination already holds part of the file, the first bytes
+    /// of both sides are compared before the rest is appended. The
+    /// comparison uses `sha256sum` (or `md5sum`) on the remote host and
+    /// falls back to reading the remote prefix over SFTP when no shell
+    /// is available. A destination that does not match, or is larger
+    /// than the source, is transferred again from the start.
+    pub fn resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    /// Splits the file into up to `ranges` parts transferred in parallel.
+    ///
+    /// Each part runs on its own SFTP channel, so a high-latency link is
+    /// not limited by the flow control window of a single channel. Parts
+    /// are at least 1 MiB. An interrupted parallel transfer leaves gaps
+    /// in the destination, so resuming it usually starts over.
+    pub fn ranges(mut self, ranges: usize) -> Self {
+        self.ranges = ranges.max(1);
+        self
+    }
+
+    /// Sets how many 32 KiB requests each part keeps in flight.
+    pub fn pipeline(mut self, requests: usize) -> Self {
+        self.pipeline = requests.max(1);
+        self
+    }
+
+    /// Limits the combined bandwidth of all ranges.
+    ///
+    /// Requests are only sent once the limiter allows their size.
+    pub fn rate_limit(mut self, limiter: RateLimiter) -> Self {
+        self.rate_limit = Some(limiter);
+        self
+    }
+
+    /// Enables progress reporting and returns a watcher for it.
+    ///
+    /// A resumed transfer starts at the number of bytes already present.
+    /// Clones of the options share the same progress channel.
+    pub fn watch_progress(&mut self) -> ProgressWatcher {
+        self.progress
+            .get_or_insert_with(|| Arc::new(ProgressReporter::new()))
+            .create_watcher()
+    }
+}
+
+/// Outcome of a successful SFTP transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SftpTransferSummary {
+    /// Size of the transferred file.
+    pub size: u64,
+    /// Offset the transfer continued from, 0 if it started over.
+    pub resumed_from: u64,
+    /// Number of parts transferred in parallel.
+    pub ranges: usize,
+}
+
+/// Splits `start..end` into at most `count` contiguous ranges of at
+/// least [`MIN_RANGE_SIZE`] bytes (except for the last one).
+pub(crate) fn split_ranges(start: u64, end: u64, count: usize) -> Vec<(u64, u64)> {
+    let len = end.saturating_sub(start);
+    if len == 0 {
+        return Vec::new();
+    }
+    let count = (count.max(1) as u64).min(len.div_ceil(MIN_RANGE_SIZE));
+    let step = len.div_ceil(count);
+    (0..count)
+        .map(|i| (start + i * step, (start + (i + 1) * step).min(end)))
+        .filter(|(from, to)| from < to)
+        .collect()
+}
+
+/// Returns the last path component of a remote path.
+pub(crate) fn remote_file_name(path: &str) -> &str {
+    path.trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or(path)
+}
+
+/// Uploads a local file over SFTP.
+///
+/// # Arguments
+///
+/// * `session` - The SSH session handle
+/// * `from` - Local source file
+/// * `to` - Remote destination file or directory
+/// * `options` - Transfer options
+pub(crate) async fn upload(
+    session: &mut client::Handle<Client>,
+    from: &str,
+    to: &str,
+    options: &SftpTransferOptions,
+) -> Result<SftpTransferSummary> {
+    let local = Path::new(from);
+    let metadata = tokio::fs::metadata(local)
+        .await
+        .map_err(|e| anyhow!("Cannot read '{}': {}", from, e))?;
+    if !metadata.is_file() {
+        return Err(anyhow!("'{}' is not a regular file", from));
+    }
+    let size = metadata.len();
+    #[cfg(unix)]
+    let mode = {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.permissions().mode() & 0o7777
+    };
+    #[cfg(not(unix))]
+    let mode = 0o644;
+
+    let sftp = sftp_open(session).await?;
+    let mut remote = to.to_string();
+    let mut existing = sftp.stat(&remote).await.ok();
+    if existing.as_ref().is_some_and(FileAttributes::is_dir) {
+        let name = local
+            .file_name()
+            .ok_or_else(|| anyhow!("'{}' has no file name", from))?;
+        remote = format!(
+            "{}/{}",
+            remote.trim_end_matches('/'),
+            name.to_string_lossy()
+        );
+        existing = sftp.stat(&remote).await.ok();
+    }
+
+    let mut start = 0;
+    if options.resume {
+        if let Some(have) = existing.as_ref().and_then(|attrs| attrs.size) {
+            start = resume_offset(session, &sftp, local, &remote, have, size).await?;
+        }
+    }
+
+    let open = SftpOpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(start == 0)
+        .mode(mode);
+    let handle = sftp.open(&remote, &open).await?;
+    let progress = options.progress.clone();
+    if let Some(progress) = &progress {
+        progress.start_at(&remote, size, start);
+    }
+
+    let ranges = split_ranges(start, size, options.ranges);
+    let mut workers = Vec::new();
+    let mut first = Some((sftp.clone(), handle.clone()));
+    for &range in &ranges {
+        let (worker_sftp, worker_handle) = match first.take() {
+            Some(first) => first,
+            None => {
+                let extra = sftp_open(session).await?;
+                let handle = extra
+                    .open(&remote, &SftpOpenOptions::new().write(true))
+                    .await?;
+                (extra, handle)
+            }
+        };
+        workers.push(tokio::spawn(upload_range(
+            worker_sftp,
+            worker_handle,
+            local.to_path_buf(),
+            range,
+            options.pipeline,
+            progress.clone(),
+            options.rate_limit.clone(),
+        )));
+    }
+    if first.is_some() {
+        sftp.close(handle).await?;
+    }
+    join_workers(workers).await?;
+
+    if existing.is_some() {
+        // The mode of the open request only applies to new files.
+        sftp.setstat(&remote, &FileAttributes::new().with_permissions(mode))
+            .await?;
+    }
+    if let Some(progress) = &progress {
+        progress.finish();
+    }
+    Ok(SftpTransferSummary {
+        size,
+        resumed_from: start,
+        ranges: ranges.len(),
+    })
+}
+
+/// Downloads a remote file over SFTP.
+///
+/// # Arguments
+///
+/// * `session` - The SSH session handle
+/// * `from` - Remote source file
+/// * `to` - Local destination file or directory
+/// * `options` - Transfer options
+pub(crate) async fn download(
+    session: &mut client::Handle<Client>,
+    from: &str,
+    to: &str,
+    options: &SftpTransferOptions,
+) -> Result<SftpTransferSummary> {
+    let sftp = sftp_open(session).await?;
+    let attrs = sftp.stat(from).await?;
+    if attrs.is_dir() {
+        return Err(anyhow!("'{}' is a directory", from));
+    }
+    let size = attrs
+        .size
+        .ok_or_else(|| anyhow!("The server did not report the size of '{}'", from))?;
+
+    let mut local = PathBuf::from(to);
+    if local.is_dir() {
+        local.push(remote_file_name(from));
+    }
+
+    let mut start = 0;
+    if options.resume {
+        if let Ok(metadata) = tokio::fs::metadata(&local).await {
+            if metadata.is_file() {
+                start = resume_offset(session, &sftp, &local, from, metadata.len(), size).await?;
+            }
+        }
+    }
+    if start == 0 {
+        scp_create_file(&local, attrs.permissions.unwrap_or(0o644)).await?;
+    }
+    let progress = options.progress.clone();
+    if let Some(progress) = &progress {
+        progress.start_at(from, size, start);
+    }
+
+    let ranges = split_ranges(start, size, options.ranges);
+    let mut workers = Vec::new();
+    for (i, &range) in ranges.iter().enumerate() {
+        let worker_sftp = if i == 0 {
+            sftp.clone()
+        } else {
+            sftp_open(session).await?
+        };
+        workers.push(tokio::spawn(download_range(
+            worker_sftp,
+            from.to_string(),
+            local.clone(),
+            range,
+            options.pipeline,
+            progress.clone(),
+            options.rate_limit.clone(),
+        )));
+    }
+    join_workers(workers).await?;
+
+    if let Some(progress) = &progress {
+        progress.finish();
+    }
+    Ok(SftpTransferSummary {
+        size,
+        resumed_from: start,
+        ranges: ranges.len(),
+    })
+}
+
+/// Waits for all range workers and returns the first error, if any.
+async fn join_workers(workers: Vec<tokio::task::JoinHandle<Result<()>>>) -> Result<()> {
+    let mut result = Ok(());
+    for worker in workers {
+        let outcome = worker
+            .await
+            .map_err(|e| anyhow!("Transfer task failed: {}", e))
+            .and_then(|outcome| outcome);
+        if result.is_ok() {
+            result = outcome;
+        }
+    }
+    result
+}
+
+/// Decides where an interrupted transfer continues.
+///
+/// # Arguments
+///
+/// * `session` - The SSH session handle
+/// * `sftp` - SFTP client for the fallback comparison
+/// * `local` - Local side of the transfer
+/// * `remote` - Remote side of the transfer
+/// * `present` - Size of the partial destination
+/// * `size` - Size of the source
+///
+/// # Returns
+///
+/// `present` if the destination is a prefix of the source, otherwise 0.
+async fn resume_offset(
+    session: &mut client::Handle<Client>,
+    sftp: &Sftp,
+    local: &Path,
+    remote: &str,
+    present: u64,
+    size: u64,
+) -> Result<u64> {
+    if present == 0 {
+        return Ok(0);
+    }
+    if present > size {
+        info!(
+            "Destination of '{}' is larger than the source, starting over",
+            remote
+        );
+        return Ok(0);
+    }
+    if prefix_matches(session, sftp, local, remote, present).await? {
+        info!("Resuming '{}' at byte {} of {}", remote, present, size);
+        Ok(present)
+    } else {
+        info!(
+            "First {} bytes of '{}' differ from the source, starting over",
+            present, remote
+        );
+        Ok(0)
+    }
+}
+
+/// Checks whether the first `len` bytes of the local and remote file match.
+async fn prefix_matches(
+    session: &mut client::Handle<Client>,
+    sftp: &Sftp,
+    local: &Path,
+    remote: &str,
+    len: u64,
+) -> Result<bool> {
+    let mut digests = Digests::new();
+    let mut reader = File::open(local).await?.take(len);
+    let mut sink = Hashing::new(tokio::io::sink(), Some(&mut digests));
+    if tokio::io::copy(&mut reader, &mut sink).await? != len {
+        return Ok(false);
+    }
+
+    let command = verify::prefix_checksum_command(&remote_path_expr(remote, None), len);
+    if let Ok((Some(0), stdout, _)) = capture_output(session, &command).await {
+        if let Ok(checksum) = verify::parse_checksum(&String::from_utf8_lossy(&stdout)) {
+            return Ok(digests.hex(checksum.algorithm).as_deref() == Some(checksum.digest.as_str()));
+        }
+    }
+
+    debug!(
+        "No remote checksum for '{}', comparing {} bytes over SFTP",
+        remote, len
+    );
+    compare_prefix(sftp, local, remote, len).await
+}
+
+/// Compares the first `len` bytes of both files by reading the remote
+/// one over SFTP.
+async fn compare_prefix(sftp: &Sftp, local: &Path, remote: &str, len: u64) -> Result<bool> {
+    let mut remote_file = sftp
+        .open_file(remote, &SftpOpenOptions::new().read(true))
+        .await?;
+    let mut local_file = File::open(local).await?;
+    let mut local_buf = vec![0u8; 4 * MAX_CHUNK as usize];
+    let mut remote_buf = vec![0u8; 4 * MAX_CHUNK as usize];
+    let mut left = len;
+    let mut matches = true;
+    while matches && left > 0 {
+        let n = left.min(local_buf.len() as u64) as usize;
+        local_file.read_exact(&mut local_buf[..n]).await?;
+        matches = remote_file.read_exact(&mut remote_buf[..n]).await.is_ok()
+            && local_buf[..n] == remote_buf[..n];
+        left -= n as u64;
+    }
+    remote_file.close().await?;
+    Ok(matches)
+}
+
+/// Uploads `start..end` of `local` through `handle` and closes it.
+async fn upload_range(
+    sftp: Sftp,
+    handle: SftpHandle,
+    local: PathBuf,
+    range: (u64, u64),
+    pipeline: usize,
+    progress: Option<Arc<ProgressReporter>>,
+    limiter: Option<RateLimiter>,
+) -> Result<()> {
+    let result = write_range(
+        &sftp,
+        &handle,
+        &local,
+        range,
+        pipeline,
+        progress.as_deref(),
+        limiter.as_ref(),
+    )
+    .await;
+    let closed = sftp.close(handle).await;
+    result.and(closed)
+}
+
+/// Sends the writes for `start..end`, keeping `pipeline` in flight.
+pub(crate) async fn write_range(
+    sftp: &Sftp,
+    handle: &SftpHandle,
+    local: &Path,
+    (start, end): (u64, u64),
+    pipeline: usize,
+    progress: Option<&ProgressReporter>,
+    limiter: Option<&RateLimiter>,
+) -> Result<()> {
+    let mut file = File::open(local).await?;
+    file.seek(SeekFrom::Start(start)).await?;
+
+    let mut in_flight: VecDeque<(usize, PendingResponse)> = VecDeque::new();
+    let mut buffer = vec![0u8; MAX_CHUNK as usize];
+    let mut offset = start;
+    while offset < end || !in_flight.is_empty() {
+        if offset < end && in_flight.len() < pipeline {
+            let want = (end - offset).min(MAX_CHUNK as u64) as usize;
+            let n = file.read(&mut buffer[..want]).await?;
+            if n == 0 {
+                return Err(anyhow!("'{}' shrank during the transfer", local.display()));
+            }
+            if let Some(limiter) = limiter {
+                limiter.acquire(n as u64).await;
+            }
+            in_flight.push_back((n, sftp.send_write(handle, offset, &buffer[..n])?));
+            offset += n as u64;
+            continue;
+        }
+        if let Some((len, status)) = in_flight.pop_front() {
+            status.wait().await?.into_ok()?;
+            if let Some(progress) = progress {
+                progress.advance(len as u64);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Downloads `start..end` of `remote` into the same range of `local`.
+async fn download_range(
+    sftp: Sftp,
+    remote: String,
+    local: PathBuf,
+    range: (u64, u64),
+    pipeline: usize,
+    progress: Option<Arc<ProgressReporter>>,
+    limiter: Option<RateLimiter>,
+) -> Result<()> {
+    let handle = sftp
+        .open(&remote, &SftpOpenOptions::new().read(true))
+        .await?;
+    let result = read_range(
+        &sftp,
+        &handle,
+        &remote,
+        &local,
+        range,
+        pipeline,
+        progress.as_deref(),
+        limiter.as_ref(),
+    )
+    .await;
+    let closed = sftp.close(handle).await;
+    result.and(closed)
+}
+
+/// Sends the reads for `start..end`, keeping `pipeline` in flight, and
+/// writes the data at its offset in `local`.
+///
+/// Short reads are completed with a follow-up request for the rest.
+#[allow(clippy::too_many_arguments)]
+async fn read_range(
+    sftp: &Sftp,
+    handle: &SftpHandle,
+    remote: &str,
+    local: &Path,
+    (start, end): (u64, u64),
+    pipeline: usize,
+    progress: Option<&ProgressReporter>,
+    limiter: Option<&RateLimiter>,
+) -> Result<()> {
+    let mut file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .open(local)
+        .await?;
+    let mut position = None;
+
+    let mut in_flight: VecDeque<(u64, u32, PendingResponse)> = VecDeque::new();
+    let mut next = start;
+    loop {
+        while next < end && in_flight.len() < pipeline {
+            let len = (end - next).min(MAX_CHUNK as u64) as u32;
+            if let Some(limiter) = limiter {
+                limiter.acquire(len as u64).await;
+            }
+            in_flight.push_back((next, len, sftp.send_read(handle, next, len)?));
+            next += len as u64;
+        }
+        let Some((offset, len, response)) = in_flight.pop_front() else {
+            break;
+        };
+        let data = match response.wait().await?.into_data()? {
+            Some(data) if !data.is_empty() && data.len() <= len as usize => data,
+            Some(_) | None => {
+                return Err(anyhow!("'{}' shrank during the transfer", remote));
+            }
+        };
+
+        if position != Some(offset) {
+            file.seek(SeekFrom::Start(offset)).await?;
+        }
+        file.write_all(&data).await?;
+        let received = data.len() as u64;
+        position = Some(offset + received);
+        if received < len as u64 {
+            let rest = len - received as u32;
+            in_flight.push_back((
+                offset + received,
+                rest,
+                sftp.send_read(handle, offset + received, rest)?,
+            ));
+        }
+        if let Some(progress) = progress {
+            progress.advance(received);
+        }
+    }
+    file.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{connect, MemoryFs};
+
+    const MIB: u64 = 1024 * 1024;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("simple_ssh_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    /// Content without short periods, so shifted data never matches.
+    fn content(len: usize) -> Vec<u8> {
+        let mut state = 0x2545_f491u32;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (state >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_transfer_options_default() {
+        let options = SftpTransferOptions::default();
+        assert!(!options.resume);
+        assert_eq!(options.ranges, 1);
+        assert_eq!(options.pipeline, 16);
+        assert!(options.progress.is_none());
+        assert!(options.rate_limit.is_none());
+    }
+
+    #[test]
+    fn test_transfer_options_builder() {
+        let options = SftpTransferOptions::new()
+            .resume(true)
+            .ranges(0)
+            .pipeline(0);
+        assert!(options.resume);
+        assert_eq!(options.ranges, 1);
+        assert_eq!(options.pipeline, 1);
+        assert_ne!(options, SftpTransferOptions::new());
+
+        let limiter = RateLimiter::new(4096);
+        let options = SftpTransferOptions::new().rate_limit(limiter.clone());
+        assert_eq!(options, SftpTransferOptions::new().rate_limit(limiter));
+
+        let mut options = SftpTransferOptions::new().ranges(4);
+        let _watcher = options.watch_progress();
+        assert_eq!(options.clone(), options);
+        assert_ne!(options, SftpTransferOptions::new().ranges(4));
+    }
+
+    #[test]
+    fn test_split_ranges() {
+        assert_eq!(split_ranges(0, 0, 4), vec![]);
+        assert_eq!(split_ranges(10, 5, 4), vec![]);
+        assert_eq!(split_ranges(0, 100, 1), vec![(0, 100)]);
+        // Small files are not split below the minimum range size.
+        assert_eq!(split_ranges(0, 100, 4), vec![(0, 100)]);
+        assert_eq!(
+            split_ranges(0, 3 * MIB, 2),
+            vec![(0, 3 * MIB / 2), (3 * MIB / 2, 3 * MIB)]
+        );
+        assert_eq!(
+            split_ranges(MIB, 3 * MIB, 8),
+            vec![(MIB, 2 * MIB), (2 * MIB, 3 * MIB)]
+        );
+    }
+
+    #[test]
+    fn test_split_ranges_covers_everything() {
+        let ranges = split_ranges(7, 10 * MIB + 3, 3);
+        assert_eq!(ranges.len(), 3);
+        assert_eq!(ranges.first().unwrap().0, 7);
+        assert_eq!(ranges.last().unwrap().1, 10 * MIB + 3);
+        for pair in ranges.windows(2) {
+            assert_eq!(pair[0].1, pair[1].0);
+        }
+    }
+
+    #[test]
+    fn test_remote_file_name() {
+        assert_eq!(remote_file_name("/srv/images/disk.img"), "disk.img");
+        assert_eq!(remote_file_name("/srv/images/"), "images");
+        assert_eq!(remote_file_name("disk.img"), "disk.img");
+    }
+
+    #[tokio::test]
+    async fn test_upload_resume() {
+        let dir = temp_dir("sftp_upload_resume");
+        let data = content(300_000);
+        let local = dir.join("data.bin");
+        std::fs::write(&local, &data).unwrap();
+        let fs = MemoryFs::new();
+        fs.add_file("/data.bin", &data[..100_000], 0o644);
+        let mut session = connect(fs.clone()).await;
+
+        let options = SftpTransferOptions::new().resume(true);
+        let summary = upload(&mut session, local.to_str().unwrap(), "/", &options)
+            .await
+            .unwrap();
+        assert_eq!(summary.size, 300_000);
+        assert_eq!(summary.resumed_from, 100_000);
+        assert_eq!(fs.file("/data.bin").unwrap(), data);
+    }
+
+    #[tokio::test]
+    async fn test_upload_resume_restarts_on_mismatch() {
+        let dir = temp_dir("sftp_upload_mismatch");
+        let data = content(300_000);
+        let local = dir.join("data.bin");
+        std::fs::write(&local, &data).unwrap();
+        let mut partial = data[..100_000].to_vec();
+        partial[50_000] ^= 0xff;
+        let fs = MemoryFs::new();
+        fs.add_file("/data.bin", &partial, 0o644);
+        let mut session = connect(fs.clone()).await;
+
+        let options = SftpTransferOptions::new().resume(true);
+        let summary = upload(&mut session, local.to_str().unwrap(), "/data.bin", &options)
+            .await
+            .unwrap();
+        assert_eq!(summary.resumed_from, 0);
+        assert_eq!(fs.file("/data.bin").unwrap(), data);
+    }
+
+    #[tokio::test]
+    async fn test_download_resume() {
+        let dir = temp_dir("sftp_download_resume");
+        let data = content(300_000);
+        let local = dir.join("data.bin");
+        std::fs::write(&local, &data[..120_000]).unwrap();
+        let fs = MemoryFs::new();
+        fs.add_file("/data.bin", &data, 0o644);
+        let mut session = connect(fs).await;
+
+        let options = SftpTransferOptions::new().resume(true);
+        let summary = download(&mut session, "/data.bin", dir.to_str().unwrap(), &options)
+            .await
+            .unwrap();
+        assert_eq!(summary.resumed_from, 120_000);
+        // Reads are capped at 10 000 bytes, so every request came back short.
+        assert_eq!(std::fs::read(&local).unwrap(), data);
+    }
+
+    #[tokio::test]
+    async fn test_download_resume_restarts_on_mismatch() {
+        let dir = temp_dir("sftp_download_mismatch");
+        let data = content(300_000);
+        let local = dir.join("data.bin");
+        let mut partial = data[..120_000].to_vec();
+        partial[0] ^= 0xff;
+        std::fs::write(&local, &partial).unwrap();
+        let fs = MemoryFs::new();
+        fs.add_file("/data.bin", &data, 0o644);
+        let mut session = connect(fs).await;
+
+        let options = SftpTransferOptions::new().resume(true);
+        let summary = download(&mut session, "/data.bin", local.to_str().unwrap(), &options)
+            .await
+            .unwrap();
+        assert_eq!(summary.resumed_from, 0);
+        assert_eq!(std::fs::read(&local).unwrap(), data);
+    }
+
+    #[tokio::test]
+    async fn test_transfer_ranges() {
+        let dir = temp_dir("sftp_ranges");
+        let data = content(4 * MIB as usize + 12_345);
+        let local = dir.join("up.bin");
+        std::fs::write(&local, &data).unwrap();
+        let fs = MemoryFs::new();
+        let mut session = connect(fs.clone()).await;
+
+        let options = SftpTransferOptions::new().ranges(4);
+        let summary = upload(&mut session, local.to_str().unwrap(), "/up.bin", &options)
+            .await
+            .unwrap();
+        assert_eq!(summary.ranges, 4);
+        assert_eq!(fs.file("/up.bin").unwrap(), data);
+
+        let back = dir.join("down.bin");
+        let summary = download(&mut session, "/up.bin", back.to_str().unwrap(), &options)
+            .await
+            .unwrap();
+        assert_eq!(summary.ranges, 4);
+        assert_eq!(std::fs::read(&back).unwrap(), data);
+    }
+}
@@ -0,0 +1,485 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2025 Julian Kahlert
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{ensure, Result};
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+use crate::expect::ExpectError;
+use crate::pty_command::{clean_output, CommandWatcher};
+use crate::PtyHandle;
+
+/// Default time a command may run before [`ShellSession::run`] gives up.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Separator around sentinel markers; never produced by echoing the command.
+const MARK: u8 = 0x1e;
+
+/// Distinguishes sentinels of commands run by this process.
+static NEXT_TOKEN: AtomicU64 = AtomicU64::new(0);
+
+/// The result of a command run through a [`ShellSession`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShellOutput {
+    /// The command's output with escape sequences stripped and `\r\n`
+    /// normalised to `\n`.
+    pub output: String,
+    /// The exit code, if the shell reported one.
+    pub exit_code: Option<i32>,
+    /// How long the command took, including the round trip.
+    pub duration: Duration,
+}
+
+impl ShellOutput {
+    /// Returns true if the command exited with code 0.
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+}
+
+/// Runs commands one at a time in a long-lived interactive shell.
+///
+/// Unlike [`Session::cmd`](crate::Session::cmd), every command runs in the
+/// same shell, so `cd`, variables and functions persist between calls.
+///
+/// The output of each command is delimited in one of two ways:
+///
+/// - If the handle was opened with
+///   [`with_command_detection`](crate::PtyBuilder::with_command_detection),
+///   the shell's OSC 133/633 marks are used and the command line is sent
+///   as typed.
+/// - Otherwise the command is wrapped in `eval` between two `printf`
+///   sentinels carrying a unique token, which works in any POSIX shell
+///   without further setup.
+///
+/// Calls take `&self` and are serialised internally, so a session can be
+/// shared between tasks behind an `Arc`.
+///
+/// ```ignore
+/// use simple_ssh::ShellSession;
+///
+/// let handle = session.pty_builder().with_command("bash").open().await?;
+/// let shell = ShellSession::new(handle);
+///
+/// shell.run("cd /var/log").await?;
+/// let result = shell.run("ls | wc -l").await?;
+/// println!("{} files, exit code {:?}", result.output.trim(), result.exit_code);
+/// ```
+#[derive(Debug)]
+pub struct ShellSession {
+    state: Mutex<ShellState>,
+    timeout: Duration,
+}
+
+/// The handle and unread output, guarded by the session lock.
+#[derive(Debug)]
+struct ShellState {
+    handle: PtyHandle,
+    commands: Option<CommandWatcher>,
+    abandoned: usize,
+    buffer: Vec<u8>,
+    eof: bool,
+}
+
+impl ShellSession {
+    /// Wraps a PTY handle running a shell.
+    ///
+    /// Uses OSC 133/633 marks when command detection is enabled on the
+    /// handle and sentinels otherwise.
+    pub fn new(handle: PtyHandle) -> Self {
+        let commands = handle.watch_commands().ok();
+        Self {
+            state: Mutex::new(ShellState {
+                handle,
+                commands,
+                abandoned: 0,
+                buffer: Vec::new(),
+                eof: false,
+            }),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Sets how long [`run`](ShellSession::run) waits for a command
+    /// (default: 60 seconds).
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Returns the wrapped handle.
+    pub fn into_inner(self) -> PtyHandle {
+        self.state.into_inner().handle
+    }
+
+    /// Runs `command` and returns its output and exit code.
+    ///
+    /// # Errors
+    ///
+    /// See [`run_with_timeout`](ShellSession::run_with_timeout).
+    pub async fn run(&self, command: &str) -> Result<ShellOutput> {
+        self.run_with_timeout(command, self.timeout).await
+    }
+
+    /// Runs `command`, waiting at most `timeout` for it to finish.
+    ///
+    /// Waits for earlier calls to complete first. On timeout the command
+    /// is interrupted with Ctrl+C.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `command` is empty, and an [`ExpectError`] if
+    /// the timeout passes or the shell exits before the command finishes.
+    pub async fn run_with_timeout(&self, command: &str, timeout: Duration) -> Result<ShellOutput> {
+        ensure!(!command.trim().is_empty(), "Empty shell command");
+
+        let mut state = self.state.lock().await;
+        let started = std::time::Instant::now();
+        let deadline = Instant::now() + timeout;
+
+        let finished = if state.commands.is_some() {
+            state.run_marked(command, deadline).await?
+        } else {
+            state.run_sentinel(command, deadline).await?
+        };
+
+        match finished {
+            Some((output, exit_code)) => Ok(ShellOutput {
+                output,
+                exit_code,
+                duration: started.elapsed(),
+            }),
+            None => {
+                let expected = format!("end of command {:?}", command);
+                let pending = clean_output(&std::mem::take(&mut state.buffer));
+                if state.eof {
+                    return Err(ExpectError::Eof { expected, pending }.into());
+                }
+                if state.commands.is_some() {
+                    // Its end mark still arrives and must not be taken for
+                    // the next command's
+                    state.abandoned += 1;
+                }
+                let _ = state.handle.write(&[0x03]).await;
+                Err(ExpectError::Timeout { expected, pending }.into())
+            }
+        }
+    }
+}
+
+impl ShellState {
+    /// Runs a command between sentinels.
+    ///
+    /// Returns `None` if the deadline passes or the shell exits first.
+    async fn run_sentinel(
+        &mut self,
+        command: &str,
+        deadline: Instant,
+    ) -> Result<Option<(String, Option<i32>)>> {
+        let token = next_token();
+        let begin = [&[MARK], format!("{}:B", token).as_bytes(), &[MARK]].concat();
+        let end = [&[MARK], format!("{}:E:", token).as_bytes()].concat();
+
+        self.buffer.clear();
+        self.handle
+            .write(sentinel_command(command, &token).as_bytes())
+            .await?;
+
+        let mut begun = false;
+        loop {
+            if !begun {
+                if let Some(pos) = find(&self.buffer, &begin) {
+                    self.buffer.drain(..pos + begin.len());
+                    begun = true;
+                }
+            }
+            if begun {
+                if let Some((output, code)) = split_at_end_marker(&mut self.buffer, &end) {
+                    let output = clean_output(&output);
+                    let output = output.strip_prefix('\n').unwrap_or(&output).to_string();
+                    return Ok(Some((output, code)));
+                }
+            }
+            if self.eof || !self.read_until(deadline).await {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Runs a command delimited by the shell's OSC 133/633 marks.
+    ///
+    /// Returns `None` if the deadline passes or the shell exits first.
+    async fn run_marked(
+        &mut self,
+        command: &str,
+        deadline: Instant,
+    ) -> Result<Option<(String, Option<i32>)>> {
+        let Some(commands) = self.commands.as_mut() else {
+            return Ok(None);
+        };
+        // Skip commands that finished after an earlier call gave up
+        while self.abandoned > 0 && commands.try_next().is_some() {
+            self.abandoned -= 1;
+        }
+
+        self.buffer.clear();
+        self.handle
+            .write(format!("{}\n", command).as_bytes())
+            .await?;
+
+        loop {
+            tokio::select! {
+                event = commands.next() => match event {
+                    Some(_) if self.abandoned > 0 => self.abandoned -= 1,
+                    event => return Ok(event.map(|e| (e.output, e.exit_code))),
+                },
+                chunk = self.handle.read(), if !self.eof => match chunk {
+                    // The output arrives with the command event; keep the
+                    // tail only for error messages
+                    Some(chunk) => {
+                        self.buffer.extend_from_slice(&chunk);
+                        let excess = self.buffer.len().saturating_sub(4096);
+                        self.buffer.drain(..excess);
+                    }
+                    None => {
+                        self.eof = true;
+                        while self.abandoned > 0 && commands.try_next().is_some() {
+                            self.abandoned -= 1;
+                        }
+                        return Ok(commands.try_next().map(|e| (e.output, e.exit_code)));
+                    }
+                },
+                _ = tokio::time::sleep_until(deadline) => return Ok(None),
+            }
+        }
+    }
+
+    /// Reads the next output chunk into the buffer.
+    ///
+    /// Returns false if the deadline passed first.
+    async fn read_until(&mut self, deadline: Instant) -> bool {
+        match tokio::time::timeout_at(deadline, self.handle.read()).await {
+            Ok(Some(chunk)) => self.buffer.extend_from_slice(&chunk),
+            Ok(None) => self.eof = true,
+            Err(_) => return false,
+        }
+        true
+    }
+}
+
+/// Returns a token that is unique to this process and unlikely to repeat
+/// across processes.
+fn next_token() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    let count = NEXT_TOKEN.fetch_add(1, Ordering::Relaxed);
+    format!("SSH{:x}{:08x}{:x}", std::process::id(), nanos, count)
+}
+
+/// Builds the input line that runs `command` between two sentinels.
+///
+/// The markers are written with `printf` octal escapes, so the echoed
+/// input never contains the marker bytes themselves. The leading space
+/// keeps the line out of the shell history where `ignorespace` is set.
+fn sentinel_command(command: &str, token: &str) -> String {
+    format!(
+        " printf '\\036%s\\036\\n' {token}:B; eval {command}; printf '\\036%s%d\\036\\n' {token}:E: \"$?\"\n",
+        token = token,
+        command = shell_escape::escape(command.into()),
+    )
+}
+
+/// Returns the position of `needle` in `haystack`.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Splits the output before a complete end marker off the buffer.
+///
+/// Returns the output and the exit code; the marker itself is removed.
+fn split_at_end_marker(buffer: &mut Vec<u8>, end: &[u8]) -> Option<(Vec<u8>, Option<i32>)> {
+    let pos = find(buffer, end)?;
+    let code_start = pos + end.len();
+    let code_len = buffer[code_start..].iter().position(|&b| b == MARK)?;
+    let code = std::str::from_utf8(&buffer[code_start..code_start + code_len])
+        .ok()
+        .and_then(|c| c.parse().ok());
+    let output = buffer[..pos].to_vec();
+    buffer.drain(..code_start + code_len + 1);
+    Some((output, code))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tokio::sync::mpsc;
+
+    /// Answers sentinel commands like a shell would, echoing the input first.
+    fn fake_shell(
+        output_tx: mpsc::Sender<Vec<u8>>,
+        mut input_rx: mpsc::Receiver<Vec<u8>>,
+        reply: impl Fn(&str) -> Option<(String, i32)> + Send + 'static,
+    ) {
+        tokio::spawn(async move {
+            while let Some(input) = input_rx.recv().await {
+                let line = String::from_utf8(input).unwrap();
+                let _ = output_tx
+                    .send(line.replace('\n', "\r\n").into_bytes())
+                    .await;
+                let token = line.split("' ").nth(1).unwrap().split(":B").next().unwrap();
+                let command = line
+                    .split("eval ")
+                    .nth(1)
+                    .unwrap()
+                    .split("; printf")
+                    .next()
+                    .unwrap();
+                let Some((output, code)) = reply(command) else {
+                    continue;
+                };
+                let answer = format!(
+                    "\x1e{t}:B\x1e\r\n{o}\x1e{t}:E:{c}\x1e\r\n$ ",
+                    t = token,
+                    o = output,
+                    c = code
+                );
+                // Split the reply to exercise markers across chunks
+                let (a, b) = answer.split_at(answer.len() / 2);
+                let _ = output_tx.send(a.as_bytes().to_vec()).await;
+                let _ = output_tx.send(b.as_bytes().to_vec()).await;
+            }
+        });
+    }
+
+    #[test]
+    fn test_sentinel_command_hides_markers_from_echo() {
+        let line = sentinel_command("echo 'a b'", "T1");
+        assert!(!line.as_bytes().contains(&MARK));
+        assert!(line.contains("eval 'echo '\\''a b'\\'''"));
+        assert!(line.ends_with("\"$?\"\n"));
+    }
+
+    #[test]
+    fn test_split_at_end_marker() {
+        let mut buffer = b"out\r\n\x1eT:E:1".to_vec();
+        assert_eq!(split_at_end_marker(&mut buffer, b"\x1eT:E:"), None);
+        buffer.extend_from_slice(b"27\x1e\r\n$ ");
+        let (output, code) = split_at_end_marker(&mut buffer, b"\x1eT:E:").unwrap();
+        assert_eq!(output, b"out\r\n");
+        assert_eq!(code, Some(127));
+        assert_eq!(buffer, b"\r\n$ ");
+    }
+
+    #[tokio::test]
+    async fn test_run_returns_output_and_exit_code() {
+        let (handle, output_tx, input_rx) = PtyHandle::detached();
+        fake_shell(output_tx, input_rx, |command| {
+            Some(match command {
+                "'ls -l'" => ("\x1b[34ma\x1b[0m\r\nb\r\n".to_string(), 0),
+                _ => ("not found\r\n".to_string(), 127),
+            })
+        });
+        let shell = ShellSession::new(handle);
+
+        let result = shell.run("ls -l").await.unwrap();
+        assert_eq!(result.output, "a\nb\n");
+        assert!(result.success());
+
+        let result = shell.run("nope").await.unwrap();
+        assert_eq!(result.output, "not found\n");
+        assert_eq!(result.exit_code, Some(127));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_runs_are_serialised() {
+        let (handle, output_tx, input_rx) = PtyHandle::detached();
+        fake_shell(output_tx, input_rx, |command| {
+            Some((format!("{}\r\n", command.trim_matches('\'')), 0))
+        });
+        let shell = Arc::new(ShellSession::new(handle));
+
+        let tasks: Vec<_> = (0..8)
+            .map(|i| {
+                let shell = shell.clone();
+                tokio::spawn(async move { (i, shell.run(&format!("echo{}", i)).await.unwrap()) })
+            })
+            .collect();
+        for task in tasks {
+            let (i, result) = task.await.unwrap();
+            assert_eq!(result.output, format!("echo{}\n", i));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_timeout_interrupts_command() {
+        let (handle, output_tx, mut input_rx) = PtyHandle::detached();
+        let shell = ShellSession::new(handle).timeout(Duration::from_millis(50));
+        let _output_tx = output_tx;
+
+        let err = shell.run("sleep 100").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ExpectError>(),
+            Some(ExpectError::Timeout { .. })
+        ));
+        assert!(input_rx.recv().await.unwrap().ends_with(b"\n"));
+        assert_eq!(input_rx.recv().await.unwrap(), vec![0x03]);
+        assert!(shell.run("  ").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_run_uses_command_marks() {
+        use crate::pty_command::{CommandDetection, CommandDetectionConfig};
+
+        let (mut handle, output_tx, mut input_rx) = PtyHandle::detached();
+        let detection = Arc::new(CommandDetection::new(CommandDetectionConfig {
+            enabled: true,
+            ..CommandDetectionConfig::default()
+        }));
+        handle.command_detection = Some(detection.clone());
+        tokio::spawn(async move {
+            while let Some(input) = input_rx.recv().await {
+                let line = String::from_utf8(input).unwrap();
+                let reply = format!(
+                    "\x1b]133;B\x07{}\r\n\x1b]133;C\x07done\r\n\x1b]133;D;3\x07\x1b]133;A\x07$ ",
+                    line.trim_end()
+                );
+                detection.feed(reply.as_bytes());
+                let _ = output_tx.send(reply.into_bytes()).await;
+            }
+        });
+        let shell = ShellSession::new(handle);
+
+        let result = shell.run("make").await.unwrap();
+        assert_eq!(result.output, "done\n");
+        assert_eq!(result.exit_code, Some(3));
+    }
+}
@@ -0,0 +1,1144 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2025 Julian Kahlert
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use anyhow::{anyhow, Result};
+use glob::{MatchOptions, Pattern};
+use log::{debug, info, warn};
+use russh::client;
+use tokio::io::AsyncReadExt;
+
+use crate::progress::{ProgressReporter, ProgressWatcher};
+use crate::rate_limit::RateLimiter;
+use crate::sftp::{sftp_open, FileAttributes, Sftp, SftpError, SftpOpenOptions, SftpStatus};
+use crate::sftp_transfer::{write_range, DEFAULT_PIPELINE};
+use crate::verify::{self, capture_output, Digests};
+use crate::Client;
+
+/// Number of files checksummed by a single remote command.
+const CHECKSUM_BATCH: usize = 64;
+
+/// How files present on both sides are compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SyncCompare {
+    /// Transfer files whose size or modification time differ.
+    #[default]
+    SizeAndTime,
+    /// Transfer files whose size or content checksum differ.
+    ///
+    /// Slower, as every file of equal size is read on both sides, but
+    /// independent of timestamps.
+    Checksum,
+}
+
+/// Options for [`Session::sync_dir`](crate::Session::sync_dir).
+///
+/// ```
+/// use simple_ssh::{SyncCompare, SyncOptions};
+///
+/// let options = SyncOptions::new()
+///     .compare(SyncCompare::Checksum)
+///     .delete(true)
+///     .exclude("*.tmp")
+///     .exclude("build/cache")
+///     .dry_run(true);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SyncOptions {
+    pub(crate) compare: SyncCompare,
+    pub(crate) delete: bool,
+    pub(crate) include: Vec<String>,
+    pub(crate) exclude: Vec<String>,
+    pub(crate) dry_run: bool,
+    pub(crate) progress: Option<Arc<ProgressReporter>>,
+    pub(crate) rate_limit: Option<RateLimiter>,
+}
+
+impl PartialEq for SyncOptions {
+    fn eq(&self, other: &Self) -> bool {
+        let same_progress = match (&self.progress, &other.progress) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        };
+        self.compare == other.compare
+            && self.delete == other.delete
+            && self.include == other.include
+            && self.exclude == other.exclude
+            && self.dry_run == other.dry_run
+            && self.rate_limit == other.rate_limit
+            && same_progress
+    }
+}
+
+impl SyncOptions {
+    /// Creates new sync options with defaults.
+    ///
+    /// Defaults:
+    /// - compare: [`SyncCompare::SizeAndTime`]
+    /// - delete: false
+    /// - no include or exclude patterns
+    /// - dry_run: false
+    /// - rate_limit: none
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Selects how files present on both sides are compared.
+    pub fn compare(mut self, compare: SyncCompare) -> Self {
+        self.compare = compare;
+        self
+    }
+
+    /// Deletes remote files and directories that do not exist locally.
+    ///
+    /// Excluded paths are never deleted.
+    pub fn delete(mut self, delete: bool) -> Self {
+        self.delete = delete;
+        self
+    }
+
+    /// Only transfers files matching `pattern`.
+    ///
+    /// May be given several times; a file is transferred if it matches
+    /// any of them. Directories are always traversed. Patterns use glob
+    /// syntax (`*`, `?`, `[abc]`, `**`). A pattern containing a `/` is
+    /// matched against the path relative to the synced directory, any
+    /// other pattern against the file name alone.
+    pub fn include(mut self, pattern: &str) -> Self {
+        self.include.push(pattern.to_string());
+        self
+    }
+
+    /// Leaves files and directories matching `pattern` alone.
+    ///
+    /// Excluded directories are not traversed. Patterns are matched like
+    /// those of [`include`](Self::include); exclusion takes precedence.
+    pub fn exclude(mut self, pattern: &str) -> Self {
+        self.exclude.push(pattern.to_string());
+        self
+    }
+
+    /// Only computes the changes and returns them without applying them.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Limits the bandwidth used for uploads.
+    pub fn rate_limit(mut self, limiter: RateLimiter) -> Self {
+        self.rate_limit = Some(limiter);
+        self
+    }
+
+    /// Enables progress reporting and returns a watcher for it.
+    ///
+    /// Every uploaded file reports its progress to the returned watcher.
+    pub fn watch_progress(&mut self) -> ProgressWatcher {
+        self.progress
+            .get_or_insert_with(|| Arc::new(ProgressReporter::new()))
+            .create_watcher()
+    }
+}
+
+/// Why a file is transferred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncReason {
+    /// The file does not exist on the remote host.
+    New,
+    /// The sizes differ.
+    Size,
+    /// The modification times differ.
+    Modified,
+    /// The checksums differ.
+    Checksum,
+    /// The remote path is a symlink or special file.
+    Replaced,
+}
+
+impl fmt::Display for SyncReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            SyncReason::New => "new",
+            SyncReason::Size => "size changed",
+            SyncReason::Modified => "modified",
+            SyncReason::Checksum => "content changed",
+            SyncReason::Replaced => "replaces non-regular file",
+        };
+        f.write_str(text)
+    }
+}
+
+/// A single change made (or, in a dry run, planned) by a sync.
+///
+/// Paths are relative to the synced directories, `.` is the root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncAction {
+    /// Create a remote directory.
+    CreateDir { path: String },
+    /// Upload a file.
+    Upload {
+        path: String,
+        size: u64,
+        reason: SyncReason,
+    },
+    /// Delete an extraneous remote file.
+    Delete { path: String },
+    /// Delete an extraneous remote directory.
+    DeleteDir { path: String },
+}
+
+impl fmt::Display for SyncAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncAction::CreateDir { path } => write!(f, "mkdir   {}/", path),
+            SyncAction::Upload { path, reason, .. } => write!(f, "upload  {} ({})", path, reason),
+            SyncAction::Delete { path } => write!(f, "delete  {}", path),
+            SyncAction::DeleteDir { path } => write!(f, "delete  {}/", path),
+        }
+    }
+}
+
+/// Result of [`Session::sync_dir`](crate::Session::sync_dir).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SyncReport {
+    /// Changes in the order they were (or would be) applied.
+    pub actions: Vec<SyncAction>,
+    /// Number of files that were already up to date.
+    pub unchanged: usize,
+    /// Total size of the uploaded files.
+    pub bytes: u64,
+    /// True if nothing was changed because of [`SyncOptions::dry_run`].
+    pub dry_run: bool,
+}
+
+impl fmt::Display for SyncReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for action in &self.actions {
+            writeln!(f, "{}", action)?;
+        }
+        let uploads = self
+            .actions
+            .iter()
+            .filter(|a| matches!(a, SyncAction::Upload { .. }))
+            .count();
+        let deletes = self
+            .actions
+            .iter()
+            .filter(|a| matches!(a, SyncAction::Delete { .. } | SyncAction::DeleteDir { .. }))
+            .count();
+        write!(
+            f,
+            "{} {} ({} bytes), {} deleted, {} unchanged",
+            uploads,
+            if self.dry_run {
+                "to upload"
+            } else {
+                "uploaded"
+            },
+            self.bytes,
+            deletes,
+            self.unchanged
+        )?;
+        if self.dry_run {
+            write!(f, " (dry run)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Kind of an entry found while walking a tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EntryKind {
+    File,
+    Dir,
+    Other,
+}
+
+/// A file or directory of one side of the sync.
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    kind: EntryKind,
+    size: u64,
+    mtime: u32,
+    atime: u32,
+    mode: u32,
+}
+
+impl Entry {
+    fn from_attrs(attrs: &FileAttributes) -> Self {
+        let kind = if attrs.is_dir() {
+            EntryKind::Dir
+        } else if attrs.is_file() {
+            EntryKind::File
+        } else {
+            EntryKind::Other
+        };
+        Entry {
+            kind,
+            size: attrs.size.unwrap_or(0),
+            mtime: attrs.mtime.unwrap_or(0),
+            atime: attrs.atime.unwrap_or(0),
+            mode: attrs.permissions.unwrap_or(0) & 0o7777,
+        }
+    }
+
+    fn from_metadata(metadata: &std::fs::Metadata) -> Self {
+        let kind = if metadata.is_dir() {
+            EntryKind::Dir
+        } else if metadata.is_file() {
+            EntryKind::File
+        } else {
+            EntryKind::Other
+        };
+        let secs = |t: std::io::Result<std::time::SystemTime>| {
+            t.ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs() as u32)
+                .unwrap_or(0)
+        };
+        #[cfg(unix)]
+        let mode = {
+            use std::os::unix::fs::PermissionsExt;
+            metadata.permissions().mode() & 0o7777
+        };
+        #[cfg(not(unix))]
+        let mode = if metadata.is_dir() { 0o755 } else { 0o644 };
+        Entry {
+            kind,
+            size: metadata.len(),
+            mtime: secs(metadata.modified()),
+            atime: secs(metadata.accessed()),
+            mode,
+        }
+    }
+}
+
+/// Compiled include and exclude patterns.
+#[derive(Debug, Default)]
+struct SyncFilter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl SyncFilter {
+    fn new(options: &SyncOptions) -> Result<Self> {
+        let compile = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|p| Pattern::new(p).map_err(|e| anyhow!("Invalid pattern '{}': {}", p, e)))
+                .collect::<Result<Vec<_>>>()
+        };
+        Ok(SyncFilter {
+            include: compile(&options.include)?,
+            exclude: compile(&options.exclude)?,
+        })
+    }
+
+    fn matches(pattern: &Pattern, path: &str) -> bool {
+        let options = MatchOptions {
+            case_sensitive: true,
+            require_literal_separator: true,
+            require_literal_leading_dot: false,
+        };
+        if pattern.as_str().contains('/') {
+            pattern.matches_with(path, options)
+        } else {
+            let name = path.rsplit('/').next().unwrap_or(path);
+            pattern.matches_with(name, options)
+        }
+    }
+
+    /// Whether the entry at `path` is left out of the sync entirely.
+    fn excludes(&self, path: &str) -> bool {
+        self.exclude.iter().any(|p| Self::matches(p, path))
+    }
+
+    /// Whether the file at `path` is transferred.
+    fn includes_file(&self, path: &str) -> bool {
+        self.include.is_empty() || self.include.iter().any(|p| Self::matches(p, path))
+    }
+}
+
+/// Joins a relative sync path to a root directory.
+fn join_path(root: &str, path: &str) -> String {
+    if path == "." {
+        root.to_string()
+    } else {
+        format!("{}/{}", root.trim_end_matches('/'), path)
+    }
+}
+
+/// Collects the local tree below `root`, keyed by relative path.
+///
+/// Symlinks and special files are skipped like `rsync` does without
+/// `--links`.
+async fn walk_local(root: &Path, filter: &SyncFilter) -> Result<BTreeMap<String, Entry>> {
+    let mut entries = BTreeMap::new();
+    let mut dirs = vec![(root.to_path_buf(), String::new())];
+    while let Some((dir, prefix)) = dirs.pop() {
+        let mut reader = tokio::fs::read_dir(&dir)
+            .await
+            .map_err(|e| anyhow!("Cannot read '{}': {}", dir.display(), e))?;
+        while let Some(item) = reader.next_entry().await? {
+            let name = item.file_name().to_string_lossy().to_string();
+            let path = format!("{}{}", prefix, name);
+            if filter.excludes(&path) {
+                continue;
+            }
+            let entry = Entry::from_metadata(&item.metadata().await?);
+            match entry.kind {
+                EntryKind::Dir => dirs.push((item.path(), format!("{}/", path))),
+                EntryKind::File if filter.includes_file(&path) => {}
+                EntryKind::File => continue,
+                EntryKind::Other => {
+                    debug!("Skipping non-regular file '{}'", item.path().display());
+                    continue;
+                }
+            }
+            entries.insert(path, entry);
+        }
+    }
+    Ok(entries)
+}
+
+/// Collects the remote tree below `root`, keyed by relative path.
+async fn walk_remote(
+    sftp: &Sftp,
+    root: &str,
+    filter: &SyncFilter,
+) -> Result<BTreeMap<String, Entry>> {
+    let mut entries = BTreeMap::new();
+    let mut dirs = vec![(root.to_string(), String::new())];
+    while let Some((dir, prefix)) = dirs.pop() {
+        for item in sftp.read_dir(&dir).await? {
+            let path = format!("{}{}", prefix, item.name);
+            if filter.excludes(&path) {
+                continue;
+            }
+            let entry = Entry::from_attrs(&item.attrs);
+            match entry.kind {
+                EntryKind::Dir => dirs.push((join_path(&dir, &item.name), format!("{}/", path))),
+                EntryKind::File if !filter.includes_file(&path) => continue,
+                _ => {}
+            }
+            entries.insert(path, entry);
+        }
+    }
+    Ok(entries)
+}
+
+/// Works out the changes needed to bring `remote` in line with `local`.
+///
+/// # Arguments
+///
+/// * `local` - Local tree
+/// * `remote` - Remote tree, `None` if the remote root does not exist
+/// * `compare` - How files present on both sides are compared
+/// * `same_content` - Files whose checksums match, for [`SyncCompare::Checksum`]
+/// * `delete` - Whether extraneous remote entries are deleted
+fn plan(
+    local: &BTreeMap<String, Entry>,
+    remote: Option<&BTreeMap<String, Entry>>,
+    compare: SyncCompare,
+    same_content: &HashSet<String>,
+    delete: bool,
+) -> Result<SyncReport> {
+    let empty = BTreeMap::new();
+    let mut report = SyncReport::default();
+    let remote = match remote {
+        Some(remote) => remote,
+        None => {
+            report.actions.push(SyncAction::CreateDir {
+                path: ".".to_string(),
+            });
+            &empty
+        }
+    };
+
+    for (path, entry) in local {
+        let existing = remote.get(path);
+        match (entry.kind, existing.map(|e| e.kind)) {
+            (EntryKind::Dir, None) => report
+                .actions
+                .push(SyncAction::CreateDir { path: path.clone() }),
+            (EntryKind::Dir, Some(EntryKind::Dir)) => {}
+            (EntryKind::File, Some(EntryKind::Dir)) => {
+                return Err(anyhow!(
+                    "'{}' is a directory on the remote host but a file locally",
+                    path
+                ));
+            }
+            (EntryKind::Dir, Some(_)) => {
+                return Err(anyhow!(
+                    "'{}' is a directory locally but not on the remote host",
+                    path
+                ));
+            }
+            (EntryKind::File, other) => {
+                let reason = match (other, existing) {
+                    (None, _) => Some(SyncReason::New),
+                    (Some(EntryKind::Other), _) => Some(SyncReason::Replaced),
+                    (_, Some(remote)) if remote.size != entry.size => Some(SyncReason::Size),
+                    _ => match compare {
+                        SyncCompare::SizeAndTime
+                            if existing.is_some_and(|r| r.mtime != entry.mtime) =>
+                        {
+                            Some(SyncReason::Modified)
+                        }
+                        SyncCompare::Checksum if !same_content.contains(path) => {
+                            Some(SyncReason::Checksum)
+                        }
+                        _ => None,
+                    },
+                };
+                match reason {
+                    Some(reason) => {
+                        report.bytes += entry.size;
+                        report.actions.push(SyncAction::Upload {
+                            path: path.clone(),
+                            size: entry.size,
+                            reason,
+                        });
+                    }
+                    None => report.unchanged += 1,
+                }
+            }
+            (EntryKind::Other, _) => {}
+        }
+    }
+
+    if delete {
+        // Reverse order visits the contents of a directory before itself.
+        for (path, entry) in remote.iter().rev() {
+            if local.contains_key(path) {
+                continue;
+            }
+            let path = path.clone();
+            report.actions.push(match entry.kind {
+                EntryKind::Dir => SyncAction::DeleteDir { path },
+                _ => SyncAction::Delete { path },
+            });
+        }
+    }
+    Ok(report)
+}
+
+/// Hashes a local file with the algorithms of [`Digests`].
+async fn local_digests(path: &Path) -> Result<Digests> {
+    let mut digests = Digests::new();
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buffer).await?;
+        if n == 0 {
+            break;
+        }
+        digests.update(&buffer[..n]);
+    }
+    Ok(digests)
+}
+
+/// Returns the files of `paths` whose local and remote checksums match.
+async fn matching_checksums(
+    session: &mut client::Handle<Client>,
+    local_root: &Path,
+    remote_root: &str,
+    paths: &[String],
+) -> Result<HashSet<String>> {
+    let mut same = HashSet::new();
+    for batch in paths.chunks(CHECKSUM_BATCH) {
+        let command = verify::checksum_files_command(remote_root, batch);
+        let (code, stdout, stderr) = capture_output(session, &command).await?;
+        if code != Some(0) {
+            return Err(anyhow!(
+                "Could not checksum remote files in '{}': {}",
+                remote_root,
+                String::from_utf8_lossy(&stderr).trim_end()
+            ));
+        }
+        for checksum in verify::parse_checksums(&String::from_utf8_lossy(&stdout)) {
+            let Some(path) = checksum.path else {
+                continue;
+            };
+            if !batch.contains(&path) {
+                continue;
+            }
+            let digests = local_digests(&local_root.join(&path)).await?;
+            if digests.hex(checksum.algorithm).as_deref() == Some(checksum.digest.as_str()) {
+                same.insert(path);
+            }
+        }
+    }
+    Ok(same)
+}
+
+/// Uploads a single file and applies its mode and timestamps.
+async fn upload_file(
+    sftp: &Sftp,
+    local: &Path,
+    remote: &str,
+    entry: &Entry,
+    label: &str,
+    progress: Option<&ProgressReporter>,
+    limiter: Option<&RateLimiter>,
+) -> Result<()> {
+    let open = SftpOpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(entry.mode);
+    let handle = sftp.open(remote, &open).await?;
+    if let Some(progress) = progress {
+        progress.start(label, entry.size);
+    }
+    let result = write_range(
+        sftp,
+        &handle,
+        local,
+        (0, entry.size),
+        DEFAULT_PIPELINE,
+        progress,
+        limiter,
+    )
+    .await;
+    let closed = sftp.close(handle).await;
+    result.and(closed)?;
+    if let Some(progress) = progress {
+        progress.finish();
+    }
+
+    // Matching times keep the next size/time comparison from re-uploading.
+    let attrs = FileAttributes::new()
+        .with_permissions(entry.mode)
+        .with_times(entry.atime, entry.mtime);
+    sftp.setstat(remote, &attrs).await
+}
+
+/// Synchronises the local directory `local` into the remote directory
+/// `remote`.
+///
+/// # Arguments
+///
+/// * `session` - The SSH session handle
+/// * `local` - Local source directory
+/// * `remote` - Remote destination directory
+/// * `options` - Sync options
+pub(crate) async fn sync_dir(
+    session: &mut client::Handle<Client>,
+    local: &str,
+    remote: &str,
+    options: &SyncOptions,
+) -> Result<SyncReport> {
+    let local_root = PathBuf::from(local);
+    if !tokio::fs::metadata(&local_root)
+        .await
+        .map_err(|e| anyhow!("Cannot read '{}': {}", local, e))?
+        .is_dir()
+    {
+        return Err(anyhow!("'{}' is not a directory", local));
+    }
+    let filter = SyncFilter::new(options)?;
+    let sftp = sftp_open(session).await?;
+
+    let local_tree = walk_local(&local_root, &filter).await?;
+    let remote_tree = match sftp.stat(remote).await {
+        Ok(attrs) if attrs.is_dir() => Some(walk_remote(&sftp, remote, &filter).await?),
+        Ok(_) => return Err(anyhow!("'{}' is not a directory", remote)),
+        Err(e)
+            if e.downcast_ref::<SftpError>()
+                .is_some_and(|e| e.status == SftpStatus::NoSuchFile) =>
+        {
+            None
+        }
+        Err(e) => return Err(e),
+    };
+
+    let mut same_content = HashSet::new();
+    if let (SyncCompare::Checksum, Some(remote_tree)) = (options.compare, &remote_tree) {
+        let candidates: Vec<String> = local_tree
+            .iter()
+            .filter(|(path, entry)| {
+                entry.kind == EntryKind::File
+                    && remote_tree
+                        .get(*path)
+                        .is_some_and(|r| r.kind == EntryKind::File && r.size == entry.size)
+            })
+            .map(|(path, _)| path.clone())
+            .collect();
+        same_content = matching_checksums(session, &local_root, remote, &candidates).await?;
+    }
+
+    let mut report = plan(
+        &local_tree,
+        remote_tree.as_ref(),
+        options.compare,
+        &same_content,
+        options.delete,
+    )?;
+    if options.dry_run {
+        report.dry_run = true;
+        return Ok(report);
+    }
+
+    let progress = options.progress.as_deref();
+    for action in &report.actions {
+        match action {
+            SyncAction::CreateDir { path } => {
+                let mode = local_tree.get(path).map_or(0o755, |e| e.mode);
+                sftp.mkdir(&join_path(remote, path), mode).await?;
+            }
+            SyncAction::Upload { path, reason, .. } => {
+                let target = join_path(remote, path);
+                if *reason == SyncReason::Replaced {
+                    sftp.remove(&target).await?;
+                }
+                let entry = &local_tree[path];
+                upload_file(
+                    &sftp,
+                    &local_root.join(path),
+                    &target,
+                    entry,
+                    path,
+                    progress,
+                    options.rate_limit.as_ref(),
+                )
+                .await?;
+            }
+            SyncAction::Delete { path } => sftp.remove(&join_path(remote, path)).await?,
+            SyncAction::DeleteDir { path } => {
+                // Excluded entries inside keep the directory alive.
+                if let Err(e) = sftp.rmdir(&join_path(remote, path)).await {
+                    warn!("Could not delete remote directory '{}': {}", path, e);
+                }
+            }
+        }
+    }
+    info!(
+        "Synchronised '{}' to '{}': {} changes, {} unchanged",
+        local,
+        remote,
+        report.actions.len(),
+        report.unchanged
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scp::{set_file_mode, set_file_times};
+    use crate::test_server::{connect, MemoryFs, Node};
+
+    fn file(size: u64, mtime: u32) -> Entry {
+        Entry {
+            kind: EntryKind::File,
+            size,
+            mtime,
+            atime: 0,
+            mode: 0o644,
+        }
+    }
+
+    fn dir() -> Entry {
+        Entry {
+            kind: EntryKind::Dir,
+            size: 0,
+            mtime: 0,
+            atime: 0,
+            mode: 0o755,
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("simple_ssh_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    /// A remote tree with a file to update, a file and a directory that
+    /// only exist remotely, and a directory holding only excluded files.
+    fn stale_remote() -> MemoryFs {
+        let fs = MemoryFs::new();
+        fs.add_dir("/dst", 0o755);
+        fs.add_file("/dst/keep.txt", b"old", 0o644);
+        fs.add_file("/dst/old.txt", b"gone", 0o644);
+        fs.add_dir("/dst/stale", 0o755);
+        fs.add_file("/dst/stale/y", b"y", 0o644);
+        fs.add_dir("/dst/cache", 0o755);
+        fs.add_file("/dst/cache/x.tmp", b"x", 0o644);
+        fs
+    }
+
+    fn tree(entries: &[(&str, Entry)]) -> BTreeMap<String, Entry> {
+        entries
+            .iter()
+            .map(|(path, entry)| (path.to_string(), entry.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_sync_options_builder() {
+        let options = SyncOptions::new()
+            .compare(SyncCompare::Checksum)
+            .delete(true)
+            .include("*.rs")
+            .exclude("target")
+            .exclude("*.tmp")
+            .dry_run(true);
+        assert_eq!(options.compare, SyncCompare::Checksum);
+        assert!(options.delete);
+        assert_eq!(options.include, vec!["*.rs"]);
+        assert_eq!(options.exclude, vec!["target", "*.tmp"]);
+        assert!(options.dry_run);
+        assert_ne!(options, SyncOptions::new());
+        assert_eq!(SyncOptions::new().compare, SyncCompare::SizeAndTime);
+    }
+
+    #[test]
+    fn test_filter_patterns() {
+        let options = SyncOptions::new()
+            .include("*.html")
+            .include("static/**")
+            .exclude("*.tmp")
+            .exclude("static/cache");
+        let filter = SyncFilter::new(&options).unwrap();
+
+        assert!(filter.excludes("a.tmp"));
+        assert!(filter.excludes("deep/dir/b.tmp"));
+        assert!(filter.excludes("static/cache"));
+        assert!(!filter.excludes("other/static/cache"));
+
+        assert!(filter.includes_file("index.html"));
+        assert!(filter.includes_file("blog/post.html"));
+        assert!(filter.includes_file("static/css/site.css"));
+        assert!(!filter.includes_file("README.md"));
+
+        assert!(SyncFilter::new(&SyncOptions::new().exclude("[")).is_err());
+        assert!(SyncFilter::new(&SyncOptions::new())
+            .unwrap()
+            .includes_file("anything"));
+    }
+
+    #[test]
+    fn test_plan_size_and_time() {
+        let local = tree(&[
+            ("a", file(10, 100)),
+            ("b", file(10, 100)),
+            ("c", file(10, 100)),
+            ("d", dir()),
+            ("d/e", file(5, 100)),
+        ]);
+        let remote = tree(&[
+            ("a", file(10, 100)),
+            ("b", file(11, 100)),
+            ("c", file(10, 99)),
+        ]);
+        let report = plan(
+            &local,
+            Some(&remote),
+            SyncCompare::SizeAndTime,
+            &HashSet::new(),
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            report.actions,
+            vec![
+                SyncAction::Upload {
+                    path: "b".into(),
+                    size: 10,
+                    reason: SyncReason::Size
+                },
+                SyncAction::Upload {
+                    path: "c".into(),
+                    size: 10,
+                    reason: SyncReason::Modified
+                },
+                SyncAction::CreateDir { path: "d".into() },
+                SyncAction::Upload {
+                    path: "d/e".into(),
+                    size: 5,
+                    reason: SyncReason::New
+                },
+            ]
+        );
+        assert_eq!(report.unchanged, 1);
+        assert_eq!(report.bytes, 25);
+    }
+
+    #[test]
+    fn test_plan_checksum_ignores_times() {
+        let local = tree(&[("a", file(10, 100)), ("b", file(10, 100))]);
+        let remote = tree(&[("a", file(10, 1)), ("b", file(10, 1))]);
+        let same: HashSet<String> = ["a".to_string()].into();
+        let report = plan(&local, Some(&remote), SyncCompare::Checksum, &same, false).unwrap();
+        assert_eq!(
+            report.actions,
+            vec![SyncAction::Upload {
+                path: "b".into(),
+                size: 10,
+                reason: SyncReason::Checksum
+            }]
+        );
+        assert_eq!(report.unchanged, 1);
+    }
+
+    #[test]
+    fn test_plan_delete_children_first() {
+        let local = tree(&[("keep", file(1, 1))]);
+        let mut link = file(0, 0);
+        link.kind = EntryKind::Other;
+        let remote = tree(&[
+            ("keep", file(1, 1)),
+            ("old", dir()),
+            ("old/x", file(1, 1)),
+            ("old/y", dir()),
+            ("old/y/z", file(1, 1)),
+            ("zlink", link),
+        ]);
+        let report = plan(
+            &local,
+            Some(&remote),
+            SyncCompare::SizeAndTime,
+            &HashSet::new(),
+            true,
+        )
+        .unwrap();
+        assert_eq!(
+            report.actions,
+            vec![
+                SyncAction::Delete {
+                    path: "zlink".into()
+                },
+                SyncAction::Delete {
+                    path: "old/y/z".into()
+                },
+                SyncAction::DeleteDir {
+                    path: "old/y".into()
+                },
+                SyncAction::Delete {
+                    path: "old/x".into()
+                },
+                SyncAction::DeleteDir { path: "old".into() },
+            ]
+        );
+
+        let report = plan(
+            &local,
+            Some(&remote),
+            SyncCompare::SizeAndTime,
+            &HashSet::new(),
+            false,
+        )
+        .unwrap();
+        assert!(report.actions.is_empty());
+    }
+
+    #[test]
+    fn test_plan_missing_root_and_conflicts() {
+        let local = tree(&[("a", file(3, 1))]);
+        let report = plan(
+            &local,
+            None,
+            SyncCompare::SizeAndTime,
+            &HashSet::new(),
+            true,
+        )
+        .unwrap();
+        assert_eq!(
+            report.actions[0],
+            SyncAction::CreateDir { path: ".".into() }
+        );
+        assert_eq!(report.actions.len(), 2);
+
+        let remote = tree(&[("a", dir())]);
+        let err = plan(
+            &local,
+            Some(&remote),
+            SyncCompare::SizeAndTime,
+            &HashSet::new(),
+            true,
+        )
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("is a directory on the remote host"));
+    }
+
+    #[test]
+    fn test_report_display() {
+        let report = SyncReport {
+            actions: vec![
+                SyncAction::CreateDir { path: "d".into() },
+                SyncAction::Upload {
+                    path: "d/a".into(),
+                    size: 4,
+                    reason: SyncReason::New,
+                },
+                SyncAction::DeleteDir { path: "x".into() },
+            ],
+            unchanged: 2,
+            bytes: 4,
+            dry_run: true,
+        };
+        assert_eq!(
+            report.to_string(),
+            "mkdir   d/\nupload  d/a (new)\ndelete  x/\n\
+             1 to upload (4 bytes), 1 deleted, 2 unchanged (dry run)"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sync_dir_unreadable_root() {
+        let local = temp_dir("sync_denied");
+        std::fs::write(local.join("a"), b"a").unwrap();
+        let fs = MemoryFs::new();
+        fs.add_dir("/dst", 0o755);
+        fs.deny("/dst");
+        let mut session = connect(fs.clone()).await;
+
+        let err = sync_dir(
+            &mut session,
+            local.to_str().unwrap(),
+            "/dst",
+            &SyncOptions::new(),
+        )
+        .await
+        .unwrap_err();
+        let err = err.downcast_ref::<SftpError>().unwrap();
+        assert_eq!(err.status, SftpStatus::PermissionDenied);
+        assert_eq!(fs.node("/dst/a"), None);
+    }
+
+    #[tokio::test]
+    async fn test_sync_dir_creates_tree() {
+        let local = temp_dir("sync_create");
+        std::fs::write(local.join("a.txt"), b"alpha").unwrap();
+        std::fs::create_dir(local.join("sub")).unwrap();
+        std::fs::write(local.join("sub/b.txt"), b"beta").unwrap();
+        set_file_mode(&local.join("a.txt"), 0o600).unwrap();
+        set_file_mode(&local.join("sub"), 0o750).unwrap();
+        set_file_times(&local.join("a.txt"), 1_600_000_000, 1_600_000_000).unwrap();
+        set_file_times(&local.join("sub/b.txt"), 1_700_000_000, 1_700_000_000).unwrap();
+        let fs = MemoryFs::new();
+        let mut session = connect(fs.clone()).await;
+        let local = local.to_str().unwrap();
+
+        let report = sync_dir(&mut session, local, "/dst", &SyncOptions::new())
+            .await
+            .unwrap();
+        assert_eq!(report.actions.len(), 4);
+        assert_eq!(report.bytes, 9);
+        assert!(matches!(fs.node("/dst"), Some(Node::Dir { .. })));
+        assert_eq!(
+            fs.node("/dst/sub"),
+            Some(Node::Dir {
+                mode: 0o750,
+                mtime: 0
+            })
+        );
+        assert_eq!(
+            fs.node("/dst/a.txt"),
+            Some(Node::File {
+                data: b"alpha".to_vec(),
+                mode: 0o600,
+                mtime: 1_600_000_000
+            })
+        );
+        assert_eq!(fs.file("/dst/sub/b.txt").unwrap(), b"beta");
+
+        // The applied times make the second run a no-op.
+        let report = sync_dir(&mut session, local, "/dst", &SyncOptions::new())
+            .await
+            .unwrap();
+        assert_eq!(report.actions, vec![]);
+        assert_eq!(report.unchanged, 2);
+    }
+
+    #[tokio::test]
+    async fn test_sync_dir_delete_keeps_excluded() {
+        let local = temp_dir("sync_delete");
+        std::fs::write(local.join("keep.txt"), b"new").unwrap();
+        let fs = stale_remote();
+        let mut session = connect(fs.clone()).await;
+
+        let options = SyncOptions::new().delete(true).exclude("*.tmp");
+        let report = sync_dir(&mut session, local.to_str().unwrap(), "/dst", &options)
+            .await
+            .unwrap();
+        for action in [
+            SyncAction::Delete {
+                path: "old.txt".into(),
+            },
+            SyncAction::Delete {
+                path: "stale/y".into(),
+            },
+            SyncAction::DeleteDir {
+                path: "stale".into(),
+            },
+            SyncAction::DeleteDir {
+                path: "cache".into(),
+            },
+        ] {
+            assert!(report.actions.contains(&action), "{}", action);
+        }
+        assert_eq!(fs.file("/dst/keep.txt").unwrap(), b"new");
+        assert_eq!(fs.node("/dst/old.txt"), None);
+        assert_eq!(fs.node("/dst/stale"), None);
+        // The excluded file keeps its directory alive.
+        assert_eq!(fs.file("/dst/cache/x.tmp").unwrap(), b"x");
+    }
+
+    #[tokio::test]
+    async fn test_sync_dir_dry_run() {
+        let local = temp_dir("sync_dry_run");
+        std::fs::write(local.join("keep.txt"), b"new").unwrap();
+        std::fs::write(local.join("added.txt"), b"added").unwrap();
+        let fs = stale_remote();
+        let before = fs.snapshot();
+        let mut session = connect(fs.clone()).await;
+
+        let options = SyncOptions::new().delete(true).dry_run(true);
+        let report = sync_dir(&mut session, local.to_str().unwrap(), "/dst", &options)
+            .await
+            .unwrap();
+        assert!(report.dry_run);
+        assert_eq!(report.actions.len(), 7);
+        assert_eq!(fs.snapshot(), before);
+    }
+
+    #[test]
+    fn test_join_path() {
+        assert_eq!(join_path("/srv/www/", "."), "/srv/www/");
+        assert_eq!(join_path("/srv/www/", "a/b"), "/srv/www/a/b");
+        assert_eq!(join_path("www", "a"), "www/a");
+    }
+}
@@ -0,0 +1,632 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2025 Julian Kahlert
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use russh::keys::{ssh_key, PrivateKey};
+use russh::server::{self, Auth, Msg, Session};
+use russh::{client, Channel, ChannelId, CryptoVec};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::connection::ConnectionMonitor;
+use crate::{connect_transport, Client};
+
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+
+const FX_EOF: u32 = 1;
+const FX_NO_SUCH_FILE: u32 = 2;
+const FX_PERMISSION_DENIED: u32 = 3;
+const FX_FAILURE: u32 = 4;
+const FX_BAD_MESSAGE: u32 = 5;
+const FX_OP_UNSUPPORTED: u32 = 8;
+
+const FXF_CREAT: u32 = 0x08;
+const FXF_TRUNC: u32 = 0x10;
+const FXF_EXCL: u32 = 0x20;
+
+/// Largest read answered in one piece; longer reads come back short so
+/// the clients have to resubmit the rest.
+const MAX_READ: usize = 10_000;
+
+/// A file or directory of a [`MemoryFs`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Node {
+    File {
+        data: Vec<u8>,
+        mode: u32,
+        mtime: u32,
+    },
+    Dir {
+        mode: u32,
+        mtime: u32,
+    },
+}
+
+impl Node {
+    fn attrs(&self) -> Vec<u8> {
+        let (size, mode, mtime) = match self {
+            Node::File { data, mode, mtime } => (data.len() as u64, S_IFREG | mode, *mtime),
+            Node::Dir { mode, mtime } => (0, S_IFDIR | mode, *mtime),
+        };
+        // Size, permissions and times.
+        let mut out = 0x0du32.to_be_bytes().to_vec();
+        out.extend_from_slice(&size.to_be_bytes());
+        out.extend_from_slice(&mode.to_be_bytes());
+        out.extend_from_slice(&mtime.to_be_bytes());
+        out.extend_from_slice(&mtime.to_be_bytes());
+        out
+    }
+}
+
+/// File tree shared by every SFTP session of a test server.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct MemoryFs {
+    nodes: Arc<Mutex<BTreeMap<String, Node>>>,
+    denied: Arc<Mutex<HashSet<String>>>,
+}
+
+impl MemoryFs {
+    /// Creates a tree holding only the root directory.
+    pub(crate) fn new() -> Self {
+        let fs = Self::default();
+        fs.add_dir("/", 0o755);
+        fs
+    }
+
+    pub(crate) fn add_dir(&self, path: &str, mode: u32) {
+        let node = Node::Dir { mode, mtime: 0 };
+        self.nodes.lock().insert(normalize(path), node);
+    }
+
+    pub(crate) fn add_file(&self, path: &str, data: &[u8], mode: u32) {
+        let node = Node::File {
+            data: data.to_vec(),
+            mode,
+            mtime: 0,
+        };
+        self.nodes.lock().insert(normalize(path), node);
+    }
+
+    /// Content of the file at `path`, `None` if there is no such file.
+    pub(crate) fn file(&self, path: &str) -> Option<Vec<u8>> {
+        match self.nodes.lock().get(&normalize(path)) {
+            Some(Node::File { data, .. }) => Some(data.clone()),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn node(&self, path: &str) -> Option<Node> {
+        self.nodes.lock().get(&normalize(path)).cloned()
+    }
+
+    #[cfg(feature = "sync")]
+    pub(crate) fn snapshot(&self) -> BTreeMap<String, Node> {
+        self.nodes.lock().clone()
+    }
+
+    /// Fails lookups of `path` (stat, open and opendir) with a permission
+    /// error; changes to the tree still go through.
+    #[cfg(feature = "sync")]
+    pub(crate) fn deny(&self, path: &str) {
+        self.denied.lock().insert(normalize(path));
+    }
+}
+
+fn normalize(path: &str) -> String {
+    match path.trim_end_matches('/') {
+        "" => "/".to_string(),
+        path => path.to_string(),
+    }
+}
+
+fn parent(path: &str) -> &str {
+    match path.rsplit_once('/') {
+        Some(("", _)) => "/",
+        Some((parent, _)) => parent,
+        None => "/",
+    }
+}
+
+/// The attributes of a request the server acts on.
+struct Attrs {
+    size: Option<u64>,
+    mode: Option<u32>,
+    mtime: Option<u32>,
+}
+
+/// Reads the fields of a request, failing with a bad message status.
+struct Fields<'a>(&'a [u8]);
+
+impl Fields<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8], u32> {
+        if self.0.len() < n {
+            return Err(FX_BAD_MESSAGE);
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> Result<u32, u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, u32> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<Vec<u8>, u32> {
+        let len = self.u32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    fn path(&mut self) -> Result<String, u32> {
+        Ok(normalize(&String::from_utf8_lossy(&self.string()?)))
+    }
+
+    fn attrs(&mut self) -> Result<Attrs, u32> {
+        let flags = self.u32()?;
+        let size = (flags & 0x01 != 0).then(|| self.u64()).transpose()?;
+        if flags & 0x02 != 0 {
+            self.take(8)?;
+        }
+        let mode = (flags & 0x04 != 0).then(|| self.u32()).transpose()?;
+        let mut mtime = None;
+        if flags & 0x08 != 0 {
+            self.u32()?;
+            mtime = Some(self.u32()?);
+        }
+        Ok(Attrs {
+            size,
+            mode: mode.map(|m| m & 0o7777),
+            mtime,
+        })
+    }
+}
+
+enum OpenHandle {
+    File(String),
+    Dir { path: String, listed: bool },
+}
+
+enum Reply {
+    Ok,
+    Handle(u32),
+    Data(Vec<u8>),
+    Attrs(Vec<u8>),
+    Names(Vec<(String, Vec<u8>)>),
+}
+
+struct SftpSession {
+    fs: MemoryFs,
+    handles: HashMap<u32, OpenHandle>,
+    next_handle: u32,
+}
+
+impl SftpSession {
+    fn check(&self, path: &str) -> Result<(), u32> {
+        if self.fs.denied.lock().contains(path) {
+            return Err(FX_PERMISSION_DENIED);
+        }
+        Ok(())
+    }
+
+    fn lookup(&self, fields: &mut Fields<'_>) -> Result<String, u32> {
+        let path = fields.path()?;
+        self.check(&path)?;
+        Ok(path)
+    }
+
+    fn open(&mut self, handle: OpenHandle) -> Reply {
+        self.next_handle += 1;
+        self.handles.insert(self.next_handle, handle);
+        Reply::Handle(self.next_handle)
+    }
+
+    fn handle_id(fields: &mut Fields<'_>) -> Result<u32, u32> {
+        let raw = fields.string()?;
+        Ok(u32::from_be_bytes(raw.try_into().map_err(|_| FX_FAILURE)?))
+    }
+
+    fn file_handle(&self, fields: &mut Fields<'_>) -> Result<String, u32> {
+        match self.handles.get(&Self::handle_id(fields)?) {
+            Some(OpenHandle::File(path)) => {
+                self.check(path)?;
+                Ok(path.clone())
+            }
+            _ => Err(FX_FAILURE),
+        }
+    }
+
+    fn set_attrs(&self, path: &str, fields: &mut Fields<'_>) -> Result<Reply, u32> {
+        let Attrs {
+            size,
+            mode,
+            mtime: time,
+        } = fields.attrs()?;
+        let mut nodes = self.fs.nodes.lock();
+        match nodes.get_mut(path).ok_or(FX_NO_SUCH_FILE)? {
+            Node::File {
+                data,
+                mode: m,
+                mtime,
+            } => {
+                if let Some(size) = size {
+                    data.resize(size as usize, 0);
+                }
+                *m = mode.unwrap_or(*m);
+                *mtime = time.unwrap_or(*mtime);
+            }
+            Node::Dir { mode: m, mtime } => {
+                *m = mode.unwrap_or(*m);
+                *mtime = time.unwrap_or(*mtime);
+            }
+        }
+        Ok(Reply::Ok)
+    }
+
+    fn respond(&mut self, kind: u8, fields: &mut Fields<'_>) -> Result<Reply, u32> {
+        match kind {
+            // open
+            3 => {
+                let path = self.lookup(fields)?;
+                let flags = fields.u32()?;
+                let mode = fields.attrs()?.mode;
+                let mut nodes = self.fs.nodes.lock();
+                match nodes.get_mut(&path) {
+                    Some(Node::Dir { .. }) => return Err(FX_FAILURE),
+                    Some(Node::File { .. }) if flags & FXF_EXCL != 0 => return Err(FX_FAILURE),
+                    Some(Node::File { data, .. }) if flags & FXF_TRUNC != 0 => data.clear(),
+                    Some(Node::File { .. }) => {}
+                    None if flags & FXF_CREAT == 0 => return Err(FX_NO_SUCH_FILE),
+                    None => {
+                        if !matches!(nodes.get(parent(&path)), Some(Node::Dir { .. })) {
+                            return Err(FX_NO_SUCH_FILE);
+                        }
+                        let node = Node::File {
+                            data: Vec::new(),
+                            mode: mode.unwrap_or(0o644),
+                            mtime: 0,
+                        };
+                        nodes.insert(path.clone(), node);
+                    }
+                }
+                drop(nodes);
+                Ok(self.open(OpenHandle::File(path)))
+            }
+            // close
+            4 => {
+                let id = Self::handle_id(fields)?;
+                self.handles.remove(&id).ok_or(FX_FAILURE)?;
+                Ok(Reply::Ok)
+            }
+            // read
+            5 => {
+                let path = self.file_handle(fields)?;
+                let offset = fields.u64()? as usize;
+                let len = fields.u32()? as usize;
+                let nodes = self.fs.nodes.lock();
+                let Some(Node::File { data, .. }) = nodes.get(&path) else {
+                    return Err(FX_NO_SUCH_FILE);
+                };
+                if offset >= data.len() {
+                    return Err(FX_EOF);
+                }
+                let end = data.len().min(offset + len.min(MAX_READ));
+                Ok(Reply::Data(data[offset..end].to_vec()))
+            }
+            // write
+            6 => {
+                let path = self.file_handle(fields)?;
+                let offset = fields.u64()? as usize;
+                let chunk = fields.string()?;
+                let mut nodes = self.fs.nodes.lock();
+                let Some(Node::File { data, .. }) = nodes.get_mut(&path) else {
+                    return Err(FX_NO_SUCH_FILE);
+                };
+                if data.len() < offset + chunk.len() {
+                    data.resize(offset + chunk.len(), 0);
+                }
+                data[offset..offset + chunk.len()].copy_from_slice(&chunk);
+                Ok(Reply::Ok)
+            }
+            // lstat, stat
+            7 | 17 => {
+                let path = self.lookup(fields)?;
+                let node = self.fs.node(&path).ok_or(FX_NO_SUCH_FILE)?;
+                Ok(Reply::Attrs(node.attrs()))
+            }
+            // fstat
+            8 => {
+                let path = self.file_handle(fields)?;
+                let node = self.fs.node(&path).ok_or(FX_NO_SUCH_FILE)?;
+                Ok(Reply::Attrs(node.attrs()))
+            }
+            // setstat
+            9 => {
+                let path = self.lookup(fields)?;
+                self.set_attrs(&path, fields)
+            }
+            // fsetstat
+            10 => {
+                let path = self.file_handle(fields)?;
+                self.set_attrs(&path, fields)
+            }
+            // opendir
+            11 => {
+                let path = self.lookup(fields)?;
+                match self.fs.node(&path) {
+                    Some(Node::Dir { .. }) => Ok(self.open(OpenHandle::Dir {
+                        path,
+                        listed: false,
+                    })),
+                    Some(_) => Err(FX_FAILURE),
+                    None => Err(FX_NO_SUCH_FILE),
+                }
+            }
+            // readdir
+            12 => {
+                let id = Self::handle_id(fields)?;
+                let Some(OpenHandle::Dir { path, listed }) = self.handles.get_mut(&id) else {
+                    return Err(FX_FAILURE);
+                };
+                if std::mem::replace(listed, true) {
+                    return Err(FX_EOF);
+                }
+                let nodes = self.fs.nodes.lock();
+                let names = nodes
+                    .iter()
+                    .filter(|(child, _)| child.as_str() != "/" && parent(child) == path)
+                    .map(|(child, node)| {
+                        let name = child.rsplit('/').next().unwrap_or_default();
+                        (name.to_string(), node.attrs())
+                    })
+                    .collect();
+                Ok(Reply::Names(names))
+            }
+            // remove
+            13 => {
+                let path = fields.path()?;
+                let mut nodes = self.fs.nodes.lock();
+                match nodes.get(&path) {
+                    Some(Node::File { .. }) => {
+                        nodes.remove(&path);
+                        Ok(Reply::Ok)
+                    }
+                    Some(Node::Dir { .. }) => Err(FX_FAILURE),
+                    None => Err(FX_NO_SUCH_FILE),
+                }
+            }
+            // mkdir
+            14 => {
+                let path = fields.path()?;
+                let mode = fields.attrs()?.mode;
+                let mut nodes = self.fs.nodes.lock();
+                if nodes.contains_key(&path) {
+                    return Err(FX_FAILURE);
+                }
+                if !matches!(nodes.get(parent(&path)), Some(Node::Dir { .. })) {
+                    return Err(FX_NO_SUCH_FILE);
+                }
+                let node = Node::Dir {
+                    mode: mode.unwrap_or(0o755),
+                    mtime: 0,
+                };
+                nodes.insert(path, node);
+                Ok(Reply::Ok)
+            }
+            // rmdir
+            15 => {
+                let path = fields.path()?;
+                let mut nodes = self.fs.nodes.lock();
+                if !matches!(nodes.get(&path), Some(Node::Dir { .. })) {
+                    return Err(FX_NO_SUCH_FILE);
+                }
+                if nodes
+                    .keys()
+                    .any(|child| child != "/" && parent(child) == path)
+                {
+                    return Err(FX_FAILURE);
+                }
+                nodes.remove(&path);
+                Ok(Reply::Ok)
+            }
+            // realpath
+            16 => {
+                let path = fields.path()?;
+                Ok(Reply::Names(vec![(path, vec![0; 4])]))
+            }
+            _ => Err(FX_OP_UNSUPPORTED),
+        }
+    }
+}
+
+fn packet(kind: u8, id: u32, body: &[u8]) -> Vec<u8> {
+    let mut out = ((5 + body.len()) as u32).to_be_bytes().to_vec();
+    out.push(kind);
+    out.extend_from_slice(&id.to_be_bytes());
+    out.extend_from_slice(body);
+    out
+}
+
+fn put_string(out: &mut Vec<u8>, value: &[u8]) {
+    out.extend_from_slice(&(value.len() as u32).to_be_bytes());
+    out.extend_from_slice(value);
+}
+
+async fn read_packet<S: AsyncRead + Unpin>(stream: &mut S) -> Option<Vec<u8>> {
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len).await.ok()?;
+    let mut packet = vec![0u8; u32::from_be_bytes(len) as usize];
+    stream.read_exact(&mut packet).await.ok()?;
+    Some(packet)
+}
+
+/// Serves SFTP version 3 on `stream` until the client goes away.
+///
+/// Reads return at most 10 000 bytes.
+pub(crate) async fn serve_sftp<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, fs: MemoryFs) {
+    if read_packet(&mut stream).await.is_none() {
+        return;
+    }
+    if stream
+        .write_all(&[0, 0, 0, 5, 2, 0, 0, 0, 3])
+        .await
+        .is_err()
+    {
+        return;
+    }
+
+    let mut session = SftpSession {
+        fs,
+        handles: HashMap::new(),
+        next_handle: 0,
+    };
+    while let Some(request) = read_packet(&mut stream).await {
+        if request.len() < 5 {
+            return;
+        }
+        let id = u32::from_be_bytes(request[1..5].try_into().unwrap());
+        let mut fields = Fields(&request[5..]);
+        let reply = match session.respond(request[0], &mut fields) {
+            Ok(Reply::Ok) => packet(101, id, &[0; 12]),
+            Ok(Reply::Handle(handle)) => {
+                let mut body = Vec::new();
+                put_string(&mut body, &handle.to_be_bytes());
+                packet(102, id, &body)
+            }
+            Ok(Reply::Data(data)) => {
+                let mut body = Vec::new();
+                put_string(&mut body, &data);
+                packet(103, id, &body)
+            }
+            Ok(Reply::Attrs(attrs)) => packet(105, id, &attrs),
+            Ok(Reply::Names(names)) => {
+                let mut body = (names.len() as u32).to_be_bytes().to_vec();
+                for (name, attrs) in names {
+                    put_string(&mut body, name.as_bytes());
+                    put_string(&mut body, name.as_bytes());
+                    body.extend_from_slice(&attrs);
+                }
+                packet(104, id, &body)
+            }
+            Err(code) => {
+                let mut body = code.to_be_bytes().to_vec();
+                body.extend_from_slice(&[0; 8]);
+                packet(101, id, &body)
+            }
+        };
+        if stream.write_all(&reply).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// SSH server offering the `sftp` subsystem on a [`MemoryFs`].
+///
+/// There is no shell: every exec request fails with exit status 127, so
+/// callers have to fall back to what SFTP offers.
+struct SftpServer {
+    fs: MemoryFs,
+    channels: HashMap<ChannelId, Channel<Msg>>,
+}
+
+impl server::Handler for SftpServer {
+    type Error = russh::Error;
+
+    async fn auth_none(&mut self, _user: &str) -> Result<Auth, Self::Error> {
+        Ok(Auth::Accept)
+    }
+
+    async fn channel_open_session(
+        &mut self,
+        channel: Channel<Msg>,
+        _session: &mut Session,
+    ) -> Result<bool, Self::Error> {
+        self.channels.insert(channel.id(), channel);
+        Ok(true)
+    }
+
+    async fn subsystem_request(
+        &mut self,
+        id: ChannelId,
+        name: &str,
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        match self.channels.remove(&id) {
+            Some(channel) if name == "sftp" => {
+                session.channel_success(id)?;
+                tokio::spawn(serve_sftp(channel.into_stream(), self.fs.clone()));
+            }
+            _ => session.channel_failure(id)?,
+        }
+        Ok(())
+    }
+
+    async fn exec_request(
+        &mut self,
+        id: ChannelId,
+        _command: &[u8],
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        self.channels.remove(&id);
+        session.channel_success(id)?;
+        session.extended_data(id, 1, CryptoVec::from_slice(b"sh: not found\n"))?;
+        session.exit_status_request(id, 127)?;
+        session.eof(id)?;
+        session.close(id)
+    }
+}
+
+/// Starts an in-process SSH server on `fs` and returns an authenticated
+/// client handle for it.
+pub(crate) async fn connect(fs: MemoryFs) -> client::Handle<Client> {
+    let key = PrivateKey::from(ssh_key::private::Ed25519Keypair::from_seed(&[7u8; 32]));
+    let config = Arc::new(server::Config {
+        keys: vec![key],
+        ..Default::default()
+    });
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let handler = SftpServer {
+            fs,
+            channels: HashMap::new(),
+        };
+        if let Ok(session) = server::run_stream(config, stream, handler).await {
+            let _ = session.await;
+        }
+    });
+
+    let connection = Arc::new(ConnectionMonitor::new());
+    let mut handle = connect_transport(client::Config::default(), addr, &connection)
+        .await
+        .unwrap();
+    assert!(handle.authenticate_none("user").await.unwrap().success());
+    handle
+}
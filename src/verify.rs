@@ -0,0 +1,454 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) 2025 Julian Kahlert
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use anyhow::{anyhow, Result};
+use log::{debug, info};
+use md5::Md5;
+use russh::client;
+use russh::ChannelMsg;
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::Client;
+
+/// Error returned when a transferred file does not match its source.
+///
+/// Can be recovered from the [`anyhow::Error`] of a transfer with
+/// `error.downcast_ref::<ChecksumMismatch>()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChecksumMismatch {
+    /// Path of the remote file.
+    pub path: String,
+    /// Hash algorithm used for the comparison (`sha256` or `md5`).
+    pub algorithm: &'static str,
+    /// Hex digest of the local data.
+    pub local: String,
+    /// Hex digest reported by the remote host.
+    pub remote: String,
+}
+
+impl fmt::Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Checksum mismatch for {}: local {} {}, remote {}",
+            self.path, self.algorithm, self.local, self.remote
+        )
+    }
+}
+
+impl std::error::Error for ChecksumMismatch {}
+
+/// Running SHA-256 and MD5 digests of the transferred data.
+///
+/// Both are computed so the comparison works with whichever tool the
+/// remote host has.
+#[derive(Default)]
+pub(crate) struct Digests {
+    sha256: Sha256,
+    md5: Md5,
+}
+
+impl Digests {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        self.sha256.update(data);
+        self.md5.update(data);
+    }
+
+    /// Returns the hex digest for `algorithm`, `None` if unknown.
+    pub(crate) fn hex(&self, algorithm: &str) -> Option<String> {
+        let bytes = match algorithm {
+            "sha256" => self.sha256.clone().finalize().to_vec(),
+            "md5" => self.md5.clone().finalize().to_vec(),
+            _ => return None,
+        };
+        Some(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+    }
+}
+
+/// Reader or writer wrapper feeding all data through [`Digests`].
+///
+/// Without digests the data passes through untouched.
+pub(crate) struct Hashing<'a, T> {
+    inner: T,
+    digests: Option<&'a mut Digests>,
+}
+
+impl<'a, T> Hashing<'a, T> {
+    pub(crate) fn new(inner: T, digests: Option<&'a mut Digests>) -> Self {
+        Self { inner, digests }
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for Hashing<'_, T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        let start = buf.filled().len();
+        let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if let (Poll::Ready(Ok(())), Some(digests)) = (&result, this.digests.as_mut()) {
+            digests.update(&buf.filled()[start..]);
+        }
+        result
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for Hashing<'_, T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        let result = Pin::new(&mut this.inner).poll_write(cx, buf);
+        if let (Poll::Ready(Ok(n)), Some(digests)) = (&result, this.digests.as_mut()) {
+            digests.update(&buf[..*n]);
+        }
+        result
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+/// Builds the remote command printing the checksum of the file selected
+/// by `path_expr` (see `remote_path_expr`).
+pub(crate) fn checksum_command(path_expr: &str) -> String {
+    format!(
+        "{} if command -v sha256sum >/dev/null 2>&1; then sha256sum \"$p\"; \
+         else md5sum \"$p\"; fi",
+        path_expr
+    )
+}
+
+/// Builds the remote command printing the checksum of the first `len`
+/// bytes of the file selected by `path_expr`.
+///
+/// Exits with 127 if `head` is missing, as an empty pipe would otherwise
+/// produce a valid looking checksum.
+pub(crate) fn prefix_checksum_command(path_expr: &str, len: u64) -> String {
+    format!(
+        "{} command -v head >/dev/null 2>&1 || exit 127; \
+         if command -v sha256sum >/dev/null 2>&1; then head -c {} \"$p\" | sha256sum; \
+         else head -c {} \"$p\" | md5sum; fi",
+        path_expr, len, len
+    )
+}
+
+/// Builds the remote command printing the checksums of `files`, given
+/// relative to the directory `root`.
+#[cfg(feature = "sync")]
+pub(crate) fn checksum_files_command(root: &str, files: &[String]) -> String {
+    let files = files
+        .iter()
+        .map(|f| shell_escape::escape(f.as_str().into()).to_string())
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(
+        "cd {} && if command -v sha256sum >/dev/null 2>&1; then sha256sum -- {}; \
+         else md5sum -- {}; fi",
+        shell_escape::escape(root.into()),
+        files,
+        files
+    )
+}
+
+/// Output line of `sha256sum` or `md5sum`.
+#[derive(Debug, PartialEq)]
+pub(crate) struct RemoteChecksum {
+    pub(crate) algorithm: &'static str,
+    pub(crate) digest: String,
+    /// File name as printed by the tool, if any.
+    pub(crate) path: Option<String>,
+}
+
+/// Parses `sha256sum`/`md5sum` output.
+pub(crate) fn parse_checksum(output: &str) -> Result<RemoteChecksum> {
+    let line = output.lines().next().unwrap_or_default();
+    let (digest, path) = line.split_once(' ').unwrap_or((line, ""));
+    let algorithm = match digest.len() {
+        64 => "sha256",
+        32 => "md5",
+        _ => return Err(anyhow!("Unexpected checksum output: {:?}", output.trim())),
+    };
+    if !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow!("Unexpected checksum output: {:?}", output.trim()));
+    }
+    // Binary mode marks the name with `*`.
+    let path = path.trim_start().trim_start_matches('*');
+    Ok(RemoteChecksum {
+        algorithm,
+        digest: digest.to_ascii_lowercase(),
+        path: (!path.is_empty()).then(|| path.to_string()),
+    })
+}
+
+/// Parses the output of `sha256sum`/`md5sum` for several files.
+///
+/// Lines that cannot be parsed, such as the escaped form used for names
+/// containing newlines or backslashes, are left out.
+#[cfg(feature = "sync")]
+pub(crate) fn parse_checksums(output: &str) -> Vec<RemoteChecksum> {
+    output
+        .lines()
+        .filter(|line| !line.starts_with('\\'))
+        .filter_map(|line| parse_checksum(line).ok())
+        .collect()
+}
+
+/// Runs a command on the remote host and captures its output.
+///
+/// # Returns
+///
+/// The exit status, stdout and stderr of the command.
+pub(crate) async fn capture_output(
+    session: &mut client::Handle<Client>,
+    command: &str,
+) -> Result<(Option<u32>, Vec<u8>, Vec<u8>)> {
+    let mut channel = session.channel_open_session().await?;
+    channel.exec(true, command).await?;
+
+    let mut code = None;
+    let mut stdout = Vec::new();
+    let mut stderr = Vec::new();
+    while let Some(msg) = channel.wait().await {
+        match msg {
+            ChannelMsg::Data { ref data } => stdout.extend_from_slice(data),
+            ChannelMsg::ExtendedData { ref data, ext: 1 } => stderr.extend_from_slice(data),
+            ChannelMsg::ExitStatus { exit_status } => code = Some(exit_status),
+            _ => {}
+        }
+    }
+    Ok((code, stdout, stderr))
+}
+
+/// Compares the checksum of a remote file with the local digests.
+///
+/// # Arguments
+///
+/// * `session` - The SSH session handle
+/// * `path_expr` - Shell snippet selecting the file, see `remote_path_expr`
+/// * `remote_path` - Path of the file reported in errors
+/// * `digests` - Digests of the transferred data
+/// * `remove` - Whether to delete the remote file on a mismatch
+pub(crate) async fn verify_remote_file(
+    session: &mut client::Handle<Client>,
+    path_expr: &str,
+    remote_path: &str,
+    digests: &Digests,
+    remove: bool,
+) -> Result<()> {
+    let command = checksum_command(path_expr);
+    let (code, stdout, stderr) = capture_output(session, &command).await?;
+    if code != Some(0) {
+        return Err(anyhow!(
+            "Could not checksum remote file '{}': {}",
+            remote_path,
+            String::from_utf8_lossy(&stderr).trim_end()
+        ));
+    }
+
+    let checksum = parse_checksum(&String::from_utf8_lossy(&stdout))?;
+    let path = checksum.path.as_deref().unwrap_or(remote_path);
+    let local = digests.hex(checksum.algorithm).unwrap_or_default();
+    if local == checksum.digest {
+        debug!("Verified {} ({} {})", path, checksum.algorithm, local);
+        return Ok(());
+    }
+
+    if remove {
+        info!("Removing corrupt remote file {}", path);
+        let command = format!("{} rm -f \"$p\"", path_expr);
+        if let Ok((code, _, stderr)) = capture_output(session, &command).await {
+            if code != Some(0) {
+                info!(
+                    "Failed to remove {}: {}",
+                    path,
+                    String::from_utf8_lossy(&stderr).trim_end()
+                );
+            }
+        }
+    }
+    Err(ChecksumMismatch {
+        path: path.to_string(),
+        algorithm: checksum.algorithm,
+        local,
+        remote: checksum.digest,
+    }
+    .into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn test_digests_known_values() {
+        let mut digests = Digests::new();
+        digests.update(b"hello ");
+        digests.update(b"world\n");
+        assert_eq!(
+            digests.hex("sha256").unwrap(),
+            "a948904f2f0f479b8f8197694b30184b0d2ed1c1cd2a1ec0fb85d299a192a447"
+        );
+        assert_eq!(
+            digests.hex("md5").unwrap(),
+            "6f5902ac237024bdd0c176cb93063dc4"
+        );
+        assert_eq!(digests.hex("sha1"), None);
+    }
+
+    #[tokio::test]
+    async fn test_hashing_reader_and_writer() {
+        let mut read_digests = Digests::new();
+        let mut reader = Hashing::new(&b"hello world\n"[..], Some(&mut read_digests));
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).await.unwrap();
+
+        let mut write_digests = Digests::new();
+        let mut out = Vec::new();
+        let mut writer = Hashing::new(&mut out, Some(&mut write_digests));
+        writer.write_all(&data).await.unwrap();
+        writer.flush().await.unwrap();
+
+        assert_eq!(out, b"hello world\n");
+        assert_eq!(read_digests.hex("md5"), write_digests.hex("md5"));
+        assert_eq!(
+            read_digests.hex("md5").unwrap(),
+            "6f5902ac237024bdd0c176cb93063dc4"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hashing_without_digests() {
+        let mut reader = Hashing::new(&b"abc"[..], None);
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).await.unwrap();
+        assert_eq!(data, b"abc");
+    }
+
+    #[test]
+    fn test_parse_checksum() {
+        let checksum = parse_checksum(
+            "A948904F2F0F479B8F8197694B30184B0D2ED1C1CD2A1EC0FB85D299A192A447  /tmp/my f\n",
+        )
+        .unwrap();
+        assert_eq!(
+            checksum,
+            RemoteChecksum {
+                algorithm: "sha256",
+                digest: "a948904f2f0f479b8f8197694b30184b0d2ed1c1cd2a1ec0fb85d299a192a447".into(),
+                path: Some("/tmp/my f".into()),
+            }
+        );
+
+        let checksum = parse_checksum("6f5902ac237024bdd0c176cb93063dc4 *f").unwrap();
+        assert_eq!(checksum.algorithm, "md5");
+        assert_eq!(checksum.path.as_deref(), Some("f"));
+        let checksum = parse_checksum("6f5902ac237024bdd0c176cb93063dc4").unwrap();
+        assert_eq!(checksum.path, None);
+
+        assert!(parse_checksum("").is_err());
+        assert!(parse_checksum("sh: md5sum: not found").is_err());
+        assert!(parse_checksum(&"z".repeat(32)).is_err());
+    }
+
+    #[test]
+    fn test_checksum_command() {
+        assert_eq!(
+            checksum_command("p=/f;"),
+            "p=/f; if command -v sha256sum >/dev/null 2>&1; then sha256sum \"$p\"; \
+             else md5sum \"$p\"; fi"
+        );
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn test_checksum_files_command() {
+        let files = vec!["a.txt".to_string(), "sub/b c".to_string()];
+        assert_eq!(
+            checksum_files_command("/srv/www", &files),
+            "cd /srv/www && if command -v sha256sum >/dev/null 2>&1; then \
+             sha256sum -- a.txt 'sub/b c'; else md5sum -- a.txt 'sub/b c'; fi"
+        );
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn test_parse_checksums() {
+        let output = format!(
+            "{}  a.txt\n\\{}  odd\\nname\n{} *sub/b c\n",
+            "1".repeat(64),
+            "2".repeat(64),
+            "3".repeat(64)
+        );
+        let checksums = parse_checksums(&output);
+        assert_eq!(checksums.len(), 2);
+        assert_eq!(checksums[0].path.as_deref(), Some("a.txt"));
+        assert_eq!(checksums[1].path.as_deref(), Some("sub/b c"));
+        assert_eq!(checksums[1].digest, "3".repeat(64));
+        assert!(parse_checksums("").is_empty());
+    }
+
+    #[test]
+    fn test_prefix_checksum_command() {
+        assert_eq!(
+            prefix_checksum_command("p=/f;", 4096),
+            "p=/f; command -v head >/dev/null 2>&1 || exit 127; \
+             if command -v sha256sum >/dev/null 2>&1; then head -c 4096 \"$p\" | sha256sum; \
+             else head -c 4096 \"$p\" | md5sum; fi"
+        );
+    }
+
+    #[test]
+    fn test_checksum_mismatch_display() {
+        let err = ChecksumMismatch {
+            path: "/tmp/f".into(),
+            algorithm: "md5",
+            local: "aa".into(),
+            remote: "bb".into(),
+        };
+        assert_eq!(
+            err.to_string(),
+            "Checksum mismatch for /tmp/f: local md5 aa, remote bb"
+        );
+    }
+}